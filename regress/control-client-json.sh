@@ -0,0 +1,39 @@
+#!/bin/sh
+
+# control clients started with -J should get one JSON object on each line
+
+PATH=/bin:/usr/bin
+TERM=screen
+
+[ -z "$TEST_TMUX" ] && TEST_TMUX=$(readlink -f ../tmux)
+TMUX="$TEST_TMUX -Ltest"
+$TMUX kill-server 2>/dev/null
+
+TMP=$(mktemp)
+OUT=$(mktemp)
+trap "rm -f $TMP $OUT" 0 1 15
+
+$TMUX -f/dev/null new -d -sfoo || exit 1
+sleep 1
+cat <<EOF|$TMUX -CJ a >$TMP
+display -p 'one "two"'
+rename-session bar
+setb -bbuf hello
+EOF
+grep -v '"time"' $TMP >$OUT
+cat <<EOF|cmp -s $OUT - || exit 1
+{"type":"session-changed","session":0,"name":"foo"}
+{"type":"session-renamed","session":0,"name":"bar"}
+{"type":"paste-buffer-changed","name":"buf"}
+{"type":"exit"}
+EOF
+grep -q '"output":\["one \\"two\\""\]}$' $TMP || exit 1
+
+# the json flag chooses JSON for one client only
+echo "display -p three" | $TMUX -C a -f json >$TMP
+grep -q '"output":\["three"\]}$' $TMP || exit 1
+echo "display -p four" | $TMUX -C a >$TMP
+grep -q '^four$' $TMP || exit 1
+$TMUX kill-server 2>/dev/null
+
+exit 0
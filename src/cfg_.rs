@@ -88,6 +88,10 @@ pub fn start_cfg() {
             );
         }
 
+        cmdq_append(
+            null_mut(),
+            cmdq_get_callback!(state_start, null_mut()).as_ptr(),
+        );
         cmdq_append(
             null_mut(),
             cmdq_get_callback!(cfg_done, null_mut()).as_ptr(),
@@ -260,7 +264,12 @@ pub unsafe fn cfg_show_causes(mut s: *mut session) {
 
         if !c.is_null() && (*c).flags.intersects(client_flag::CONTROL) {
             for cause in CFG_CAUSES.lock().unwrap().drain(..) {
-                control_write!(c, "%config-error {}", cause.to_string_lossy());
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let json = control_json::new("config-error").string("message", cause.to_bytes());
+                    control_write_json(c, json);
+                } else {
+                    control_write!(c, "%config-error {}", cause.to_string_lossy());
+                }
             }
             return;
         }
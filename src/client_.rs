@@ -417,7 +417,13 @@ pub unsafe extern "C-unwind" fn client_main(
                 kill(ppid, SIGHUP);
             }
         } else if (*&raw const CLIENT_FLAGS).intersects(client_flag::CONTROL) {
-            if CLIENT_EXITREASON != client_exitreason::CLIENT_EXIT_NONE {
+            if (*&raw const CLIENT_FLAGS).intersects(client_flag::CONTROL_JSON) {
+                let mut json = control_json::new("exit");
+                if CLIENT_EXITREASON != client_exitreason::CLIENT_EXIT_NONE {
+                    json = json.string("reason", client_exit_message().as_bytes());
+                }
+                println!("{}", json.finish());
+            } else if CLIENT_EXITREASON != client_exitreason::CLIENT_EXIT_NONE {
                 println!("%exit {}", client_exit_message());
             } else {
                 println!("%exit");
@@ -14,13 +14,14 @@
 use std::io::Write;
 
 use crate::*;
+use crate::options_::*;
 
 pub static CMD_CAPTURE_PANE_ENTRY: cmd_entry = cmd_entry {
     name: "capture-pane",
     alias: Some("capturep"),
 
-    args: args_parse::new("ab:CeE:JNpPqS:Tt:", 0, 0, None),
-    usage: "[-aCeJNpPqT] [-b buffer-name] [-E end-line] [-S start-line] [-t target-pane]",
+    args: args_parse::new("ab:CDeE:F:JNpPqS:Tt:", 0, 0, None),
+    usage: "[-aCDeJNpPqT] [-b buffer-name] [-E end-line] [-F json|html] [-S start-line] [-t target-pane]",
 
     source: cmd_entry_flag::zeroed(),
     target: cmd_entry_flag::new(b't', cmd_find_type::CMD_FIND_PANE, cmd_find_flags::empty()),
@@ -96,6 +97,355 @@ unsafe fn cmd_capture_pane_pending(
     }
 }
 
+/// A run of cells on a line with the same attributes, colours and hyperlink.
+struct cmd_capture_pane_run {
+    px: u32,
+    nx: u32,
+    gc: grid_cell,
+}
+
+/// Split a line into runs of cells with the same style.
+unsafe fn cmd_capture_pane_runs(gd: *mut grid, py: u32) -> Vec<cmd_capture_pane_run> {
+    unsafe {
+        let mut runs: Vec<cmd_capture_pane_run> = Vec::new();
+        let mut gc: grid_cell = zeroed();
+
+        let gl = grid_peek_line(gd, py);
+        for px in 0..(*gl).cellused {
+            grid_get_cell(gd, px, py, &mut gc);
+            if let Some(last) = runs.last_mut()
+                && (gc.flags.intersects(grid_flag::PADDING)
+                    || (last.gc.attr == gc.attr
+                        && last.gc.fg == gc.fg
+                        && last.gc.bg == gc.bg
+                        && last.gc.us == gc.us
+                        && last.gc.link == gc.link))
+            {
+                last.nx += 1;
+                continue;
+            }
+            runs.push(cmd_capture_pane_run { px, nx: 1, gc });
+        }
+        runs
+    }
+}
+
+/// Get the text of some cells on a line.
+unsafe fn cmd_capture_pane_text(
+    gd: *mut grid,
+    px: u32,
+    py: u32,
+    nx: u32,
+    flags: grid_string_flags,
+    s: *mut screen,
+) -> String {
+    unsafe {
+        let cp = grid_string_cells(gd, px, py, nx, null_mut(), flags, s);
+        let text = CStr::from_ptr(cp.cast()).to_string_lossy().into_owned();
+        free_(cp);
+        text
+    }
+}
+
+/// Get the URI of a cell's hyperlink.
+unsafe fn cmd_capture_pane_link(s: *mut screen, gc: *const grid_cell) -> Option<String> {
+    unsafe {
+        if (*gc).link == 0 || (*s).hyperlinks.is_null() {
+            return None;
+        }
+        let mut uri = null();
+        if !hyperlinks_get((*s).hyperlinks, (*gc).link, &raw mut uri, null_mut(), null_mut()) {
+            return None;
+        }
+        Some(_s(uri).to_string())
+    }
+}
+
+/// Append a string to JSON output.
+fn cmd_capture_pane_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            _ if (ch as u32) < 0x20 || ch == '\x7f' => {
+                out.push_str(&format!("\\u{:04x}", ch as u32));
+            }
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+/// Append a line as a JSON object: its text, whether it is wrapped, any OSC
+/// 133 marks and the runs of cells with the same style.
+unsafe fn cmd_capture_pane_json(
+    out: &mut String,
+    gd: *mut grid,
+    py: u32,
+    flags: grid_string_flags,
+    s: *mut screen,
+) {
+    unsafe {
+        let gl = grid_peek_line(gd, py);
+
+        out.push_str(&format!("{{\"line\":{},\"text\":", py as i64 - (*gd).hsize as i64));
+        cmd_capture_pane_json_string(out, &cmd_capture_pane_text(gd, 0, py, (*gd).sx, flags, s));
+        out.push_str(&format!(
+            ",\"wrapped\":{}",
+            (*gl).flags.intersects(grid_line_flag::WRAPPED)
+        ));
+        if (*gl).time != 0 {
+            out.push_str(&format!(",\"time\":{}", (*gl).time));
+        } else {
+            out.push_str(",\"time\":null");
+        }
+
+        out.push_str(",\"marks\":[");
+        let mut marks = Vec::new();
+        for (flag, name) in [
+            (grid_line_flag::START_PROMPT, "\"prompt\""),
+            (grid_line_flag::START_INPUT, "\"input\""),
+            (grid_line_flag::START_OUTPUT, "\"output\""),
+            (grid_line_flag::END_OUTPUT, "\"end\""),
+        ] {
+            if (*gl).flags.intersects(flag) {
+                marks.push(name);
+            }
+        }
+        out.push_str(&marks.join(","));
+        out.push(']');
+        if (*gl).flags.intersects(grid_line_flag::END_OUTPUT) && (*gl).exit_status != -1 {
+            out.push_str(&format!(",\"exit_status\":{}", (*gl).exit_status));
+        } else {
+            out.push_str(",\"exit_status\":null");
+        }
+
+        out.push_str(",\"cells\":[");
+        for (i, run) in cmd_capture_pane_runs(gd, py).iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            out.push_str(&format!("{{\"x\":{},\"width\":{},\"text\":", run.px, run.nx));
+            let text = cmd_capture_pane_text(gd, run.px, py, run.nx, grid_string_flags::empty(), s);
+            cmd_capture_pane_json_string(out, &text);
+            for (name, colour) in [("fg", run.gc.fg), ("bg", run.gc.bg), ("us", run.gc.us)] {
+                out.push_str(&format!(",\"{name}\":"));
+                cmd_capture_pane_json_string(out, &colour_tostring(colour));
+            }
+            out.push_str(",\"attributes\":[");
+            let attributes: Vec<_> = attributes_tostring(run.gc.attr & !grid_attr::GRID_ATTR_CHARSET)
+                .split(',')
+                .filter(|name| !name.is_empty() && *name != "none")
+                .map(|name| format!("\"{name}\""))
+                .collect();
+            out.push_str(&attributes.join(","));
+            out.push_str("],\"link\":");
+            match cmd_capture_pane_link(s, &run.gc) {
+                Some(uri) => cmd_capture_pane_json_string(out, &uri),
+                None => out.push_str("null"),
+            }
+            out.push('}');
+        }
+        out.push_str("]}\n");
+    }
+}
+
+/// Append a string to HTML output.
+fn cmd_capture_pane_html_string(out: &mut String, s: &str) {
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+}
+
+/// Check if a hyperlink may be used as a link in HTML. Other schemes like
+/// javascript: could run code when the document is opened.
+fn cmd_capture_pane_html_link(uri: &str) -> bool {
+    let Some((scheme, _)) = uri.split_once(':') else {
+        return false;
+    };
+    ["http", "https", "file", "mailto"]
+        .iter()
+        .any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+}
+
+/// Get a colour as RGB for HTML, using the pane's palette.
+unsafe fn cmd_capture_pane_html_colour(wp: *mut window_pane, mut c: i32) -> Option<String> {
+    unsafe {
+        if COLOUR_DEFAULT(c) {
+            return None;
+        }
+        let palette = colour_palette_get(Some(&(*wp).palette), c);
+        if palette != -1 {
+            c = palette;
+        }
+        let c = colour_force_rgb(c);
+        if c == -1 {
+            return None;
+        }
+        let (r, g, b) = colour_split_rgb(c);
+        Some(format!("#{r:02x}{g:02x}{b:02x}"))
+    }
+}
+
+/// Get the CSS for the style of a run of cells.
+unsafe fn cmd_capture_pane_html_style(wp: *mut window_pane, gc: *const grid_cell) -> String {
+    unsafe {
+        let mut style = Vec::new();
+        let mut fg = cmd_capture_pane_html_colour(wp, (*gc).fg);
+        let mut bg = cmd_capture_pane_html_colour(wp, (*gc).bg);
+        let attr = (*gc).attr;
+
+        if attr.intersects(grid_attr::GRID_ATTR_REVERSE) {
+            std::mem::swap(&mut fg, &mut bg);
+            fg.get_or_insert_with(|| "var(--bg)".to_string());
+            bg.get_or_insert_with(|| "var(--fg)".to_string());
+        }
+        if attr.intersects(grid_attr::GRID_ATTR_HIDDEN) {
+            fg = Some("transparent".to_string());
+        }
+        if let Some(fg) = fg {
+            style.push(format!("color:{fg}"));
+        }
+        if let Some(bg) = bg {
+            style.push(format!("background-color:{bg}"));
+        }
+        if attr.intersects(grid_attr::GRID_ATTR_BRIGHT) {
+            style.push("font-weight:bold".to_string());
+        }
+        if attr.intersects(grid_attr::GRID_ATTR_DIM) {
+            style.push("opacity:0.5".to_string());
+        }
+        if attr.intersects(grid_attr::GRID_ATTR_ITALICS) {
+            style.push("font-style:italic".to_string());
+        }
+
+        let mut lines = Vec::new();
+        if attr.intersects(GRID_ATTR_ALL_UNDERSCORE) {
+            lines.push("underline");
+        }
+        if attr.intersects(grid_attr::GRID_ATTR_OVERLINE) {
+            lines.push("overline");
+        }
+        if attr.intersects(grid_attr::GRID_ATTR_STRIKETHROUGH) {
+            lines.push("line-through");
+        }
+        if !lines.is_empty() {
+            style.push(format!("text-decoration-line:{}", lines.join(" ")));
+        }
+        for (flag, name) in [
+            (grid_attr::GRID_ATTR_UNDERSCORE_2, "double"),
+            (grid_attr::GRID_ATTR_UNDERSCORE_3, "wavy"),
+            (grid_attr::GRID_ATTR_UNDERSCORE_4, "dotted"),
+            (grid_attr::GRID_ATTR_UNDERSCORE_5, "dashed"),
+        ] {
+            if attr.intersects(flag) {
+                style.push(format!("text-decoration-style:{name}"));
+            }
+        }
+        if let Some(us) = cmd_capture_pane_html_colour(wp, (*gc).us) {
+            style.push(format!("text-decoration-color:{us}"));
+        }
+        style.join(";")
+    }
+}
+
+/// Append a line of HTML, with a span for each run of cells and a link for
+/// hyperlinks.
+unsafe fn cmd_capture_pane_html(
+    out: &mut String,
+    wp: *mut window_pane,
+    gd: *mut grid,
+    py: u32,
+    s: *mut screen,
+) {
+    unsafe {
+        for run in cmd_capture_pane_runs(gd, py) {
+            let text = cmd_capture_pane_text(gd, run.px, py, run.nx, grid_string_flags::empty(), s);
+            let link = cmd_capture_pane_link(s, &run.gc)
+                .filter(|uri| cmd_capture_pane_html_link(uri));
+            if let Some(uri) = &link {
+                out.push_str("<a href=\"");
+                cmd_capture_pane_html_string(out, uri);
+                out.push_str("\">");
+            }
+            let style = cmd_capture_pane_html_style(wp, &run.gc);
+            if style.is_empty() {
+                cmd_capture_pane_html_string(out, &text);
+            } else {
+                out.push_str(&format!("<span style=\"{style}\">"));
+                cmd_capture_pane_html_string(out, &text);
+                out.push_str("</span>");
+            }
+            if link.is_some() {
+                out.push_str("</a>");
+            }
+        }
+    }
+}
+
+/// Capture lines as JSON, one object per line, or as an HTML document.
+unsafe fn cmd_capture_pane_structured(
+    args: *mut args,
+    wp: *mut window_pane,
+    gd: *mut grid,
+    top: u32,
+    bottom: u32,
+    len: *mut usize,
+) -> *mut u8 {
+    unsafe {
+        let s = &raw mut (*wp).base;
+        let html = streq_(args_get(args, b'F'), "html");
+        let join_lines = args_has(args, 'J');
+
+        let mut flags = grid_string_flags::empty();
+        if !join_lines && !args_has(args, 'N') {
+            flags |= grid_string_flags::GRID_STRING_TRIM_SPACES;
+        }
+
+        let mut out = String::new();
+        if html {
+            let fg = cmd_capture_pane_html_colour(wp, 7).unwrap_or_default();
+            let bg = cmd_capture_pane_html_colour(wp, 0).unwrap_or_default();
+            out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>");
+            cmd_capture_pane_html_string(&mut out, &_s((*s).title).to_string());
+            out.push_str("</title>\n<style>\n");
+            out.push_str(&format!(":root {{ --fg: {fg}; --bg: {bg}; }}\n"));
+            out.push_str("body { color: var(--fg); background-color: var(--bg); }\n");
+            out.push_str("a { color: inherit; }\n");
+            out.push_str("</style>\n</head>\n<body>\n<pre>");
+        }
+        for py in top..=bottom {
+            if html {
+                cmd_capture_pane_html(&mut out, wp, gd, py, s);
+                let gl = grid_peek_line(gd, py);
+                if !join_lines || !(*gl).flags.intersects(grid_line_flag::WRAPPED) {
+                    out.push('\n');
+                }
+            } else {
+                cmd_capture_pane_json(&mut out, gd, py, flags, s);
+            }
+        }
+        if html {
+            out.push_str("</pre>\n</body>\n</html>\n");
+        }
+
+        *len = out.len();
+        let buf = xmalloc(out.len() + 1).as_ptr().cast::<u8>();
+        memcpy_(buf, out.as_bytes().as_ptr(), out.len());
+        *buf.add(out.len()) = b'\0';
+        buf
+    }
+}
+
 unsafe fn cmd_capture_pane_history(
     args: *mut args,
     item: *mut cmdq_item,
@@ -103,7 +453,8 @@ unsafe fn cmd_capture_pane_history(
     len: *mut usize,
 ) -> *mut u8 {
     unsafe {
-        let gd: *mut grid;
+        let mut gd: *mut grid;
+        let mut spill_gd: *mut grid = null_mut();
         let mut gl: *const grid_line;
         let mut gc: *mut grid_cell = null_mut();
         let mut n;
@@ -131,6 +482,33 @@ unsafe fn cmd_capture_pane_history(
         }
 
         let sflag: *const u8 = args_get(args, b'S');
+
+        // If the start is further back than the history in memory, capture
+        // from a copy of the grid including the history spilled to disk.
+        if grid_spill_size(gd) != 0 && !sflag.is_null() {
+            let mut spill = streq_(sflag, "-");
+            if !spill {
+                n = args_strtonum_and_expand(
+                    args,
+                    b'S',
+                    i32::MIN as i64,
+                    i16::MAX as i64,
+                    item,
+                    &raw mut cause,
+                );
+                if cause.is_null() {
+                    spill = n < 0 && (-n) as u32 > (*gd).hsize;
+                } else {
+                    free_(cause);
+                    cause = null_mut();
+                }
+            }
+            if spill {
+                spill_gd = grid_spill_expand(gd);
+                gd = spill_gd;
+            }
+        }
+
         let mut top;
         if !sflag.is_null() && streq_(sflag, "-") {
             top = 0;
@@ -149,7 +527,7 @@ unsafe fn cmd_capture_pane_history(
             } else if n < 0 && (-n) as u32 > (*gd).hsize {
                 top = 0;
             } else {
-                top = (*gd).hsize + n as u32;
+                top = ((*gd).hsize as i64 + n) as u32;
             }
             if top > (*gd).hsize + (*gd).sy - 1 {
                 top = (*gd).hsize + (*gd).sy - 1;
@@ -174,7 +552,7 @@ unsafe fn cmd_capture_pane_history(
             } else if n < 0 && (-n) as u32 > (*gd).hsize {
                 bottom = 0;
             } else {
-                bottom = (*gd).hsize + n as u32;
+                bottom = ((*gd).hsize as i64 + n) as u32;
             }
             if bottom > (*gd).hsize + (*gd).sy - 1 {
                 bottom = (*gd).hsize + (*gd).sy - 1;
@@ -187,6 +565,14 @@ unsafe fn cmd_capture_pane_history(
             top = tmp;
         }
 
+        if args_has(args, 'F') {
+            let buf = cmd_capture_pane_structured(args, wp, gd, top, bottom, len);
+            if !spill_gd.is_null() {
+                grid_destroy(spill_gd);
+            }
+            return buf;
+        }
+
         let join_lines = args_has(args, 'J');
         if args_has(args, 'e') {
             flags |= grid_string_flags::GRID_STRING_WITH_SEQUENCES;
@@ -201,21 +587,37 @@ unsafe fn cmd_capture_pane_history(
             flags |= grid_string_flags::GRID_STRING_TRIM_SPACES;
         }
 
+        let time_format = if args_has(args, 'D') {
+            options_get_string_((*(*wp).window).options, "timestamp-format")
+        } else {
+            null()
+        };
+
         let mut buf = null_mut();
+        let mut joined = false;
         for i in top..=bottom {
+            gl = grid_peek_line(gd, i);
+            if !time_format.is_null() && !joined {
+                let time = format!("{} ", format_line_time((*gl).time, time_format));
+                buf = cmd_capture_pane_append(buf, len, time.as_bytes().as_ptr(), time.len());
+            }
+
             line = grid_string_cells(gd, 0, i, sx, &raw mut gc, flags, (*wp).screen);
             linelen = strlen(line);
 
             buf = cmd_capture_pane_append(buf, len, line, linelen);
 
-            gl = grid_peek_line(gd, i);
-            if !join_lines || !(*gl).flags.intersects(grid_line_flag::WRAPPED) {
+            joined = join_lines && (*gl).flags.intersects(grid_line_flag::WRAPPED);
+            if !joined {
                 *buf.add(*len) = b'\n' as _;
                 (*len) += 1;
             }
 
             free_(line);
         }
+        if !spill_gd.is_null() {
+            grid_destroy(spill_gd);
+        }
         buf
     }
 }
@@ -235,6 +637,18 @@ unsafe fn cmd_capture_pane_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_re
             return cmd_retval::CMD_RETURN_NORMAL;
         }
 
+        if let Some(format) = cstr_to_str_(args_get(args, b'F'))
+            && format != "json"
+            && format != "html"
+        {
+            cmdq_error!(item, "unknown format: {format}");
+            return cmd_retval::CMD_RETURN_ERROR;
+        }
+        if args_has(args, 'F') && args_has(args, 'P') {
+            cmdq_error!(item, "-F and -P cannot be used together");
+            return cmd_retval::CMD_RETURN_ERROR;
+        }
+
         let mut len = 0;
         let buf = if args_has(args, 'P') {
             cmd_capture_pane_pending(args, wp, &raw mut len)
@@ -249,7 +663,12 @@ unsafe fn cmd_capture_pane_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_re
             if len > 0 && *buf.add(len - 1) == b'\n' {
                 len -= 1;
             }
-            if (*c).flags.intersects(client_flag::CONTROL) {
+            if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                for line in std::slice::from_raw_parts(buf.cast::<u8>(), len).split(|ch| *ch == b'\n') {
+                    control_write_line(c, line);
+                }
+                free_(buf);
+            } else if (*c).flags.intersects(client_flag::CONTROL) {
                 control_write!(c, "{1:0$}", len, _s(buf));
             } else {
                 if !file_can_print(c) {
@@ -279,3 +698,33 @@ unsafe fn cmd_capture_pane_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_re
         cmd_retval::CMD_RETURN_NORMAL
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cmd_capture_pane_json_string() {
+        let mut out = String::new();
+        cmd_capture_pane_json_string(&mut out, "a \"b\" \\ \t\n\x1b\x7f é");
+        assert_eq!(out, r#""a \"b\" \\ \t\n\u001b\u007f é""#);
+    }
+
+    #[test]
+    fn test_cmd_capture_pane_html_string() {
+        let mut out = String::new();
+        cmd_capture_pane_html_string(&mut out, "<a href=\"x\">&amp;</a> é");
+        assert_eq!(out, "&lt;a href=&quot;x&quot;&gt;&amp;amp;&lt;/a&gt; é");
+    }
+
+    #[test]
+    fn test_cmd_capture_pane_html_link() {
+        assert!(cmd_capture_pane_html_link("https://example.com/"));
+        assert!(cmd_capture_pane_html_link("HTTP://example.com/"));
+        assert!(cmd_capture_pane_html_link("file:///tmp/x"));
+        assert!(cmd_capture_pane_html_link("mailto:someone@example.com"));
+        assert!(!cmd_capture_pane_html_link("javascript:alert(1)"));
+        assert!(!cmd_capture_pane_html_link("data:text/html,<b>x</b>"));
+        assert!(!cmd_capture_pane_html_link("example.com"));
+    }
+}
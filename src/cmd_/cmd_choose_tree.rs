@@ -12,6 +12,7 @@
 // IN AN ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING
 // OUT OF OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
 
+use crate::libc::{REG_EXTENDED, REG_NOSUB};
 use crate::*;
 
 pub static CMD_CHOOSE_TREE_ENTRY: cmd_entry = cmd_entry {
@@ -56,6 +57,20 @@ pub static CMD_CHOOSE_BUFFER_ENTRY: cmd_entry = cmd_entry {
     exec: cmd_choose_tree_exec,
 };
 
+pub static CMD_CHOOSE_SEARCH_ENTRY: cmd_entry = cmd_entry {
+    name: "choose-search",
+    alias: None,
+
+    args: args_parse::new("F:f:iK:NO:rt:Z", 1, 2, Some(cmd_choose_search_args_parse)),
+    usage: "[-iNrZ] [-F format] [-f filter] [-K key-format] [-O sort-order] [-t target-pane] pattern [template]",
+
+    target: cmd_entry_flag::new(b't', cmd_find_type::CMD_FIND_PANE, cmd_find_flags::empty()),
+    source: cmd_entry_flag::zeroed(),
+
+    flags: cmd_flag::empty(),
+    exec: cmd_choose_tree_exec,
+};
+
 pub static CMD_CUSTOMIZE_MODE_ENTRY: cmd_entry = cmd_entry {
     name: "customize-mode",
     alias: None,
@@ -78,6 +93,17 @@ fn cmd_choose_tree_args_parse(
     args_parse_type::ARGS_PARSE_COMMANDS_OR_STRING
 }
 
+fn cmd_choose_search_args_parse(
+    _args: *mut args,
+    idx: u32,
+    _cause: *mut *mut u8,
+) -> args_parse_type {
+    if idx == 0 {
+        return args_parse_type::ARGS_PARSE_STRING;
+    }
+    args_parse_type::ARGS_PARSE_COMMANDS_OR_STRING
+}
+
 unsafe fn cmd_choose_tree_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_retval {
     unsafe {
         let args = cmd_get_args(self_);
@@ -94,6 +120,15 @@ unsafe fn cmd_choose_tree_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_ret
                 return cmd_retval::CMD_RETURN_NORMAL;
             }
             &raw const WINDOW_CLIENT_MODE
+        } else if std::ptr::eq(cmd_get_entry(self_), &CMD_CHOOSE_SEARCH_ENTRY) {
+            if args_has(args, 'r') {
+                let pattern = args_string(args, 0);
+                if regexp_compile(pattern, REG_EXTENDED | REG_NOSUB).is_none() {
+                    cmdq_error!(item, "invalid regular expression: {}", _s(pattern));
+                    return cmd_retval::CMD_RETURN_ERROR;
+                }
+            }
+            &raw const WINDOW_SEARCH_MODE
         } else if std::ptr::eq(cmd_get_entry(self_), &CMD_CUSTOMIZE_MODE_ENTRY) {
             &raw const WINDOW_CUSTOMIZE_MODE
         } else {
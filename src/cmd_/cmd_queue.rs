@@ -642,6 +642,11 @@ pub unsafe fn cmdq_fire_command(item: *mut cmdq_item) -> cmd_retval {
                 .intersects(cmdq_state_flags::CMDQ_STATE_CONTROL);
             cmdq_guard(item, c!("begin"), flags);
 
+            if !server_acl_check_command(item, cmd) {
+                retval = cmd_retval::CMD_RETURN_ERROR;
+                break 'out;
+            }
+
             if (*item).client.is_null() {
                 (*item).client = cmd_find_client(item, None, 1);
             }
@@ -858,7 +863,7 @@ pub unsafe fn cmdq_guard(item: *mut cmdq_item, guard: *const u8, flags: bool) {
         let number = (*item).number;
 
         if !c.is_null() && (*c).flags.intersects(client_flag::CONTROL) {
-            control_write!(c, "%{} {} {} {}", _s(guard), t, number, flags as i32);
+            control_write_guard(c, cstr_to_str(guard), t, number, flags);
         }
     }
 }
@@ -919,7 +924,7 @@ pub unsafe fn cmdq_error_(item: *mut cmdq_item, args: std::fmt::Arguments) {
                 free_(tmp);
             }
             if (*c).flags.intersects(client_flag::CONTROL) {
-                control_write!(c, "{}", _s(msg));
+                control_write_line(c, CStr::from_ptr(msg.cast()).to_bytes());
             } else {
                 file_error!(c, "{}\n", _s(msg));
             }
@@ -42,13 +42,25 @@ pub unsafe fn cmd_refresh_client_update_subscription(tc: *mut client, value: *co
             *split = b'\0' as _;
             split = split.add(1);
 
+            // Subscriptions to notifications are name:event=pattern,...:format
+            // and the format may be left out.
             let what = split;
             split = strchr(what, ':' as i32);
+            let events = libc::strncmp(what, c!("event="), 6) == 0;
             if split.is_null() {
+                if !events {
+                    break 'out;
+                }
+                split = c!("").cast_mut();
+            } else {
+                *split = b'\0';
+                split = split.add(1);
+            }
+            if events {
+                let notify = control_sub_type::CONTROL_SUB_NOTIFY;
+                control_add_sub(tc, name, notify, -1, what.add(6), split);
                 break 'out;
             }
-            *split = b'\0';
-            split = split.add(1);
 
             let subtype = if streq_(what, "%*") {
                 control_sub_type::CONTROL_SUB_ALL_PANES
@@ -61,7 +73,7 @@ pub unsafe fn cmd_refresh_client_update_subscription(tc: *mut client, value: *co
             } else {
                 control_sub_type::CONTROL_SUB_SESSION
             };
-            control_add_sub(tc, name, subtype, subid, split);
+            control_add_sub(tc, name, subtype, subid, null(), split);
         }
 
         // out:
@@ -0,0 +1,66 @@
+// Save and restore sessions with a state file.
+use crate::*;
+use crate::options_::*;
+
+pub static CMD_SAVE_STATE_ENTRY: cmd_entry = cmd_entry {
+    name: "save-state",
+    alias: None,
+
+    args: args_parse::new("S", 0, 1, None),
+    usage: "[-S] [path]",
+
+    flags: cmd_flag::CMD_AFTERHOOK,
+    exec: cmd_save_state_exec,
+    source: cmd_entry_flag::zeroed(),
+    target: cmd_entry_flag::zeroed(),
+};
+
+pub static CMD_RESTORE_STATE_ENTRY: cmd_entry = cmd_entry {
+    name: "restore-state",
+    alias: None,
+
+    args: args_parse::new("", 0, 1, None),
+    usage: "[path]",
+
+    flags: cmd_flag::CMD_STARTSERVER.union(cmd_flag::CMD_AFTERHOOK),
+    exec: cmd_save_state_exec,
+    source: cmd_entry_flag::zeroed(),
+    target: cmd_entry_flag::zeroed(),
+};
+
+unsafe fn cmd_save_state_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_retval {
+    unsafe {
+        let args = cmd_get_args(self_);
+        let c = cmdq_get_client(item);
+
+        let path = if args_count(args) != 0 {
+            let expanded = format_single_from_target(item, args_string(args, 0));
+            let path = state_find_file(c, expanded);
+            free_(expanded);
+            path
+        } else {
+            state_find_file(null_mut(), options_get_string_(GLOBAL_OPTIONS, "state-file"))
+        };
+        let Some(path) = path else {
+            cmdq_error!(item, "no state file");
+            return cmd_retval::CMD_RETURN_ERROR;
+        };
+
+        if std::ptr::eq(cmd_get_entry(self_), &CMD_SAVE_STATE_ENTRY) {
+            if let Err(cause) = state_save(&path, args_has(args, 'S')) {
+                cmdq_error!(item, "{cause}");
+                return cmd_retval::CMD_RETURN_ERROR;
+            }
+            return cmd_retval::CMD_RETURN_NORMAL;
+        }
+
+        let (_, errors) = state_restore(item, &path);
+        if errors.is_empty() {
+            return cmd_retval::CMD_RETURN_NORMAL;
+        }
+        for cause in errors {
+            cmdq_error!(item, "{cause}");
+        }
+        cmd_retval::CMD_RETURN_ERROR
+    }
+}
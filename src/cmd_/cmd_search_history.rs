@@ -0,0 +1,76 @@
+// Search the history of every pane.
+use crate::compat::{queue::tailq_foreach, tree::rb_foreach};
+use crate::*;
+
+const SEARCH_HISTORY_TEMPLATE: &CStr = cstring_concat!(
+    "#{session_name}:#{window_index}.#{pane_index} ",
+    "#{search_line}: #{search_text}"
+);
+
+pub static CMD_SEARCH_HISTORY_ENTRY: cmd_entry = cmd_entry {
+    name: "search-history",
+    alias: None,
+
+    args: args_parse::new("F:ir", 1, 1, None),
+    usage: "[-ir] [-F format] pattern",
+
+    flags: cmd_flag::CMD_AFTERHOOK,
+    exec: cmd_search_history_exec,
+    source: cmd_entry_flag::zeroed(),
+    target: cmd_entry_flag::zeroed(),
+};
+
+unsafe fn cmd_search_history_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_retval {
+    unsafe {
+        let args = cmd_get_args(self_);
+        let pattern = args_string(args, 0);
+
+        let mut template = args_get_(args, 'F');
+        if template.is_null() {
+            template = SEARCH_HISTORY_TEMPLATE.as_ptr().cast();
+        }
+
+        // Windows linked into several sessions are only searched once.
+        let mut searched = Vec::new();
+        for s in rb_foreach(&raw mut SESSIONS).map(NonNull::as_ptr) {
+            for wl in rb_foreach(&raw mut (*s).windows).map(NonNull::as_ptr) {
+                let w = (*wl).window;
+                if searched.contains(&(*w).id) {
+                    continue;
+                }
+                searched.push((*w).id);
+
+                for wp in tailq_foreach::<_, discr_entry>(&raw mut (*w).panes) {
+                    let mut cb = |py: u32, hsize: u32, line: &CStr| {
+                        let ft = format_create(
+                            cmdq_get_client(item),
+                            item,
+                            FORMAT_NONE,
+                            format_flags::empty(),
+                        );
+                        format_add!(ft, "search_line", "{}", py as i64 - hsize as i64);
+                        format_add!(ft, "search_text", "{}", line.to_string_lossy());
+                        format_defaults(ft, null_mut(), NonNull::new(s), NonNull::new(wl), Some(wp));
+
+                        let expanded = format_expand(ft, template);
+                        cmdq_print!(item, "{}", _s(expanded));
+                        free_(expanded);
+                        format_free(ft);
+                    };
+                    if !window_pane_search_history(
+                        wp.as_ptr(),
+                        pattern,
+                        args_has(args, 'r'),
+                        args_has(args, 'i'),
+                        &mut cb,
+                    ) {
+                        cmdq_error!(item, "invalid regular expression: {}", _s(pattern));
+                        return cmd_retval::CMD_RETURN_ERROR;
+                    }
+                }
+            }
+        }
+
+        cmd_retval::CMD_RETURN_NORMAL
+    }
+}
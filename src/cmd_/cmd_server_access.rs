@@ -19,10 +19,10 @@ pub static CMD_SERVER_ACCESS_ENTRY: cmd_entry = cmd_entry {
     name: "server-access",
     alias: None,
 
-    args: args_parse::new("adlrw", 0, 1, None),
-    usage: "[-adlrw] [-t target-pane] [user]",
+    args: args_parse::new("adlp:rt:w", 0, 1, None),
+    usage: "[-adlrw] [-p profile] [-t target-client] [user]",
 
-    flags: cmd_flag::CMD_CLIENT_CANFAIL,
+    flags: cmd_flag::CMD_CLIENT_CANFAIL.union(cmd_flag::CMD_CLIENT_TFLAG),
     exec: cmd_server_access_exec,
     source: cmd_entry_flag::zeroed(),
     target: cmd_entry_flag::zeroed(),
@@ -48,6 +48,30 @@ unsafe fn cmd_server_access_deny(item: *mut cmdq_item, pw: *mut libc::passwd) ->
     }
 }
 
+/// Set or remove the profile of a client rather than a user.
+unsafe fn cmd_server_access_client(
+    item: *mut cmdq_item,
+    args: *mut args,
+    c: *mut client,
+) -> cmd_retval {
+    unsafe {
+        if !args_has(args, 'p') && !args_has(args, 'w') {
+            cmdq_error!(item, "missing user argument");
+            return cmd_retval::CMD_RETURN_ERROR;
+        }
+        if c.is_null() {
+            cmdq_error!(item, "no client");
+            return cmd_retval::CMD_RETURN_ERROR;
+        }
+        free_((*c).profile);
+        (*c).profile = null_mut();
+        if args_has(args, 'p') {
+            (*c).profile = xstrdup(args_get(args, b'p')).as_ptr();
+        }
+        cmd_retval::CMD_RETURN_NORMAL
+    }
+}
+
 unsafe fn cmd_server_access_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_retval {
     unsafe {
         let args = cmd_get_args(self_);
@@ -57,10 +81,20 @@ unsafe fn cmd_server_access_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_r
             server_acl_display(item);
             return cmd_retval::CMD_RETURN_NORMAL;
         }
-        if args_count(args) == 0 {
-            cmdq_error!(item, "missing user argument");
+        if args_has(args, 'p') && args_has(args, 'w') {
+            cmdq_error!(item, "-p and -w cannot be used together");
             return cmd_retval::CMD_RETURN_ERROR;
         }
+        if args_has(args, 'p') {
+            let profile = args_get(args, b'p');
+            if server_acl_profile_allows(profile, "").is_none() {
+                cmdq_error!(item, "unknown profile: {}", _s(profile));
+                return cmd_retval::CMD_RETURN_ERROR;
+            }
+        }
+        if args_count(args) == 0 {
+            return cmd_server_access_client(item, args, c);
+        }
 
         let name = format_single(
             item,
@@ -108,17 +142,22 @@ unsafe fn cmd_server_access_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_r
             }
             server_acl_user_allow((*pw).pw_uid);
             // Do not return - allow -r or -w with -a.
-        } else if (args_has(args, 'r') || args_has(args, 'w'))
+        } else if (args_has(args, 'r') || args_has(args, 'w') || args_has(args, 'p'))
             && server_acl_user_find((*pw).pw_uid).is_null()
         {
             server_acl_user_allow((*pw).pw_uid);
-        } /* -r or -w implies -a if user does not exist. */
+        } /* -r, -w or -p implies -a if user does not exist. */
+
+        if args_has(args, 'p') {
+            server_acl_user_set_profile((*pw).pw_uid, args_get(args, b'p'));
+        }
 
         if args_has(args, 'w') {
             if server_acl_user_find((*pw).pw_uid).is_null() {
                 cmdq_error!(item, "user {} not found", _s((*pw).pw_name));
                 return cmd_retval::CMD_RETURN_ERROR;
             }
+            server_acl_user_set_profile((*pw).pw_uid, null());
             server_acl_user_allow_write((*pw).pw_uid);
             return cmd_retval::CMD_RETURN_NORMAL;
         }
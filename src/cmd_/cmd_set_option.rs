@@ -243,6 +243,25 @@ pub unsafe fn cmd_set_option_exec(self_: *mut cmd, item: *mut cmdq_item) -> cmd_
                 }
 
                 options_push_changes(&name);
+
+                let scope = match scope {
+                    OPTIONS_TABLE_SERVER => "server",
+                    OPTIONS_TABLE_SESSION => "session",
+                    OPTIONS_TABLE_WINDOW => "window",
+                    _ => "pane",
+                };
+                let o = options_get(&mut *oo, &name);
+                let value = if o.is_null() {
+                    xstrdup_(c"").as_ptr()
+                } else {
+                    options_to_string(o, idx, 0)
+                };
+                if args_has(args, 'u') || args_has(args, 'U') {
+                    notify_option(c"option-unset", target, &name, scope, value);
+                } else {
+                    notify_option(c"option-set", target, &name, scope, value);
+                }
+                free_(value);
             }
             // out:
             free_(argument);
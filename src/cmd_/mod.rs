@@ -67,6 +67,8 @@ pub mod cmd_respawn_window;
 pub mod cmd_rotate_window;
 pub mod cmd_run_shell;
 pub mod cmd_save_buffer;
+pub mod cmd_save_state;
+pub mod cmd_search_history;
 pub mod cmd_select_layout;
 pub mod cmd_select_pane;
 pub mod cmd_select_window;
@@ -92,7 +94,7 @@ use cmd_bind_key::CMD_BIND_KEY_ENTRY;
 use cmd_break_pane::CMD_BREAK_PANE_ENTRY;
 use cmd_capture_pane::{CMD_CAPTURE_PANE_ENTRY, CMD_CLEAR_HISTORY_ENTRY};
 use cmd_choose_tree::{
-    CMD_CHOOSE_BUFFER_ENTRY, CMD_CHOOSE_CLIENT_ENTRY, CMD_CHOOSE_TREE_ENTRY,
+    CMD_CHOOSE_BUFFER_ENTRY, CMD_CHOOSE_CLIENT_ENTRY, CMD_CHOOSE_SEARCH_ENTRY, CMD_CHOOSE_TREE_ENTRY,
     CMD_CUSTOMIZE_MODE_ENTRY,
 };
 use cmd_command_prompt::CMD_COMMAND_PROMPT_ENTRY;
@@ -138,6 +140,8 @@ use cmd_rotate_window::CMD_ROTATE_WINDOW_ENTRY;
 use cmd_run_shell::CMD_RUN_SHELL_ENTRY;
 use cmd_save_buffer::CMD_SAVE_BUFFER_ENTRY;
 use cmd_save_buffer::CMD_SHOW_BUFFER_ENTRY;
+use cmd_save_state::{CMD_RESTORE_STATE_ENTRY, CMD_SAVE_STATE_ENTRY};
+use cmd_search_history::CMD_SEARCH_HISTORY_ENTRY;
 use cmd_select_layout::CMD_NEXT_LAYOUT_ENTRY;
 use cmd_select_layout::CMD_PREVIOUS_LAYOUT_ENTRY;
 use cmd_select_layout::CMD_SELECT_LAYOUT_ENTRY;
@@ -170,13 +174,14 @@ use cmd_switch_client::CMD_SWITCH_CLIENT_ENTRY;
 use cmd_unbind_key::CMD_UNBIND_KEY_ENTRY;
 use cmd_wait_for::CMD_WAIT_FOR_ENTRY;
 
-pub static CMD_TABLE: [&cmd_entry; 90] = [
+pub static CMD_TABLE: [&cmd_entry; 94] = [
     &CMD_ATTACH_SESSION_ENTRY,
     &CMD_BIND_KEY_ENTRY,
     &CMD_BREAK_PANE_ENTRY,
     &CMD_CAPTURE_PANE_ENTRY,
     &CMD_CHOOSE_BUFFER_ENTRY,
     &CMD_CHOOSE_CLIENT_ENTRY,
+    &CMD_CHOOSE_SEARCH_ENTRY,
     &CMD_CHOOSE_TREE_ENTRY,
     &CMD_CLEAR_HISTORY_ENTRY,
     &CMD_CLEAR_PROMPT_HISTORY_ENTRY,
@@ -230,9 +235,12 @@ pub static CMD_TABLE: [&cmd_entry; 90] = [
     &CMD_RESIZE_WINDOW_ENTRY,
     &CMD_RESPAWN_PANE_ENTRY,
     &CMD_RESPAWN_WINDOW_ENTRY,
+    &CMD_RESTORE_STATE_ENTRY,
     &CMD_ROTATE_WINDOW_ENTRY,
     &CMD_RUN_SHELL_ENTRY,
     &CMD_SAVE_BUFFER_ENTRY,
+    &CMD_SAVE_STATE_ENTRY,
+    &CMD_SEARCH_HISTORY_ENTRY,
     &CMD_SELECT_LAYOUT_ENTRY,
     &CMD_SELECT_PANE_ENTRY,
     &CMD_SELECT_WINDOW_ENTRY,
@@ -7,7 +7,7 @@ pub unsafe fn b64_ntop(src: *const u8, srclength: usize, target: *mut u8, targsi
     let dst = unsafe { std::slice::from_raw_parts_mut(target.cast::<MaybeUninit<u8>>(), targsize) };
 
     match ntop(src, dst) {
-        Ok(out) => (out.len() - 1) as i32,
+        Ok(out) => out.len() as i32,
         Err(()) => -1,
     }
 }
@@ -43,8 +43,15 @@ const REVERSE: [u8; u8::MAX as usize] = const {
 
 /// decode
 fn pton<'out>(src: &'_ [u8], dst: &'out mut [MaybeUninit<u8>]) -> Result<&'out mut [u8], ()> {
-    // dst must be at least 3/4 of src, and room for NUL byte
-    if src.len().div_ceil(4) * 3 + 1 > dst.len() {
+    // dst must be at least 3/4 of src less the padding, and room for NUL byte
+    let pad = src
+        .iter()
+        .rev()
+        .filter(|b| !b.is_ascii_whitespace())
+        .take(2)
+        .take_while(|&&b| b == b'=')
+        .count();
+    if (src.len().div_ceil(4) * 3).saturating_sub(pad) + 1 > dst.len() {
         return Err(());
     }
 
@@ -56,19 +63,28 @@ fn pton<'out>(src: &'_ [u8], dst: &'out mut [MaybeUninit<u8>]) -> Result<&'out m
             ch,
             it.next().ok_or(())?,
             it.next().ok_or(())?,
-            it.next().ok_or(())?, // TODO consider special handling for missing =
+            it.next().ok_or(())?,
         ];
 
-        for g in chunk {
-            if !matches!(g, b'A'..=b'Z' | b'a'..=b'z' | b'+' | b'/') {
+        // Padding is only allowed at the end, as the last one or two characters.
+        let pad = match chunk {
+            [_, _, b'=', b'='] => 2,
+            [_, _, _, b'='] => 1,
+            _ => 0,
+        };
+        if pad != 0 && it.next().is_some() {
+            return Err(());
+        }
+        for g in &chunk[..4 - pad] {
+            if !matches!(g, b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'+' | b'/') {
                 return Err(());
             }
         }
 
         let a = REVERSE[chunk[0] as usize];
         let b = REVERSE[chunk[1] as usize];
-        let c = REVERSE[chunk[2] as usize];
-        let d = REVERSE[chunk[3] as usize];
+        let c = if pad == 2 { 0 } else { REVERSE[chunk[2] as usize] };
+        let d = if pad != 0 { 0 } else { REVERSE[chunk[3] as usize] };
 
         //        a                 b                 c                 d
         // X X 0 0 0 0 0 0 | X X 0 0 0 0 0 0 | X X 0 0 0 0 0 0 | X X 0 0 0 0 0 0
@@ -76,10 +92,11 @@ fn pton<'out>(src: &'_ [u8], dst: &'out mut [MaybeUninit<u8>]) -> Result<&'out m
         // ( a << 2  ) ( b >> 4 )    (b<<4) (    c >> 2    )     (c<<4)(      d       )
         // 0  0  0  0  0  0  0  0  |  0  0  0  0  0  0  0  0  |  0  0  0  0  0  0  0  0
         //
-        dst[i] = MaybeUninit::new(a << 2 | b >> 4);
-        dst[i + 1] = MaybeUninit::new(b << 4 | c >> 2);
-        dst[i + 2] = MaybeUninit::new(c << 6 | d);
-        i += 3;
+        let bytes = [a << 2 | b >> 4, b << 4 | c >> 2, c << 6 | d];
+        for byte in &bytes[..3 - pad] {
+            dst[i] = MaybeUninit::new(*byte);
+            i += 1;
+        }
     }
 
     dst[i] = MaybeUninit::new(0);
@@ -150,6 +167,50 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_b64_pton_digits() {
+        let input = crate::c!("MTIz");
+        let mut output = [0u8; 4];
+
+        unsafe {
+            let result = b64_pton(input, output.as_mut_ptr(), output.len());
+            assert_eq!(&output, b"123\0");
+            assert_eq!(result, 3);
+        }
+    }
+
+    #[test]
+    fn test_b64_pton_padding() {
+        let mut output = [0u8; 8];
+
+        unsafe {
+            let result = b64_pton(crate::c!("TWFuTWE="), output.as_mut_ptr(), output.len());
+            assert_eq!(&output[..6], b"ManMa\0");
+            assert_eq!(result, 5);
+
+            let result = b64_pton(crate::c!("TQ=="), output.as_mut_ptr(), output.len());
+            assert_eq!(&output[..2], b"M\0");
+            assert_eq!(result, 1);
+
+            let result = b64_pton(crate::c!("TQ==TWFu"), output.as_mut_ptr(), output.len());
+            assert_eq!(result, -1);
+        }
+    }
+
+    #[test]
+    fn test_b64_ntop() {
+        let mut output = [0u8; 13];
+
+        unsafe {
+            let result = b64_ntop(c"echo hi\r".as_ptr().cast(), 8, output.as_mut_ptr(), output.len());
+            assert_eq!(&output, b"ZWNobyBoaQ0=\0");
+            assert_eq!(result, 12);
+
+            let result = b64_ntop(c"M".as_ptr().cast(), 1, output.as_mut_ptr(), 4);
+            assert_eq!(result, -1);
+        }
+    }
+
     #[test]
     fn test_b64_pton_invalid() {
         let input = crate::c!("****");
@@ -164,12 +225,12 @@ mod tests {
     #[test]
     fn test_b64_pton_partial() {
         let input = crate::c!("TWE=");
-        let mut output = [0u8; 2];
-        // TODO currently not supporting missing =, but we could
+        let mut output = [0u8; 3];
 
         unsafe {
             let result = b64_pton(input, output.as_mut_ptr(), output.len());
-            assert_eq!(result, -1);
+            assert_eq!(&output, b"Ma\0");
+            assert_eq!(result, 2);
         }
     }
 }
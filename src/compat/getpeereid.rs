@@ -1,7 +1,56 @@
-pub unsafe fn getpeereid(_s: i32, uid: *mut libc::uid_t, gid: *mut libc::gid_t) -> i32 {
+#[cfg(target_os = "linux")]
+pub unsafe fn getpeereid(s: i32, uid: *mut libc::uid_t, gid: *mut libc::gid_t) -> i32 {
     unsafe {
-        *uid = libc::geteuid();
-        *gid = libc::getegid();
+        let mut uc: libc::ucred = std::mem::zeroed();
+        let mut len = size_of::<libc::ucred>() as libc::socklen_t;
+
+        if libc::getsockopt(
+            s,
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&raw mut uc).cast(),
+            &raw mut len,
+        ) == -1
+        {
+            return -1;
+        }
+        *uid = uc.uid;
+        *gid = uc.gid;
     }
     0
 }
+
+#[cfg(not(target_os = "linux"))]
+pub unsafe fn getpeereid(s: i32, uid: *mut libc::uid_t, gid: *mut libc::gid_t) -> i32 {
+    unsafe { libc::getpeereid(s, uid, gid) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::fd::AsRawFd;
+
+    #[test]
+    fn test_getpeereid() {
+        let (a, _b) = std::os::unix::net::UnixStream::pair().unwrap();
+        let mut uid = libc::uid_t::MAX;
+        let mut gid = libc::gid_t::MAX;
+
+        unsafe {
+            assert_eq!(getpeereid(a.as_raw_fd(), &raw mut uid, &raw mut gid), 0);
+            assert_eq!(uid, libc::geteuid());
+            assert_eq!(gid, libc::getegid());
+        }
+    }
+
+    #[test]
+    fn test_getpeereid_not_socket() {
+        let file = std::fs::File::open("/dev/null").unwrap();
+        let mut uid = 0;
+        let mut gid = 0;
+
+        unsafe {
+            assert_eq!(getpeereid(file.as_raw_fd(), &raw mut uid, &raw mut gid), -1);
+        }
+    }
+}
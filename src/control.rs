@@ -12,6 +12,7 @@
 // WHATSOEVER RESULTING FROM LOSS OF MIND, USE, DATA OR PROFITS, WHETHER
 // IN AN ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING
 // OUT OF OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+use crate::compat::b64::b64_ntop;
 use crate::*;
 
 #[repr(C)]
@@ -89,6 +90,8 @@ pub struct control_sub {
     pub format: *mut u8,
     pub type_: control_sub_type,
     pub id: u32,
+    /// comma separated patterns of the notifications for notify subscriptions
+    pub events: *mut u8,
 
     pub last: *mut u8,
 
@@ -114,6 +117,18 @@ pub struct control_state {
 
     pub subs: control_subs,
     pub subs_timer: event,
+
+    /// the running command, if any
+    pub reply: Option<Box<control_reply>>,
+}
+
+/// The lines of output of a command, written in one object to JSON clients.
+/// Whether the client wants JSON is fixed when the command begins, so a
+/// command changing it does not mix the two in its reply.
+#[derive(Default)]
+pub struct control_reply {
+    pub json: bool,
+    pub lines: Vec<Vec<u8>>,
 }
 
 /// Low and high watermarks.
@@ -129,6 +144,111 @@ pub const CONTROL_MAXIMUM_AGE: u64 = 300000;
 pub const CONTROL_IGNORE_FLAGS: client_flag =
     client_flag::CONTROL_NOOUTPUT.union(CLIENT_UNATTACHEDFLAGS);
 
+/// A line for control clients using JSON: an object with a type and typed
+/// fields. Ids are numbers without their prefix and pane output is base64.
+pub struct control_json(String);
+
+impl control_json {
+    pub fn new(type_: &str) -> Self {
+        let mut json = Self::object();
+        json.key("type");
+        control_json_escape(&mut json.0, type_.as_bytes());
+        json
+    }
+
+    /// Start an object without a type, to be put inside another.
+    pub fn object() -> Self {
+        control_json(String::from("{"))
+    }
+
+    fn key(&mut self, key: &str) {
+        if self.0.len() > 1 {
+            self.0.push(',');
+        }
+        control_json_escape(&mut self.0, key.as_bytes());
+        self.0.push(':');
+    }
+
+    pub fn string(mut self, key: &str, value: impl AsRef<[u8]>) -> Self {
+        self.key(key);
+        control_json_escape(&mut self.0, value.as_ref());
+        self
+    }
+
+    pub fn number(mut self, key: &str, value: impl Into<i64>) -> Self {
+        self.key(key);
+        self.0.push_str(&value.into().to_string());
+        self
+    }
+
+    pub fn boolean(mut self, key: &str, value: bool) -> Self {
+        self.key(key);
+        self.0.push_str(if value { "true" } else { "false" });
+        self
+    }
+
+    pub fn null(mut self, key: &str) -> Self {
+        self.key(key);
+        self.0.push_str("null");
+        self
+    }
+
+    pub fn strings(mut self, key: &str, values: &[Vec<u8>]) -> Self {
+        self.key(key);
+        self.0.push('[');
+        for (i, value) in values.iter().enumerate() {
+            if i != 0 {
+                self.0.push(',');
+            }
+            control_json_escape(&mut self.0, value);
+        }
+        self.0.push(']');
+        self
+    }
+
+    pub fn data(mut self, key: &str, value: &[u8]) -> Self {
+        self.key(key);
+        let mut out = vec![0u8; value.len().div_ceil(3) * 4 + 1];
+        let n = unsafe { b64_ntop(value.as_ptr(), value.len(), out.as_mut_ptr(), out.len()) };
+        self.0.push('"');
+        self.0.push_str(std::str::from_utf8(&out[..n.max(0) as usize]).unwrap_or(""));
+        self.0.push('"');
+        self
+    }
+
+    /// Add a value which is already JSON.
+    #[cfg(feature = "web")]
+    pub fn raw(mut self, key: &str, value: &str) -> Self {
+        self.key(key);
+        self.0.push_str(value);
+        self
+    }
+
+    pub fn finish(mut self) -> String {
+        self.0.push('}');
+        self.0
+    }
+}
+
+/// Append a JSON string. Invalid UTF-8 is replaced.
+fn control_json_escape(out: &mut String, value: &[u8]) {
+    out.push('"');
+    for ch in String::from_utf8_lossy(value).chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ if (ch as u32) < 0x20 || ch == '\u{7f}' => {
+                out.push_str(&format!("\\u{:04x}", ch as u32));
+            }
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
 pub fn control_pane_cmp(cp1: &control_pane, cp2: &control_pane) -> cmp::Ordering {
     cp1.pane.cmp(&cp2.pane)
 }
@@ -195,6 +315,7 @@ pub unsafe fn control_free_sub(cs: *mut control_state, csub: *mut control_sub) {
         rb_remove(&raw mut (*cs).subs, csub);
         free_((*csub).name);
         free_((*csub).format);
+        free_((*csub).events);
         free_(csub);
     }
 }
@@ -319,6 +440,17 @@ pub unsafe fn control_set_pane_off(c: *mut client, wp: *mut window_pane) {
     }
 }
 
+/// Tell the client output from a pane has been paused or continued.
+unsafe fn control_write_pane_state(c: *mut client, state: &str, wp: *mut window_pane) {
+    unsafe {
+        if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+            control_write_json(c, control_json::new(state).number("pane", (*wp).id));
+        } else {
+            control_write!(c, "%{} %{}", state, (*wp).id);
+        }
+    }
+}
+
 pub unsafe fn control_continue_pane(c: *mut client, wp: *mut window_pane) {
     unsafe {
         let cp = control_get_pane(c, wp);
@@ -326,7 +458,7 @@ pub unsafe fn control_continue_pane(c: *mut client, wp: *mut window_pane) {
             (*cp).flags &= !CONTROL_PANE_PAUSED;
             memcpy__(&raw mut (*cp).offset, &raw const (*wp).offset);
             memcpy__(&raw mut (*cp).queued, &raw const (*wp).offset);
-            control_write!(c, "%continue %{}", (*wp).id);
+            control_write_pane_state(c, "continue", wp);
         }
     }
 }
@@ -337,7 +469,7 @@ pub unsafe fn control_pause_pane(c: *mut client, wp: *mut window_pane) {
         if !(*cp).flags & CONTROL_PANE_PAUSED != 0 {
             (*cp).flags |= CONTROL_PANE_PAUSED;
             control_discard_pane(c, cp);
-            control_write!(c, "%pause %{}", (*wp).id);
+            control_write_pane_state(c, "pause", wp);
         }
     }
 }
@@ -346,22 +478,19 @@ pub unsafe fn control_vwrite(c: *mut client, args: std::fmt::Arguments) {
     unsafe {
         let cs = (*c).control_state;
 
-        let mut s = args.to_string();
-        s.push('\0');
-        let s: *mut u8 = s.as_mut_ptr().cast();
+        let s = args.to_string();
 
         log_debug!(
             "{}: {}: writing line: {}",
             "control_vwrite",
             _s((*c).name),
-            _s(s)
+            s
         );
 
-        bufferevent_write((*cs).write_event, s.cast(), strlen(s));
+        bufferevent_write((*cs).write_event, s.as_bytes().as_ptr().cast(), s.len());
         bufferevent_write((*cs).write_event, c!("\n").cast(), 1);
 
         bufferevent_enable((*cs).write_event, EV_WRITE);
-        free_(s);
     }
 }
 
@@ -398,6 +527,77 @@ pub unsafe fn control_write_(c: *mut client, args: std::fmt::Arguments) {
     }
 }
 
+pub unsafe fn control_write_json(c: *mut client, json: control_json) {
+    unsafe {
+        control_write!(c, "{}", json.finish());
+    }
+}
+
+/// Write the guard line before or after the output of a command. JSON clients
+/// instead get one reply object holding all of the output once the command
+/// has finished.
+pub unsafe fn control_write_guard(
+    c: *mut client,
+    guard: &str,
+    t: time_t,
+    number: u32,
+    flags: bool,
+) {
+    unsafe {
+        let cs = (*c).control_state;
+        let json = (*c).flags.intersects(client_flag::CONTROL_JSON);
+
+        if guard == "begin" {
+            (*cs).reply = Some(Box::new(control_reply {
+                json,
+                lines: Vec::new(),
+            }));
+            if !json {
+                control_write!(c, "%{} {} {} {}", guard, t, number, flags as i32);
+            }
+            return;
+        }
+        let reply = (*cs).reply.take().unwrap_or_else(|| {
+            Box::new(control_reply {
+                json,
+                lines: Vec::new(),
+            })
+        });
+        if !reply.json {
+            control_write!(c, "%{} {} {} {}", guard, t, number, flags as i32);
+            return;
+        }
+        let json = control_json::new("reply")
+            .number("time", t)
+            .number("number", number)
+            .number("flags", flags as i32)
+            .boolean("error", guard == "error")
+            .strings("output", &reply.lines);
+        control_write_json(c, json);
+    }
+}
+
+/// Write a line of command output, added to the reply for JSON clients.
+pub unsafe fn control_write_line(c: *mut client, line: &[u8]) {
+    unsafe {
+        let cs = (*c).control_state;
+
+        let json = match &mut (*cs).reply {
+            Some(reply) if reply.json => {
+                reply.lines.push(line.to_vec());
+                return;
+            }
+            Some(_) => false,
+            None => (*c).flags.intersects(client_flag::CONTROL_JSON),
+        };
+        if json {
+            control_write_json(c, control_json::new("message").string("text", line));
+        } else {
+            control_write!(c, "{}", String::from_utf8_lossy(line));
+        }
+    }
+}
+
 pub unsafe fn control_check_age(
     c: *mut client,
     wp: *mut window_pane,
@@ -429,7 +629,7 @@ pub unsafe fn control_check_age(
             }
             (*cp).flags |= CONTROL_PANE_PAUSED;
             control_discard_pane(c, cp);
-            control_write!(c, "%pause %{}", (*wp).id);
+            control_write_pane_state(c, "pause", wp);
         } else {
             if age < CONTROL_MAXIMUM_AGE {
                 return 0;
@@ -522,7 +722,7 @@ pub unsafe fn control_error(item: *mut cmdq_item, data: *mut c_void) -> cmd_retv
         let error = data as *mut u8;
 
         cmdq_guard(item, c!("begin"), true);
-        control_write!(c, "parse error: {}", _s(error));
+        control_write_line(c, format!("parse error: {}", _s(error)).as_bytes());
         cmdq_guard(item, c!("error"), true);
 
         free_(error);
@@ -621,12 +821,15 @@ pub unsafe fn control_append_data(
     size: usize,
 ) -> *mut evbuffer {
     unsafe {
+        let json = (*c).flags.intersects(client_flag::CONTROL_JSON);
         if message.is_null() {
             message = evbuffer_new();
             if message.is_null() {
                 fatalx("out of memory");
             }
-            if (*c).flags.intersects(client_flag::CONTROL_PAUSEAFTER) {
+            if json {
+                // The data is kept as it is and encoded when written.
+            } else if (*c).flags.intersects(client_flag::CONTROL_PAUSEAFTER) {
                 evbuffer_add_printf!(message, "%extended-output %{} {} : ", (*wp).id, age);
             } else {
                 evbuffer_add_printf!(message, "%output %{} ", (*wp).id);
@@ -639,6 +842,11 @@ pub unsafe fn control_append_data(
         if new_size < size {
             fatalx_!("not enough data: {} < {}", new_size, size);
         }
+        if json {
+            evbuffer_add(message, new_data.cast(), size);
+            window_pane_update_used_data(wp, &raw mut (*cp).offset, size);
+            return message;
+        }
         for i in 0..size {
             if *new_data.add(i) < b' ' || *new_data.add(i) == b'\\' {
                 evbuffer_add_printf!(message, "\\{:03o}", *new_data.add(i) as i32);
@@ -651,10 +859,34 @@ pub unsafe fn control_append_data(
     }
 }
 
-pub unsafe fn control_write_data(c: *mut client, message: *mut evbuffer) {
+pub unsafe fn control_write_data(
+    c: *mut client,
+    cp: *mut control_pane,
+    age: u64,
+    mut message: *mut evbuffer,
+) {
     unsafe {
         let cs = (*c).control_state;
 
+        if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+            let data = std::slice::from_raw_parts(
+                EVBUFFER_DATA(message).cast::<u8>(),
+                EVBUFFER_LENGTH(message),
+            );
+            let mut json = control_json::new("output").number("pane", (*cp).pane);
+            if (*c).flags.intersects(client_flag::CONTROL_PAUSEAFTER) {
+                json = json.number("age", age as i64);
+            }
+            let line = json.data("data", data).finish();
+            evbuffer_free(message);
+
+            message = evbuffer_new();
+            if message.is_null() {
+                fatalx("out of memory");
+            }
+            evbuffer_add(message, line.as_bytes().as_ptr().cast(), line.len());
+        }
+
         log_debug!(
             "control_write_data: {0}: {2:1$}",
             _s((*c).name),
@@ -672,6 +904,7 @@ pub unsafe fn control_write_pending(c: *mut client, cp: *mut control_pane, limit
     unsafe {
         let cs = (*c).control_state;
         let mut message: *mut evbuffer = null_mut();
+        let mut first = 0;
         let mut used = 0;
         let mut size;
         let mut cb;
@@ -716,6 +949,9 @@ pub unsafe fn control_write_pending(c: *mut client, cp: *mut control_pane, limit
             }
             used += size;
 
+            if message.is_null() {
+                first = age;
+            }
             message = control_append_data(c, cp, age, message, transmute_ptr(wp), size);
 
             (*cb).size -= size;
@@ -726,7 +962,7 @@ pub unsafe fn control_write_pending(c: *mut client, cp: *mut control_pane, limit
                 cb = tailq_first(&raw mut (*cs).all_blocks);
                 if !cb.is_null() && (*cb).size == 0 {
                     if wp.is_some() && !message.is_null() {
-                        control_write_data(c, message);
+                        control_write_data(c, cp, first, message);
                         message = null_mut();
                     }
                     control_flush_all_blocks(c);
@@ -734,7 +970,7 @@ pub unsafe fn control_write_pending(c: *mut client, cp: *mut control_pane, limit
             }
         }
         if !message.is_null() {
-            control_write_data(c, message);
+            control_write_data(c, cp, first, message);
         }
         !tailq_empty(&raw mut (*cp).blocks) as i32
     }
@@ -878,10 +1114,126 @@ pub unsafe fn control_stop(c: *mut client) {
         }
         control_reset_offsets(c);
 
+        (*cs).reply = None;
         free_(cs);
     }
 }
 
+/// Write a changed subscription value. The window and pane are null for
+/// session subscriptions and the pane for window subscriptions.
+unsafe fn control_write_sub_changed(
+    c: *mut client,
+    csub: *mut control_sub,
+    wl: *mut winlink,
+    wp: *mut window_pane,
+    value: *const u8,
+) {
+    unsafe {
+        let s = (*c).session;
+
+        if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+            let mut json = control_json::new("subscription-changed")
+                .string("name", CStr::from_ptr((*csub).name.cast()).to_bytes())
+                .number("session", (*s).id);
+            if wl.is_null() {
+                json = json.null("window").null("window_index");
+            } else {
+                json = json
+                    .number("window", (*(*wl).window).id)
+                    .number("window_index", (*wl).idx);
+            }
+            if wp.is_null() {
+                json = json.null("pane");
+            } else {
+                json = json.number("pane", (*wp).id);
+            }
+            json = json.string("value", CStr::from_ptr(value.cast()).to_bytes());
+            control_write_json(c, json);
+            return;
+        }
+
+        let window = if wl.is_null() {
+            "- -".to_string()
+        } else {
+            format!("@{} {}", (*(*wl).window).id, (*wl).idx)
+        };
+        let pane = if wp.is_null() {
+            "-".to_string()
+        } else {
+            format!("%{}", (*wp).id)
+        };
+        control_write!(
+            c,
+            "%subscription-changed {} ${} {} {} : {}",
+            _s((*csub).name),
+            (*s).id,
+            window,
+            pane,
+            _s(value),
+        );
+    }
+}
+
+/// Write the value of a notify subscription for a notification.
+unsafe fn control_write_sub_event(
+    c: *mut client,
+    csub: *mut control_sub,
+    event: *const u8,
+    value: *const u8,
+) {
+    unsafe {
+        if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+            let json = control_json::new("subscription-event")
+                .string("name", CStr::from_ptr((*csub).name.cast()).to_bytes())
+                .string("event", CStr::from_ptr(event.cast()).to_bytes())
+                .string("value", CStr::from_ptr(value.cast()).to_bytes());
+            control_write_json(c, json);
+            return;
+        }
+        control_write!(
+            c,
+            "%subscription-event {} {} : {}",
+            _s((*csub).name),
+            _s(event),
+            _s(value),
+        );
+    }
+}
+
+/// Check the notify subscriptions of a client against a notification. The
+/// format is expanded with the hook formats and the notification's target.
+pub unsafe fn control_check_subs_notify(c: *mut client, ne: *mut notify_entry) {
+    unsafe {
+        let cs = (*c).control_state;
+        let fs = &raw mut (*ne).fs;
+
+        for csub in rb_foreach(&raw mut (*cs).subs).map(NonNull::as_ptr) {
+            if !matches!((*csub).type_, control_sub_type::CONTROL_SUB_NOTIFY) {
+                continue;
+            }
+            let matched = cstr_to_str((*csub).events).split(',').any(|pattern| {
+                let pattern = CString::new(pattern).unwrap();
+                libc::fnmatch(pattern.as_ptr().cast(), (*ne).name, 0) == 0
+            });
+            if !matched {
+                continue;
+            }
+
+            let ft = if cmd_find_empty_state(fs) != 0 || !cmd_find_valid_state(fs) {
+                format_create_defaults(null_mut(), c, null_mut(), null_mut(), null_mut())
+            } else {
+                format_create_defaults(null_mut(), c, (*fs).s, (*fs).wl, (*fs).wp)
+            };
+            format_merge(ft, (*ne).formats);
+            let value = format_expand(ft, (*csub).format);
+            format_free(ft);
+
+            control_write_sub_event(c, csub, (*ne).name, value);
+            free_(value);
+        }
+    }
+}
+
 pub unsafe fn control_check_subs_session(c: *mut client, csub: *mut control_sub) {
     unsafe {
         let s = (*c).session;
@@ -894,13 +1246,7 @@ pub unsafe fn control_check_subs_session(c: *mut client, csub: *mut control_sub)
             free_(value);
             return;
         }
-        control_write!(
-            c,
-            "%subscription-changed {} ${} - - - : {}",
-            _s((*csub).name),
-            (*s).id,
-            _s(value),
-        );
+        control_write_sub_changed(c, csub, null_mut(), null_mut(), value);
         free_((*csub).last);
         (*csub).last = value;
     }
@@ -941,16 +1287,7 @@ pub unsafe fn control_check_subs_pane(c: *mut client, csub: *mut control_sub) {
                 free_(value);
                 continue;
             }
-            control_write!(
-                c,
-                "%subscription-changed {} ${} @{} {} %{} : {}",
-                _s((*csub).name),
-                (*s).id,
-                (*w).id,
-                (*wl).idx,
-                (*wp).id,
-                _s(value),
-            );
+            control_write_sub_changed(c, csub, wl, wp, value);
             free_((*csp).last);
             (*csp).last = value;
         }
@@ -984,16 +1321,7 @@ pub unsafe fn control_check_subs_all_panes(c: *mut client, csub: *mut control_su
                     free_(value);
                     continue;
                 }
-                control_write!(
-                    c,
-                    "%subscription-changed {} ${} @{} {} %{} : {}",
-                    _s((*csub).name),
-                    (*s).id,
-                    (*w).id,
-                    (*wl).idx,
-                    (*wp).id,
-                    _s(value),
-                );
+                control_write_sub_changed(c, csub, wl, wp, value);
                 free_((*csp).last);
                 (*csp).last = value;
             }
@@ -1037,15 +1365,7 @@ pub unsafe fn control_check_subs_window(c: *mut client, csub: *mut control_sub)
                 free_(value);
                 continue;
             }
-            control_write!(
-                c,
-                "%subscription-changed {} ${} @{} {} - : {}",
-                _s((*csub).name),
-                (*s).id,
-                (*w).id,
-                (*wl).idx,
-                _s(value),
-            );
+            control_write_sub_changed(c, csub, wl, null_mut(), value);
             free_((*csw).last);
             (*csw).last = value;
         }
@@ -1079,15 +1399,7 @@ pub unsafe fn control_check_subs_all_windows(c: *mut client, csub: *mut control_
                 free_(value);
                 continue;
             }
-            control_write!(
-                c,
-                "%subscription-changed {} ${} @{} {} - : {}",
-                _s((*csub).name),
-                (*s).id,
-                (*w).id,
-                (*wl).idx,
-                _s(value),
-            );
+            control_write_sub_changed(c, csub, wl, null_mut(), value);
             free_((*csw).last);
             (*csw).last = value;
         }
@@ -1119,16 +1431,21 @@ pub unsafe extern "C-unwind" fn control_check_subs_timer(
                 control_sub_type::CONTROL_SUB_ALL_WINDOWS => {
                     control_check_subs_all_windows(c, csub);
                 }
+                control_sub_type::CONTROL_SUB_NOTIFY => (),
             }
         }
     }
 }
 
+/// Add a subscription, replacing any with the same name. Notify
+/// subscriptions are written when a notification matching one of the
+/// patterns in events happens rather than checked on a timer.
 pub unsafe fn control_add_sub(
     c: *mut client,
     name: *mut u8,
     type_: control_sub_type,
     id: i32,
+    events: *const u8,
     format: *const u8,
 ) {
     unsafe {
@@ -1151,11 +1468,18 @@ pub unsafe fn control_add_sub(
         (*csub).type_ = type_;
         (*csub).id = id as u32;
         (*csub).format = xstrdup(format).as_ptr();
+        if !events.is_null() {
+            (*csub).events = xstrdup(events).as_ptr();
+        }
         rb_insert(&raw mut (*cs).subs, csub);
 
         rb_init(&raw mut (*csub).panes);
         rb_init(&raw mut (*csub).windows);
 
+        if matches!((*csub).type_, control_sub_type::CONTROL_SUB_NOTIFY) {
+            return;
+        }
+
         if !evtimer_initialized(&raw mut (*cs).subs_timer) {
             evtimer_set(
                 &raw mut (*cs).subs_timer,
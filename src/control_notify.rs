@@ -28,7 +28,48 @@ pub unsafe fn control_notify_pane_mode_changed(pane: c_int) {
                     continue;
                 }
 
-                control_write!(c, "%pane-mode-changed %{}", pane);
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let json = control_json::new("pane-mode-changed").number("pane", pane);
+                    control_write_json(c, json);
+                } else {
+                    control_write!(c, "%pane-mode-changed %{}", pane);
+                }
+            }
+        }
+    }
+}
+
+pub unsafe fn control_notify_pane_notification(pane: c_int) {
+    unsafe {
+        let wp = window_pane_find_by_id(pane as u32);
+        if wp.is_null() {
+            return;
+        }
+        // The title and body are already escaped; spaces in the title are
+        // escaped too so it is a single argument.
+        let title = _s((*wp).notification_title).to_string().replace(' ', "\\040");
+
+        for c in tailq_foreach(&raw mut CLIENTS).map(NonNull::as_ptr) {
+            {
+                if !CONTROL_SHOULD_NOTIFY_CLIENT!(c) {
+                    continue;
+                }
+
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let json = control_json::new("notification")
+                        .number("pane", pane)
+                        .string("title", CStr::from_ptr((*wp).notification_title.cast()).to_bytes())
+                        .string("body", CStr::from_ptr((*wp).notification_body.cast()).to_bytes());
+                    control_write_json(c, json);
+                } else {
+                    control_write!(
+                        c,
+                        "%notification %{} {} {}",
+                        pane,
+                        title,
+                        _s((*wp).notification_body)
+                    );
+                }
             }
         }
     }
@@ -59,6 +100,21 @@ pub unsafe fn control_notify_window_layout_changed(w: *mut window) {
                 }
 
                 if let Some(wl) = winlink_find_by_window(&raw mut (*s).windows, w) {
+                    if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                        let field = |template: &str| {
+                            let cp = format_single(null_mut(), template, c, null_mut(), wl.as_ptr(), null_mut());
+                            let value = CStr::from_ptr(cp.cast()).to_bytes().to_vec();
+                            free_(cp);
+                            value
+                        };
+                        let json = control_json::new("layout-change")
+                            .number("window", (*w).id)
+                            .string("layout", field("#{window_layout}"))
+                            .string("visible_layout", field("#{window_visible_layout}"))
+                            .string("flags", field("#{window_raw_flags}"));
+                        control_write_json(c, json);
+                        continue;
+                    }
                     let cp =
                         format_single(null_mut(), cstr_to_str(template), c, null_mut(), wl.as_ptr(), null_mut());
                     control_write!(c, "{}", _s(cp));
@@ -77,12 +133,19 @@ pub unsafe fn control_notify_window_pane_changed(w: *mut window) {
                     continue;
                 }
 
-                control_write!(
-                    c,
-                    "%window-pane-changed @{} %{}",
-                    (*w).id,
-                    (*(*w).active).id,
-                );
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let json = control_json::new("window-pane-changed")
+                        .number("window", (*w).id)
+                        .number("pane", (*(*w).active).id);
+                    control_write_json(c, json);
+                } else {
+                    control_write!(
+                        c,
+                        "%window-pane-changed @{} %{}",
+                        (*w).id,
+                        (*(*w).active).id,
+                    );
+                }
             }
         }
     }
@@ -97,7 +160,11 @@ pub unsafe fn control_notify_window_unlinked(_s: *mut session, w: *mut window) {
                 }
                 let cs = (*c).session;
 
-                if !winlink_find_by_window_id(&raw mut (*cs).windows, (*w).id).is_null() {
+                let linked = !winlink_find_by_window_id(&raw mut (*cs).windows, (*w).id).is_null();
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let type_ = if linked { "window-close" } else { "unlinked-window-close" };
+                    control_write_json(c, control_json::new(type_).number("window", (*w).id));
+                } else if linked {
                     control_write!(c, "%window-close @{}", (*w).id);
                 } else {
                     control_write!(c, "%unlinked-window-close @{}", (*w).id);
@@ -116,7 +183,11 @@ pub unsafe fn control_notify_window_linked(_s: *mut session, w: *mut window) {
                 }
                 let cs = (*c).session;
 
-                if !winlink_find_by_window_id(&raw mut (*cs).windows, (*w).id).is_null() {
+                let linked = !winlink_find_by_window_id(&raw mut (*cs).windows, (*w).id).is_null();
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let type_ = if linked { "window-add" } else { "unlinked-window-add" };
+                    control_write_json(c, control_json::new(type_).number("window", (*w).id));
+                } else if linked {
                     control_write!(c, "%window-add @{}", (*w).id);
                 } else {
                     control_write!(c, "%unlinked-window-add @{}", (*w).id);
@@ -135,7 +206,14 @@ pub unsafe fn control_notify_window_renamed(w: *mut window) {
                 }
                 let cs = (*c).session;
 
-                if !winlink_find_by_window_id(&raw mut (*cs).windows, (*w).id).is_null() {
+                let linked = !winlink_find_by_window_id(&raw mut (*cs).windows, (*w).id).is_null();
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let type_ = if linked { "window-renamed" } else { "unlinked-window-renamed" };
+                    let json = control_json::new(type_)
+                        .number("window", (*w).id)
+                        .string("name", CStr::from_ptr((*w).name.cast()).to_bytes());
+                    control_write_json(c, json);
+                } else if linked {
                     control_write!(c, "%window-renamed @{} {}", (*w).id, _s((*w).name));
                 } else {
                     control_write!(c, "%unlinked-window-renamed @{} {}", (*w).id, _s((*w).name),);
@@ -158,7 +236,16 @@ pub unsafe fn control_notify_client_session_changed(cc: *mut client) {
                     continue;
                 }
 
-                if cc == c {
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let mut json = if cc == c {
+                        control_json::new("session-changed")
+                    } else {
+                        control_json::new("client-session-changed")
+                            .string("client", CStr::from_ptr((*cc).name.cast()).to_bytes())
+                    };
+                    json = json.number("session", (*s).id).string("name", &*(*s).name);
+                    control_write_json(c, json);
+                } else if cc == c {
                     control_write!(c, "%session-changed ${} {}", (*s).id, (*s).name);
                 } else {
                     control_write!(
@@ -178,7 +265,14 @@ pub unsafe fn control_notify_client_detached(cc: *mut client) {
     unsafe {
         for c in tailq_foreach(&raw mut CLIENTS).map(NonNull::as_ptr) {
             {
-                if CONTROL_SHOULD_NOTIFY_CLIENT!(c) {
+                if !CONTROL_SHOULD_NOTIFY_CLIENT!(c) {
+                    continue;
+                }
+
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let name = CStr::from_ptr((*cc).name.cast()).to_bytes();
+                    control_write_json(c, control_json::new("client-detached").string("client", name));
+                } else {
                     control_write!(c, "%client-detached {}", _s((*cc).name));
                 }
             }
@@ -194,7 +288,14 @@ pub unsafe fn control_notify_session_renamed(s: *mut session) {
                     continue;
                 }
 
-                control_write!(c, "%session-renamed ${} {}", (*s).id, (*s).name);
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let json = control_json::new("session-renamed")
+                        .number("session", (*s).id)
+                        .string("name", &*(*s).name);
+                    control_write_json(c, json);
+                } else {
+                    control_write!(c, "%session-renamed ${} {}", (*s).id, (*s).name);
+                }
             }
         }
     }
@@ -208,7 +309,11 @@ pub unsafe fn control_notify_session_created(_: *mut session) {
                     continue;
                 }
 
-                control_write!(c, "%sessions-changed");
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    control_write_json(c, control_json::new("sessions-changed"));
+                } else {
+                    control_write!(c, "%sessions-changed");
+                }
             }
         }
     }
@@ -222,7 +327,11 @@ pub unsafe fn control_notify_session_closed(_: *mut session) {
                     continue;
                 }
 
-                control_write!(c, "%sessions-changed");
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    control_write_json(c, control_json::new("sessions-changed"));
+                } else {
+                    control_write!(c, "%sessions-changed");
+                }
             }
         }
     }
@@ -236,12 +345,19 @@ pub unsafe fn control_notify_session_window_changed(s: *mut session) {
                     continue;
                 }
 
-                control_write!(
-                    c,
-                    "%session-window-changed ${} @{}",
-                    (*s).id,
-                    (*(*(*s).curw).window).id,
-                );
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let json = control_json::new("session-window-changed")
+                        .number("session", (*s).id)
+                        .number("window", (*(*(*s).curw).window).id);
+                    control_write_json(c, json);
+                } else {
+                    control_write!(
+                        c,
+                        "%session-window-changed ${} @{}",
+                        (*s).id,
+                        (*(*(*s).curw).window).id,
+                    );
+                }
             }
         }
     }
@@ -255,7 +371,12 @@ pub unsafe fn control_notify_paste_buffer_changed(name: *const u8) {
                     continue;
                 }
 
-                control_write!(c, "%paste-buffer-changed {}", _s(name));
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let name = CStr::from_ptr(name.cast()).to_bytes();
+                    control_write_json(c, control_json::new("paste-buffer-changed").string("name", name));
+                } else {
+                    control_write!(c, "%paste-buffer-changed {}", _s(name));
+                }
             }
         }
     }
@@ -269,7 +390,22 @@ pub unsafe fn control_notify_paste_buffer_deleted(name: *const u8) {
                     continue;
                 }
 
-                control_write!(c, "%paste-buffer-deleted {}", _s(name));
+                if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+                    let name = CStr::from_ptr(name.cast()).to_bytes();
+                    control_write_json(c, control_json::new("paste-buffer-deleted").string("name", name));
+                } else {
+                    control_write!(c, "%paste-buffer-deleted {}", _s(name));
+                }
+            }
+        }
+    }
+}
+
+pub unsafe fn control_notify_subscriptions(ne: *mut notify_entry) {
+    unsafe {
+        for c in tailq_foreach(&raw mut CLIENTS).map(NonNull::as_ptr) {
+            if CONTROL_SHOULD_NOTIFY_CLIENT!(c) {
+                control_check_subs_notify(c, ne);
             }
         }
     }
@@ -15,8 +15,8 @@ use std::borrow::Cow;
 
 use crate::compat::HOST_NAME_MAX;
 use crate::libc::{
-    FNM_CASEFOLD, REG_NOSUB, ctime_r, getpwuid, getuid, ispunct, localtime_r, memcpy, regcomp,
-    regex_t, regexec, regfree, strchr, strcmp, strcspn, strftime, strstr, strtod, tm,
+    FNM_CASEFOLD, REG_NOSUB, ctime_r, getpwuid, getuid, ispunct, localtime_r, memcpy,
+    strchr, strcmp, strcspn, strftime, strstr, strtod, tm,
 };
 use crate::*;
 use crate::options_::*;
@@ -891,7 +891,7 @@ pub unsafe fn format_cb_current_path(ft: *mut format_tree) -> format_table_type
             return format_table_type::None;
         }
 
-        let cwd = osdep_get_cwd((*wp).fd);
+        let cwd = window_pane_get_cwd(wp);
         if cwd.is_null() {
             return format_table_type::None;
         }
@@ -899,7 +899,9 @@ pub unsafe fn format_cb_current_path(ft: *mut format_tree) -> format_table_type
     }
 }
 
-/// Callback for `history_bytes`.
+/// Callback for `history_bytes`. This is the memory used by the lines and
+/// their cells, so a compressed line counts only its compressed size rather
+/// than the size of its cells as before lines were compressed.
 pub unsafe fn format_cb_history_bytes(ft: *mut format_tree) -> format_table_type {
     unsafe {
         let wp = (*ft).wp;
@@ -909,13 +911,7 @@ pub unsafe fn format_cb_history_bytes(ft: *mut format_tree) -> format_table_type
         }
 
         let gd = (*wp).base.grid;
-        let mut size: usize = 0;
-
-        for i in 0..((*gd).hsize + (*gd).sy) {
-            let gl = grid_get_line(gd, i);
-            size += (*gl).cellsize as usize * std::mem::size_of::<grid_cell>();
-            size += (*gl).extdsize as usize * std::mem::size_of::<grid_cell>();
-        }
+        let mut size = grid_memory_size(gd);
         size += ((*gd).hsize + (*gd).sy) as usize * std::mem::size_of::<grid_line>();
 
         format!("{size}").into()
@@ -937,7 +933,7 @@ pub unsafe fn format_cb_history_all_bytes(ft: *mut format_tree) -> format_table_
         let mut extended_cells = 0;
 
         for i in 0..lines {
-            let gl = grid_get_line(gd, i);
+            let gl = grid_get_line_compressed(gd, i);
             cells += (*gl).cellsize;
             extended_cells += (*gl).extdsize;
         }
@@ -1502,6 +1498,18 @@ pub unsafe fn format_cb_client_readonly(ft: *mut format_tree) -> format_table_ty
     }
 }
 
+pub unsafe fn format_cb_client_profile(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        if !(*ft).c.is_null() {
+            let profile = server_acl_get_profile((*ft).c);
+            if !profile.is_null() {
+                return format!("{}", _s(profile)).into();
+            }
+        }
+        format_table_type::None
+    }
+}
+
 pub unsafe fn format_cb_client_session(ft: *mut format_tree) -> format_table_type {
     unsafe {
         if !(*ft).c.is_null() && !(*(*ft).c).session.is_null() {
@@ -1661,6 +1669,16 @@ pub unsafe fn format_cb_history_limit(ft: *mut format_tree) -> format_table_type
     }
 }
 
+/// Callback for `history_file_size`.
+pub unsafe fn format_cb_history_file_size(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        if !(*ft).wp.is_null() {
+            return format!("{}", grid_spill_size((*(*ft).wp).base.grid)).into();
+        }
+        format_table_type::None
+    }
+}
+
 /// Callback for `history_size`.
 pub unsafe fn format_cb_history_size(ft: *mut format_tree) -> format_table_type {
     unsafe {
@@ -1929,6 +1947,59 @@ pub unsafe fn format_cb_pane_bottom(ft: *mut format_tree) -> format_table_type {
     }
 }
 
+/// Callback for `pane_command_duration`.
+pub unsafe fn format_cb_pane_command_duration(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let wp = (*ft).wp;
+        if wp.is_null() || (*wp).command_start.tv_sec == 0 {
+            return format_table_type::None;
+        }
+        let mut end = (*wp).command_end;
+        if end.tv_sec == 0 && libc::gettimeofday(&raw mut end, null_mut()) != 0 {
+            fatal("gettimeofday failed");
+        }
+        let mut tv: timeval = zeroed();
+        timersub(&raw const end, &raw const (*wp).command_start, &raw mut tv);
+        format!("{}", tv.tv_sec * 1000 + tv.tv_usec / 1000).into()
+    }
+}
+
+/// Callback for `pane_command_running`.
+pub unsafe fn format_cb_pane_command_running(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let wp = (*ft).wp;
+        if !wp.is_null() {
+            if (*wp).command_start.tv_sec != 0 && (*wp).command_end.tv_sec == 0 {
+                return "1".into();
+            }
+            return "0".into();
+        }
+        format_table_type::None
+    }
+}
+
+/// Callback for `pane_command_start`.
+pub unsafe fn format_cb_pane_command_start(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let wp = (*ft).wp;
+        if !wp.is_null() && (*wp).command_start.tv_sec != 0 {
+            return format_table_type::Time((*wp).command_start);
+        }
+        format_table_type::None
+    }
+}
+
+/// Callback for `pane_command_status`.
+pub unsafe fn format_cb_pane_command_status(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let wp = (*ft).wp;
+        if !wp.is_null() && (*wp).command_end.tv_sec != 0 && (*wp).command_status != -1 {
+            return format!("{}", (*wp).command_status).into();
+        }
+        format_table_type::None
+    }
+}
+
 /// Callback for `pane_dead`.
 pub unsafe fn format_cb_pane_dead(ft: *mut format_tree) -> format_table_type {
     unsafe {
@@ -2062,6 +2133,9 @@ pub unsafe fn format_cb_pane_unseen_changes(ft: *mut format_tree) -> format_tabl
 pub unsafe fn format_cb_pane_key_mode(ft: *mut format_tree) -> format_table_type {
     unsafe {
         if !(*ft).wp.is_null() && !(*(*ft).wp).screen.is_null() {
+            if !(*(*(*ft).wp).screen).kitty_keys.flags.is_empty() {
+                return "Kitty".into();
+            }
             match (*(*(*ft).wp).screen).mode & EXTENDED_KEY_MODES {
                 mode_flag::MODE_KEYS_EXTENDED => return "Ext 1".into(),
                 mode_flag::MODE_KEYS_EXTENDED_2 => {
@@ -2087,6 +2161,39 @@ pub unsafe fn format_cb_pane_last(ft: *mut format_tree) -> format_table_type {
     }
 }
 
+/// Callback for `pane_last_notification`.
+pub unsafe fn format_cb_pane_last_notification(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let wp = (*ft).wp;
+        if !wp.is_null() && !(*wp).notification_body.is_null() {
+            return format!("{}", _s((*wp).notification_body)).into();
+        }
+        format_table_type::None
+    }
+}
+
+/// Callback for `pane_last_notification_time`.
+pub unsafe fn format_cb_pane_last_notification_time(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let wp = (*ft).wp;
+        if !wp.is_null() && (*wp).notification_time.tv_sec != 0 {
+            return format_table_type::Time((*wp).notification_time);
+        }
+        format_table_type::None
+    }
+}
+
+/// Callback for `pane_last_notification_title`.
+pub unsafe fn format_cb_pane_last_notification_title(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let wp = (*ft).wp;
+        if !wp.is_null() && !(*wp).notification_title.is_null() {
+            return format!("{}", _s((*wp).notification_title)).into();
+        }
+        format_table_type::None
+    }
+}
+
 /// Callback for `pane_left`.
 pub unsafe fn format_cb_pane_left(ft: *mut format_tree) -> format_table_type {
     unsafe {
@@ -2173,6 +2280,42 @@ pub unsafe fn format_cb_pane_pipe(ft: *mut format_tree) -> format_table_type {
     }
 }
 
+/// Callback for `pane_reported_cwd`.
+pub unsafe fn format_cb_pane_reported_cwd(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let wp = (*ft).wp;
+        if !wp.is_null() && !(*wp).reported_cwd.is_null() {
+            return format!("{}", _s((*wp).reported_cwd)).into();
+        }
+        format_table_type::None
+    }
+}
+
+/// Callback for `pane_reported_host`.
+pub unsafe fn format_cb_pane_reported_host(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let wp = (*ft).wp;
+        if !wp.is_null() && !(*wp).reported_host.is_null() {
+            return format!("{}", _s((*wp).reported_host)).into();
+        }
+        format_table_type::None
+    }
+}
+
+/// Callback for `pane_reported_local`.
+pub unsafe fn format_cb_pane_reported_local(ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let wp = (*ft).wp;
+        if !wp.is_null() && !(*wp).reported_cwd.is_null() {
+            if window_pane_host_is_local((*wp).reported_host) {
+                return "1".into();
+            }
+            return "0".into();
+        }
+        format_table_type::None
+    }
+}
+
 /// Callback for `pane_right`.
 pub unsafe fn format_cb_pane_right(ft: *mut format_tree) -> format_table_type {
     unsafe {
@@ -2277,6 +2420,34 @@ pub unsafe fn format_cb_server_sessions(_ft: *mut format_tree) -> format_table_t
     }
 }
 
+/// Callback for `server_history_compressed`.
+pub unsafe fn format_cb_server_history_compressed(_ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let mut n: u32 = 0;
+        for wp in rb_foreach(&raw mut ALL_WINDOW_PANES).map(NonNull::as_ptr) {
+            n += grid_compressed_size((*wp).base.grid).0;
+            if !(*wp).base.saved_grid.is_null() {
+                n += grid_compressed_size((*wp).base.saved_grid).0;
+            }
+        }
+        format!("{n}").into()
+    }
+}
+
+/// Callback for `server_history_saved`.
+pub unsafe fn format_cb_server_history_saved(_ft: *mut format_tree) -> format_table_type {
+    unsafe {
+        let mut size: usize = 0;
+        for wp in rb_foreach(&raw mut ALL_WINDOW_PANES).map(NonNull::as_ptr) {
+            size += grid_compressed_size((*wp).base.grid).1;
+            if !(*wp).base.saved_grid.is_null() {
+                size += grid_compressed_size((*wp).base.saved_grid).1;
+            }
+        }
+        format!("{size}").into()
+    }
+}
+
 /// Callback for `session_attached`.
 pub unsafe fn format_cb_session_attached(ft: *mut format_tree) -> format_table_type {
     unsafe {
@@ -2866,6 +3037,11 @@ pub unsafe fn format_cb_client_mode_format(_ft: *mut format_tree) -> format_tabl
     WINDOW_CLIENT_MODE.default_format.unwrap().into()
 }
 
+/// Callback for `search_mode_format`.
+pub unsafe fn format_cb_search_mode_format(_ft: *mut format_tree) -> format_table_type {
+    WINDOW_SEARCH_MODE.default_format.unwrap().into()
+}
+
 /// Callback for `tree_mode_format`.
 pub unsafe fn format_cb_tree_mode_format(_ft: *mut format_tree) -> format_table_type {
     WINDOW_TREE_MODE.default_format.unwrap().into()
@@ -2956,6 +3132,7 @@ static FORMAT_TABLE: &[format_table_entry] = &[
     format_table_entry::new("client_name", format_cb_client_name),
     format_table_entry::new("client_pid", format_cb_client_pid),
     format_table_entry::new("client_prefix", format_cb_client_prefix),
+    format_table_entry::new("client_profile", format_cb_client_profile),
     format_table_entry::new("client_readonly", format_cb_client_readonly),
     format_table_entry::new("client_session", format_cb_client_session),
     format_table_entry::new("client_termfeatures", format_cb_client_termfeatures),
@@ -2974,6 +3151,7 @@ static FORMAT_TABLE: &[format_table_entry] = &[
     format_table_entry::new("cursor_y", format_cb_cursor_y),
     format_table_entry::new("history_all_bytes", format_cb_history_all_bytes),
     format_table_entry::new("history_bytes", format_cb_history_bytes),
+    format_table_entry::new("history_file_size", format_cb_history_file_size),
     format_table_entry::new("history_limit", format_cb_history_limit),
     format_table_entry::new("history_size", format_cb_history_size),
     format_table_entry::new("host", format_cb_host),
@@ -3005,6 +3183,10 @@ static FORMAT_TABLE: &[format_table_entry] = &[
     format_table_entry::new("pane_at_top", format_cb_pane_at_top),
     format_table_entry::new("pane_bg", format_cb_pane_bg),
     format_table_entry::new("pane_bottom", format_cb_pane_bottom),
+    format_table_entry::new("pane_command_duration", format_cb_pane_command_duration),
+    format_table_entry::new("pane_command_running", format_cb_pane_command_running),
+    format_table_entry::new("pane_command_start", format_cb_pane_command_start),
+    format_table_entry::new("pane_command_status", format_cb_pane_command_status),
     format_table_entry::new("pane_current_command", format_cb_current_command),
     format_table_entry::new("pane_current_path", format_cb_current_path),
     format_table_entry::new("pane_dead", format_cb_pane_dead),
@@ -3020,6 +3202,9 @@ static FORMAT_TABLE: &[format_table_entry] = &[
     format_table_entry::new("pane_input_off", format_cb_pane_input_off),
     format_table_entry::new("pane_key_mode", format_cb_pane_key_mode),
     format_table_entry::new("pane_last", format_cb_pane_last),
+    format_table_entry::new("pane_last_notification", format_cb_pane_last_notification),
+    format_table_entry::new("pane_last_notification_time", format_cb_pane_last_notification_time),
+    format_table_entry::new("pane_last_notification_title", format_cb_pane_last_notification_title),
     format_table_entry::new("pane_left", format_cb_pane_left),
     format_table_entry::new("pane_marked", format_cb_pane_marked),
     format_table_entry::new("pane_marked_set", format_cb_pane_marked_set),
@@ -3027,6 +3212,9 @@ static FORMAT_TABLE: &[format_table_entry] = &[
     format_table_entry::new("pane_path", format_cb_pane_path),
     format_table_entry::new("pane_pid", format_cb_pane_pid),
     format_table_entry::new("pane_pipe", format_cb_pane_pipe),
+    format_table_entry::new("pane_reported_cwd", format_cb_pane_reported_cwd),
+    format_table_entry::new("pane_reported_host", format_cb_pane_reported_host),
+    format_table_entry::new("pane_reported_local", format_cb_pane_reported_local),
     format_table_entry::new("pane_right", format_cb_pane_right),
     format_table_entry::new("pane_search_string", format_cb_pane_search_string),
     format_table_entry::new("pane_start_command", format_cb_start_command),
@@ -3041,6 +3229,12 @@ static FORMAT_TABLE: &[format_table_entry] = &[
     format_table_entry::new("pid", format_cb_pid),
     format_table_entry::new("scroll_region_lower", format_cb_scroll_region_lower),
     format_table_entry::new("scroll_region_upper", format_cb_scroll_region_upper),
+    format_table_entry::new("search_mode_format", format_cb_search_mode_format),
+    format_table_entry::new(
+        "server_history_compressed",
+        format_cb_server_history_compressed,
+    ),
+    format_table_entry::new("server_history_saved", format_cb_server_history_saved),
     format_table_entry::new("server_sessions", format_cb_server_sessions),
     format_table_entry::new("session_activity", format_cb_session_activity),
     format_table_entry::new("session_alerts", format_cb_session_alerts),
@@ -3178,6 +3372,9 @@ pub unsafe fn format_create(
         (*ft).item = item;
         (*ft).tag = tag as u32;
         (*ft).flags = flags;
+        if !item.is_null() && !server_acl_check_jobs(item) {
+            (*ft).flags |= format_flags::FORMAT_NOJOBS;
+        }
 
         if !item.is_null() {
             format_create_add_item(ft, item);
@@ -3419,6 +3616,26 @@ pub unsafe fn format_pretty_time(t: time_t, seconds: i32) -> *mut u8 {
     }
 }
 
+/// Format the time a line was written with strftime, or spaces of the same
+/// width if the line has no time.
+pub unsafe fn format_line_time(t: time_t, fmt: *const u8) -> String {
+    unsafe {
+        const SIZEOF_S: usize = 256;
+        let mut s = [0u8; SIZEOF_S];
+        let mut tm = MaybeUninit::<tm>::uninit();
+        let tm = tm.as_mut_ptr();
+
+        let when = if t == 0 { libc::time(null_mut()) } else { t };
+        localtime_r(&raw const when, tm);
+        let n = strftime(s.as_mut_ptr(), SIZEOF_S, fmt, tm);
+        let out = String::from_utf8_lossy(&s[..n]);
+        if t == 0 {
+            return " ".repeat(out.chars().count());
+        }
+        out.into_owned()
+    }
+}
+
 /// Find a format entry.
 fn format_find(
     ft: *mut format_tree,
@@ -3855,8 +4072,6 @@ pub unsafe fn format_match(
 ) -> *mut u8 {
     unsafe {
         let mut s = c!("");
-        let mut r = MaybeUninit::<regex_t>::uninit();
-        let r = r.as_mut_ptr();
         let mut flags: i32 = 0;
 
         if (*fm).argc >= 1 {
@@ -3874,14 +4089,12 @@ pub unsafe fn format_match(
             if !strchr(s, b'i' as i32).is_null() {
                 flags |= REG_ICASE;
             }
-            if regcomp(r, pattern, flags) != 0 {
+            let Some(r) = regexp_compile(pattern, flags) else {
                 return xstrdup(c!("0")).as_ptr();
-            }
-            if regexec(r, text, 0, null_mut(), 0) != 0 {
-                regfree(r);
+            };
+            if !regexp_exec(&r, text, 0, &mut []) {
                 return xstrdup(c!("0")).as_ptr();
             }
-            regfree(r);
         }
         xstrdup(c!("1")).as_ptr()
     }
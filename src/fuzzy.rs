@@ -0,0 +1,148 @@
+// Fuzzy matching of a pattern as a subsequence of some text. Each match is
+// scored so better matches can be ranked first: characters at the start of
+// words and runs of consecutive characters score more and gaps between the
+// matched characters cost a little.
+
+const FUZZY_SCORE_MATCH: i32 = 16;
+const FUZZY_SCORE_GAP_START: i32 = -3;
+const FUZZY_SCORE_GAP_EXTEND: i32 = -1;
+const FUZZY_BONUS_BOUNDARY: i32 = 8;
+const FUZZY_BONUS_CAMEL: i32 = 7;
+const FUZZY_BONUS_CONSECUTIVE: i32 = 8;
+
+/// Bonus for matching the character at position i of text.
+fn fuzzy_bonus(text: &[char], i: usize) -> i32 {
+    if i == 0 {
+        return FUZZY_BONUS_BOUNDARY;
+    }
+    let (prev, ch) = (text[i - 1], text[i]);
+    if !prev.is_alphanumeric() && ch.is_alphanumeric() {
+        FUZZY_BONUS_BOUNDARY
+    } else if prev.is_lowercase() && ch.is_uppercase() {
+        FUZZY_BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+fn fuzzy_equal(a: char, b: char, icase: bool) -> bool {
+    if icase {
+        a == b || a.to_lowercase().eq(b.to_lowercase())
+    } else {
+        a == b
+    }
+}
+
+/// Find the best match of pattern in text. Returns the score and the
+/// positions in text of each character of the pattern, or None if the pattern
+/// is empty or is not a subsequence of the text.
+pub fn fuzzy_match(pattern: &[char], text: &[char], icase: bool) -> Option<(i32, Vec<usize>)> {
+    let (m, n) = (pattern.len(), text.len());
+    if m == 0 || m > n {
+        return None;
+    }
+
+    // Check the pattern is a subsequence at all before doing any more work.
+    let mut first = None;
+    let mut i = 0;
+    for (j, ch) in text.iter().enumerate() {
+        if i < m && fuzzy_equal(pattern[i], *ch, icase) {
+            first.get_or_insert(j);
+            i += 1;
+        }
+    }
+    if i != m {
+        return None;
+    }
+    let first = first?;
+
+    // scores[i * n + j] is the best score of a match of the first i + 1
+    // characters of the pattern with character i at position j of the text.
+    // The best match ending in a gap before j is kept as each row is walked.
+    let mut scores = vec![i32::MIN; m * n];
+    for i in 0..m {
+        let mut gap = i32::MIN;
+        for j in first + i..n {
+            if i > 0 && j >= 2 && scores[(i - 1) * n + j - 2] != i32::MIN {
+                gap = gap.max(scores[(i - 1) * n + j - 2] + FUZZY_SCORE_GAP_START);
+            }
+            let mut best = if i == 0 { 0 } else { gap };
+            if i > 0 && scores[(i - 1) * n + j - 1] != i32::MIN {
+                best = best.max(scores[(i - 1) * n + j - 1] + FUZZY_BONUS_CONSECUTIVE);
+            }
+            if best != i32::MIN && fuzzy_equal(pattern[i], text[j], icase) {
+                let mut bonus = fuzzy_bonus(text, j);
+                if i == 0 {
+                    bonus *= 2;
+                }
+                scores[i * n + j] = best + FUZZY_SCORE_MATCH + bonus;
+            }
+            if gap != i32::MIN {
+                gap += FUZZY_SCORE_GAP_EXTEND;
+            }
+        }
+    }
+
+    // Find the best end and walk back to recover the positions.
+    let (mut j, score) = (0..n)
+        .map(|j| (j, scores[(m - 1) * n + j]))
+        .filter(|(_, score)| *score != i32::MIN)
+        .max_by_key(|(j, score)| (*score, std::cmp::Reverse(*j)))?;
+    let mut positions = vec![0; m];
+    positions[m - 1] = j;
+    for i in (1..m).rev() {
+        j = (0..j)
+            .filter(|k| scores[(i - 1) * n + k] != i32::MIN)
+            .max_by_key(|k| {
+                let prev = scores[(i - 1) * n + k];
+                if k + 1 == j {
+                    (prev + FUZZY_BONUS_CONSECUTIVE, *k)
+                } else {
+                    let extend = FUZZY_SCORE_GAP_EXTEND * (j - k - 2) as i32;
+                    (prev + FUZZY_SCORE_GAP_START + extend, *k)
+                }
+            })?;
+        positions[i - 1] = j;
+    }
+    Some((score, positions))
+}
+
+/// Score a match of pattern in text, ignoring case if the pattern is all
+/// lowercase.
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<i32> {
+    let icase = !pattern.chars().any(char::is_uppercase);
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    fuzzy_match(&pattern, &text, icase).map(|(score, _)| score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(pattern: &str, text: &str) -> Option<Vec<usize>> {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        fuzzy_match(&pattern, &text, true).map(|(_, positions)| positions)
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert_eq!(positions("abc", "xaxbxc"), Some(vec![1, 3, 5]));
+        assert_eq!(positions("abc", "acb"), None);
+        assert_eq!(positions("", "abc"), None);
+        assert_eq!(positions("fb", "foo bar"), Some(vec![0, 4]));
+        assert_eq!(positions("bar", "b a bar"), Some(vec![4, 5, 6]));
+        assert_eq!(positions("ÉA", "état"), Some(vec![0, 2]));
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        // Consecutive characters and word starts rank first.
+        assert!(fuzzy_score("main", "src/main.rs") > fuzzy_score("main", "m_a_i_n"));
+        assert!(fuzzy_score("wc", "window_copy") > fuzzy_score("wc", "awesomecat"));
+        assert!(fuzzy_score("wc", "windowCopy") > fuzzy_score("wc", "windowxcopy"));
+        assert_eq!(fuzzy_score("W", "window"), None);
+        assert!(fuzzy_score("w", "Window").is_some());
+    }
+}
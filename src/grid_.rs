@@ -203,8 +203,300 @@ unsafe fn grid_compact_line(gl: *mut grid_line) {
     }
 }
 
+/// History lines further back than this are compressed.
+const GRID_COMPRESS_LINES: u32 = 1000;
+
+/// Append a variable length number to compressed line data.
+pub fn grid_compress_put(buf: &mut Vec<u8>, mut n: u32) {
+    while n >= 0x80 {
+        buf.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// Read a variable length number from compressed line data.
+pub fn grid_compress_get(data: &[u8], off: &mut usize) -> u32 {
+    let mut n = 0u32;
+    let mut shift = 0;
+    while let Some(&ch) = data.get(*off) {
+        *off += 1;
+        n |= ((ch & 0x7f) as u32) << shift;
+        if ch & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    n
+}
+
+/// Is this cell a single byte, single width character?
+fn grid_compress_plain(gc: &grid_cell) -> bool {
+    gc.data.size == 1 && gc.data.width == 1
+}
+
+/// Do two cells have the same attributes, so they can share a run?
+fn grid_compress_same(gc1: &grid_cell, gc2: &grid_cell) -> bool {
+    gc1.flags == gc2.flags
+        && gc1.attr == gc2.attr
+        && gc1.fg == gc2.fg
+        && gc1.bg == gc2.bg
+        && gc1.us == gc2.us
+        && gc1.link == gc2.link
+        && grid_compress_plain(gc1) == grid_compress_plain(gc2)
+}
+
+/// Memory used by the cells of an uncompressed line.
+unsafe fn grid_line_bytes(gl: *const grid_line) -> usize {
+    unsafe {
+        (*gl).cellsize as usize * size_of::<grid_cell_entry>()
+            + (*gl).extdsize as usize * size_of::<grid_extd_entry>()
+    }
+}
+
+/// Compress a line. The cells are stored as runs with the same attributes,
+/// each followed by its text. The size of the uncompressed cells comes first,
+/// so the memory saved can be worked out without expanding the line.
+pub unsafe fn grid_compress_line(gl: *mut grid_line) {
+    unsafe {
+        if (*gl).cellsize == 0
+            || (*gl)
+                .flags
+                .intersects(grid_line_flag::COMPRESSED | grid_line_flag::DEAD)
+        {
+            return;
+        }
+
+        let mut buf: Vec<u8> = Vec::with_capacity((*gl).cellused as usize + 16);
+        grid_compress_put(&mut buf, grid_line_bytes(gl) as u32);
+
+        let mut cells: Vec<grid_cell> = Vec::with_capacity((*gl).cellsize as usize);
+        for px in 0..(*gl).cellsize {
+            let mut gc: grid_cell = zeroed();
+            grid_get_cell1(gl, px, &raw mut gc);
+            cells.push(gc);
+        }
+        for run in cells.chunk_by(grid_compress_same) {
+            let gc = &run[0];
+            grid_compress_put(&mut buf, run.len() as u32);
+            buf.push(gc.flags.bits());
+            grid_compress_put(&mut buf, gc.attr.bits() as u32);
+            grid_compress_put(&mut buf, gc.fg as u32);
+            grid_compress_put(&mut buf, gc.bg as u32);
+            grid_compress_put(&mut buf, gc.us as u32);
+            grid_compress_put(&mut buf, gc.link);
+            if grid_compress_plain(gc) {
+                buf.push(1);
+                buf.extend(run.iter().map(|gc| gc.data.data[0]));
+            } else {
+                buf.push(0);
+                for gc in run {
+                    buf.push(gc.data.size);
+                    buf.push(gc.data.width);
+                    buf.extend_from_slice(&gc.data.data[..gc.data.size as usize]);
+                }
+            }
+        }
+
+        free_((*gl).celldata);
+        (*gl).celldata = null_mut();
+        free_((*gl).extddata);
+        (*gl).extddata = null_mut();
+        (*gl).extdsize = 0;
+
+        (*gl).compressedsize = buf.len() as u32;
+        (*gl).compressed = xmalloc(buf.len()).as_ptr().cast();
+        std::ptr::copy_nonoverlapping(buf.as_ptr(), (*gl).compressed, buf.len());
+        (*gl).flags &= !grid_line_flag::EXTENDED;
+        (*gl).flags |= grid_line_flag::COMPRESSED;
+    }
+}
+
+/// Decode the cells in compressed line data, calling a function with the
+/// position of each cell up to limit.
+unsafe fn grid_decode_cells(data: &[u8], limit: u32, mut f: impl FnMut(u32, &grid_cell)) {
+    unsafe {
+        let mut off = 0;
+        grid_compress_get(data, &mut off);
+
+        let mut px = 0;
+        while off < data.len() && px < limit {
+            let mut gc: grid_cell = zeroed();
+            let n = grid_compress_get(data, &mut off);
+            gc.flags = grid_flag::from_bits_truncate(data[off]);
+            off += 1;
+            gc.attr = grid_attr::from_bits_truncate(grid_compress_get(data, &mut off) as u16);
+            gc.fg = grid_compress_get(data, &mut off) as i32;
+            gc.bg = grid_compress_get(data, &mut off) as i32;
+            gc.us = grid_compress_get(data, &mut off) as i32;
+            gc.link = grid_compress_get(data, &mut off);
+            let plain = data[off] != 0;
+            off += 1;
+
+            for _ in 0..n {
+                if px >= limit || off >= data.len() {
+                    break;
+                }
+                if plain {
+                    utf8_set(&raw mut gc.data, data[off]);
+                    off += 1;
+                } else {
+                    let size = (data[off] as usize).min(UTF8_SIZE);
+                    gc.data = zeroed();
+                    gc.data.size = size as u8;
+                    gc.data.have = size as u8;
+                    gc.data.width = data[off + 1];
+                    off += 2;
+                    gc.data.data[..size].copy_from_slice(&data[off..off + size]);
+                    off += size;
+                }
+                f(px, &gc);
+                px += 1;
+            }
+        }
+    }
+}
+
+/// Expand a compressed line back into cells.
+unsafe fn grid_uncompress_line(gd: *mut grid, gl: *mut grid_line) {
+    unsafe {
+        grid_spill_fetch(gd, gl);
+        if !(*gl).flags.intersects(grid_line_flag::COMPRESSED) {
+            return;
+        }
+        let data = std::slice::from_raw_parts((*gl).compressed, (*gl).compressedsize as usize);
+
+        (*gl).celldata = xcalloc_::<grid_cell_entry>((*gl).cellsize as usize).as_ptr();
+        (*gl).flags &= !(grid_line_flag::COMPRESSED | grid_line_flag::EXTENDED);
+
+        grid_decode_cells(data, (*gl).cellsize, |px, gc| {
+            let gce = (*gl).celldata.add(px as usize);
+            if grid_need_extended_cell(gce, gc) {
+                grid_extended_cell(gl, gce, gc);
+            } else {
+                grid_store_cell(gce, gc, gc.data.data[0]);
+                (*gce).flags |= gc.flags & grid_flag::CLEARED;
+            }
+        });
+
+        free_((*gl).compressed);
+        (*gl).compressed = null_mut();
+        (*gl).compressedsize = 0;
+        (*gd).expanded.push(gl.offset_from((*gd).linedata) as u32);
+    }
+}
+
+/// Call a function for each used cell of a line before limit. A compressed
+/// line is decoded without being expanded, and a line spilled to disk is read
+/// without being kept, so this is suitable for looking at every line in the
+/// history.
+pub unsafe fn grid_peek_cells(
+    gd: *mut grid,
+    py: u32,
+    limit: u32,
+    mut f: impl FnMut(u32, &grid_cell),
+) {
+    unsafe {
+        let gl = (*gd).linedata.add(py as usize);
+        let end = limit.min((*gl).cellused);
+
+        if !(*gl).flags.intersects(grid_line_flag::COMPRESSED) {
+            let mut gc: grid_cell = zeroed();
+            for x in 0..end {
+                grid_get_cell1(gl, x, &raw mut gc);
+                f(x, &gc);
+            }
+        } else if (*gl).flags.intersects(grid_line_flag::SPILLED) {
+            if let Some(data) = grid_spill_read(gd, gl) {
+                grid_decode_cells(&data, end, f);
+            }
+        } else {
+            let data = std::slice::from_raw_parts((*gl).compressed, (*gl).compressedsize as usize);
+            grid_decode_cells(data, end, f);
+        }
+    }
+}
+
+/// Append the text of the used cells of a line from px to px + nx, leaving
+/// out padding, without expanding the line.
+pub unsafe fn grid_peek_text(gd: *mut grid, px: u32, py: u32, nx: u32, text: &mut Vec<u8>) {
+    unsafe {
+        grid_peek_cells(gd, py, px + nx, |x, gc| {
+            if x >= px && !gc.flags.intersects(grid_flag::PADDING) {
+                text.extend_from_slice(&gc.data.data[..gc.data.size as usize]);
+            }
+        });
+    }
+}
+
+/// Compress the line which has just passed the limit and any lines further
+/// back which have been expanded since the history was last compressed.
+unsafe fn grid_compress_history(gd: *mut grid) {
+    unsafe {
+        if (*gd).hsize <= GRID_COMPRESS_LINES {
+            (*gd).expanded.clear();
+            return;
+        }
+        let end = (*gd).hsize - GRID_COMPRESS_LINES;
+        grid_compress_line((*gd).linedata.add(end as usize - 1));
+        for yy in std::mem::take(&mut (*gd).expanded) {
+            if yy < end {
+                grid_compress_line((*gd).linedata.add(yy as usize));
+            }
+        }
+    }
+}
+
+/// Count the compressed lines in a grid and the memory saved by compressing
+/// them.
+pub unsafe fn grid_compressed_size(gd: *mut grid) -> (u32, usize) {
+    unsafe {
+        let mut lines = 0;
+        let mut saved = 0;
+        for yy in 0..(*gd).hsize {
+            let gl = (*gd).linedata.add(yy as usize);
+            if !(*gl).flags.intersects(grid_line_flag::COMPRESSED)
+                || (*gl).flags.intersects(grid_line_flag::SPILLED)
+            {
+                continue;
+            }
+            let data = std::slice::from_raw_parts((*gl).compressed, (*gl).compressedsize as usize);
+            let size = grid_compress_get(data, &mut 0) as usize;
+            saved += size.saturating_sub(data.len());
+            lines += 1;
+        }
+        (lines, saved)
+    }
+}
+
+/// Memory used by the cells of a grid, without expanding compressed lines.
+pub unsafe fn grid_memory_size(gd: *mut grid) -> usize {
+    unsafe {
+        let mut size = 0;
+        for yy in 0..((*gd).hsize + (*gd).sy) {
+            let gl = (*gd).linedata.add(yy as usize);
+            if (*gl).flags.intersects(grid_line_flag::COMPRESSED) {
+                size += (*gl).compressedsize as usize;
+            } else {
+                size += grid_line_bytes(gl);
+            }
+        }
+        size
+    }
+}
+
 /// Get line data.
 pub unsafe fn grid_get_line(gd: *mut grid, line: c_uint) -> *mut grid_line {
+    unsafe {
+        let gl = (*gd).linedata.add(line as usize);
+        grid_uncompress_line(gd, gl);
+        gl
+    }
+}
+
+/// Get line data without expanding it if it is compressed, so only the line
+/// flags and sizes may be used.
+pub unsafe fn grid_get_line_compressed(gd: *mut grid, line: c_uint) -> *mut grid_line {
     unsafe { (*gd).linedata.add(line as usize) }
 }
 
@@ -290,6 +582,8 @@ unsafe fn grid_free_line(gd: *mut grid, py: c_uint) {
         (*(*gd).linedata.add(py as usize)).celldata = null_mut();
         free_((*(*gd).linedata.add(py as usize)).extddata);
         (*(*gd).linedata.add(py as usize)).extddata = null_mut();
+        free_((*(*gd).linedata.add(py as usize)).compressed);
+        (*(*gd).linedata.add(py as usize)).compressed = null_mut();
     }
 }
 
@@ -311,6 +605,10 @@ pub fn grid_create(sx: u32, sy: u32, hlimit: u32) -> *mut grid {
         hscrolled: 0,
         hsize: 0,
         hlimit,
+        expanded: Vec::new(),
+        spill: null_mut(),
+        spilled: null_mut(),
+        index: null_mut(),
         linedata: if sy != 0 {
             xcalloc_::<grid_line>(sy as usize).as_ptr()
         } else {
@@ -322,8 +620,12 @@ pub fn grid_create(sx: u32, sy: u32, hlimit: u32) -> *mut grid {
 /// Destroy grid.
 pub unsafe fn grid_destroy(gd: *mut grid) {
     unsafe {
+        grid_index_free(gd);
+        grid_spill_free(gd);
+        grid_spill_free_lines(gd);
         grid_free_lines(gd, 0, (*gd).hsize + (*gd).sy);
         free_((*gd).linedata);
+        drop(std::mem::take(&mut (*gd).expanded));
         free_(gd);
     }
 }
@@ -380,6 +682,11 @@ pub unsafe fn grid_compare(ga: *mut grid, gb: *mut grid) -> c_int {
 unsafe fn grid_trim_history(gd: *mut grid, ny: c_uint) {
     unsafe {
         grid_free_lines(gd, 0, ny);
+        grid_spill_remove_lines(gd, ny);
+        (*gd).expanded.retain(|&yy| yy >= ny);
+        for yy in &mut (*gd).expanded {
+            *yy -= ny;
+        }
         libc::memmove(
             (*gd).linedata as *mut c_void,
             (*gd).linedata.add(ny as usize) as *const c_void,
@@ -403,7 +710,9 @@ pub unsafe fn grid_collect_history(gd: *mut grid) {
             ny = (*gd).hsize;
         }
 
-        // Free the lines from 0 to ny then move the remaining lines over them.
+        // Write the lines to the spill file if there is one, then free the
+        // lines from 0 to ny and move the remaining lines over them.
+        grid_spill_add(gd, 0, ny);
         grid_trim_history(gd, ny);
 
         (*gd).hsize -= ny;
@@ -423,6 +732,7 @@ pub unsafe fn grid_remove_history(gd: *mut grid, ny: c_uint) {
             grid_free_line(gd, (*gd).hsize + (*gd).sy - 1 - yy);
         }
         (*gd).hsize -= ny;
+        grid_index_remove(gd, ny);
     }
 }
 
@@ -437,8 +747,14 @@ pub unsafe fn grid_scroll_history(gd: *mut grid, bg: c_uint) {
 
         (*gd).hscrolled += 1;
         grid_compact_line(&mut (*(*gd).linedata.add((*gd).hsize as usize)));
-        (*(*gd).linedata.add((*gd).hsize as usize)).time = CURRENT_TIME;
+        let gl = (*gd).linedata.add((*gd).hsize as usize);
+        if (*gl).time == 0 {
+            (*gl).time = CURRENT_TIME;
+        }
         (*gd).hsize += 1;
+        grid_index_add(gd, 1);
+
+        grid_compress_history(gd);
     }
 }
 
@@ -446,9 +762,11 @@ pub unsafe fn grid_scroll_history(gd: *mut grid, bg: c_uint) {
 pub unsafe fn grid_clear_history(gd: *mut grid) {
     unsafe {
         grid_trim_history(gd, (*gd).hsize);
+        grid_spill_clear(gd);
 
         (*gd).hscrolled = 0;
         (*gd).hsize = 0;
+        grid_index_reset(gd);
 
         (*gd).linedata = xreallocarray_((*gd).linedata, (*gd).sy as usize).as_ptr();
     }
@@ -478,7 +796,9 @@ pub unsafe fn grid_scroll_history_region(
 
         // Move line into history
         std::ptr::copy_nonoverlapping(gl_upper, gl_history, 1);
-        (*gl_history).time = CURRENT_TIME;
+        if (*gl_history).time == 0 {
+            (*gl_history).time = CURRENT_TIME;
+        }
 
         // Move region up and clear bottom line
         std::ptr::copy(gl_upper.add(1), gl_upper, (lower - upper) as usize);
@@ -487,6 +807,9 @@ pub unsafe fn grid_scroll_history_region(
         // Move history offset down
         (*gd).hscrolled += 1;
         (*gd).hsize += 1;
+        grid_index_add(gd, 1);
+
+        grid_compress_history(gd);
     }
 }
 
@@ -494,6 +817,7 @@ pub unsafe fn grid_scroll_history_region(
 unsafe fn grid_expand_line(gd: *mut grid, py: c_uint, mut sx: c_uint, bg: c_uint) {
     unsafe {
         let gl = (*gd).linedata.add(py as usize);
+        grid_uncompress_line(gd, gl);
         if sx <= (*gl).cellsize {
             return;
         }
@@ -531,7 +855,9 @@ pub unsafe fn grid_peek_line(gd: *mut grid, py: c_uint) -> *mut grid_line {
         if grid_check_y(gd, c!("grid_peek_line"), py) != 0 {
             return null_mut();
         }
-        (*gd).linedata.add(py as usize)
+        let gl = (*gd).linedata.add(py as usize);
+        grid_uncompress_line(gd, gl);
+        gl
     }
 }
 
@@ -580,7 +906,9 @@ pub unsafe fn grid_get_cell(gd: *mut grid, px: c_uint, py: c_uint, gc: *mut grid
         {
             std::ptr::copy(&raw const GRID_DEFAULT_CELL, gc, 1);
         } else {
-            grid_get_cell1((*gd).linedata.add(py as usize), px, gc);
+            let gl = (*gd).linedata.add(py as usize);
+            grid_uncompress_line(gd, gl);
+            grid_get_cell1(gl, px, gc);
         }
     }
 }
@@ -608,6 +936,16 @@ pub unsafe fn grid_set_cell(gd: *mut grid, px: c_uint, py: c_uint, gc: *const gr
     }
 }
 
+/// Mark a line as written now.
+pub unsafe fn grid_touch_line(gd: *mut grid, py: c_uint) {
+    unsafe {
+        if grid_check_y(gd, c!("grid_touch_line"), py) != 0 {
+            return;
+        }
+        (*(*gd).linedata.add(py as usize)).time = CURRENT_TIME;
+    }
+}
+
 /// Set padding at position.
 pub unsafe fn grid_set_padding(gd: *mut grid, px: c_uint, py: c_uint) {
     unsafe {
@@ -811,6 +1149,24 @@ pub unsafe fn grid_move_cells(
     }
 }
 
+/// SGR codes for each attribute. Codes above 10 are written as two
+/// subparameters, so 42 is 4:2.
+static GRID_STRING_ATTRS: [(grid_attr, c_uint); 13] = [
+    (grid_attr::GRID_ATTR_BRIGHT, 1),
+    (grid_attr::GRID_ATTR_DIM, 2),
+    (grid_attr::GRID_ATTR_ITALICS, 3),
+    (grid_attr::GRID_ATTR_UNDERSCORE, 4),
+    (grid_attr::GRID_ATTR_BLINK, 5),
+    (grid_attr::GRID_ATTR_REVERSE, 7),
+    (grid_attr::GRID_ATTR_HIDDEN, 8),
+    (grid_attr::GRID_ATTR_STRIKETHROUGH, 9),
+    (grid_attr::GRID_ATTR_UNDERSCORE_2, 42),
+    (grid_attr::GRID_ATTR_UNDERSCORE_3, 43),
+    (grid_attr::GRID_ATTR_UNDERSCORE_4, 44),
+    (grid_attr::GRID_ATTR_UNDERSCORE_5, 45),
+    (grid_attr::GRID_ATTR_OVERLINE, 53),
+];
+
 /// Get ANSI foreground sequence.
 unsafe fn grid_string_cells_fg(gc: *const grid_cell, values: *mut c_int) -> usize {
     unsafe {
@@ -929,6 +1285,35 @@ unsafe fn grid_string_cells_us(gc: *const grid_cell, values: *mut c_int) -> usiz
     }
 }
 
+/// Get the SGR parameters to set the attributes and colours of a cell after a
+/// reset, for example 0;1;38;5;100.
+pub unsafe fn grid_string_cells_sgr(gc: *const grid_cell) -> String {
+    unsafe {
+        let mut out = String::from("0");
+        for &(mask, code) in &GRID_STRING_ATTRS {
+            if (*gc).attr.intersects(mask) {
+                if code < 10 {
+                    out.push_str(&format!(";{code}"));
+                } else {
+                    out.push_str(&format!(";{}:{}", code / 10, code % 10));
+                }
+            }
+        }
+
+        let mut values: [c_int; 8] = [0; 8];
+        for get in [grid_string_cells_fg, grid_string_cells_bg, grid_string_cells_us] {
+            let n = get(gc, values.as_mut_ptr());
+            if n == 1 && (values[0] == 39 || values[0] == 49) {
+                continue; // default colour
+            }
+            for value in &values[..n] {
+                out.push_str(&format!(";{value}"));
+            }
+        }
+        out
+    }
+}
+
 /// Add on SGR code.
 unsafe fn grid_string_cells_add_code(
     buf: *mut u8,
@@ -1041,24 +1426,8 @@ unsafe fn grid_string_cells_code(
         let mut id: *const u8 = null();
         let mut has_link = false;
 
-        static ATTRS: [(grid_attr, c_uint); 13] = [
-            (grid_attr::GRID_ATTR_BRIGHT, 1),
-            (grid_attr::GRID_ATTR_DIM, 2),
-            (grid_attr::GRID_ATTR_ITALICS, 3),
-            (grid_attr::GRID_ATTR_UNDERSCORE, 4),
-            (grid_attr::GRID_ATTR_BLINK, 5),
-            (grid_attr::GRID_ATTR_REVERSE, 7),
-            (grid_attr::GRID_ATTR_HIDDEN, 8),
-            (grid_attr::GRID_ATTR_STRIKETHROUGH, 9),
-            (grid_attr::GRID_ATTR_UNDERSCORE_2, 42),
-            (grid_attr::GRID_ATTR_UNDERSCORE_3, 43),
-            (grid_attr::GRID_ATTR_UNDERSCORE_4, 44),
-            (grid_attr::GRID_ATTR_UNDERSCORE_5, 45),
-            (grid_attr::GRID_ATTR_OVERLINE, 53),
-        ];
-
         // If any attribute is removed, begin with 0
-        for &(mask, _) in &ATTRS {
+        for &(mask, _) in &GRID_STRING_ATTRS {
             if !attr.intersects(mask) && lastattr.intersects(mask)
                 || ((*lastgc).us != 8 && (*gc).us == 8)
             {
@@ -1070,7 +1439,7 @@ unsafe fn grid_string_cells_code(
         }
 
         // For each attribute that is newly set, add its code
-        for &(mask, code) in &ATTRS {
+        for &(mask, code) in &GRID_STRING_ATTRS {
             if attr.intersects(mask) && !lastattr.intersects(mask) {
                 s[n as usize] = code as c_int;
                 n += 1;
@@ -1314,9 +1683,10 @@ pub unsafe fn grid_duplicate_lines(
         for _ in 0..ny {
             let srcl = (*src).linedata.add(sy as usize);
             let dstl = (*dst).linedata.add(dy as usize);
+            grid_spill_fetch(src, srcl);
 
             std::ptr::copy_nonoverlapping(srcl, dstl, 1);
-            if (*srcl).cellsize != 0 {
+            if !(*srcl).celldata.is_null() {
                 (*dstl).celldata =
                     xreallocarray_::<grid_cell_entry>(null_mut(), (*srcl).cellsize as usize)
                         .as_ptr();
@@ -1341,6 +1711,14 @@ pub unsafe fn grid_duplicate_lines(
             } else {
                 (*dstl).extddata = null_mut();
             }
+            if (*srcl).compressedsize != 0 {
+                (*dstl).compressed = xmalloc((*srcl).compressedsize as usize).as_ptr().cast();
+                std::ptr::copy_nonoverlapping(
+                    (*srcl).compressed,
+                    (*dstl).compressed,
+                    (*srcl).compressedsize as usize,
+                );
+            }
 
             sy += 1;
             dy += 1;
@@ -1401,6 +1779,7 @@ unsafe fn grid_reflow_join(
 
         // Add a new target line
         let (to, gl) = if already == 0 {
+            grid_uncompress_line(gd, (*gd).linedata.add(yy as usize));
             let to = (*target).sy;
             let gl = grid_reflow_move(target, (*gd).linedata.add(yy as usize));
             (to, gl)
@@ -1435,6 +1814,7 @@ unsafe fn grid_reflow_join(
             }
 
             // Is destination line now full? Copy first char separately
+            grid_uncompress_line(gd, (*gd).linedata.add(line as usize));
             grid_get_cell1((*gd).linedata.add(line as usize), 0, &mut gc);
             if width + gc.data.width as u32 > sx {
                 break;
@@ -1483,8 +1863,7 @@ unsafe fn grid_reflow_join(
 
         // Remove lines that were completely consumed
         for i in (yy + 1)..(yy + 1 + lines) {
-            free((*(*gd).linedata.add(i as usize)).celldata.cast());
-            free((*(*gd).linedata.add(i as usize)).extddata.cast());
+            grid_free_line(gd, i);
             grid_reflow_dead((*gd).linedata.add(i as usize));
         }
 
@@ -1501,6 +1880,7 @@ unsafe fn grid_reflow_join(
 unsafe fn grid_reflow_split(target: *mut grid, gd: *mut grid, sx: u32, yy: u32, at: u32) {
     unsafe {
         let gl = (*gd).linedata.add(yy as usize);
+        grid_uncompress_line(gd, gl);
         let mut gc = zeroed();
         let used = (*gl).cellused;
         let flags = (*gl).flags;
@@ -1546,6 +1926,11 @@ unsafe fn grid_reflow_split(target: *mut grid, gd: *mut grid, sx: u32, yy: u32,
             (*(*target).linedata.add(line as usize)).flags |= grid_line_flag::WRAPPED;
         }
 
+        // New lines were written at the same time as the original line
+        for i in 1..lines {
+            (*first.add(i as usize)).time = (*gl).time;
+        }
+
         // Move remainder of original line
         (*gl).cellsize = at;
         (*gl).cellused = at;
@@ -1569,15 +1954,32 @@ unsafe fn grid_reflow_split(target: *mut grid, gd: *mut grid, sx: u32, yy: u32,
 /// Reflow lines on grid to new width
 pub unsafe fn grid_reflow(gd: *mut grid, sx: u32) {
     unsafe {
+        // Placeholder lines are found by their position, so read them before
+        // anything moves.
+        grid_spill_fetch_all(gd);
+
         // Create destination grid - just used as container for line data
         let target = grid_create((*gd).sx, 0, 0);
 
+        // If there is an index, keep the blooms of history lines which are
+        // moved unchanged. A line may lose cells to a join with the line
+        // before, so check its size has not changed.
+        let index = !(*gd).index.is_null();
+        let mut blooms = Vec::new();
+        let mut used = Vec::new();
+        if index {
+            for yy in 0..(*gd).hsize {
+                used.push((*(*gd).linedata.add(yy as usize)).cellused);
+            }
+        }
+
         // Loop over each source line
         for yy in 0..((*gd).hsize + (*gd).sy) {
             let gl = (*gd).linedata.add(yy as usize);
             if (*gl).flags.intersects(grid_line_flag::DEAD) {
                 continue;
             }
+            let first = (*target).sy;
 
             // Work out width of this line. at is point where available width is hit,
             // width is full line width
@@ -1585,6 +1987,13 @@ pub unsafe fn grid_reflow(gd: *mut grid, sx: u32) {
             let mut width = 0;
             let mut gc = zeroed();
 
+            // Compressed lines only need to be expanded if they are not going
+            // to be moved unchanged.
+            if (*gl).flags.intersects(grid_line_flag::COMPRESSED)
+                && ((*gl).cellused > sx || (*gl).flags.intersects(grid_line_flag::WRAPPED))
+            {
+                grid_uncompress_line(gd, gl);
+            }
             if !(*gl).flags.intersects(grid_line_flag::EXTENDED) {
                 width = (*gl).cellused;
                 if width > sx {
@@ -1602,23 +2011,31 @@ pub unsafe fn grid_reflow(gd: *mut grid, sx: u32) {
                 }
             }
 
-            // If line exactly right, move across unchanged
-            if width == sx {
+            let moved = if width == sx {
+                // If line exactly right, move across unchanged
                 grid_reflow_move(target, gl);
-                continue;
-            }
-
-            // If line too big, needs to be split
-            if width > sx {
+                true
+            } else if width > sx {
+                // If line too big, needs to be split
                 grid_reflow_split(target, gd, sx, yy, at);
-                continue;
-            }
-
-            // If line was previously wrapped, join as much as possible of next line
-            if (*gl).flags.intersects(grid_line_flag::WRAPPED) {
+                false
+            } else if (*gl).flags.intersects(grid_line_flag::WRAPPED) {
+                // If line was previously wrapped, join as much as possible of
+                // next line
                 grid_reflow_join(target, gd, sx, yy, width, 0);
+                false
             } else {
                 grid_reflow_move(target, gl);
+                true
+            };
+
+            if index {
+                let to = (*target).linedata.add(first as usize);
+                if moved && used.get(yy as usize) == Some(&(*to).cellused) {
+                    blooms.push(grid_index_row(gd, yy));
+                } else {
+                    blooms.resize(blooms.len() + ((*target).sy - first) as usize, None);
+                }
             }
         }
 
@@ -1632,7 +2049,15 @@ pub unsafe fn grid_reflow(gd: *mut grid, sx: u32) {
         }
         free((*gd).linedata.cast());
         (*gd).linedata = (*target).linedata;
+        drop(std::mem::take(&mut (*target).expanded));
         free(target.cast());
+        grid_index_reflow(gd, &blooms);
+
+        // Lines have moved, so compress the whole history.
+        (*gd).expanded.clear();
+        for yy in 0..(*gd).hsize.saturating_sub(GRID_COMPRESS_LINES) {
+            grid_compress_line((*gd).linedata.add(yy as usize));
+        }
     }
 }
 
@@ -1737,3 +2162,115 @@ pub unsafe fn grid_line_length(gd: *mut grid, py: u32) -> u32 {
         px
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_compress_line() {
+        unsafe {
+            let gd = grid_create(20, 1, 0);
+            let mut gc: grid_cell = GRID_DEFAULT_CELL;
+
+            gc.fg = 1;
+            grid_set_cells(gd, 0, 0, &gc, b"hello".as_ptr(), 5);
+            gc.fg = COLOUR_FLAG_RGB | 0x102030;
+            gc.attr = grid_attr::GRID_ATTR_BRIGHT | grid_attr::GRID_ATTR_UNDERSCORE_2;
+            utf8_set(&mut gc.data, b'x');
+            grid_set_cell(gd, 5, 0, &gc);
+            gc = GRID_DEFAULT_CELL;
+            gc.data = utf8_data::new(*"\u{4e2d}".as_bytes().first_chunk::<3>().unwrap(), 3, 3, 2);
+            grid_set_cell(gd, 6, 0, &gc);
+            grid_set_padding(gd, 7, 0);
+            grid_clear(gd, 10, 0, 5, 1, 4);
+
+            let gl = (*gd).linedata;
+            let mut before = Vec::new();
+            for px in 0..(*gl).cellsize {
+                let mut gc: grid_cell = zeroed();
+                grid_get_cell(gd, px, 0, &mut gc);
+                before.push(gc);
+            }
+
+            grid_compress_line(gl);
+            assert!((*gl).flags.intersects(grid_line_flag::COMPRESSED));
+            assert!((*gl).celldata.is_null());
+
+            for (px, gc1) in before.iter().enumerate() {
+                let mut gc2: grid_cell = zeroed();
+                grid_get_cell(gd, px as u32, 0, &mut gc2);
+                assert!(grid_cells_equal(gc1, &gc2), "cell {px}");
+            }
+            assert!(!(*gl).flags.intersects(grid_line_flag::COMPRESSED));
+            assert!((*gl).flags.intersects(grid_line_flag::EXTENDED));
+            assert_eq!((*gd).expanded, [0]);
+
+            grid_destroy(gd);
+        }
+    }
+
+    #[test]
+    fn test_grid_compress_history() {
+        unsafe {
+            let gd = grid_create(10, 1, 2000);
+            let gc: grid_cell = GRID_DEFAULT_CELL;
+            let compressed = move |yy| {
+                let gl = grid_get_line_compressed(gd, yy);
+                (*gl).flags.intersects(grid_line_flag::COMPRESSED)
+            };
+
+            for _ in 0..GRID_COMPRESS_LINES + 10 {
+                grid_set_cells(gd, 0, (*gd).hsize, &gc, b"line".as_ptr(), 4);
+                grid_scroll_history(gd, 8);
+            }
+            assert!((0..10).all(compressed));
+            assert!(!compressed(10));
+
+            // Expanded lines are compressed again by the next scroll, and
+            // follow the lines as the oldest are collected.
+            grid_peek_line(gd, 3);
+            grid_peek_line(gd, 7);
+            assert_eq!((*gd).expanded, [3, 7]);
+            grid_trim_history(gd, 2);
+            (*gd).hsize -= 2;
+            assert_eq!((*gd).expanded, [1, 5]);
+            grid_peek_line(gd, 0);
+            grid_scroll_history(gd, 8);
+            assert!((*gd).expanded.is_empty());
+            assert!((0..9).all(compressed));
+            assert!(!compressed(9));
+
+            grid_destroy(gd);
+        }
+    }
+
+    #[test]
+    fn test_grid_peek_text() {
+        unsafe {
+            let gd = grid_create(20, 1, 0);
+            let mut gc: grid_cell = GRID_DEFAULT_CELL;
+
+            grid_set_cells(gd, 0, 0, &gc, b"ab".as_ptr(), 2);
+            gc.data = utf8_data::new(*"\u{4e2d}".as_bytes().first_chunk::<3>().unwrap(), 3, 3, 2);
+            grid_set_cell(gd, 2, 0, &gc);
+            grid_set_padding(gd, 3, 0);
+            gc.fg = 1;
+            grid_set_cells(gd, 4, 0, &gc, b"cd".as_ptr(), 2);
+
+            let gl = (*gd).linedata;
+            let mut text = Vec::new();
+            grid_peek_text(gd, 0, 0, 20, &mut text);
+            assert_eq!(text, "ab\u{4e2d}cd".as_bytes());
+
+            grid_compress_line(gl);
+            text.clear();
+            grid_peek_text(gd, 1, 0, 4, &mut text);
+            assert_eq!(text, "b\u{4e2d}c".as_bytes());
+            assert!((*gl).flags.intersects(grid_line_flag::COMPRESSED));
+            assert!((*gd).expanded.is_empty());
+
+            grid_destroy(gd);
+        }
+    }
+}
@@ -0,0 +1,612 @@
+// Index of the lines in a grid's history, for searching.
+//
+// Each line that enters the history gets an absolute number and a small bloom
+// filter of the trigrams in its text, lowercased and without whitespace. A
+// search for a string or a regular expression with a literal part makes a
+// mask of the trigrams it needs; any line whose filter does not have all the
+// bits of the mask cannot match and can be skipped. Lines are added to the
+// index from a timer rather than as they are written, and any line not in the
+// index must always be searched, so the index never makes a search miss a
+// match. Lines are read without expanding them if they are compressed.
+//
+// When the grid is reflowed, lines which are moved unchanged keep their bloom
+// and the others are made again from the timer; lines removed from the bottom
+// of the history are dropped. Either changes the line numbers, so copies of
+// the history made before can no longer use the index. Clearing the history
+// resets the index.
+
+use std::collections::VecDeque;
+
+use crate::*;
+
+/// Trigrams in a line of text.
+pub type grid_index_bloom = [u64; 4];
+
+/// Bloom for lines not in the index, which might match any search.
+const GRID_INDEX_ALL: grid_index_bloom = [u64::MAX; 4];
+
+/// Number of lines added to the index at once.
+const GRID_INDEX_BATCH: u64 = 1000;
+
+/// Index of a grid's history.
+pub struct grid_index {
+    gd: *mut grid,
+
+    /// changed when the index is reset so that old line numbers are no longer
+    /// valid
+    generation: u64,
+
+    /// number of lines that have entered the history, including those which
+    /// have since been dropped; this is the absolute number of the next line
+    added: u64,
+
+    /// absolute number of the line in blooms[0]
+    first: u64,
+    blooms: VecDeque<grid_index_bloom>,
+
+    /// absolute numbers of lines in blooms which must be made again, in
+    /// order; until they are, their blooms match anything
+    stale: VecDeque<u64>,
+
+    timer: event,
+}
+
+/// A copy of a grid's history, for looking up lines in the index.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct grid_index_view {
+    generation: u64,
+    first: u64,
+    rows: u32,
+}
+
+/// Create the index for a grid.
+pub unsafe fn grid_index_create(gd: *mut grid) {
+    unsafe {
+        if !(*gd).index.is_null() {
+            return;
+        }
+        let gi = Box::into_raw(Box::new(grid_index {
+            gd,
+            generation: 0,
+            added: 0,
+            first: 0,
+            blooms: VecDeque::new(),
+            stale: VecDeque::new(),
+            timer: zeroed(),
+        }));
+        evtimer_set(
+            &raw mut (*gi).timer,
+            grid_index_timer,
+            NonNull::new_unchecked(gi),
+        );
+        (*gd).index = gi;
+        grid_index_reset(gd);
+    }
+}
+
+/// Free a grid's index.
+pub unsafe fn grid_index_free(gd: *mut grid) {
+    unsafe {
+        if (*gd).index.is_null() {
+            return;
+        }
+        let gi = Box::from_raw((*gd).index);
+        (*gd).index = null_mut();
+        evtimer_del(&raw const gi.timer as *mut event);
+    }
+}
+
+/// Throw away the index and start again with the current history.
+pub unsafe fn grid_index_reset(gd: *mut grid) {
+    unsafe {
+        let gi = (*gd).index;
+        if gi.is_null() {
+            return;
+        }
+        (*gi).generation += 1;
+        (*gi).added = grid_spill_size(gd) as u64 + (*gd).hsize as u64;
+        (*gi).first = 0;
+        (*gi).blooms.clear();
+        (*gi).stale.clear();
+        grid_index_start(gi);
+    }
+}
+
+/// Note lines removed from the bottom of the history.
+pub unsafe fn grid_index_remove(gd: *mut grid, ny: u32) {
+    unsafe {
+        let gi = (*gd).index;
+        if gi.is_null() {
+            return;
+        }
+        (*gi).generation += 1;
+        (*gi).added = (*gi).added.saturating_sub(ny as u64);
+
+        let keep = (*gi).added.saturating_sub((*gi).first) as usize;
+        (*gi).blooms.truncate(keep);
+        while (*gi).stale.back().is_some_and(|&abs| abs >= (*gi).added) {
+            (*gi).stale.pop_back();
+        }
+    }
+}
+
+/// Get the bloom for a line of a grid's history before it is reflowed, or
+/// None if it is not in the index.
+pub unsafe fn grid_index_row(gd: *mut grid, py: u32) -> Option<grid_index_bloom> {
+    unsafe {
+        let gi = (*gd).index;
+        if gi.is_null() || py >= (*gd).hsize {
+            return None;
+        }
+        let abs = (*gi).added - (*gd).hsize as u64 + py as u64;
+        if abs < (*gi).first || (*gi).stale.binary_search(&abs).is_ok() {
+            return None;
+        }
+        (*gi).blooms.get((abs - (*gi).first) as usize).copied()
+    }
+}
+
+/// Replace the index after a grid is reflowed. The blooms are those for each
+/// line of the new history which was moved unchanged; other lines are made
+/// again from the timer.
+pub unsafe fn grid_index_reflow(gd: *mut grid, blooms: &[Option<grid_index_bloom>]) {
+    unsafe {
+        let gi = (*gd).index;
+        if gi.is_null() {
+            return;
+        }
+        let spilled = grid_spill_size(gd) as u64;
+
+        (*gi).generation += 1;
+        (*gi).added = spilled + (*gd).hsize as u64;
+        (*gi).first = 0;
+        (*gi).blooms.clear();
+        (*gi).stale.clear();
+
+        (*gi).blooms.resize(spilled as usize, GRID_INDEX_ALL);
+        for py in 0..(*gd).hsize {
+            match blooms.get(py as usize).copied().flatten() {
+                Some(bloom) => (*gi).blooms.push_back(bloom),
+                None => {
+                    (*gi).blooms.push_back(GRID_INDEX_ALL);
+                    (*gi).stale.push_back(spilled + py as u64);
+                }
+            }
+        }
+        if !(*gi).stale.is_empty() {
+            grid_index_start(gi);
+        }
+    }
+}
+
+/// Note lines added to the bottom of the history.
+pub unsafe fn grid_index_add(gd: *mut grid, ny: u32) {
+    unsafe {
+        let gi = (*gd).index;
+        if gi.is_null() {
+            return;
+        }
+        (*gi).added += ny as u64;
+        grid_index_start(gi);
+    }
+}
+
+/// Start the timer to add lines to the index.
+unsafe fn grid_index_start(gi: *mut grid_index) {
+    unsafe {
+        if evtimer_pending(&raw mut (*gi).timer, null_mut()) == 0 {
+            let tv = timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            };
+            evtimer_add(&raw mut (*gi).timer, &raw const tv);
+        }
+    }
+}
+
+/// Timer to add lines to the index.
+unsafe extern "C-unwind" fn grid_index_timer(_fd: i32, _events: i16, gi: NonNull<grid_index>) {
+    unsafe {
+        let gi = gi.as_ptr();
+        if grid_index_update((*gi).gd, GRID_INDEX_BATCH) {
+            grid_index_start(gi);
+        }
+    }
+}
+
+/// Drop lines that have left the history and make up to limit blooms, first
+/// for stale lines and then for lines that are not yet indexed. Returns true
+/// if there are lines left to do.
+pub unsafe fn grid_index_update(gd: *mut grid, limit: u64) -> bool {
+    unsafe {
+        let gi = (*gd).index;
+        if gi.is_null() {
+            return false;
+        }
+        let spilled = grid_spill_size(gd) as u64;
+        let available = spilled + (*gd).hsize as u64;
+        if available > (*gi).added {
+            grid_index_reset(gd);
+            return true;
+        }
+        let oldest = (*gi).added - available;
+
+        if (*gi).first < oldest {
+            let n = ((oldest - (*gi).first) as usize).min((*gi).blooms.len());
+            (*gi).blooms.drain(..n);
+            (*gi).first = oldest;
+        }
+
+        let bloom = |abs: u64| {
+            let row = abs - oldest;
+            if row < spilled {
+                GRID_INDEX_ALL
+            } else {
+                grid_index_line(gd, (row - spilled) as u32)
+            }
+        };
+
+        let mut done = 0;
+        while done < limit
+            && let Some(abs) = (*gi).stale.pop_front()
+        {
+            if abs >= (*gi).first
+                && let Some(b) = (*gi).blooms.get_mut((abs - (*gi).first) as usize)
+            {
+                *b = bloom(abs);
+                done += 1;
+            }
+        }
+
+        let mut next = (*gi).first + (*gi).blooms.len() as u64;
+        let end = (*gi).added.min(next.saturating_add(limit - done));
+        while next < end {
+            (*gi).blooms.push_back(bloom(next));
+            next += 1;
+        }
+        next < (*gi).added || !(*gi).stale.is_empty()
+    }
+}
+
+/// Add a trigram to a bloom.
+fn grid_index_set(bloom: &mut grid_index_bloom, trigram: &[u8]) {
+    let h = (trigram[0] as u32 | (trigram[1] as u32) << 8 | (trigram[2] as u32) << 16)
+        .wrapping_mul(0x9e37_79b1);
+    for bit in [h >> 24, (h >> 16) & 0xff] {
+        bloom[(bit / 64) as usize] |= 1 << (bit % 64);
+    }
+}
+
+/// Add the trigrams in some text to a bloom. Only ASCII is lowercased, so
+/// for a search ignoring case any trigrams with other characters are left
+/// out.
+fn grid_index_text(bloom: &mut grid_index_bloom, text: &[u8], icase: bool) {
+    for word in text.split(u8::is_ascii_whitespace) {
+        for trigram in word.windows(3) {
+            if icase && !trigram.is_ascii() {
+                continue;
+            }
+            let lower = trigram.to_ascii_lowercase();
+            grid_index_set(bloom, &lower);
+        }
+    }
+}
+
+/// Make the bloom for a line of history. If the line above was wrapped, the
+/// trigrams across the join are included.
+unsafe fn grid_index_line(gd: *mut grid, py: u32) -> grid_index_bloom {
+    unsafe {
+        let mut text = Vec::new();
+
+        if py == 0 {
+            if grid_spill_size(gd) != 0 {
+                return GRID_INDEX_ALL;
+            }
+        } else {
+            let gl = grid_get_line_compressed(gd, py - 1);
+            if (*gl).flags.intersects(grid_line_flag::WRAPPED) {
+                let used = (*gl).cellused;
+                let px = used.saturating_sub(2);
+                grid_peek_text(gd, px, py - 1, used - px, &mut text);
+            }
+        }
+        let used = (*grid_get_line_compressed(gd, py)).cellused;
+        grid_peek_text(gd, 0, py, used, &mut text);
+
+        let mut bloom = [0; 4];
+        grid_index_text(&mut bloom, &text, false);
+        bloom
+    }
+}
+
+/// Make the mask for a search for some text, or None if it is too short to
+/// use the index.
+pub fn grid_index_mask(text: &[u8], icase: bool) -> Option<grid_index_bloom> {
+    let mut mask = [0; 4];
+    grid_index_text(&mut mask, text, icase);
+    if mask == [0; 4] {
+        return None;
+    }
+    Some(mask)
+}
+
+/// Make the mask for a search for a regular expression from the literal text
+/// any match must contain, or None if there is none. This understands both the
+/// built in syntax and POSIX extended syntax.
+pub fn grid_index_regex_mask(pattern: &[u8], icase: bool) -> Option<grid_index_bloom> {
+    let mut literals: Vec<Vec<u8>> = Vec::new();
+    let mut run: Vec<u8> = Vec::new();
+    let mut depth = 0;
+
+    // Inline flags like (?i) may change how the rest of the pattern matches.
+    if pattern.contains(&b'|') || pattern.windows(2).any(|w| w == b"(?") {
+        return None;
+    }
+
+    let mut i = 0;
+    while i < pattern.len() {
+        let ch = pattern[i];
+        i += 1;
+        match ch {
+            b'?' | b'*' | b'{' => {
+                // The character before is optional.
+                run.pop();
+                if ch == b'{' {
+                    while i < pattern.len() && pattern[i] != b'}' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'[' => {
+                if i < pattern.len() && pattern[i] == b'^' {
+                    i += 1;
+                }
+                if i < pattern.len() && pattern[i] == b']' {
+                    i += 1;
+                }
+                while i < pattern.len() && pattern[i] != b']' {
+                    if pattern[i] == b'\\' {
+                        i += 2;
+                        continue;
+                    }
+                    // Skip classes like [:digit:] inside the brackets.
+                    if pattern[i] == b'['
+                        && let Some(&term @ (b':' | b'.' | b'=')) = pattern.get(i + 1)
+                    {
+                        i += 2;
+                        while i + 1 < pattern.len()
+                            && !(pattern[i] == term && pattern[i + 1] == b']')
+                        {
+                            i += 1;
+                        }
+                        i += 2;
+                        continue;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'\\' => {
+                // Skip the arguments of escapes like \x41, \u{263a} or \pL.
+                let ch = pattern.get(i).copied();
+                i += 1;
+                if matches!(ch, Some(b'x' | b'u' | b'U' | b'p' | b'P')) {
+                    if pattern.get(i) == Some(&b'{') {
+                        while i < pattern.len() && pattern[i] != b'}' {
+                            i += 1;
+                        }
+                        i += 1;
+                    } else if matches!(ch, Some(b'p' | b'P')) {
+                        i += 1;
+                    } else {
+                        while i < pattern.len() && pattern[i].is_ascii_hexdigit() {
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            b'.' | b'^' | b'$' | b'+' => {}
+            _ => {
+                if depth == 0 {
+                    run.push(ch);
+                }
+                continue;
+            }
+        }
+        literals.push(std::mem::take(&mut run));
+    }
+    literals.push(run);
+
+    let mut mask = [0; 4];
+    for literal in literals {
+        grid_index_text(&mut mask, &literal, icase);
+    }
+    if mask == [0; 4] {
+        return None;
+    }
+    Some(mask)
+}
+
+/// Get a view of the current history of a grid. The first rows lines of a
+/// copy of the history, including any lines spilled to disk, can then be
+/// looked up in the index until it is reset.
+pub unsafe fn grid_index_view(gd: *mut grid) -> grid_index_view {
+    unsafe {
+        let gi = (*gd).index;
+        if gi.is_null() {
+            return grid_index_view::default();
+        }
+        let rows = grid_spill_size(gd) + (*gd).hsize;
+        grid_index_view {
+            generation: (*gi).generation,
+            first: (*gi).added.saturating_sub(rows as u64),
+            rows,
+        }
+    }
+}
+
+/// Get the bloom for a row of a copy of the history, or None if it is not in
+/// the index.
+pub unsafe fn grid_index_get(
+    gd: *mut grid,
+    view: &grid_index_view,
+    row: u32,
+) -> Option<grid_index_bloom> {
+    unsafe {
+        let gi = (*gd).index;
+        if gi.is_null() || (*gi).generation != view.generation || row >= view.rows {
+            return None;
+        }
+        let abs = view.first + row as u64;
+        if abs < (*gi).first {
+            return None;
+        }
+        (*gi).blooms.get((abs - (*gi).first) as usize).copied()
+    }
+}
+
+/// Find the row a row of a copy of the history is now, as the history has
+/// scrolled since, or None if it has left the history or the index has been
+/// reset.
+pub unsafe fn grid_index_find(gd: *mut grid, view: &grid_index_view, row: u32) -> Option<u32> {
+    unsafe {
+        let gi = (*gd).index;
+        if gi.is_null() || (*gi).generation != view.generation {
+            return None;
+        }
+        let rows = grid_spill_size(gd) as u64 + (*gd).hsize as u64;
+        let first = (*gi).added.saturating_sub(rows);
+        u32::try_from((view.first + row as u64).checked_sub(first)?).ok()
+    }
+}
+
+/// Check whether a match for a mask could start on a row of a copy of the
+/// history. Matches may continue onto following wrapped lines, so all their
+/// trigrams are used.
+pub unsafe fn grid_index_check(
+    gd: *mut grid,
+    view: &grid_index_view,
+    copy: *mut grid,
+    row: u32,
+    mask: &grid_index_bloom,
+) -> bool {
+    unsafe {
+        let mut bloom = [0; 4];
+        let mut py = row;
+        loop {
+            let Some(line) = grid_index_get(gd, view, py) else {
+                return true;
+            };
+            for (b, l) in bloom.iter_mut().zip(line) {
+                *b |= l;
+            }
+            if bloom.iter().zip(mask).all(|(b, m)| b & m == *m) {
+                return true;
+            }
+            if py + 1 >= (*copy).hsize + (*copy).sy
+                || !(*grid_get_line_compressed(copy, py))
+                    .flags
+                    .intersects(grid_line_flag::WRAPPED)
+            {
+                return false;
+            }
+            py += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(mask: Option<grid_index_bloom>, text: &str) -> bool {
+        let Some(mask) = mask else {
+            return true;
+        };
+        let mut bloom = [0; 4];
+        grid_index_text(&mut bloom, text.as_bytes(), false);
+        bloom.iter().zip(&mask).all(|(b, m)| b & m == *m)
+    }
+
+    #[test]
+    fn test_grid_index_mask() {
+        assert!(grid_index_mask(b"ab", false).is_none());
+        assert!(grid_index_mask(b"a b c", false).is_none());
+        assert!(matches(grid_index_mask(b"Hello", false), "say hello world"));
+        assert!(matches(grid_index_mask(b"lo wor", false), "say hello world"));
+        assert!(!matches(grid_index_mask(b"goodbye", false), "say hello world"));
+    }
+
+    #[test]
+    fn test_grid_index_regex_mask() {
+        assert!(grid_index_regex_mask(b"foo|bar", false).is_none());
+        assert!(grid_index_regex_mask(b"a.b.c", false).is_none());
+        assert!(grid_index_regex_mask(b"(hello)?", false).is_none());
+        assert!(matches(grid_index_regex_mask(b"hel+o", false), "helllo"));
+        assert!(matches(grid_index_regex_mask(b"colou?r", false), "color"));
+        assert!(matches(grid_index_regex_mask(b"^error: [0-9]+ files", false), "error: 12 files"));
+        assert!(matches(grid_index_regex_mask(b"ab{2}cde", false), "abbcde"));
+        assert!(!matches(grid_index_regex_mask(b"error.*failed", false), "warning: failed"));
+        assert!(matches(grid_index_regex_mask(b"[[:digit:]]abc", false), "5abc"));
+        assert!(grid_index_regex_mask(b"(?i)hello", false).is_none());
+        assert!(matches(grid_index_regex_mask(b"\\x41BCDEF", false), "ABCDEF"));
+        assert!(matches(grid_index_regex_mask(b"\\u{263a}smile", false), "\u{263a}smile"));
+        assert!(matches(grid_index_regex_mask(b"\\pLabcd", false), "xabcd"));
+        assert!(matches(grid_index_regex_mask(b"\\p{Greek}abcd", false), "\u{3b1}abcd"));
+        assert!(matches(grid_index_regex_mask(b"[\\]x]abcd", false), "]abcd"));
+        assert!(matches(grid_index_regex_mask(b"\\bword\\b", false), "a word here"));
+        assert!(matches(grid_index_mask("ÉCOLE".as_bytes(), true), "école"));
+    }
+
+    #[test]
+    fn test_grid_index_reflow() {
+        unsafe {
+            event_init();
+            let gd = grid_create(20, 2, 100);
+            grid_index_create(gd);
+
+            for text in ["first line", "abcdefghijklmnopqrst", "third line", "last line"] {
+                let py = (*gd).hsize;
+                grid_set_cells(gd, 0, py, &GRID_DEFAULT_CELL, text.as_bytes().as_ptr(), text.len());
+                grid_scroll_history(gd, 8);
+            }
+            while grid_index_update(gd, u64::MAX) {}
+            for py in 0..(*gd).hsize {
+                assert_eq!(grid_index_row(gd, py), Some(grid_index_line(gd, py)));
+            }
+
+            // The long line is split in two and must be indexed again.
+            grid_reflow(gd, 12);
+            assert_eq!((*gd).hsize, 5);
+            for py in 0..(*gd).hsize {
+                let expected = (py != 1 && py != 2).then(|| grid_index_line(gd, py));
+                assert_eq!(grid_index_row(gd, py), expected, "row {py}");
+            }
+            while grid_index_update(gd, u64::MAX) {}
+            for py in 0..(*gd).hsize {
+                assert_eq!(grid_index_row(gd, py), Some(grid_index_line(gd, py)));
+            }
+
+            grid_remove_history(gd, 2);
+            assert!(!grid_index_update(gd, u64::MAX));
+            for py in 0..(*gd).hsize {
+                assert_eq!(grid_index_row(gd, py), Some(grid_index_line(gd, py)));
+            }
+
+            // Rows of a copy of the history move up as lines are dropped from
+            // the top and are lost when the grid is reflowed.
+            let view = grid_index_view(gd);
+            (*gd).hlimit = (*gd).hsize;
+            grid_collect_history(gd);
+            assert_eq!(grid_index_find(gd, &view, 0), None);
+            assert_eq!(grid_index_find(gd, &view, 2), Some(1));
+            grid_reflow(gd, 20);
+            assert_eq!(grid_index_find(gd, &view, 2), None);
+
+            grid_destroy(gd);
+        }
+    }
+}
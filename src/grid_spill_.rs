@@ -0,0 +1,428 @@
+// History spilled to disk.
+//
+// When a grid with a spill file collects its history, the oldest lines are
+// compressed and their data appended to a private file in the socket
+// directory rather than being freed. The file is unlinked as soon as it is
+// created. The flags, sizes and time of each line are kept in memory, so a
+// copy of the history can be made without reading the file: each spilled
+// line becomes a placeholder line which is read from the file only when its
+// cells are needed.
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::FromRawFd;
+use std::rc::Rc;
+
+use crate::*;
+
+/// Size of the chunks copied when compacting the spill file.
+const GRID_SPILL_COPY_SIZE: usize = 64 * 1024;
+
+/// A line in the spill file.
+#[derive(Copy, Clone)]
+struct grid_spill_line {
+    offset: u64,
+    size: u32,
+
+    flags: grid_line_flag,
+    cellsize: u32,
+    cellused: u32,
+    time: time_t,
+    exit_status: i32,
+}
+
+/// Lines spilled from a grid's history.
+pub struct grid_spill {
+    /// maximum number of lines to keep
+    limit: u32,
+
+    file: Option<Rc<File>>,
+    size: u64,
+
+    /// spilled lines, oldest first; lines before first have been dropped
+    /// because of the limit
+    lines: Vec<grid_spill_line>,
+    first: usize,
+}
+
+/// Placeholder lines in a grid which have not yet been read from a spill
+/// file. The file is shared with the grid it was spilled from, so it stays
+/// open even if that grid starts a new file.
+pub struct grid_spill_lines {
+    file: Rc<File>,
+
+    /// first line in the grid which is a placeholder
+    start: u32,
+
+    /// offset and size of each line in the file
+    lines: Vec<(u64, u32)>,
+}
+
+/// Set the spill limit for a grid, creating or freeing the spill as needed.
+pub unsafe fn grid_spill_set(gd: *mut grid, limit: u32) {
+    unsafe {
+        if limit == 0 {
+            grid_spill_free(gd);
+            return;
+        }
+        if (*gd).spill.is_null() {
+            (*gd).spill = Box::into_raw(Box::new(grid_spill {
+                limit,
+                file: None,
+                size: 0,
+                lines: Vec::new(),
+                first: 0,
+            }));
+        } else {
+            (*(*gd).spill).limit = limit;
+        }
+    }
+}
+
+/// Free a grid's spill.
+pub unsafe fn grid_spill_free(gd: *mut grid) {
+    unsafe {
+        if (*gd).spill.is_null() {
+            return;
+        }
+        drop(Box::from_raw((*gd).spill));
+        (*gd).spill = null_mut();
+    }
+}
+
+/// Create a new spill file. It is unlinked straight away so it is removed
+/// when the last grid using it is freed.
+unsafe fn grid_spill_create() -> Option<Rc<File>> {
+    unsafe {
+        let template = format!("{}-history-XXXXXX\0", _s(SOCKET_PATH));
+        let mut template = template.into_bytes();
+        let fd = libc::mkstemp(template.as_mut_ptr().cast());
+        if fd == -1 {
+            log_debug!("couldn't create history file: {}", strerror(errno!()));
+            return None;
+        }
+        libc::unlink(template.as_ptr().cast());
+        Some(Rc::new(File::from_raw_fd(fd)))
+    }
+}
+
+/// Number of lines spilled from a grid.
+pub unsafe fn grid_spill_size(gd: *mut grid) -> u32 {
+    unsafe {
+        if (*gd).spill.is_null() {
+            return 0;
+        }
+        let gsp = &*(*gd).spill;
+        (gsp.lines.len() - gsp.first) as u32
+    }
+}
+
+/// Forget all lines spilled from a grid. Any copies keep the old file.
+pub unsafe fn grid_spill_clear(gd: *mut grid) {
+    unsafe {
+        if (*gd).spill.is_null() {
+            return;
+        }
+        let gsp = &mut *(*gd).spill;
+        gsp.file = None;
+        gsp.size = 0;
+        gsp.lines.clear();
+        gsp.first = 0;
+    }
+}
+
+/// Drop the oldest lines over the limit. Once more than half the file is
+/// dropped lines, copy the remaining lines into a new file. A new file is
+/// used rather than moving the lines to the start of this one because copies
+/// may still have placeholders for lines in it.
+unsafe fn grid_spill_trim(gsp: &mut grid_spill) {
+    unsafe {
+        let lines = gsp.lines.len() - gsp.first;
+        if lines > gsp.limit as usize {
+            gsp.first += lines - gsp.limit as usize;
+        }
+        if gsp.first == 0 || gsp.first < gsp.lines.len() - gsp.first {
+            return;
+        }
+        let Some(file) = &gsp.file else {
+            return;
+        };
+        let Some(new) = grid_spill_create() else {
+            return;
+        };
+
+        let start = gsp.lines.get(gsp.first).map_or(gsp.size, |gsl| gsl.offset);
+        let mut buf = vec![0u8; GRID_SPILL_COPY_SIZE];
+        let mut off = start;
+        while off < gsp.size {
+            let n = ((gsp.size - off) as usize).min(buf.len());
+            if file.read_exact_at(&mut buf[..n], off).is_err()
+                || new.write_all_at(&buf[..n], off - start).is_err()
+            {
+                log_debug!("couldn't compact history file");
+                return;
+            }
+            off += n as u64;
+        }
+
+        gsp.file = Some(new);
+        gsp.lines.drain(..gsp.first);
+        for gsl in &mut gsp.lines {
+            gsl.offset -= start;
+        }
+        gsp.first = 0;
+        gsp.size -= start;
+    }
+}
+
+/// Append lines from the top of the history to the spill file. The lines are
+/// compressed but not freed.
+pub unsafe fn grid_spill_add(gd: *mut grid, py: u32, ny: u32) {
+    unsafe {
+        if (*gd).spill.is_null() || ny == 0 {
+            return;
+        }
+        let gsp = &mut *(*gd).spill;
+        if gsp.file.is_none() {
+            gsp.file = grid_spill_create();
+        }
+        let Some(file) = &gsp.file else {
+            return;
+        };
+
+        let mut buf: Vec<u8> = Vec::new();
+        let mut lines = Vec::with_capacity(ny as usize);
+        for yy in py..(py + ny) {
+            let gl = grid_get_line_compressed(gd, yy);
+            if (*gl).flags.intersects(grid_line_flag::DEAD) {
+                continue;
+            }
+            grid_compress_line(gl);
+            lines.push(grid_spill_line {
+                offset: gsp.size + buf.len() as u64,
+                size: (*gl).compressedsize,
+                flags: (*gl).flags & !grid_line_flag::COMPRESSED,
+                cellsize: (*gl).cellsize,
+                cellused: (*gl).cellused,
+                time: (*gl).time,
+                exit_status: (*gl).exit_status,
+            });
+            if (*gl).compressedsize != 0 {
+                buf.extend_from_slice(std::slice::from_raw_parts(
+                    (*gl).compressed,
+                    (*gl).compressedsize as usize,
+                ));
+            }
+        }
+
+        if let Err(e) = file.write_all_at(&buf, gsp.size) {
+            log_debug!("couldn't write history file: {e}");
+            return;
+        }
+        gsp.size += buf.len() as u64;
+        gsp.lines.extend(lines);
+        grid_spill_trim(gsp);
+    }
+}
+
+/// Put placeholders for the lines spilled from a grid into another grid,
+/// starting at line dy, which must have room for them and must not already
+/// have placeholders. Returns the number of lines added.
+pub unsafe fn grid_spill_load(gd: *mut grid, dst: *mut grid, dy: u32) -> u32 {
+    unsafe {
+        if (*gd).spill.is_null() {
+            return 0;
+        }
+        let gsp = &*(*gd).spill;
+        let Some(file) = &gsp.file else {
+            return 0;
+        };
+
+        let mut lines = Vec::with_capacity(gsp.lines.len() - gsp.first);
+        for (n, gsl) in gsp.lines[gsp.first..].iter().enumerate() {
+            let gl = grid_get_line_compressed(dst, dy + n as u32);
+
+            (*gl).flags = gsl.flags;
+            (*gl).time = gsl.time;
+            (*gl).exit_status = gsl.exit_status;
+            if gsl.size != 0 {
+                (*gl).cellsize = gsl.cellsize;
+                (*gl).cellused = gsl.cellused;
+                (*gl).flags |= grid_line_flag::COMPRESSED | grid_line_flag::SPILLED;
+            } else {
+                (*gl).cellsize = 0;
+                (*gl).cellused = 0;
+            }
+            lines.push((gsl.offset, gsl.size));
+        }
+
+        let n = lines.len() as u32;
+        (*dst).spilled = Box::into_raw(Box::new(grid_spill_lines {
+            file: file.clone(),
+            start: dy,
+            lines,
+        }));
+        n
+    }
+}
+
+/// Read the data for a placeholder line from the spill file.
+pub unsafe fn grid_spill_read(gd: *mut grid, gl: *mut grid_line) -> Option<Vec<u8>> {
+    unsafe {
+        let gsl = (*gd).spilled.as_ref()?;
+        let py = gl.offset_from((*gd).linedata) as usize;
+        let &(offset, size) = gsl.lines.get(py.checked_sub(gsl.start as usize)?)?;
+
+        let mut data = vec![0u8; size as usize];
+        if let Err(e) = gsl.file.read_exact_at(&mut data, offset) {
+            log_debug!("couldn't read history file: {e}");
+            return None;
+        }
+        Some(data)
+    }
+}
+
+/// Read the data for a placeholder line from the spill file and keep it. The
+/// line is left compressed. If it cannot be read, it is left empty.
+pub unsafe fn grid_spill_fetch(gd: *mut grid, gl: *mut grid_line) {
+    unsafe {
+        if !(*gl).flags.intersects(grid_line_flag::SPILLED) {
+            return;
+        }
+        let data = grid_spill_read(gd, gl);
+        (*gl).flags &= !grid_line_flag::SPILLED;
+
+        match data {
+            Some(data) => {
+                (*gl).compressed = xmalloc(data.len()).as_ptr().cast();
+                std::ptr::copy_nonoverlapping(data.as_ptr(), (*gl).compressed, data.len());
+                (*gl).compressedsize = data.len() as u32;
+            }
+            None => {
+                (*gl).flags &= !grid_line_flag::COMPRESSED;
+                (*gl).cellsize = 0;
+                (*gl).cellused = 0;
+            }
+        }
+    }
+}
+
+/// Read the data for all placeholder lines in a grid, before lines are moved
+/// so they no longer match their place in the file.
+pub unsafe fn grid_spill_fetch_all(gd: *mut grid) {
+    unsafe {
+        if (*gd).spilled.is_null() {
+            return;
+        }
+        let gsl = &*(*gd).spilled;
+        let end = (gsl.start as usize + gsl.lines.len()).min(((*gd).hsize + (*gd).sy) as usize);
+        for yy in gsl.start as usize..end {
+            grid_spill_fetch(gd, (*gd).linedata.add(yy));
+        }
+        grid_spill_free_lines(gd);
+    }
+}
+
+/// Free the placeholder lines in a grid.
+pub unsafe fn grid_spill_free_lines(gd: *mut grid) {
+    unsafe {
+        if (*gd).spilled.is_null() {
+            return;
+        }
+        drop(Box::from_raw((*gd).spilled));
+        (*gd).spilled = null_mut();
+    }
+}
+
+/// Adjust placeholder lines after ny lines are removed from the top of a
+/// grid.
+pub unsafe fn grid_spill_remove_lines(gd: *mut grid, ny: u32) {
+    unsafe {
+        if (*gd).spilled.is_null() {
+            return;
+        }
+        let gsl = &mut *(*gd).spilled;
+        if ny > gsl.start {
+            let n = ((ny - gsl.start) as usize).min(gsl.lines.len());
+            gsl.lines.drain(..n);
+            gsl.start = 0;
+        } else {
+            gsl.start -= ny;
+        }
+        if gsl.lines.is_empty() {
+            grid_spill_free_lines(gd);
+        }
+    }
+}
+
+/// Create a copy of a grid with the lines spilled to disk put back at the top
+/// of the history as placeholders.
+pub unsafe fn grid_spill_expand(gd: *mut grid) -> *mut grid {
+    unsafe {
+        let spilled = grid_spill_size(gd);
+        let lines = (*gd).hsize + (*gd).sy;
+
+        let new = grid_create((*gd).sx, spilled + lines, 0);
+        let loaded = grid_spill_load(gd, new, 0);
+        grid_duplicate_lines(new, loaded, gd, 0, lines);
+
+        (*new).hsize = loaded + (*gd).hsize;
+        (*new).sy = (*gd).sy;
+        (*new).hscrolled = (*gd).hscrolled;
+        new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn line_text(gd: *mut grid, py: u32) -> String {
+        unsafe {
+            let mut text = String::new();
+            for px in 0..(*grid_get_line(gd, py)).cellused {
+                let mut gc: grid_cell = zeroed();
+                grid_get_cell(gd, px, py, &mut gc);
+                text.push(gc.data.data[0] as char);
+            }
+            text
+        }
+    }
+
+    #[test]
+    fn test_grid_spill_expand() {
+        unsafe {
+            let gd = grid_create(10, 1, 10);
+            grid_spill_set(gd, 100);
+
+            let gc: grid_cell = GRID_DEFAULT_CELL;
+            for n in 0..50 {
+                let text = format!("line {n}");
+                grid_collect_history(gd);
+                grid_set_cells(gd, 0, (*gd).hsize, &gc, text.as_bytes().as_ptr(), text.len());
+                grid_scroll_history(gd, 8);
+            }
+            let spilled = grid_spill_size(gd);
+            assert!(spilled != 0);
+
+            let new = grid_spill_expand(gd);
+            assert_eq!((*new).hsize, spilled + (*gd).hsize);
+            let gl = grid_get_line_compressed(new, 0);
+            assert!((*gl).flags.intersects(grid_line_flag::SPILLED));
+            assert!((*gl).compressed.is_null());
+
+            // Clearing the history starts a new file but the copy still
+            // reads the old one.
+            grid_clear_history(gd);
+            for py in [(*new).hsize - 1, 0, 3] {
+                assert_eq!(line_text(new, py), format!("line {py}"));
+            }
+
+            // Trimming the copy moves the remaining placeholders up.
+            grid_collect_history(new);
+            assert_eq!(line_text(new, 3), "line 4");
+
+            grid_destroy(new);
+            grid_destroy(gd);
+        }
+    }
+}
@@ -29,12 +29,14 @@ pub unsafe fn grid_view_get_cell(gd: *mut grid, px: u32, py: u32, gc: *mut grid_
 pub unsafe fn grid_view_set_cell(gd: *mut grid, px: u32, py: u32, gc: *const grid_cell) {
     unsafe {
         grid_set_cell(gd, grid_view_x(gd, px), grid_view_y(gd, py), gc);
+        grid_touch_line(gd, grid_view_y(gd, py));
     }
 }
 
 pub unsafe fn grid_view_set_padding(gd: *mut grid, px: u32, py: u32) {
     unsafe {
         grid_set_padding(gd, grid_view_x(gd, px), grid_view_y(gd, py));
+        grid_touch_line(gd, grid_view_y(gd, py));
     }
 }
 
@@ -48,6 +50,7 @@ pub unsafe fn grid_view_set_cells(
 ) {
     unsafe {
         grid_set_cells(gd, grid_view_x(gd, px), grid_view_y(gd, py), gc, s, slen);
+        grid_touch_line(gd, grid_view_y(gd, py));
     }
 }
 
@@ -26,7 +26,11 @@ unsafe fn image_free(im: NonNull<image>) {
         ALL_IMAGES_COUNT -= 1;
 
         tailq_remove::<_, discr_entry>(&raw mut (*s).images, im);
-        crate::image_sixel::sixel_free((*im).data);
+        if !(*im).kitty.is_null() {
+            crate::image_kitty::kitty_free((*im).kitty);
+        } else {
+            crate::image_sixel::sixel_free((*im).data);
+        }
         free_((*im).fallback);
         free_(im);
     }
@@ -43,12 +47,23 @@ pub unsafe fn image_free_all(s: *mut screen) -> bool {
     }
 }
 
+/// Get the size of an image in cells.
+unsafe fn image_size_in_cells(im: *const image) -> (u32, u32) {
+    unsafe {
+        if !(*im).kitty.is_null() {
+            crate::image_kitty::kitty_size_in_cells(&*(*im).kitty)
+        } else {
+            crate::image_sixel::sixel_size_in_cells(&*(*im).data)
+        }
+    }
+}
+
 /// Create text placeholder for an image.
-pub fn image_fallback(sx: u32, sy: u32) -> CString {
+pub fn image_fallback(name: &str, sx: u32, sy: u32) -> CString {
     let sx = sx as usize;
     let sy = sy as usize;
 
-    let label = CString::new(format!("SIXEL IMAGE ({sx}x{sy})\r\n")).unwrap();
+    let label = CString::new(format!("{name} IMAGE ({sx}x{sy})\r\n")).unwrap();
 
     // Allocate first line.
     let lsize = label.to_bytes_with_nul().len();
@@ -77,10 +92,19 @@ pub fn image_fallback(sx: u32, sy: u32) -> CString {
 }
 
 pub unsafe fn image_store(s: *mut screen, si: *mut sixel_image) -> *mut image {
+    unsafe { image_add(s, si, null_mut()) }
+}
+
+pub unsafe fn image_store_kitty(s: *mut screen, ki: *mut kitty_image) -> *mut image {
+    unsafe { image_add(s, null_mut(), ki) }
+}
+
+unsafe fn image_add(s: *mut screen, si: *mut sixel_image, ki: *mut kitty_image) -> *mut image {
     unsafe {
         let mut im = Box::new(image {
             s,
             data: si,
+            kitty: ki,
             px: (*s).cx,
             py: (*s).cy,
             sx: 0,
@@ -90,9 +114,10 @@ pub unsafe fn image_store(s: *mut screen, si: *mut sixel_image) -> *mut image {
             entry: zeroed(),
         });
 
-        (im.sx, im.sy) = crate::image_sixel::sixel_size_in_cells(&*si);
+        (im.sx, im.sy) = image_size_in_cells(&*im);
 
-        im.fallback = image_fallback(im.sx, im.sy).into_raw().cast();
+        let name = if ki.is_null() { "SIXEL" } else { "KITTY" };
+        im.fallback = image_fallback(name, im.sx, im.sy).into_raw().cast();
 
         tailq_insert_tail::<image, discr_entry>(&raw mut (*s).images, &mut *im);
         tailq_insert_tail::<image, discr_all_entry>(&raw mut ALL_IMAGES, &mut *im);
@@ -105,6 +130,22 @@ pub unsafe fn image_store(s: *mut screen, si: *mut sixel_image) -> *mut image {
     }
 }
 
+/// Free kitty images, all of them or those with an id.
+pub unsafe fn image_free_kitty(s: *mut screen, id: Option<u32>) -> bool {
+    unsafe {
+        let mut redraw = false;
+
+        for im in tailq_foreach::<_, discr_entry>(&raw mut (*s).images) {
+            let ki = (*im.as_ptr()).kitty;
+            if !ki.is_null() && crate::image_kitty::kitty_matches(&*ki, id) {
+                image_free(im);
+                redraw = true;
+            }
+        }
+        redraw
+    }
+}
+
 pub unsafe fn image_check_line(s: *mut screen, py: u32, ny: u32) -> bool {
     unsafe {
         let mut redraw = false;
@@ -155,25 +196,36 @@ pub unsafe fn image_scroll_up(s: *mut screen, lines: u32) -> bool {
             let sx = (*im.as_ptr()).sx;
             let sy = ((*im.as_ptr()).py + (*im.as_ptr()).sy) - lines;
 
-            let new = crate::image_sixel::sixel_scale(
-                (*im.as_ptr()).data,
-                0,
-                0,
-                0,
-                (*im.as_ptr()).sy - sy,
-                sx,
-                sy,
-                1,
-            );
-            crate::image_sixel::sixel_free((*im.as_ptr()).data);
-            (*im.as_ptr()).data = new;
+            if !(*im.as_ptr()).kitty.is_null() {
+                crate::image_kitty::kitty_crop_top(
+                    &mut *(*im.as_ptr()).kitty,
+                    (*im.as_ptr()).sy - sy,
+                );
+            } else {
+                let new = crate::image_sixel::sixel_scale(
+                    (*im.as_ptr()).data,
+                    0,
+                    0,
+                    0,
+                    (*im.as_ptr()).sy - sy,
+                    sx,
+                    sy,
+                    1,
+                );
+                crate::image_sixel::sixel_free((*im.as_ptr()).data);
+                (*im.as_ptr()).data = new;
+            }
 
             (*im.as_ptr()).py = 0;
-            ((*im.as_ptr()).sx, (*im.as_ptr()).sy) =
-                crate::image_sixel::sixel_size_in_cells(&*(*im.as_ptr()).data);
+            ((*im.as_ptr()).sx, (*im.as_ptr()).sy) = image_size_in_cells(im.as_ptr());
 
             free_((*im.as_ptr()).fallback);
-            (*im.as_ptr()).fallback = image_fallback((*im.as_ptr()).sx, (*im.as_ptr()).sy)
+            let name = if (*im.as_ptr()).kitty.is_null() {
+                "SIXEL"
+            } else {
+                "KITTY"
+            };
+            (*im.as_ptr()).fallback = image_fallback(name, (*im.as_ptr()).sx, (*im.as_ptr()).sy)
                 .into_raw()
                 .cast();
             redraw = true;
@@ -0,0 +1,490 @@
+// Kitty graphics protocol images.
+//
+// Applications send images as APC sequences, \033_G<control data>;<payload>\033\.
+// The control data is a list of key=value pairs and the payload is base64. The
+// image data is never decoded: it is kept as received and passed to terminals
+// which support the protocol, which scale it to the cells it covers. Each
+// terminal is sent an image once and after that it is only placed by its id.
+use std::collections::VecDeque;
+
+use crate::compat::b64::b64_pton;
+use crate::*;
+
+/// Largest image payload accepted, in bytes of base64.
+const KITTY_PAYLOAD_LIMIT: usize = 32 * 1024 * 1024;
+
+/// Most images kept transmitted but not displayed for each pane, and the most
+/// bytes of base64 they may use. The oldest are dropped to make room.
+const KITTY_STORED_IMAGES: usize = 32;
+const KITTY_STORED_LIMIT: usize = 2 * KITTY_PAYLOAD_LIMIT;
+
+/// Size of each chunk when sending an image to a terminal.
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// Remove every placement from a terminal but keep the image data, which is
+/// not sent again when the images are placed after a redraw.
+pub const KITTY_DELETE_PLACEMENTS: &[u8] = b"\x1b_Ga=d,d=a,q=2\x1b\\";
+
+/// Next image id used with the outside terminal.
+static mut KITTY_NEXT_ID: u32 = 1;
+
+pub struct kitty_image {
+    /// Id given by the application, 0 if none.
+    id: u32,
+    /// Id used with the outside terminal, unique across all panes.
+    number: u32,
+
+    format: u32,
+    compressed: bool,
+
+    /// Size in pixels.
+    width: u32,
+    height: u32,
+
+    /// Size in cells.
+    cols: u32,
+    rows: u32,
+
+    /// Cells cropped from the top after scrolling.
+    top: u32,
+
+    /// Base64 image data.
+    payload: Vec<u8>,
+
+    /// Terminals the image data has been sent to, by their serial number.
+    sent: Vec<u64>,
+}
+
+/// A parsed APC G command.
+#[derive(Default)]
+struct kitty_command {
+    action: u8,
+    delete: u8,
+    quiet: u32,
+    id: u32,
+    more: bool,
+
+    format: u32,
+    compressed: bool,
+    width: u32,
+    height: u32,
+    cols: u32,
+    rows: u32,
+
+    payload: Vec<u8>,
+}
+
+/// Images being received in chunks or transmitted without being displayed.
+/// One of these is kept by the input parser for each pane.
+#[derive(Default)]
+pub struct kitty_state {
+    pending: Option<kitty_command>,
+    /// Stored images, oldest first.
+    stored: VecDeque<kitty_command>,
+}
+
+impl kitty_state {
+    fn find(&self, id: u32) -> Option<&kitty_command> {
+        self.stored.iter().find(|kc| kc.id == id)
+    }
+
+    fn remove(&mut self, id: u32) {
+        self.stored.retain(|kc| kc.id != id);
+    }
+
+    /// Store an image, replacing any with the same id and dropping the oldest
+    /// images if there are too many or they are too big.
+    fn store(&mut self, kc: kitty_command) {
+        self.remove(kc.id);
+        self.stored.push_back(kc);
+
+        let mut size: usize = self.stored.iter().map(|kc| kc.payload.len()).sum();
+        while self.stored.len() > KITTY_STORED_IMAGES || size > KITTY_STORED_LIMIT {
+            let Some(old) = self.stored.pop_front() else {
+                break;
+            };
+            log_debug!("kitty_state_store: dropping image {}", old.id);
+            size -= old.payload.len();
+        }
+    }
+}
+
+/// What the input parser should do after a command.
+pub enum kitty_action {
+    /// Nothing, apart from any reply.
+    None,
+    /// Display an image at the cursor.
+    Display(*mut kitty_image),
+    /// Delete displayed images, all of them or only those with the given id.
+    Delete(Option<u32>),
+}
+
+fn kitty_parse_number(value: &[u8]) -> Option<u32> {
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+/// Parse the control data of a command.
+fn kitty_parse_command(buf: &[u8]) -> Option<kitty_command> {
+    let (control, payload) = match buf.iter().position(|&ch| ch == b';') {
+        Some(i) => (&buf[..i], &buf[i + 1..]),
+        None => (buf, &b""[..]),
+    };
+    let mut kc = kitty_command {
+        action: b't',
+        delete: b'a',
+        format: 32,
+        payload: payload.to_vec(),
+        ..Default::default()
+    };
+
+    for pair in control.split(|&ch| ch == b',').filter(|p| !p.is_empty()) {
+        let [key, b'=', value @ ..] = pair else {
+            return None;
+        };
+        match key {
+            b'a' => kc.action = *value.first()?,
+            b'd' => kc.delete = *value.first()?,
+            b'o' => kc.compressed = value == b"z",
+            b'm' => kc.more = kitty_parse_number(value)? != 0,
+            b'q' => kc.quiet = kitty_parse_number(value)?,
+            b'i' => kc.id = kitty_parse_number(value)?,
+            b'f' => kc.format = kitty_parse_number(value)?,
+            b's' => kc.width = kitty_parse_number(value)?,
+            b'v' => kc.height = kitty_parse_number(value)?,
+            b'c' => kc.cols = kitty_parse_number(value)?,
+            b'r' => kc.rows = kitty_parse_number(value)?,
+            _ => (), // other keys are not needed
+        }
+    }
+    Some(kc)
+}
+
+/// Get the size of a PNG from its header, which is at the start of the data.
+fn kitty_png_size(payload: &[u8]) -> Option<(u32, u32)> {
+    // The header and the IHDR chunk with the size are the first 24 bytes,
+    // which is 32 bytes of base64.
+    let encoded = CString::new(payload.get(..32)?).ok()?;
+    let mut decoded = [0u8; 25];
+    if unsafe { b64_pton(encoded.as_ptr().cast(), decoded.as_mut_ptr(), decoded.len()) } != 24 {
+        return None;
+    }
+    if &decoded[..8] != b"\x89PNG\r\n\x1a\n" || &decoded[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(decoded[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(decoded[20..24].try_into().unwrap());
+    Some((width, height))
+}
+
+/// Create an image to display from a transmitted command. The cell size given
+/// in the placement overrides any in the original transmission.
+unsafe fn kitty_create(
+    kc: &kitty_command,
+    place: &kitty_command,
+    xpixel: u32,
+    ypixel: u32,
+) -> Option<*mut kitty_image> {
+    unsafe {
+        let (width, height) = if kc.format == 100 && !kc.compressed {
+            kitty_png_size(&kc.payload)?
+        } else {
+            (kc.width, kc.height)
+        };
+
+        let mut cols = if place.cols != 0 { place.cols } else { kc.cols };
+        let mut rows = if place.rows != 0 { place.rows } else { kc.rows };
+        if cols == 0 {
+            cols = width.div_ceil(xpixel);
+        }
+        if rows == 0 {
+            rows = height.div_ceil(ypixel);
+        }
+        if cols == 0 || rows == 0 {
+            return None;
+        }
+
+        let number = KITTY_NEXT_ID;
+        KITTY_NEXT_ID = KITTY_NEXT_ID.wrapping_add(1).max(1);
+
+        Some(Box::into_raw(Box::new(kitty_image {
+            id: kc.id,
+            number,
+            format: kc.format,
+            compressed: kc.compressed,
+            width,
+            height,
+            cols,
+            rows,
+            top: 0,
+            payload: kc.payload.clone(),
+            sent: Vec::new(),
+        })))
+    }
+}
+
+/// Process an APC G command, buf is the string after the G. Returns what to do
+/// and a reply to send to the application, if any.
+pub unsafe fn kitty_parse(
+    state: &mut kitty_state,
+    buf: &[u8],
+    xpixel: u32,
+    ypixel: u32,
+) -> (kitty_action, Option<String>) {
+    unsafe {
+        let Some(mut kc) = kitty_parse_command(buf) else {
+            return (kitty_action::None, None);
+        };
+
+        // Add any continuation chunk to the pending image.
+        if let Some(mut pending) = state.pending.take() {
+            if pending.payload.len() + kc.payload.len() > KITTY_PAYLOAD_LIMIT {
+                log_debug!("kitty_parse: image too big");
+                return (kitty_action::None, None);
+            }
+            pending.payload.append(&mut kc.payload);
+            if kc.more {
+                state.pending = Some(pending);
+                return (kitty_action::None, None);
+            }
+            kc = pending;
+        } else if kc.more && matches!(kc.action, b't' | b'T') {
+            if kc.payload.len() <= KITTY_PAYLOAD_LIMIT {
+                state.pending = Some(kc);
+            }
+            return (kitty_action::None, None);
+        }
+        log_debug!(
+            "kitty_parse: action {} id {} ({} bytes)",
+            kc.action as char,
+            kc.id,
+            kc.payload.len()
+        );
+
+        let reply = |message: &str| {
+            if kc.id == 0 || kc.quiet >= 2 || (kc.quiet == 1 && message == "OK") {
+                return None;
+            }
+            Some(format!("\x1b_Gi={};{}\x1b\\", kc.id, message))
+        };
+
+        match kc.action {
+            b'q' => (kitty_action::None, reply("OK")),
+            b't' | b'T' => {
+                let action = if kc.action == b'T' {
+                    match kitty_create(&kc, &kc, xpixel, ypixel) {
+                        Some(ki) => kitty_action::Display(ki),
+                        None => return (kitty_action::None, reply("EINVAL:bad image")),
+                    }
+                } else {
+                    kitty_action::None
+                };
+                let message = reply("OK");
+                if kc.id != 0 {
+                    kc.action = b't';
+                    state.store(kc);
+                }
+                (action, message)
+            }
+            b'p' => {
+                let Some(stored) = state.find(kc.id) else {
+                    return (kitty_action::None, reply("ENOENT:no such image"));
+                };
+                match kitty_create(stored, &kc, xpixel, ypixel) {
+                    Some(ki) => (kitty_action::Display(ki), reply("OK")),
+                    None => (kitty_action::None, reply("EINVAL:bad image")),
+                }
+            }
+            b'd' => match kc.delete {
+                b'i' => (kitty_action::Delete(Some(kc.id)), None),
+                b'I' => {
+                    state.remove(kc.id);
+                    (kitty_action::Delete(Some(kc.id)), None)
+                }
+                b'A' => {
+                    state.stored.clear();
+                    (kitty_action::Delete(None), None)
+                }
+                _ => (kitty_action::Delete(None), None),
+            },
+            _ => (kitty_action::None, reply("EINVAL:unknown action")),
+        }
+    }
+}
+
+pub unsafe fn kitty_free(ki: *mut kitty_image) {
+    unsafe {
+        for c in tailq_foreach(&raw mut CLIENTS).map(NonNull::as_ptr) {
+            if (*c).session.is_null() || (*c).tty.term.is_null() {
+                continue;
+            }
+            if (*(*c).tty.term)
+                .flags
+                .intersects(term_flags::TERM_KITTYGRAPHICS)
+            {
+                let delete = format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", (*ki).number);
+                tty_puts_(&raw mut (*c).tty, delete.as_bytes());
+            }
+        }
+        drop(Box::from_raw(ki));
+    }
+}
+
+/// Does this image match a delete command?
+pub fn kitty_matches(ki: &kitty_image, id: Option<u32>) -> bool {
+    id.is_none_or(|id| ki.id == id)
+}
+
+pub fn kitty_size_in_cells(ki: &kitty_image) -> (u32, u32) {
+    (ki.cols, ki.rows)
+}
+
+/// Clamp an image to fit in the given number of cells.
+pub fn kitty_clamp(ki: &mut kitty_image, sx: u32, sy: u32) {
+    ki.cols = ki.cols.min(sx);
+    ki.rows = ki.rows.min(sy);
+}
+
+/// Crop lines from the top of an image when it is scrolled.
+pub fn kitty_crop_top(ki: &mut kitty_image, lines: u32) {
+    ki.top += lines;
+    ki.rows -= lines;
+}
+
+/// Create the sequences to display the part of an image at ox,oy with size
+/// sx,sy in cells on the terminal with the given serial number. The image data
+/// is transmitted the first time, after that the placement is replaced. The
+/// source rectangle is set to the part shown, in pixels, so the terminal crops
+/// it.
+pub fn kitty_print(
+    ki: &mut kitty_image,
+    serial: u64,
+    ox: u32,
+    oy: u32,
+    sx: u32,
+    sy: u32,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    if !ki.sent.contains(&serial) {
+        let mut control = format!("a=t,q=2,i={},f={}", ki.number, ki.format);
+        if ki.format != 100 {
+            control.push_str(&format!(",s={},v={}", ki.width, ki.height));
+        }
+        if ki.compressed {
+            control.push_str(",o=z");
+        }
+
+        out.reserve(ki.payload.len() + 64);
+        let mut chunks = ki.payload.chunks(KITTY_CHUNK_SIZE).peekable();
+        let mut first = true;
+        while let Some(chunk) = chunks.next() {
+            let more = chunks.peek().is_some() as u32;
+            out.extend_from_slice(b"\x1b_G");
+            if first {
+                out.extend_from_slice(control.as_bytes());
+                out.push(b',');
+                first = false;
+            }
+            out.extend_from_slice(format!("m={more};").as_bytes());
+            out.extend_from_slice(chunk);
+            out.extend_from_slice(b"\x1b\\");
+        }
+        ki.sent.push(serial);
+    }
+
+    let total = ki.rows + ki.top;
+    let x = (ox as u64 * ki.width as u64 / ki.cols as u64) as u32;
+    let y = ((oy + ki.top) as u64 * ki.height as u64 / total as u64) as u32;
+    let w = (sx as u64 * ki.width as u64 / ki.cols as u64) as u32;
+    let h = (sy as u64 * ki.height as u64 / total as u64) as u32;
+
+    let mut control = format!("a=p,q=2,C=1,i={},p=1,c={sx},r={sy}", ki.number);
+    if ki.width != 0 && ki.height != 0 {
+        control.push_str(&format!(",x={x},y={y},w={w},h={h}"));
+    }
+    out.extend_from_slice(format!("\x1b_G{control}\x1b\\").as_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kitty_state_store() {
+        let mut state = kitty_state::default();
+        for id in 1..=KITTY_STORED_IMAGES as u32 + 2 {
+            state.store(kitty_command {
+                id,
+                ..Default::default()
+            });
+        }
+        assert_eq!(state.stored.len(), KITTY_STORED_IMAGES);
+        assert!(state.find(2).is_none());
+        assert!(state.find(3).is_some());
+
+        // Big images push out the oldest until they fit.
+        let big = vec![b'A'; KITTY_PAYLOAD_LIMIT];
+        for id in [100, 101, 102] {
+            state.store(kitty_command {
+                id,
+                payload: big.clone(),
+                ..Default::default()
+            });
+        }
+        assert!(state.find(100).is_none());
+        assert!(state.find(101).is_some() && state.find(102).is_some());
+        assert_eq!(state.stored.len(), 2);
+    }
+
+    #[test]
+    fn test_kitty_print() {
+        let mut ki = kitty_image {
+            id: 1,
+            number: 7,
+            format: 32,
+            compressed: false,
+            width: 10,
+            height: 10,
+            cols: 2,
+            rows: 2,
+            top: 0,
+            payload: b"AAAA".to_vec(),
+            sent: Vec::new(),
+        };
+
+        let first = String::from_utf8(kitty_print(&mut ki, 1, 0, 0, 2, 2)).unwrap();
+        assert!(first.starts_with("\x1b_Ga=t,q=2,i=7,f=32,s=10,v=10,m=0;AAAA\x1b\\"));
+        assert!(first.ends_with("\x1b_Ga=p,q=2,C=1,i=7,p=1,c=2,r=2,x=0,y=0,w=10,h=10\x1b\\"));
+
+        let again = String::from_utf8(kitty_print(&mut ki, 1, 0, 0, 2, 2)).unwrap();
+        assert!(again.starts_with("\x1b_Ga=p,"));
+        let other = String::from_utf8(kitty_print(&mut ki, 2, 0, 0, 2, 2)).unwrap();
+        assert!(other.starts_with("\x1b_Ga=t,"));
+    }
+
+    #[test]
+    fn test_kitty_delete_placements() {
+        // Lowercase d=a: an uppercase A would free the image data, which is
+        // only sent once, so placing it again after a redraw would fail.
+        assert_eq!(KITTY_DELETE_PLACEMENTS, b"\x1b_Ga=d,d=a,q=2\x1b\\");
+
+        let mut ki = kitty_image {
+            id: 1,
+            number: 7,
+            format: 100,
+            compressed: false,
+            width: 0,
+            height: 0,
+            cols: 2,
+            rows: 2,
+            top: 0,
+            payload: b"AAAA".to_vec(),
+            sent: vec![1],
+        };
+        assert_eq!(
+            kitty_print(&mut ki, 1, 0, 0, 2, 2),
+            b"\x1b_Ga=p,q=2,C=1,i=7,p=1,c=2,r=2\x1b\\"
+        );
+    }
+}
@@ -35,7 +35,7 @@
 use crate::compat::b64::{b64_ntop, b64_pton};
 use crate::libc::{strchr, strpbrk, strtol};
 use crate::*;
-use crate::options_::{options_get_number_, options_get_only, options_remove_or_default, options_set_number};
+use crate::options_::{options_get_number_, options_get_only, options_get_string_, options_remove_or_default, options_set_number};
 
 // Input parser cell.
 #[repr(C)]
@@ -127,8 +127,28 @@ pub struct input_ctx {
 
     /// All input received since we were last in the ground state. Sent to control clients on connection.
     since_ground: *mut evbuffer,
+
+    /// Kitty graphics images not yet displayed.
+    #[cfg(feature = "sixel")]
+    kitty: Option<Box<kitty_state>>,
+
+    /// Kitty notification (OSC 99) being received in chunks.
+    notification: Option<Box<input_notification>>,
+}
+
+/// Parts of a kitty notification received so far.
+#[derive(Default)]
+struct input_notification {
+    id: Vec<u8>,
+    title: Vec<u8>,
+    body: Vec<u8>,
+    /// Too big, the remaining chunks are ignored.
+    discard: bool,
 }
 
+/// Largest kitty notification accepted, in bytes of title and body.
+const INPUT_NOTIFICATION_LIMIT: usize = 64 * 1024;
+
 // Command table entry.
 #[repr(C)]
 struct input_table_entry {
@@ -209,16 +229,23 @@ enum input_csi_type {
     INPUT_CSI_DA,
     INPUT_CSI_DA_TWO,
     INPUT_CSI_DCH,
+    INPUT_CSI_DECRQM,
+    INPUT_CSI_DECRQM_PRIVATE,
     INPUT_CSI_DECSCUSR,
     INPUT_CSI_DECSTBM,
     INPUT_CSI_DL,
     INPUT_CSI_DSR,
+    INPUT_CSI_DSR_PRIVATE,
     INPUT_CSI_ECH,
     INPUT_CSI_ED,
     INPUT_CSI_EL,
     INPUT_CSI_HPA,
     INPUT_CSI_ICH,
     INPUT_CSI_IL,
+    INPUT_CSI_KKEYS_POP,
+    INPUT_CSI_KKEYS_PUSH,
+    INPUT_CSI_KKEYS_QUERY,
+    INPUT_CSI_KKEYS_SET,
     INPUT_CSI_MODOFF,
     INPUT_CSI_MODSET,
     INPUT_CSI_RCP,
@@ -239,7 +266,7 @@ enum input_csi_type {
 }
 
 /// control (csi) command table.
-static INPUT_CSI_TABLE: [input_table_entry; 40] = [
+static INPUT_CSI_TABLE: [input_table_entry; 47] = [
     input_table_entry::new_csi('@', c"", input_csi_type::INPUT_CSI_ICH),
     input_table_entry::new_csi('A', c"", input_csi_type::INPUT_CSI_CUU),
     input_table_entry::new_csi('B', c"", input_csi_type::INPUT_CSI_CUD),
@@ -274,12 +301,19 @@ static INPUT_CSI_TABLE: [input_table_entry; 40] = [
     input_table_entry::new_csi('m', c">", input_csi_type::INPUT_CSI_MODSET),
     input_table_entry::new_csi('n', c"", input_csi_type::INPUT_CSI_DSR),
     input_table_entry::new_csi('n', c">", input_csi_type::INPUT_CSI_MODOFF),
+    input_table_entry::new_csi('n', c"?", input_csi_type::INPUT_CSI_DSR_PRIVATE),
+    input_table_entry::new_csi('p', c"$", input_csi_type::INPUT_CSI_DECRQM),
+    input_table_entry::new_csi('p', c"?$", input_csi_type::INPUT_CSI_DECRQM_PRIVATE),
     input_table_entry::new_csi('q', c" ", input_csi_type::INPUT_CSI_DECSCUSR),
     input_table_entry::new_csi('q', c">", input_csi_type::INPUT_CSI_XDA),
     input_table_entry::new_csi('r', c"", input_csi_type::INPUT_CSI_DECSTBM),
     input_table_entry::new_csi('s', c"", input_csi_type::INPUT_CSI_SCP),
     input_table_entry::new_csi('t', c"", input_csi_type::INPUT_CSI_WINOPS),
     input_table_entry::new_csi('u', c"", input_csi_type::INPUT_CSI_RCP),
+    input_table_entry::new_csi('u', c"<", input_csi_type::INPUT_CSI_KKEYS_POP),
+    input_table_entry::new_csi('u', c"=", input_csi_type::INPUT_CSI_KKEYS_SET),
+    input_table_entry::new_csi('u', c">", input_csi_type::INPUT_CSI_KKEYS_PUSH),
+    input_table_entry::new_csi('u', c"?", input_csi_type::INPUT_CSI_KKEYS_QUERY),
 ];
 
 /// Input transition.
@@ -977,6 +1011,9 @@ pub unsafe fn input_free(ictx: *mut input_ctx) {
 
         free_((*ictx).input_buf);
         evbuffer_free((*ictx).since_ground);
+        #[cfg(feature = "sixel")]
+        drop((*ictx).kitty.take());
+        drop((*ictx).notification.take());
 
         free_(ictx);
     }
@@ -992,6 +1029,7 @@ pub unsafe fn input_reset(ictx: *mut input_ctx, clear: i32) {
         input_reset_cell(ictx);
 
         if clear != 0 && !wp.is_null() {
+            screen_write_stop_sync(wp);
             if tailq_empty(&raw const (*wp).modes) {
                 screen_write_start_pane(sctx, wp, &raw mut (*wp).base);
             } else {
@@ -1456,6 +1494,7 @@ unsafe fn input_esc_dispatch(ictx: *mut input_ctx) -> i32 {
         match input_esc_type::try_from((INPUT_ESC_TABLE[entry]).type_) {
             Ok(input_esc_type::INPUT_ESC_RIS) => {
                 colour_palette_clear(NonNull::new((*ictx).palette).map(|e| &mut *e.as_ptr()));
+                screen_write_stop_sync((*ictx).wp);
                 input_reset_cell(ictx);
                 screen_write_reset(sctx);
                 screen_write_fullredraw(sctx);
@@ -1609,6 +1648,30 @@ unsafe fn input_csi_dispatch(ictx: *mut input_ctx) -> i32 {
                     }
                 }
             }
+            Ok(input_csi_type::INPUT_CSI_KKEYS_PUSH) => {
+                // Only follow the application if "extended-keys" is not off.
+                let n = input_get(ictx, 0, 0, 0);
+                if n != -1 && options_get_number_(GLOBAL_OPTIONS, "extended-keys") != 0 {
+                    screen_kitty_keys_push(s, kitty_key_flags::from_bits_truncate(n as u32));
+                }
+            }
+            Ok(input_csi_type::INPUT_CSI_KKEYS_POP) => {
+                let n = input_get(ictx, 0, 1, 1);
+                if n != -1 {
+                    screen_kitty_keys_pop(s, n as u32);
+                }
+            }
+            Ok(input_csi_type::INPUT_CSI_KKEYS_SET) => {
+                let n = input_get(ictx, 0, 0, 0);
+                let m = input_get(ictx, 1, 1, 1);
+                if n != -1 && m != -1 && options_get_number_(GLOBAL_OPTIONS, "extended-keys") != 0
+                {
+                    screen_kitty_keys_set(s, kitty_key_flags::from_bits_truncate(n as u32), m);
+                }
+            }
+            Ok(input_csi_type::INPUT_CSI_KKEYS_QUERY) => {
+                input_reply!(ictx, "\x1b[?{}u", (*s).kitty_keys.flags.bits());
+            }
             Ok(input_csi_type::INPUT_CSI_WINOPS) => input_csi_dispatch_winops(ictx),
             Ok(input_csi_type::INPUT_CSI_CUU) => {
                 let n = input_get(ictx, 0, 1, 1);
@@ -1661,6 +1724,8 @@ unsafe fn input_csi_dispatch(ictx: *mut input_ctx) -> i32 {
                     screen_write_deletecharacter(sctx, n as u32, bg);
                 }
             }
+            Ok(input_csi_type::INPUT_CSI_DECRQM) => input_csi_dispatch_decrqm(ictx),
+            Ok(input_csi_type::INPUT_CSI_DECRQM_PRIVATE) => input_csi_dispatch_decrqm_private(ictx),
             Ok(input_csi_type::INPUT_CSI_DECSTBM) => {
                 let n = input_get(ictx, 0, 1, 1);
                 let m = input_get(ictx, 1, 1, screen_size_y(s) as i32);
@@ -1680,6 +1745,13 @@ unsafe fn input_csi_dispatch(ictx: *mut input_ctx) -> i32 {
                 6 => input_reply!(ictx, "\x1b[{};{}R", (*s).cy + 1, (*s).cx + 1),
                 _ => log_debug!("{}: unknown '{}'", __func__, (*ictx).ch as u8 as char),
             },
+            Ok(input_csi_type::INPUT_CSI_DSR_PRIVATE) => match input_get(ictx, 0, 0, 0) {
+                -1 => (),
+                996 if !(*ictx).wp.is_null() => {
+                    input_reply!(ictx, "\x1b[?997;{}n", input_theme((*ictx).wp));
+                }
+                _ => log_debug!("{}: unknown '{}'", __func__, (*ictx).ch as u8 as char),
+            },
             Ok(input_csi_type::INPUT_CSI_ED) => {
                 match input_get(ictx, 0, 0, 0) {
                     -1 => (),
@@ -1845,6 +1917,9 @@ unsafe fn input_csi_dispatch_rm_private(ictx: *mut input_ctx) {
                 47 | 1047 => screen_write_alternateoff(sctx, gc, 0),
                 1049 => screen_write_alternateoff(sctx, gc, 1),
                 2004 => screen_write_mode_clear(sctx, mode_flag::MODE_BRACKETPASTE),
+                2026 => screen_write_stop_sync((*ictx).wp),
+                2027 => screen_write_mode_clear(sctx, mode_flag::MODE_GRAPHEMES),
+                2031 => screen_write_mode_clear(sctx, mode_flag::MODE_THEME),
                 _ => log_debug!(
                     "{}: unknown '{}'",
                     "input_csi_dispatch_rm_private",
@@ -1919,6 +1994,14 @@ unsafe fn input_csi_dispatch_sm_private(ictx: *mut input_ctx) {
                 47 | 1047 => screen_write_alternateon(sctx, gc, 0),
                 1049 => screen_write_alternateon(sctx, gc, 1),
                 2004 => screen_write_mode_set(sctx, mode_flag::MODE_BRACKETPASTE),
+                2026 => screen_write_start_sync((*ictx).wp),
+                2027 => screen_write_mode_set(sctx, mode_flag::MODE_GRAPHEMES),
+                2031 => {
+                    screen_write_mode_set(sctx, mode_flag::MODE_THEME);
+                    if !(*ictx).wp.is_null() {
+                        (*(*ictx).wp).last_theme = input_theme((*ictx).wp);
+                    }
+                }
                 _ => log_debug!(
                     "{}: unknown '{}'",
                     "input_csi_dispatch_sm_private",
@@ -1929,6 +2012,72 @@ unsafe fn input_csi_dispatch_sm_private(ictx: *mut input_ctx) {
     }
 }
 
+/// Handle CSI DECRQM.
+unsafe fn input_csi_dispatch_decrqm(ictx: *mut input_ctx) {
+    unsafe {
+        let s = (*ictx).ctx.s;
+
+        let n = input_get(ictx, 0, 0, -1);
+        if n == -1 {
+            return;
+        }
+
+        // 0 is not recognised, 1 is set, 2 is reset and 4 is permanently reset.
+        let value = match n {
+            4 if (*s).mode.intersects(mode_flag::MODE_INSERT) => 1, // IRM
+            4 => 2,
+            20 => 4, // LNM
+            _ => 0,
+        };
+        input_reply!(ictx, "\x1b[{n};{value}$y");
+    }
+}
+
+/// Handle CSI private DECRQM.
+unsafe fn input_csi_dispatch_decrqm_private(ictx: *mut input_ctx) {
+    unsafe {
+        let wp = (*ictx).wp;
+        let s = (*ictx).ctx.s;
+
+        let n = input_get(ictx, 0, 0, -1);
+        if n == -1 {
+            return;
+        }
+
+        let flag = match n {
+            1 => Some(mode_flag::MODE_KCURSOR),                    // DECCKM
+            6 => Some(mode_flag::MODE_ORIGIN),                     // DECOM
+            7 => Some(mode_flag::MODE_WRAP),                       // DECAWM
+            12 => Some(mode_flag::MODE_CURSOR_BLINKING),           // att610
+            25 => Some(mode_flag::MODE_CURSOR),                    // DECTCEM
+            1000 => Some(mode_flag::MODE_MOUSE_STANDARD),
+            1002 => Some(mode_flag::MODE_MOUSE_BUTTON),
+            1003 => Some(mode_flag::MODE_MOUSE_ALL),
+            1004 => Some(mode_flag::MODE_FOCUSON),
+            1005 => Some(mode_flag::MODE_MOUSE_UTF8),
+            1006 => Some(mode_flag::MODE_MOUSE_SGR),
+            2004 => Some(mode_flag::MODE_BRACKETPASTE),
+            2027 => Some(mode_flag::MODE_GRAPHEMES),
+            2031 => Some(mode_flag::MODE_THEME),
+            _ => None,
+        };
+
+        // 0 is not recognised, 1 is set, 2 is reset and 4 is permanently reset.
+        let value = match n {
+            _ if flag.is_some_and(|flag| (*s).mode.intersects(flag)) => 1,
+            _ if flag.is_some() => 2,
+            3 => 4, // DECCOLM, 132 columns is not supported
+            47 | 1047 | 1049 if !(*s).saved_grid.is_null() => 1,
+            47 | 1047 | 1049 => 2,
+            2026 if wp.is_null() => 0,
+            2026 if (*wp).base.mode.intersects(mode_flag::MODE_SYNC) => 1,
+            2026 => 2,
+            _ => 0,
+        };
+        input_reply!(ictx, "\x1b[?{n};{value}$y");
+    }
+}
+
 /// Handle CSI graphics SM.
 unsafe fn input_csi_dispatch_sm_graphics(ictx: *mut input_ctx) {
     #[cfg(feature = "sixel")]
@@ -2343,6 +2492,19 @@ unsafe fn input_dcs_dispatch(ictx: *mut input_ctx) -> i32 {
             return 0;
         }
 
+        let data = std::slice::from_raw_parts(buf, len);
+        match (&(&(*ictx).interm_buf)[..(*ictx).interm_len], data) {
+            (b"$", [b'q', request @ ..]) => {
+                input_dcs_decrqss(ictx, request);
+                return 0;
+            }
+            (b"+", [b'q', request @ ..]) => {
+                input_dcs_xtgettcap(ictx, request);
+                return 0;
+            }
+            _ => (),
+        }
+
         #[cfg(feature = "sixel")]
         {
             use crate::image_sixel::sixel_parse;
@@ -2350,6 +2512,7 @@ unsafe fn input_dcs_dispatch(ictx: *mut input_ctx) -> i32 {
 
             let w = (*wp).window;
             if *buf == b'q'
+                && (*ictx).interm_len == 0
                 && let Some(si) = NonNull::new(sixel_parse(buf, len, (*w).xpixel, (*w).ypixel))
             {
                 screen_write_sixelimage(sctx, si.as_ptr(), (*ictx).cell.cell.bg as _);
@@ -2377,6 +2540,113 @@ unsafe fn input_dcs_dispatch(ictx: *mut input_ctx) -> i32 {
     }
 }
 
+/// Handle DECRQSS, a request for the current setting of SGR, DECSTBM or
+/// DECSCUSR. The reply is DCS 1 $ r with the setting, or DCS 0 $ r if it is not
+/// supported.
+unsafe fn input_dcs_decrqss(ictx: *mut input_ctx, request: &[u8]) {
+    unsafe {
+        let s = (*ictx).ctx.s;
+
+        let value = match request {
+            b"m" => Some(grid_string_cells_sgr(&raw const (*ictx).cell.cell)),
+            b"r" => Some(format!("{};{}", (*s).rupper + 1, (*s).rlower + 1)),
+            b" q" => {
+                let blinking = (*s).mode.intersects(mode_flag::MODE_CURSOR_BLINKING);
+                let style = match (*s).cstyle {
+                    screen_cursor_style::SCREEN_CURSOR_DEFAULT => 0,
+                    screen_cursor_style::SCREEN_CURSOR_BLOCK => 2 - blinking as u32,
+                    screen_cursor_style::SCREEN_CURSOR_UNDERLINE => 4 - blinking as u32,
+                    screen_cursor_style::SCREEN_CURSOR_BAR => 6 - blinking as u32,
+                };
+                Some(style.to_string())
+            }
+            _ => None,
+        };
+        match value {
+            Some(value) => input_reply!(
+                ictx,
+                "\x1bP1$r{}{}\x1b\\",
+                value,
+                String::from_utf8_lossy(request)
+            ),
+            None => input_reply!(ictx, "\x1bP0$r\x1b\\"),
+        }
+    }
+}
+
+/// Capabilities reported to XTGETTCAP, as for the default tmux-256color
+/// terminal with the extensions tmux understands. None is a flag.
+static INPUT_CAPABILITIES: &[(&str, Option<&str>)] = &[
+    ("Co", Some("256")),
+    ("Cr", Some("\x1b]112\x07")),
+    ("Cs", Some("\x1b]12;%p1%s\x07")),
+    ("Dsbp", Some("\x1b[?2004l")),
+    ("Dsfcs", Some("\x1b[?1004l")),
+    ("Enbp", Some("\x1b[?2004h")),
+    ("Enfcs", Some("\x1b[?1004h")),
+    ("Hls", Some("\x1b]8;%?%p1%l%tid=%p1%s%;;%p2%s\x1b\\")),
+    ("Ms", Some("\x1b]52;%p1%s;%p2%s\x07")),
+    ("PE", Some("\x1b[201~")),
+    ("PS", Some("\x1b[200~")),
+    ("RGB", None),
+    ("Se", Some("\x1b[2 q")),
+    ("Setulc", Some("\x1b[58:2::%p1%{65536}%/%d:%p1%{256}%/%{255}%&%d:%p1%{255}%&%d%;m")),
+    ("Smol", Some("\x1b[53m")),
+    ("Smulx", Some("\x1b[4:%p1%dm")),
+    ("Ss", Some("\x1b[%p1%d q")),
+    ("Sync", Some("\x1b[?2026%?%p1%{1}%-%tl%eh%;")),
+    ("Tc", None),
+    ("colors", Some("256")),
+    ("rmxx", Some("\x1b[29m")),
+    ("setrgbb", Some("\x1b[48:2:%p1%d:%p2%d:%p3%dm")),
+    ("setrgbf", Some("\x1b[38:2:%p1%d:%p2%d:%p3%dm")),
+    ("smxx", Some("\x1b[9m")),
+];
+
+/// Handle XTGETTCAP, a request for terminfo capabilities. The names are hex
+/// encoded and separated by semicolons. Each is answered separately with DCS 1
+/// + r name=value, or DCS 0 + r name if it is unknown.
+unsafe fn input_dcs_xtgettcap(ictx: *mut input_ctx, request: &[u8]) {
+    unsafe {
+        for hex in request.split(|&ch| ch == b';') {
+            let hex = String::from_utf8_lossy(hex);
+            let name = input_hex_decode(&hex).and_then(|name| String::from_utf8(name).ok());
+            log_debug!("{}: {:?}", "input_dcs_xtgettcap", name);
+
+            let value = match name.as_deref() {
+                Some("TN" | "name") => Some(Some(
+                    cstr_to_str(options_get_string_(GLOBAL_OPTIONS, "default-terminal"))
+                        .to_string(),
+                )),
+                Some(name) => INPUT_CAPABILITIES
+                    .iter()
+                    .find(|(cap, _)| *cap == name)
+                    .map(|(_, value)| value.map(str::to_string)),
+                None => None,
+            };
+            match value {
+                Some(Some(value)) => {
+                    let value: String = value.bytes().map(|b| format!("{b:02X}")).collect();
+                    input_reply!(ictx, "\x1bP1+r{hex}={value}\x1b\\");
+                }
+                Some(None) => input_reply!(ictx, "\x1bP1+r{hex}\x1b\\"),
+                None => input_reply!(ictx, "\x1bP0+r{hex}\x1b\\"),
+            }
+        }
+    }
+}
+
+/// Decode a string of hex digits.
+fn input_hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
 /// OSC string started.
 unsafe fn input_enter_osc(ictx: *mut input_ctx) {
     unsafe {
@@ -2441,21 +2711,25 @@ unsafe fn input_exit_osc(ictx: *mut input_ctx) {
                 if utf8_isvalid(p.cast()) {
                     screen_set_path((*sctx).s, p.cast());
                     if !wp.is_null() {
+                        input_osc_7(wp, p.cast());
                         server_redraw_window_borders((*wp).window);
                         server_status_window((*wp).window);
                     }
                 }
             }
             8 => input_osc_8(ictx, p.cast()),
+            9 => input_osc_9(ictx, p.cast()),
             10 => input_osc_10(ictx, p.cast()),
             11 => input_osc_11(ictx, p.cast()),
             12 => input_osc_12(ictx, p.cast()),
             52 => input_osc_52(ictx, p.cast()),
+            99 => input_osc_99(ictx, p.cast()),
             104 => input_osc_104(ictx, p.cast()),
             110 => input_osc_110(ictx, p.cast()),
             111 => input_osc_111(ictx, p.cast()),
             112 => input_osc_112(ictx, p.cast()),
             133 => input_osc_133(ictx, p.cast()),
+            777 => input_osc_777(ictx, p.cast()),
             _ => log_debug!("{}: unknown '{}'", "input_exit_osc", option),
         }
     }
@@ -2483,6 +2757,12 @@ unsafe fn input_exit_apc(ictx: *mut input_ctx) {
         }
         log_debug!("input_exit_apc: \"{}\"", _s((*ictx).input_buf.cast::<u8>()));
 
+        #[cfg(feature = "sixel")]
+        if *(*ictx).input_buf == b'G' {
+            input_kitty_graphics(ictx);
+            return;
+        }
+
         if screen_set_title((*sctx).s, (*ictx).input_buf.cast()) != 0 && !wp.is_null() {
             notify_pane(c"pane-title-changed", wp);
             server_redraw_window_borders((*wp).window);
@@ -2491,6 +2771,41 @@ unsafe fn input_exit_apc(ictx: *mut input_ctx) {
     }
 }
 
+/// Handle a kitty graphics protocol command (APC G).
+#[cfg(feature = "sixel")]
+unsafe fn input_kitty_graphics(ictx: *mut input_ctx) {
+    use crate::image_::image_free_kitty;
+    use crate::image_kitty::{kitty_action, kitty_parse};
+    use crate::screen_write::screen_write_kittyimage;
+
+    unsafe {
+        let sctx = &raw mut (*ictx).ctx;
+        let wp = (*ictx).wp;
+        if wp.is_null() {
+            return;
+        }
+        let w = (*wp).window;
+
+        let buf = std::slice::from_raw_parts((*ictx).input_buf.add(1), (*ictx).input_len - 1);
+        let state = (*ictx).kitty.get_or_insert_default();
+        let (action, reply) = kitty_parse(state, buf, (*w).xpixel, (*w).ypixel);
+        match action {
+            kitty_action::None => (),
+            kitty_action::Display(ki) => {
+                screen_write_kittyimage(sctx, ki, (*ictx).cell.cell.bg as _);
+            }
+            kitty_action::Delete(id) => {
+                if image_free_kitty((*sctx).s, id) {
+                    (*wp).flags |= window_pane_flags::PANE_REDRAW;
+                }
+            }
+        }
+        if let Some(reply) = reply {
+            input_reply!(ictx, "{reply}");
+        }
+    }
+}
+
 /// Rename string started.
 unsafe fn input_enter_rename(ictx: *mut input_ctx) {
     unsafe {
@@ -2668,6 +2983,50 @@ unsafe fn input_osc_4(ictx: *mut input_ctx, p: *const u8) {
     }
 }
 
+/// Decode a `file://host/path` URI into the host and path. The path is
+/// percent-decoded and must be absolute.
+fn input_parse_file_uri(uri: &[u8]) -> Option<(String, String)> {
+    let rest = uri.strip_prefix(b"file://")?;
+    let slash = rest.iter().position(|&ch| ch == b'/')?;
+    let (host, encoded) = rest.split_at(slash);
+
+    let mut path = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%' {
+            let hex = std::str::from_utf8(encoded.get(i + 1..i + 3)?).ok()?;
+            path.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            path.push(encoded[i]);
+            i += 1;
+        }
+    }
+    if path.iter().any(|&ch| ch < b' ') {
+        return None;
+    }
+
+    let host = String::from_utf8(host.to_vec()).ok()?;
+    let path = String::from_utf8(path).ok()?;
+    Some((host, path))
+}
+
+/// Store the working directory from an OSC 7 sequence, which is a file URI.
+unsafe fn input_osc_7(wp: *mut window_pane, p: *const u8) {
+    unsafe {
+        let Some((host, path)) = input_parse_file_uri(CStr::from_ptr(p.cast()).to_bytes()) else {
+            log_debug!("{}: bad URI \"{}\"", "input_osc_7", _s(p));
+            return;
+        };
+        log_debug!("{}: %{} host {} path {}", "input_osc_7", (*wp).id, host, path);
+
+        free_((*wp).reported_host);
+        free_((*wp).reported_cwd);
+        (*wp).reported_host = xstrdup__(&host);
+        (*wp).reported_cwd = xstrdup__(&path);
+    }
+}
+
 /// Handle the OSC 8 sequence for embedding hyperlinks.
 unsafe fn input_osc_8(ictx: *mut input_ctx, p: *const u8) {
     unsafe {
@@ -2763,6 +3122,60 @@ unsafe fn input_get_bg_client(wp: *mut window_pane) -> i32 {
     }
 }
 
+/// Get the background colour of a pane, from a control client, the pane's
+/// style or an attached client's terminal.
+unsafe fn input_get_bg(wp: *mut window_pane) -> i32 {
+    unsafe {
+        let mut defaults: grid_cell = zeroed();
+
+        let c = input_get_bg_control_client(wp);
+        if c != -1 {
+            return c;
+        }
+        tty_default_colours(&raw mut defaults, wp);
+        if COLOUR_DEFAULT(defaults.bg) {
+            return input_get_bg_client(wp);
+        }
+        defaults.bg
+    }
+}
+
+/// Get the theme of a pane for mode 2031 from its background colour: 1 if it
+/// is dark or unknown and 2 if it is light.
+unsafe fn input_theme(wp: *mut window_pane) -> u32 {
+    unsafe {
+        let mut c = input_get_bg(wp);
+        let mapped = colour_palette_get(Some(&(*wp).palette), c);
+        if mapped != -1 {
+            c = mapped;
+        }
+        if c == -1 || colour_force_rgb(c) == -1 {
+            return 1;
+        }
+        let (r, g, b) = colour_split_rgb(colour_force_rgb(c));
+        let luma = 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+        if luma < 128 * 1000 { 1 } else { 2 }
+    }
+}
+
+/// Tell panes which have asked for theme notifications (mode 2031) if the theme
+/// has changed.
+pub unsafe fn input_report_theme_all() {
+    unsafe {
+        for wp in rb_foreach(&raw mut ALL_WINDOW_PANES).map(NonNull::as_ptr) {
+            if (*wp).ictx.is_null() || !(*wp).base.mode.intersects(mode_flag::MODE_THEME) {
+                continue;
+            }
+            let theme = input_theme(wp);
+            if theme != (*wp).last_theme {
+                log_debug!("%{}: theme changed to {}", (*wp).id, theme);
+                (*wp).last_theme = theme;
+                input_reply!((*wp).ictx, "\x1b[?997;{theme}n");
+            }
+        }
+    }
+}
+
 /// If any control mode client exists that has provided a bg color, return it.
 /// Otherwise, return -1.
 unsafe fn input_get_bg_control_client(wp: *mut window_pane) -> i32 {
@@ -2860,28 +3273,16 @@ unsafe fn input_osc_110(ictx: *mut input_ctx, p: *const u8) {
 unsafe fn input_osc_11(ictx: *mut input_ctx, p: *const u8) {
     unsafe {
         let wp = (*ictx).wp;
-        let mut defaults: grid_cell = zeroed();
-
-        let mut c;
 
         if streq_(p, "?") {
             if wp.is_null() {
                 return;
             }
-            c = input_get_bg_control_client(wp);
-            if c == -1 {
-                tty_default_colours(&raw mut defaults, wp);
-                if COLOUR_DEFAULT(defaults.bg) {
-                    c = input_get_bg_client(wp);
-                } else {
-                    c = defaults.bg;
-                }
-            }
-            input_osc_colour_reply(ictx, 11, c);
+            input_osc_colour_reply(ictx, 11, input_get_bg(wp));
             return;
         }
 
-        c = colour_parse_x11(p);
+        let c = colour_parse_x11(p);
         if c == -1 {
             log_debug!("bad OSC 11: {}", _s(p));
             return;
@@ -2953,6 +3354,7 @@ unsafe fn input_osc_112(ictx: *mut input_ctx, p: *const u8) {
 /// Handle the OSC 133 sequence.
 unsafe fn input_osc_133(ictx: *mut input_ctx, p: *const u8) {
     unsafe {
+        let wp = (*ictx).wp;
         let gd = (*(*ictx).ctx.s).grid;
         let line = (*(*ictx).ctx.s).cy + (*gd).hsize;
 
@@ -2963,12 +3365,194 @@ unsafe fn input_osc_133(ictx: *mut input_ctx, p: *const u8) {
 
         match *p {
             b'A' => (*gl).flags |= grid_line_flag::START_PROMPT,
-            b'C' => (*gl).flags |= grid_line_flag::START_OUTPUT,
+            b'B' => (*gl).flags |= grid_line_flag::START_INPUT,
+            b'C' => {
+                (*gl).flags |= grid_line_flag::START_OUTPUT;
+                if !wp.is_null() {
+                    if libc::gettimeofday(&raw mut (*wp).command_start, null_mut()) != 0 {
+                        fatal("gettimeofday failed");
+                    }
+                    timerclear(&raw mut (*wp).command_end);
+                    (*wp).command_status = -1;
+                }
+            }
+            b'D' => {
+                // The exit status follows as D;status, it may be missing.
+                let mut status = -1;
+                if *p.add(1) == b';'
+                    && let Ok(value) = CStr::from_ptr(p.add(2).cast()).to_string_lossy().split(';').next().unwrap_or("").parse()
+                {
+                    status = value;
+                }
+                (*gl).flags |= grid_line_flag::END_OUTPUT;
+                (*gl).exit_status = status;
+
+                if !wp.is_null() && (*wp).command_start.tv_sec != 0 {
+                    if libc::gettimeofday(&raw mut (*wp).command_end, null_mut()) != 0 {
+                        fatal("gettimeofday failed");
+                    }
+                    (*wp).command_status = status;
+                }
+            }
             _ => (),
         }
     }
 }
 
+/// Record a desktop notification for the pane and pass it on to any clients
+/// with the pane's window whose terminals can show it.
+unsafe fn input_notify(ictx: *mut input_ctx, title: &[u8], body: &[u8]) {
+    unsafe {
+        let wp = (*ictx).wp;
+        if wp.is_null() || (title.is_empty() && body.is_empty()) {
+            return;
+        }
+        let flags =
+            vis_flags::VIS_OCTAL | vis_flags::VIS_CSTYLE | vis_flags::VIS_TAB | vis_flags::VIS_NL;
+
+        free_((*wp).notification_title);
+        free_((*wp).notification_body);
+        let title = CString::new(title.split(|&ch| ch == 0).next().unwrap_or(b"")).unwrap();
+        let body = CString::new(body.split(|&ch| ch == 0).next().unwrap_or(b"")).unwrap();
+        utf8_stravis(&raw mut (*wp).notification_title, title.as_ptr().cast(), flags);
+        utf8_stravis(&raw mut (*wp).notification_body, body.as_ptr().cast(), flags);
+        if libc::gettimeofday(&raw mut (*wp).notification_time, null_mut()) != 0 {
+            fatal("gettimeofday failed");
+        }
+        log_debug!(
+            "{}: %{} \"{}\" \"{}\"",
+            "input_notify",
+            (*wp).id,
+            _s((*wp).notification_title),
+            _s((*wp).notification_body)
+        );
+
+        notify_pane(c"pane-notification", wp);
+
+        for c in tailq_foreach(&raw mut CLIENTS).map(NonNull::as_ptr) {
+            if (*c).session.is_null()
+                || (*c).flags.intersects(client_flag::CONTROL)
+                || !session_has((*c).session, (*wp).window)
+            {
+                continue;
+            }
+            tty_notification(
+                &raw mut (*c).tty,
+                (*wp).notification_title,
+                (*wp).notification_body,
+            );
+        }
+    }
+}
+
+/// Handle the OSC 9 sequence for a notification. OSC 9;4 is used by some
+/// terminals to report progress and is ignored.
+unsafe fn input_osc_9(ictx: *mut input_ctx, p: *const u8) {
+    unsafe {
+        let body = CStr::from_ptr(p.cast()).to_bytes();
+        if body == b"4" || body.starts_with(b"4;") {
+            return;
+        }
+        input_notify(ictx, b"", body);
+    }
+}
+
+/// Handle the OSC 777 sequence for a notification, OSC 777;notify;title;body.
+unsafe fn input_osc_777(ictx: *mut input_ctx, p: *const u8) {
+    unsafe {
+        let Some(rest) = CStr::from_ptr(p.cast()).to_bytes().strip_prefix(b"notify;") else {
+            return;
+        };
+        let mut parts = rest.splitn(2, |&ch| ch == b';');
+        let title = parts.next().unwrap_or(b"");
+        let body = parts.next().unwrap_or(b"");
+        input_notify(ictx, title, body);
+    }
+}
+
+/// Handle the OSC 99 sequence for a kitty notification. This is OSC
+/// 99;metadata;payload where the metadata is a list of key=value separated by
+/// colons. The title and body may be sent separately and in chunks, the
+/// notification is shown when a chunk without d=0 arrives. Other payload
+/// types, such as icons, and actions are ignored.
+unsafe fn input_osc_99(ictx: *mut input_ctx, p: *const u8) {
+    unsafe {
+        let buf = CStr::from_ptr(p.cast()).to_bytes();
+        let Some(i) = buf.iter().position(|&ch| ch == b';') else {
+            return;
+        };
+        let (metadata, payload) = (&buf[..i], &buf[i + 1..]);
+
+        let mut id: &[u8] = b"";
+        let mut done = true;
+        let mut encoded = false;
+        let mut title = true;
+        for pair in metadata.split(|&ch| ch == b':') {
+            match pair {
+                [b'i', b'=', value @ ..] => id = value,
+                [b'd', b'=', value @ ..] => done = value != b"0",
+                [b'e', b'=', value @ ..] => encoded = value == b"1",
+                [b'p', b'=', value @ ..] => match value {
+                    b"title" => title = true,
+                    b"body" => title = false,
+                    _ => return,
+                },
+                _ => (),
+            }
+        }
+
+        let mut decoded = Vec::new();
+        let payload = if encoded {
+            let Ok(src) = CString::new(payload) else {
+                return;
+            };
+            decoded.resize(payload.len() + 1, 0);
+            let Ok(len) = usize::try_from(b64_pton(src.as_ptr().cast(), decoded.as_mut_ptr(), decoded.len())) else {
+                return;
+            };
+            decoded.truncate(len);
+            &decoded[..]
+        } else {
+            payload
+        };
+
+        let pending = (*ictx).notification.get_or_insert_default();
+        if pending.id != id {
+            **pending = input_notification {
+                id: id.to_vec(),
+                ..Default::default()
+            };
+        }
+        if pending.discard {
+            // Too big already, wait for the end.
+        } else if pending.title.len() + pending.body.len() + payload.len()
+            > INPUT_NOTIFICATION_LIMIT
+        {
+            log_debug!("input_osc_99: notification too big");
+            pending.title = Vec::new();
+            pending.body = Vec::new();
+            pending.discard = true;
+        } else if title {
+            pending.title.extend_from_slice(payload);
+        } else {
+            pending.body.extend_from_slice(payload);
+        }
+        if !done {
+            return;
+        }
+
+        let n = (*ictx).notification.take().unwrap();
+        if n.discard {
+            return;
+        }
+        if n.body.is_empty() {
+            input_notify(ictx, b"", &n.title);
+        } else {
+            input_notify(ictx, &n.title, &n.body);
+        }
+    }
+}
+
 /// Handle the OSC 52 sequence for setting the clipboard.
 unsafe fn input_osc_52(ictx: *mut input_ctx, p: *const u8) {
     let __func__ = "input_osc_52";
@@ -3125,3 +3709,281 @@ pub unsafe fn input_reply_clipboard(
         free_(out);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+    use crate::options_::{options_set_string, options_test_globals};
+
+    /// Panes are kept in global trees so tests which create them take turns.
+    static PANES: Mutex<()> = Mutex::new(());
+
+    /// A pane with an input parser whose replies are kept in a buffer.
+    struct TestPane {
+        wp: *mut window_pane,
+        /// how much of the buffer has been returned already
+        used: std::cell::Cell<usize>,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl TestPane {
+        unsafe fn new() -> Self {
+            unsafe {
+                let lock = PANES.lock().unwrap_or_else(|e| e.into_inner());
+                options_test_globals();
+                event_init();
+
+                let w = window_create(80, 24, 0, 0);
+                let wp = window_add_pane(w, null_mut(), 100, spawn_flags::empty());
+                let bev = bufferevent_new(-1, None, None, None, null_mut());
+                (*wp).ictx = input_init(wp, bev, &raw mut (*wp).palette);
+                Self {
+                    wp,
+                    used: Default::default(),
+                    _lock: lock,
+                }
+            }
+        }
+
+        /// Parse some input and return any replies to it.
+        unsafe fn parse(&self, input: &str) -> String {
+            unsafe {
+                let mut input = input.as_bytes().to_vec();
+                input_parse_buffer(self.wp, input.as_mut_ptr(), input.len());
+
+                let out = bufferevent_get_output((*(*self.wp).ictx).event);
+                let len = evbuffer_get_length(out);
+                if len == self.used.get() {
+                    return String::new();
+                }
+                let data = std::slice::from_raw_parts(evbuffer_pullup(out, -1), len);
+                String::from_utf8_lossy(&data[self.used.replace(len)..]).into_owned()
+            }
+        }
+
+        /// The grid line the cursor is on.
+        unsafe fn line(&self) -> *mut grid_line {
+            unsafe {
+                let gd = (*self.wp).base.grid;
+                grid_get_line(gd, (*gd).hsize + (*self.wp).base.cy)
+            }
+        }
+    }
+
+    #[test]
+    fn test_input_osc_133() {
+        unsafe {
+            let pane = TestPane::new();
+            let wp = pane.wp;
+
+            pane.parse("\x1b]133;A\x07$ \x1b]133;B\x1b\\ls\r\n");
+            let gd = (*wp).base.grid;
+            let prompt = grid_get_line(gd, (*gd).hsize);
+            assert!((*prompt).flags.contains(grid_line_flag::START_PROMPT));
+            assert!((*prompt).flags.contains(grid_line_flag::START_INPUT));
+            assert_eq!((*wp).command_status, -1);
+            assert_eq!((*wp).command_start.tv_sec, 0);
+
+            pane.parse("\x1b]133;C\x07");
+            assert!((*pane.line()).flags.intersects(grid_line_flag::START_OUTPUT));
+            assert_ne!((*wp).command_start.tv_sec, 0);
+            assert_eq!((*wp).command_end.tv_sec, 0);
+
+            // The exit status may have more parameters after it.
+            pane.parse("out\r\n\x1b]133;D;2;aid=1\x07");
+            let end = pane.line();
+            assert!((*end).flags.intersects(grid_line_flag::END_OUTPUT));
+            assert_eq!((*end).exit_status, 2);
+            assert_eq!((*wp).command_status, 2);
+            assert_ne!((*wp).command_end.tv_sec, 0);
+
+            // A missing or bad exit status is unknown.
+            for input in ["\x1b]133;D\x07", "\x1b]133;D;\x07", "\x1b]133;D;x\x07"] {
+                pane.parse("\x1b]133;C\x07\r\n");
+                pane.parse(input);
+                assert_eq!((*pane.line()).exit_status, -1, "{input:?}");
+                assert_eq!((*wp).command_status, -1, "{input:?}");
+            }
+
+            // Unknown marks do nothing.
+            pane.parse("\r\n\x1b]133;Z\x07");
+            assert!(!(*pane.line()).flags.intersects(
+                grid_line_flag::START_PROMPT
+                    | grid_line_flag::START_INPUT
+                    | grid_line_flag::START_OUTPUT
+                    | grid_line_flag::END_OUTPUT
+            ));
+        }
+    }
+
+    #[test]
+    fn test_input_decrqss() {
+        unsafe {
+            let pane = TestPane::new();
+
+            assert_eq!(pane.parse("\x1bP$qm\x1b\\"), "\x1bP1$r0m\x1b\\");
+            pane.parse("\x1b[1;4;31m");
+            assert_eq!(pane.parse("\x1bP$qm\x1b\\"), "\x1bP1$r0;1;4;31m\x1b\\");
+            pane.parse("\x1b[0m");
+
+            assert_eq!(pane.parse("\x1bP$qr\x1b\\"), "\x1bP1$r1;24r\x1b\\");
+            pane.parse("\x1b[5;10r");
+            assert_eq!(pane.parse("\x1bP$qr\x1b\\"), "\x1bP1$r5;10r\x1b\\");
+
+            assert_eq!(pane.parse("\x1bP$q q\x1b\\"), "\x1bP1$r0 q\x1b\\");
+            pane.parse("\x1b[6 q");
+            assert_eq!(pane.parse("\x1bP$q q\x1b\\"), "\x1bP1$r6 q\x1b\\");
+            pane.parse("\x1b[3 q");
+            assert_eq!(pane.parse("\x1bP$q q\x1b\\"), "\x1bP1$r3 q\x1b\\");
+
+            // Anything else is invalid.
+            assert_eq!(pane.parse("\x1bP$qs\x1b\\"), "\x1bP0$r\x1b\\");
+            assert_eq!(pane.parse("\x1bP$q\x1b\\"), "\x1bP0$r\x1b\\");
+        }
+    }
+
+    #[test]
+    fn test_input_xtgettcap() {
+        unsafe {
+            let pane = TestPane::new();
+
+            // Names and values are hex encoded and the name is returned as
+            // it was sent.
+            assert_eq!(pane.parse("\x1bP+q436F\x1b\\"), "\x1bP1+r436F=323536\x1b\\");
+            assert_eq!(pane.parse("\x1bP+q436f\x1b\\"), "\x1bP1+r436f=323536\x1b\\");
+            // TN is default-terminal, "screen".
+            assert_eq!(pane.parse("\x1bP+q544E\x1b\\"), "\x1bP1+r544E=73637265656E\x1b\\");
+            assert_eq!(
+                pane.parse("\x1bP+q536D6F6C\x1b\\"),
+                "\x1bP1+r536D6F6C=1B5B35336D\x1b\\"
+            );
+
+            // Flags have no value, and each name gets its own reply.
+            assert_eq!(
+                pane.parse("\x1bP+q524742;54630;787878\x1b\\"),
+                "\x1bP1+r524742\x1b\\\x1bP0+r54630\x1b\\\x1bP0+r787878\x1b\\"
+            );
+            assert_eq!(pane.parse("\x1bP+q5463\x1b\\"), "\x1bP1+r5463\x1b\\");
+
+            // Names which are not hex are invalid.
+            assert_eq!(pane.parse("\x1bP+qCo\x1b\\"), "\x1bP0+rCo\x1b\\");
+            assert_eq!(pane.parse("\x1bP+q\x1b\\"), "\x1bP0+r\x1b\\");
+        }
+    }
+
+    #[test]
+    fn test_input_decrqm() {
+        unsafe {
+            let pane = TestPane::new();
+
+            assert_eq!(pane.parse("\x1b[4$p"), "\x1b[4;2$y");
+            pane.parse("\x1b[4h");
+            assert_eq!(pane.parse("\x1b[4$p"), "\x1b[4;1$y");
+            assert_eq!(pane.parse("\x1b[20$p"), "\x1b[20;4$y");
+            assert_eq!(pane.parse("\x1b[99$p"), "\x1b[99;0$y");
+
+            assert_eq!(pane.parse("\x1b[?25$p"), "\x1b[?25;1$y");
+            assert_eq!(pane.parse("\x1b[?2004$p"), "\x1b[?2004;2$y");
+            pane.parse("\x1b[?2004h");
+            assert_eq!(pane.parse("\x1b[?2004$p"), "\x1b[?2004;1$y");
+            assert_eq!(pane.parse("\x1b[?3$p"), "\x1b[?3;4$y");
+            assert_eq!(pane.parse("\x1b[?1049$p"), "\x1b[?1049;2$y");
+            pane.parse("\x1b[?1049h");
+            assert_eq!(pane.parse("\x1b[?1049$p"), "\x1b[?1049;1$y");
+            assert_eq!(pane.parse("\x1b[?2026$p"), "\x1b[?2026;2$y");
+            assert_eq!(pane.parse("\x1b[?9999$p"), "\x1b[?9999;0$y");
+        }
+    }
+
+    #[test]
+    fn test_input_theme() {
+        unsafe {
+            let pane = TestPane::new();
+            let wp = pane.wp;
+            let style = move |style: &str| {
+                options_set_string!((*wp).options, "window-style", false, "{style}");
+                (*wp).flags |= window_pane_flags::PANE_STYLECHANGED;
+            };
+
+            // An unknown background is dark.
+            assert_eq!(input_theme(wp), 1);
+            assert_eq!(pane.parse("\x1b[?996n"), "\x1b[?997;1n");
+
+            // Light is a luma of at least half.
+            for (bg, theme) in [
+                ("#ffffff", 2),
+                ("#808080", 2),
+                ("#7f7f7f", 1),
+                ("#00ff00", 2),
+                ("#0000ff", 1),
+                ("#ff0000", 1),
+                ("colour3", 1),
+                ("colour11", 2),
+                ("colour255", 2),
+                ("black", 1),
+            ] {
+                style(&format!("bg={bg}"));
+                assert_eq!(input_theme(wp), theme, "{bg}");
+            }
+            style("bg=#ffffff");
+            assert_eq!(pane.parse("\x1b[?996n"), "\x1b[?997;2n");
+
+            // The pane's palette changes what the colour looks like.
+            style("bg=colour0");
+            assert_eq!(input_theme(wp), 1);
+            colour_palette_set(Some(&mut (*wp).palette), 0, colour_join_rgb(255, 255, 255));
+            assert_eq!(input_theme(wp), 2);
+            colour_palette_set(Some(&mut (*wp).palette), 0, -1);
+            style("default");
+        }
+    }
+
+    #[test]
+    fn test_input_theme_report() {
+        unsafe {
+            let pane = TestPane::new();
+            let wp = pane.wp;
+            let style = move |style: &str| {
+                options_set_string!((*wp).options, "window-style", false, "{style}");
+                (*wp).flags |= window_pane_flags::PANE_STYLECHANGED;
+                input_report_theme_all();
+            };
+
+            // Nothing is reported unless the mode is set.
+            style("bg=#ffffff");
+            assert_eq!(pane.parse(""), "");
+
+            // Setting the mode does not report the theme, changes to it are
+            // reported once.
+            assert_eq!(pane.parse("\x1b[?2031h"), "");
+            style("bg=#000000");
+            assert_eq!(pane.parse(""), "\x1b[?997;1n");
+            style("bg=#101010");
+            assert_eq!(pane.parse(""), "");
+            style("bg=#eeeeee");
+            assert_eq!(pane.parse(""), "\x1b[?997;2n");
+
+            pane.parse("\x1b[?2031l");
+            style("default");
+            assert_eq!(pane.parse(""), "");
+        }
+    }
+
+    #[test]
+    fn test_parse_file_uri() {
+        assert_eq!(
+            input_parse_file_uri(b"file://host/tmp/a%20b"),
+            Some(("host".to_string(), "/tmp/a b".to_string()))
+        );
+        assert_eq!(
+            input_parse_file_uri(b"file:///home"),
+            Some((String::new(), "/home".to_string()))
+        );
+        assert_eq!(input_parse_file_uri(b"/home"), None);
+        assert_eq!(input_parse_file_uri(b"file://host"), None);
+        assert_eq!(input_parse_file_uri(b"file://host/a%2"), None);
+        assert_eq!(input_parse_file_uri(b"file://host/a%0a"), None);
+    }
+}
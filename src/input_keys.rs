@@ -409,6 +409,199 @@ pub unsafe fn input_key_mode1(bev: *mut bufferevent, key: key_code) -> i32 {
     -1
 }
 
+/// Outputs the key using the kitty keyboard protocol, as requested by the
+/// application with CSI > u. Keys which the protocol leaves in the legacy form
+/// return -1 so they are sent by the caller, unless they are releases which are
+/// never sent in legacy form.
+pub unsafe fn input_key_kitty(
+    bev: *mut bufferevent,
+    mut key: key_code,
+    mut event: key_code,
+    flags: kitty_key_flags,
+) -> i32 {
+    let __func__ = c!("input_key_kitty");
+    unsafe {
+        let mut wc: wchar_t = 0;
+
+        // Without event reporting, repeats are presses and releases are lost.
+        if !flags.intersects(kitty_key_flags::KITTY_KEYS_REPORT_EVENTS) {
+            if event == KEYC_RELEASE {
+                return 0;
+            }
+            event = 0;
+        }
+        let legacy = if event == KEYC_RELEASE { 0 } else { -1 };
+        let all = flags.intersects(kitty_key_flags::KITTY_KEYS_REPORT_ALL);
+
+        // Backtab is Shift-Tab.
+        if (key & KEYC_MASK_KEY) == keyc::KEYC_BTAB as u64 {
+            key = b'\t' as u64 | (key & !KEYC_MASK_KEY) | KEYC_SHIFT;
+        }
+
+        // Work out the base key and modifiers. C0 control characters which
+        // arrived without a Ctrl modifier are turned back into keys with Ctrl.
+        let mut onlykey = key & KEYC_MASK_KEY;
+        let mut modifiers = key & KEYC_MASK_MODIFIERS;
+        if onlykey < 0x20
+            && onlykey != b'\t' as u64
+            && onlykey != b'\r' as u64
+            && onlykey != b'\x1b' as u64
+        {
+            onlykey = match onlykey {
+                0 => b' ' as u64,
+                0x1c..=0x1f => onlykey + 0x40,
+                _ => onlykey + 0x60,
+            };
+            modifiers |= KEYC_CTRL;
+        }
+        if onlykey == keyc::KEYC_BSPACE as u64 {
+            onlykey = 0x7f;
+        }
+
+        // Keys are reported unshifted, with the shifted key as alternate.
+        let mut shifted = None;
+        if onlykey < 0x80 && (onlykey as u8).is_ascii_uppercase() {
+            shifted = Some(onlykey);
+            onlykey = (onlykey as u8).to_ascii_lowercase() as u64;
+            modifiers |= KEYC_SHIFT;
+        }
+        let mut number = 0u32;
+        if modifiers & KEYC_SHIFT != 0 {
+            number |= 1;
+        }
+        if modifiers & KEYC_META != 0 {
+            number |= 2;
+        }
+        if modifiers & KEYC_CTRL != 0 {
+            number |= 4;
+        }
+        let mut suffix = String::new();
+        if number != 0 || event != 0 {
+            suffix = format!(";{}", number + 1);
+            match event {
+                KEYC_REPEAT => suffix.push_str(":2"),
+                KEYC_RELEASE => suffix.push_str(":3"),
+                _ => (),
+            }
+        }
+
+        // Functional keys have their own sequences. Unmodified presses are sent
+        // in the legacy form unless all keys are to be reported.
+        let functional = match onlykey {
+            k if k == keyc::KEYC_UP as u64 => Some((1, 'A')),
+            k if k == keyc::KEYC_DOWN as u64 => Some((1, 'B')),
+            k if k == keyc::KEYC_RIGHT as u64 => Some((1, 'C')),
+            k if k == keyc::KEYC_LEFT as u64 => Some((1, 'D')),
+            k if k == keyc::KEYC_END as u64 => Some((1, 'F')),
+            k if k == keyc::KEYC_HOME as u64 => Some((1, 'H')),
+            k if k == keyc::KEYC_F1 as u64 => Some((1, 'P')),
+            k if k == keyc::KEYC_F2 as u64 => Some((1, 'Q')),
+            k if k == keyc::KEYC_F3 as u64 => Some((13, '~')),
+            k if k == keyc::KEYC_F4 as u64 => Some((1, 'S')),
+            k if k == keyc::KEYC_F5 as u64 => Some((15, '~')),
+            k if k == keyc::KEYC_F6 as u64 => Some((17, '~')),
+            k if k == keyc::KEYC_F7 as u64 => Some((18, '~')),
+            k if k == keyc::KEYC_F8 as u64 => Some((19, '~')),
+            k if k == keyc::KEYC_F9 as u64 => Some((20, '~')),
+            k if k == keyc::KEYC_F10 as u64 => Some((21, '~')),
+            k if k == keyc::KEYC_F11 as u64 => Some((23, '~')),
+            k if k == keyc::KEYC_F12 as u64 => Some((24, '~')),
+            k if k == keyc::KEYC_IC as u64 => Some((2, '~')),
+            k if k == keyc::KEYC_DC as u64 => Some((3, '~')),
+            k if k == keyc::KEYC_PPAGE as u64 => Some((5, '~')),
+            k if k == keyc::KEYC_NPAGE as u64 => Some((6, '~')),
+            _ => None,
+        };
+        if let Some((n, end)) = functional {
+            if suffix.is_empty() && !all {
+                return legacy;
+            }
+            let out = if n == 1 && suffix.is_empty() {
+                format!("\x1b[{end}")
+            } else {
+                format!("\x1b[{n}{suffix}{end}")
+            };
+            input_key_write(__func__, bev, out.as_bytes().as_ptr().cast(), out.len());
+            return 0;
+        }
+
+        // The keypad has its own key numbers.
+        let keypad = match onlykey {
+            k if k == keyc::KEYC_KP_ZERO as u64 => Some(57399),
+            k if k == keyc::KEYC_KP_ONE as u64 => Some(57400),
+            k if k == keyc::KEYC_KP_TWO as u64 => Some(57401),
+            k if k == keyc::KEYC_KP_THREE as u64 => Some(57402),
+            k if k == keyc::KEYC_KP_FOUR as u64 => Some(57403),
+            k if k == keyc::KEYC_KP_FIVE as u64 => Some(57404),
+            k if k == keyc::KEYC_KP_SIX as u64 => Some(57405),
+            k if k == keyc::KEYC_KP_SEVEN as u64 => Some(57406),
+            k if k == keyc::KEYC_KP_EIGHT as u64 => Some(57407),
+            k if k == keyc::KEYC_KP_NINE as u64 => Some(57408),
+            k if k == keyc::KEYC_KP_PERIOD as u64 => Some(57409),
+            k if k == keyc::KEYC_KP_SLASH as u64 => Some(57410),
+            k if k == keyc::KEYC_KP_STAR as u64 => Some(57411),
+            k if k == keyc::KEYC_KP_MINUS as u64 => Some(57412),
+            k if k == keyc::KEYC_KP_PLUS as u64 => Some(57413),
+            k if k == keyc::KEYC_KP_ENTER as u64 => Some(57414),
+            _ => None,
+        };
+        if let Some(n) = keypad {
+            let out = format!("\x1b[{n}{suffix}u");
+            input_key_write(__func__, bev, out.as_bytes().as_ptr().cast(), out.len());
+            return 0;
+        }
+
+        // Anything else that isn't a character is left alone.
+        if KEYC_IS_UNICODE(onlykey) {
+            let ud = utf8_to_data(onlykey as u32);
+            if utf8_towc(&ud, &raw mut wc) != utf8_state::UTF8_DONE {
+                return -1;
+            }
+            onlykey = wc as u64;
+        } else if onlykey > 0x7f {
+            return legacy;
+        }
+
+        // Text keys and unmodified Enter, Tab and Backspace are sent in the
+        // legacy form unless all keys are to be reported. Escape is always
+        // reported so it can't be confused with the start of a sequence.
+        let text = onlykey != 0x7f
+            && onlykey != b'\t' as u64
+            && onlykey != b'\r' as u64
+            && onlykey != b'\x1b' as u64;
+        if !all {
+            if text && modifiers & (KEYC_META | KEYC_CTRL) == 0 {
+                return legacy;
+            }
+            if !text && onlykey != b'\x1b' as u64 && modifiers == 0 {
+                return legacy;
+            }
+        }
+
+        let mut out = format!("\x1b[{onlykey}");
+        if let Some(shifted) = shifted
+            && flags.intersects(kitty_key_flags::KITTY_KEYS_REPORT_ALTERNATES)
+        {
+            out.push_str(&format!(":{shifted}"));
+        }
+        if flags.intersects(kitty_key_flags::KITTY_KEYS_REPORT_TEXT)
+            && text
+            && event != KEYC_RELEASE
+            && modifiers & (KEYC_META | KEYC_CTRL) == 0
+        {
+            if suffix.is_empty() {
+                suffix = ";1".to_string();
+            }
+            suffix.push_str(&format!(";{}", shifted.unwrap_or(onlykey)));
+        }
+        out.push_str(&suffix);
+        out.push('u');
+        log_debug!("{}: key 0x{:x} is {:?}", _s(__func__), key, out);
+        input_key_write(__func__, bev, out.as_bytes().as_ptr().cast(), out.len());
+        0
+    }
+}
+
 /// Translate a key code into an output key sequence.
 pub unsafe fn input_key(s: *mut screen, bev: *mut bufferevent, mut key: key_code) -> i32 {
     let __func__ = c!("input_key");
@@ -428,6 +621,19 @@ pub unsafe fn input_key(s: *mut screen, bev: *mut bufferevent, mut key: key_code
             return 0;
         }
 
+        // Is the application using the kitty keyboard protocol? Releases are
+        // only ever sent that way.
+        let event = key & KEYC_MASK_EVENT;
+        key &= !KEYC_MASK_EVENT;
+        if !(*s).kitty_keys.flags.is_empty()
+            && input_key_kitty(bev, key, event, (*s).kitty_keys.flags) == 0
+        {
+            return 0;
+        }
+        if event == KEYC_RELEASE {
+            return 0;
+        }
+
         // Is this backspace?
         if (key & KEYC_MASK_KEY) == keyc::KEYC_BSPACE as u64 {
             let mut newkey = options_get_number_(GLOBAL_OPTIONS, "backspace") as key_code;
@@ -687,3 +893,114 @@ pub unsafe fn input_key_mouse(wp: *mut window_pane, m: *mut mouse_event) {
         input_key_write(__func__, (*wp).event, buf, len);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a key with the kitty keyboard protocol, returning what
+    /// input_key_kitty returned and what it wrote.
+    unsafe fn kitty(key: key_code, event: key_code, flags: kitty_key_flags) -> (i32, String) {
+        unsafe {
+            let bev = bufferevent_new(-1, None, None, None, null_mut());
+            let retval = input_key_kitty(bev, key, event, flags);
+            let out = bufferevent_get_output(bev);
+            let mut text = String::new();
+            let len = evbuffer_get_length(out);
+            if len != 0 {
+                let data = std::slice::from_raw_parts(evbuffer_pullup(out, -1), len);
+                text = String::from_utf8(data.to_vec()).unwrap();
+            }
+            bufferevent_free(bev);
+            (retval, text)
+        }
+    }
+
+    #[test]
+    fn test_input_key_kitty_modifiers() {
+        unsafe {
+            event_init();
+            let flags = kitty_key_flags::KITTY_KEYS_DISAMBIGUATE;
+            let key = |key| kitty(key, 0, flags);
+
+            // Plain text is left to the legacy encoding.
+            assert_eq!(key(b'a' as u64), (-1, String::new()));
+            assert_eq!(key(b'A' as u64), (-1, String::new()));
+            assert_eq!(key(b'\r' as u64), (-1, String::new()));
+
+            assert_eq!(key(b'a' as u64 | KEYC_CTRL), (0, "\x1b[97;5u".into()));
+            assert_eq!(key(0x01), (0, "\x1b[97;5u".into()));
+            assert_eq!(key(b'a' as u64 | KEYC_META), (0, "\x1b[97;3u".into()));
+            assert_eq!(key(b'A' as u64 | KEYC_CTRL), (0, "\x1b[97;6u".into()));
+            assert_eq!(
+                key(b'a' as u64 | KEYC_CTRL | KEYC_META | KEYC_SHIFT),
+                (0, "\x1b[97;8u".into())
+            );
+            assert_eq!(key(b'\x1b' as u64), (0, "\x1b[27u".into()));
+            assert_eq!(key(b'\r' as u64 | KEYC_SHIFT), (0, "\x1b[13;2u".into()));
+            assert_eq!(key(keyc::KEYC_BTAB as u64), (0, "\x1b[9;2u".into()));
+            assert_eq!(key(keyc::KEYC_BSPACE as u64 | KEYC_CTRL), (0, "\x1b[127;5u".into()));
+
+            assert_eq!(key(keyc::KEYC_UP as u64), (-1, String::new()));
+            assert_eq!(key(keyc::KEYC_UP as u64 | KEYC_CTRL), (0, "\x1b[1;5A".into()));
+            assert_eq!(key(keyc::KEYC_F5 as u64 | KEYC_SHIFT), (0, "\x1b[15;2~".into()));
+
+            let all = kitty_key_flags::KITTY_KEYS_REPORT_ALL
+                | kitty_key_flags::KITTY_KEYS_REPORT_ALTERNATES
+                | kitty_key_flags::KITTY_KEYS_REPORT_TEXT;
+            assert_eq!(kitty(b'a' as u64, 0, all), (0, "\x1b[97;1;97u".into()));
+            assert_eq!(kitty(b'A' as u64, 0, all), (0, "\x1b[97:65;2;65u".into()));
+            assert_eq!(kitty(keyc::KEYC_UP as u64, 0, all), (0, "\x1b[A".into()));
+        }
+    }
+
+    #[test]
+    fn test_input_key_kitty_events() {
+        unsafe {
+            event_init();
+            let flags =
+                kitty_key_flags::KITTY_KEYS_DISAMBIGUATE | kitty_key_flags::KITTY_KEYS_REPORT_EVENTS;
+
+            // Releases are never sent in legacy form, so those of plain text
+            // keys are only reported if all keys are.
+            assert_eq!(kitty(b'a' as u64, KEYC_RELEASE, flags), (0, String::new()));
+            let all = flags | kitty_key_flags::KITTY_KEYS_REPORT_ALL;
+            assert_eq!(kitty(b'a' as u64, KEYC_RELEASE, all), (0, "\x1b[97;1:3u".into()));
+            assert_eq!(
+                kitty(b'a' as u64 | KEYC_CTRL, KEYC_REPEAT, flags),
+                (0, "\x1b[97;5:2u".into())
+            );
+            assert_eq!(
+                kitty(keyc::KEYC_LEFT as u64, KEYC_RELEASE, flags),
+                (0, "\x1b[1;1:3D".into())
+            );
+
+            // Without event reporting releases are dropped and repeats are
+            // presses.
+            let flags = kitty_key_flags::KITTY_KEYS_DISAMBIGUATE;
+            assert_eq!(
+                kitty(b'a' as u64 | KEYC_CTRL, KEYC_RELEASE, flags),
+                (0, String::new())
+            );
+            assert_eq!(
+                kitty(b'a' as u64 | KEYC_CTRL, KEYC_REPEAT, flags),
+                (0, "\x1b[97;5u".into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_input_key_kitty_keypad() {
+        unsafe {
+            event_init();
+            let flags = kitty_key_flags::KITTY_KEYS_DISAMBIGUATE;
+
+            assert_eq!(kitty(keyc::KEYC_KP_ZERO as u64, 0, flags), (0, "\x1b[57399u".into()));
+            assert_eq!(kitty(keyc::KEYC_KP_ENTER as u64, 0, flags), (0, "\x1b[57414u".into()));
+            assert_eq!(
+                kitty(keyc::KEYC_KP_PLUS as u64 | KEYC_SHIFT, 0, flags),
+                (0, "\x1b[57413;2u".into())
+            );
+        }
+    }
+}
@@ -64,9 +64,12 @@ cfg_pub_mods! {
     mod environ_;
     mod file;
     mod format;
+    mod fuzzy;
     mod format_draw_;
     mod grid_;
+    mod grid_index_;
     mod grid_reader_;
+    mod grid_spill_;
     mod grid_view;
     mod hyperlinks_;
     mod input;
@@ -87,6 +90,7 @@ cfg_pub_mods! {
     mod paste;
     mod popup;
     mod proc;
+    mod regex_;
     mod regsub;
     mod resize;
     mod screen_;
@@ -98,6 +102,7 @@ cfg_pub_mods! {
     mod server_fn;
     mod session_;
     mod spawn;
+    mod state;
     mod status;
     mod style_;
     mod tmux;
@@ -109,12 +114,14 @@ cfg_pub_mods! {
     mod tty_term_;
     mod utf8;
     mod utf8_combined;
+    mod utf8_table;
     mod window_;
     mod window_buffer;
     mod window_client;
     mod window_clock;
     mod window_copy;
     mod window_customize;
+    mod window_search;
     mod window_tree;
     mod xmalloc;
 }
@@ -160,8 +167,11 @@ use crate::{
     file::*,
     format::*,
     format_draw_::*,
+    fuzzy::*,
     grid_::*,
+    grid_index_::*,
     grid_reader_::*,
+    grid_spill_::*,
     grid_view::*,
     hyperlinks_::*,
     input::*,
@@ -182,6 +192,7 @@ use crate::{
     paste::*,
     popup::*,
     proc::*,
+    regex_::*,
     regsub::regsub,
     resize::*,
     screen_::*,
@@ -193,6 +204,7 @@ use crate::{
     server_fn::*,
     session_::*,
     spawn::*,
+    state::*,
     status::*,
     style_::*,
     tmux::*,
@@ -204,12 +216,14 @@ use crate::{
     tty_term_::*,
     utf8::*,
     utf8_combined::*,
+    utf8_table::*,
     window_::*,
     window_buffer::WINDOW_BUFFER_MODE,
     window_client::WINDOW_CLIENT_MODE,
     window_clock::{WINDOW_CLOCK_MODE, WINDOW_CLOCK_TABLE},
     window_copy::{window_copy_add, *},
     window_customize::WINDOW_CUSTOMIZE_MODE,
+    window_search::WINDOW_SEARCH_MODE,
     window_tree::WINDOW_TREE_MODE,
     xmalloc::*,
 };
@@ -217,13 +231,22 @@ use crate::{
 #[cfg(feature = "sixel")]
 mod image_;
 #[cfg(feature = "sixel")]
+mod image_kitty;
+#[cfg(feature = "sixel")]
 mod image_sixel;
 #[cfg(feature = "sixel")]
+use image_kitty::{kitty_image, kitty_state};
+#[cfg(feature = "sixel")]
 use image_sixel::sixel_image;
 
 #[cfg(feature = "utempter")]
 mod utempter;
 
+#[cfg(feature = "web")]
+mod web;
+#[cfg(feature = "web")]
+use web::{web_start, web_stop};
+
 macro_rules! env_or {
     ($key:literal, $default:expr) => {
         match std::option_env!($key) {
@@ -332,6 +355,13 @@ const KEYC_MASK_MODIFIERS: c_ulonglong = 0x00f00000000000;
 const KEYC_MASK_FLAGS: c_ulonglong = 0xff000000000000;
 const KEYC_MASK_KEY: c_ulonglong = 0x000fffffffffff;
 
+// Key event bits, only reported by terminals using the kitty keyboard
+// protocol. A key without either is a press. These are outside the flags so
+// they are never part of a key binding.
+const KEYC_REPEAT: c_ulonglong = 0x100000000000000;
+const KEYC_RELEASE: c_ulonglong = 0x200000000000000;
+const KEYC_MASK_EVENT: c_ulonglong = 0x300000000000000;
+
 const KEYC_NUSER: c_ulonglong = 1000;
 
 #[expect(non_snake_case)]
@@ -451,6 +481,7 @@ enum tty_code_code {
     TTYC_DSBP,
     TTYC_DSEKS,
     TTYC_DSFCS,
+    TTYC_DSKKS,
     TTYC_DSMG,
     TTYC_E3,
     TTYC_ECH,
@@ -461,6 +492,7 @@ enum tty_code_code {
     TTYC_ENBP,
     TTYC_ENEKS,
     TTYC_ENFCS,
+    TTYC_ENKKS,
     TTYC_ENMG,
     TTYC_FSL,
     TTYC_HLS,
@@ -611,6 +643,7 @@ enum tty_code_code {
     TTYC_KUP7,
     TTYC_MS,
     TTYC_NOBR,
+    TTYC_NOTIFY,
     TTYC_OL,
     TTYC_OP,
     TTYC_RECT,
@@ -683,6 +716,9 @@ bitflags::bitflags! {
         const MODE_CURSOR_VERY_VISIBLE = 0x10000;
         const MODE_CURSOR_BLINKING_SET = 0x20000;
         const MODE_KEYS_EXTENDED_2 = 0x40000;
+        const MODE_SYNC = 0x80000;
+        const MODE_THEME = 0x100000;
+        const MODE_GRAPHEMES = 0x200000;
     }
 }
 
@@ -770,6 +806,10 @@ bitflags::bitflags! {
         const DEAD         = 1 << 2; // 0x4
         const START_PROMPT = 1 << 3; // 0x8
         const START_OUTPUT = 1 << 4; // 0x10
+        const START_INPUT  = 1 << 5; // 0x20
+        const END_OUTPUT   = 1 << 6; // 0x40
+        const COMPRESSED   = 1 << 7; // 0x80
+        const SPILLED      = 1 << 8; // 0x100
     }
 }
 
@@ -894,6 +934,16 @@ struct grid_line {
 
     flags: grid_line_flag,
     time: time_t,
+
+    /// exit status of the command which ended on this line, if `END_OUTPUT`
+    /// is set; -1 if it was not reported
+    exit_status: i32,
+
+    /// encoded cells if `COMPRESSED` is set, in which case `celldata` and
+    /// `extddata` are empty; not yet read from the spill file if `SPILLED`
+    /// is also set
+    compressed: *mut u8,
+    compressedsize: u32,
 }
 
 const GRID_HISTORY: i32 = 0x1; // scroll lines into history
@@ -911,6 +961,18 @@ struct grid {
     hlimit: u32,
 
     linedata: *mut grid_line,
+
+    /// history lines uncompressed since the history was last compressed
+    expanded: Vec<u32>,
+
+    /// lines collected from the history are written here if not null
+    spill: *mut grid_spill,
+
+    /// placeholder lines still to be read from a spill file if not null
+    spilled: *mut grid_spill_lines,
+
+    /// index of the history for searching if not null
+    index: *mut grid_index,
 }
 
 /// Virtual cursor in a grid.
@@ -1016,7 +1078,10 @@ impl crate::compat::queue::Entry<image, discr_entry> for image {
 #[derive(Copy, Clone)]
 struct image {
     s: *mut screen,
+    /// Sixel image, or null for a kitty image.
     data: *mut sixel_image,
+    /// Kitty image, or null for a sixel image.
+    kitty: *mut kitty_image,
     fallback: *mut u8,
     px: u32,
     py: u32,
@@ -1030,6 +1095,33 @@ struct image {
 #[cfg(feature = "sixel")]
 type images = tailq_head<image>;
 
+bitflags::bitflags! {
+    /// Kitty keyboard protocol progressive enhancement flags.
+    #[repr(transparent)]
+    #[derive(Copy, Clone, Default, Eq, PartialEq)]
+    struct kitty_key_flags : u32 {
+        const KITTY_KEYS_DISAMBIGUATE = 0x1;
+        const KITTY_KEYS_REPORT_EVENTS = 0x2;
+        const KITTY_KEYS_REPORT_ALTERNATES = 0x4;
+        const KITTY_KEYS_REPORT_ALL = 0x8;
+        const KITTY_KEYS_REPORT_TEXT = 0x10;
+    }
+}
+
+/// Number of entries kept on the kitty keyboard flags stack; pushing more
+/// discards the oldest.
+const KITTY_KEYS_STACK_SIZE: usize = 8;
+
+/// Kitty keyboard protocol state. The main and alternate screens each have
+/// their own.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct screen_kitty_keys {
+    flags: kitty_key_flags,
+    stack: [kitty_key_flags; KITTY_KEYS_STACK_SIZE],
+    depth: usize,
+}
+
 /// Cursor style.
 #[repr(i32)]
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -1078,6 +1170,9 @@ struct screen {
     saved_cell: grid_cell,
     saved_flags: i32,
 
+    kitty_keys: screen_kitty_keys,
+    saved_kitty_keys: screen_kitty_keys,
+
     tabs: Option<Rc<RefCell<BitStr>>>,
     sel: *mut screen_sel,
 
@@ -1330,6 +1425,11 @@ struct window_pane {
     shell: *mut u8,
     cwd: *mut u8,
 
+    /// working directory reported by the application with OSC 7 and the
+    /// host it is on
+    reported_host: *mut u8,
+    reported_cwd: *mut u8,
+
     pid: pid_t,
     tty: [u8; TTY_NAME_MAX],
     status: i32,
@@ -1344,6 +1444,25 @@ struct window_pane {
     resize_queue: window_pane_resizes,
     resize_timer: event,
 
+    /// fires if the application never ends a synchronized update (mode 2026)
+    sync_timer: event,
+
+    /// last command as reported by OSC 133: when its output started and
+    /// finished (zero while it is running) and its exit status (-1 if not
+    /// reported)
+    command_start: timeval,
+    command_end: timeval,
+    command_status: i32,
+
+    /// theme last reported for mode 2031, 1 for dark and 2 for light
+    last_theme: u32,
+
+    /// last desktop notification sent with OSC 9, 99 or 777, escaped for
+    /// display; the title may be empty
+    notification_title: *mut u8,
+    notification_body: *mut u8,
+    notification_time: timeval,
+
     ictx: *mut input_ctx,
 
     cached_gc: grid_cell,
@@ -1716,6 +1835,7 @@ bitflags::bitflags! {
         const TERM_RGBCOLOURS = 0x10;
         const TERM_VT100LIKE = 0x20;
         const TERM_SIXEL = 0x40;
+        const TERM_KITTYGRAPHICS = 0x80;
     }
 }
 
@@ -1823,6 +1943,11 @@ struct tty {
 
     key_timer: event,
     key_tree: *mut tty_key,
+
+    /// Number given each time the terminal is started, to remember which
+    /// kitty images it has been sent.
+    #[cfg(feature = "sixel")]
+    serial: u64,
 }
 
 type tty_ctx_redraw_cb = Option<unsafe fn(*const tty_ctx)>;
@@ -2274,6 +2399,7 @@ bitflags::bitflags! {
         const WINDOWSIZECHANGED  = 0x0400000000u64;
         const CLIPBOARDBUFFER    = 0x0800000000u64;
         const BRACKETPASTING     = 0x1000000000u64;
+        const CONTROL_JSON       = 0x2000000000u64;
     }
 }
 
@@ -2353,6 +2479,8 @@ struct client {
     status: status_line,
 
     flags: client_flag,
+    /// permission profile overriding the user's, or null
+    profile: *mut u8,
 
     exit_type: exit_type,
     exit_msgtype: msgtype,
@@ -2419,6 +2547,7 @@ enum control_sub_type {
     CONTROL_SUB_ALL_PANES,
     CONTROL_SUB_WINDOW,
     CONTROL_SUB_ALL_WINDOWS,
+    CONTROL_SUB_NOTIFY,
 }
 
 const KEY_BINDING_REPEAT: i32 = 0x1;
@@ -51,6 +51,10 @@ pub unsafe fn strftime(s: *mut u8, max: usize, format: *const u8, tm: *const tm)
     unsafe { ::libc::strftime(s.cast(), max, format.cast(), tm.cast()) }
 }
 
+pub unsafe fn strptime(s: *const u8, format: *const u8, tm: *mut tm) -> *mut u8 {
+    unsafe { ::libc::strptime(s.cast(), format.cast(), tm).cast() }
+}
+
 pub unsafe fn strdup(cs: *const u8) -> *mut u8 {
     unsafe { ::libc::strdup(cs.cast()).cast() }
 }
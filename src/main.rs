@@ -7,21 +7,50 @@ use ::std::{
 struct MyAlloc;
 #[global_allocator]
 static ALLOCATOR: MyAlloc = MyAlloc;
+
+// malloc only guarantees alignment for the fundamental types, anything more
+// (such as the vectors used by the regex crate) must be asked for.
+const MALLOC_ALIGN: usize = 16;
+
 unsafe impl GlobalAlloc for MyAlloc {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        unsafe { libc::malloc(layout.size()) as *mut u8 }
+        if layout.align() <= MALLOC_ALIGN {
+            return unsafe { libc::malloc(layout.size()) as *mut u8 };
+        }
+        let mut ptr = std::ptr::null_mut();
+        if unsafe { libc::posix_memalign(&mut ptr, layout.align(), layout.size()) } != 0 {
+            return std::ptr::null_mut();
+        }
+        ptr as *mut u8
     }
     unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
         unsafe { libc::free(ptr.cast()) }
     }
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
         let align = layout.align();
+        if align > MALLOC_ALIGN {
+            let ptr = unsafe { self.alloc(layout) };
+            if !ptr.is_null() {
+                unsafe { std::ptr::write_bytes(ptr, 0, layout.size()) };
+            }
+            return ptr;
+        }
         // exploit we know align must be a non-zero power of 2 to do a faster division
         let nmemb = (layout.size() + align - 1) >> align.trailing_zeros();
         unsafe { libc::calloc(nmemb, align) as *mut u8 }
     }
-    unsafe fn realloc(&self, ptr: *mut u8, _layout: Layout, new_size: usize) -> *mut u8 {
-        unsafe { libc::realloc(ptr.cast(), new_size) as *mut u8 }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if layout.align() <= MALLOC_ALIGN {
+            return unsafe { libc::realloc(ptr.cast(), new_size) as *mut u8 };
+        }
+        unsafe {
+            let new = self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+            if !new.is_null() {
+                std::ptr::copy_nonoverlapping(ptr, new, layout.size().min(new_size));
+                self.dealloc(ptr, layout);
+            }
+            new
+        }
     }
 }
 
@@ -81,6 +81,7 @@ pub struct mode_tree_data {
     preview: bool,
     search: *mut u8,
     filter: *mut u8,
+    fuzzy: *mut u8,
     no_matches: i32,
     search_dir: mode_tree_search_dir,
 }
@@ -239,6 +240,48 @@ unsafe fn mode_tree_build_lines(mtd: *mut mode_tree_data, mtl: *mut mode_tree_li
     }
 }
 
+/// Score a fuzzy match of an item's name or text.
+unsafe fn mode_tree_fuzzy_score(mti: *mut mode_tree_item, pattern: &str) -> Option<i32> {
+    unsafe {
+        let name = fuzzy_score(pattern, cstr_to_str((*mti).name));
+        if (*mti).text.is_null() {
+            return name;
+        }
+        name.max(fuzzy_score(pattern, cstr_to_str((*mti).text)))
+    }
+}
+
+/// Remove items that do not fuzzy match and have no children that do, and
+/// sort the rest best first. Items with children that match are expanded and
+/// the children of items that match themselves are all kept. Returns the best
+/// score in the list.
+unsafe fn mode_tree_fuzzy_filter(mtl: *mut mode_tree_list, pattern: &str) -> Option<i32> {
+    unsafe {
+        let mut items = Vec::new();
+        for mti in tailq_foreach(mtl).map(NonNull::as_ptr) {
+            tailq_remove(mtl, mti);
+
+            let mut score = mode_tree_fuzzy_score(mti, pattern);
+            if score.is_none() {
+                score = mode_tree_fuzzy_filter(&raw mut (*mti).children, pattern);
+                if score.is_some() {
+                    (*mti).expanded = true;
+                }
+            }
+            match score {
+                Some(score) => items.push((score, mti)),
+                None => mode_tree_free_item(mti),
+            }
+        }
+
+        items.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        for (_, mti) in &items {
+            tailq_insert_tail(mtl, *mti);
+        }
+        items.first().map(|(score, _)| *score)
+    }
+}
+
 unsafe fn mode_tree_clear_tagged(mtl: *mut mode_tree_list) {
     unsafe {
         for mti in tailq_foreach(mtl).map(NonNull::as_ptr) {
@@ -441,6 +484,7 @@ pub unsafe fn mode_tree_start(
             } else {
                 null_mut()
             },
+            fuzzy: null_mut(),
             no_matches: Default::default(),
             search_dir: zeroed(),
         });
@@ -534,6 +578,17 @@ pub unsafe fn mode_tree_build(mtd: *mut mode_tree_data) {
                 &raw mut tag,
                 null_mut(),
             );
+        } else if !(*mtd).fuzzy.is_null() {
+            mode_tree_fuzzy_filter(&raw mut (*mtd).children, cstr_to_str((*mtd).fuzzy));
+            if tailq_empty(&raw mut (*mtd).children) {
+                (*mtd).no_matches = 1;
+                (*mtd).buildcb.unwrap()(
+                    NonNull::new((*mtd).modedata).unwrap(),
+                    &raw mut (*mtd).sort_crit,
+                    &raw mut tag,
+                    (*mtd).filter,
+                );
+            }
         }
 
         mode_tree_free_items(&raw mut (*mtd).saved);
@@ -580,6 +635,7 @@ pub unsafe fn mode_tree_free(mtd: *mut mode_tree_data) {
 
         free_((*mtd).search);
         free_((*mtd).filter);
+        free_((*mtd).fuzzy);
 
         (*mtd).dead = 1;
         mode_tree_remove_ref(mtd);
@@ -859,7 +915,9 @@ pub unsafe fn mode_tree_draw(mtd: &mut mode_tree_data) {
                     "active".len()
                 };
 
-                if !mtd.filter.is_null() && w as usize - 2 >= strlen(text) + 10 + n + 2 {
+                if (!mtd.filter.is_null() || !mtd.fuzzy.is_null())
+                    && w as usize - 2 >= strlen(text) + 10 + n + 2
+                {
                     screen_write_puts!(&raw mut ctx, &raw mut gc0, " (filter: ");
                     if mtd.no_matches != 0 {
                         screen_write_puts!(&raw mut ctx, &raw mut gc, "no matches");
@@ -1087,6 +1145,58 @@ pub unsafe fn mode_tree_filter_free(data: NonNull<mode_tree_data>) {
     }
 }
 
+pub unsafe fn mode_tree_fuzzy_callback(
+    _c: *mut client,
+    data: NonNull<mode_tree_data>,
+    mut s: *const u8,
+    done: i32,
+) -> i32 {
+    unsafe {
+        let mtd: *mut mode_tree_data = data.as_ptr();
+
+        if (*mtd).dead != 0 {
+            return 0;
+        }
+
+        // Skip the prefix given while typing.
+        if !s.is_null() && done == 0 && *s != b'\0' {
+            s = s.add(1);
+        }
+
+        free_((*mtd).fuzzy);
+        if s.is_null() || *s == b'\0' {
+            (*mtd).fuzzy = null_mut();
+        } else {
+            (*mtd).fuzzy = xstrdup(s).as_ptr();
+        }
+        mode_tree_build(mtd);
+
+        // Move to the best match.
+        if !(*mtd).fuzzy.is_null() && (*mtd).no_matches == 0 {
+            let pattern = cstr_to_str((*mtd).fuzzy);
+            let best = (*mtd)
+                .line_list
+                .iter()
+                .filter_map(|line| Some((mode_tree_fuzzy_score(line.item, pattern)?, line.item)))
+                .max_by_key(|(score, _)| *score);
+            if let Some((_, mti)) = best {
+                mode_tree_set_current(mtd, (*mti).tag);
+            }
+        }
+
+        mode_tree_draw(&mut *mtd);
+        (*(*mtd).wp).flags |= window_pane_flags::PANE_REDRAW;
+
+        0
+    }
+}
+
+pub unsafe fn mode_tree_fuzzy_free(data: NonNull<mode_tree_data>) {
+    unsafe {
+        mode_tree_remove_ref(data.cast().as_ptr());
+    }
+}
+
 pub unsafe fn mode_tree_menu_callback(
     _menu: *mut menu,
     _idx: u32,
@@ -1261,6 +1371,7 @@ pub unsafe fn mode_tree_key(
             pub const J: u64 = 'j' as u64;
 
             pub const F: u64 = 'f' as u64;
+            pub const F_UPPER: u64 = 'F' as u64;
             pub const V: u64 = 'v' as u64;
 
             pub const N: u64 = 'n' as u64;
@@ -1463,6 +1574,20 @@ pub unsafe fn mode_tree_key(
                     prompt_type::PROMPT_TYPE_SEARCH,
                 );
             }
+            code::F_UPPER => {
+                (*mtd).references += 1;
+                status_prompt_set(
+                    c,
+                    null_mut(),
+                    c!("(fuzzy) "),
+                    (*mtd).fuzzy,
+                    mode_tree_fuzzy_callback,
+                    mode_tree_fuzzy_free,
+                    mtd,
+                    prompt_flags::PROMPT_NOFORMAT | prompt_flags::PROMPT_INCREMENTAL,
+                    prompt_type::PROMPT_TYPE_SEARCH,
+                );
+            }
             code::V => {
                 (*mtd).preview = !(*mtd).preview;
                 mode_tree_build(mtd);
@@ -1501,3 +1626,56 @@ pub unsafe fn mode_tree_run_command(
         free_(command);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn item(mtl: *mut mode_tree_list, name: &str) -> *mut mode_tree_item {
+        unsafe {
+            let mti = xcalloc1::<mode_tree_item>() as *mut mode_tree_item;
+            (*mti).name = xstrdup__(name);
+            tailq_init(&raw mut (*mti).children);
+            tailq_insert_tail(mtl, mti);
+            mti
+        }
+    }
+
+    unsafe fn names(mtl: *mut mode_tree_list) -> Vec<&'static str> {
+        unsafe {
+            tailq_foreach(mtl)
+                .map(|mti| cstr_to_str((*mti.as_ptr()).name))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_mode_tree_fuzzy_filter() {
+        unsafe {
+            let mut list: mode_tree_list = zeroed();
+            let mtl = &raw mut list;
+            tailq_init(mtl);
+
+            let main = item(mtl, "m_a_i_n");
+            item(&raw mut (*main).children, "other");
+            let work = item(mtl, "work");
+            (*work).expanded = false;
+            item(&raw mut (*work).children, "xmaxixn");
+            let editor = item(mtl, "editor");
+            item(&raw mut (*editor).children, "notes");
+            item(mtl, "main");
+
+            // Best first, items matching themselves keep all their children
+            // and items with matching children are expanded.
+            let best = mode_tree_fuzzy_filter(mtl, "main");
+            assert_eq!(best, fuzzy_score("main", "main"));
+            assert_eq!(names(mtl), ["main", "m_a_i_n", "work"]);
+            assert_eq!(names(&raw mut (*main).children), ["other"]);
+            assert_eq!(names(&raw mut (*work).children), ["xmaxixn"]);
+            assert!((*work).expanded);
+
+            assert_eq!(mode_tree_fuzzy_filter(mtl, "zzz"), None);
+            assert!(names(mtl).is_empty());
+        }
+    }
+}
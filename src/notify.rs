@@ -135,6 +135,9 @@ pub unsafe fn notify_callback(item: *mut cmdq_item, data: *mut c_void) -> cmd_re
         if streq_((*ne).name, "pane-mode-changed") {
             control_notify_pane_mode_changed((*ne).pane);
         }
+        if streq_((*ne).name, "pane-notification") {
+            control_notify_pane_notification((*ne).pane);
+        }
         if streq_((*ne).name, "window-layout-changed") {
             control_notify_window_layout_changed((*ne).window);
         }
@@ -174,6 +177,7 @@ pub unsafe fn notify_callback(item: *mut cmdq_item, data: *mut c_void) -> cmd_re
         if streq_((*ne).name, "paste-buffer-deleted") {
             control_notify_paste_buffer_deleted((*ne).pbname);
         }
+        control_notify_subscriptions(ne);
 
         notify_insert_hook(item, ne);
 
@@ -209,12 +213,31 @@ pub unsafe fn notify_add(
     wp: *mut window_pane,
     pbname: Option<&str>,
 ) {
-    let __func__ = c!("notify_add");
+    unsafe {
+        let ne = notify_create(name, fs, c, s, w, wp, pbname);
+        if !ne.is_null() {
+            notify_queue(ne);
+        }
+    }
+}
+
+/// Create a notify entry, or return null if hooks are not being run. The
+/// entry can have more formats added before it is queued.
+unsafe fn notify_create(
+    name: &'static CStr,
+    fs: *mut cmd_find_state,
+    c: *mut client,
+    s: *mut session,
+    w: *mut window,
+    wp: *mut window_pane,
+    pbname: Option<&str>,
+) -> *mut notify_entry {
+    let __func__ = c!("notify_create");
     unsafe {
         let item = cmdq_running(null_mut());
         if !item.is_null() && cmdq_get_flags(item).intersects(cmdq_state_flags::CMDQ_STATE_NOHOOKS)
         {
-            return;
+            return null_mut();
         }
 
         let ne = xcalloc1::<notify_entry>() as *mut notify_entry;
@@ -246,7 +269,9 @@ pub unsafe fn notify_add(
         if !wp.is_null() {
             format_add!((*ne).formats, "hook_pane", "%%{}", (*wp).id);
         }
-        format_log_debug((*ne).formats, __func__);
+        if let Some(pbname) = pbname {
+            format_add!((*ne).formats, "hook_buffer", "{}", pbname);
+        }
 
         if !c.is_null() {
             (*c).references += 1;
@@ -263,6 +288,13 @@ pub unsafe fn notify_add(
             session_add_ref((*ne).fs.s, __func__);
         } /* cmd_find_valid_state needs session */
 
+        ne
+    }
+}
+
+unsafe fn notify_queue(ne: *mut notify_entry) {
+    unsafe {
+        format_log_debug((*ne).formats, c!("notify_queue"));
         cmdq_append(
             null_mut(),
             cmdq_get_callback!(notify_callback, ne.cast()).as_ptr(),
@@ -423,3 +455,24 @@ pub unsafe fn notify_paste_buffer(pbname: &str, deleted: bool) {
         }
     }
 }
+
+/// Notify that an option has been set or unset. The value is the one the
+/// option now has, which may be inherited.
+pub unsafe fn notify_option(
+    name: &'static CStr,
+    fs: *mut cmd_find_state,
+    option: &str,
+    scope: &str,
+    value: *const u8,
+) {
+    unsafe {
+        let ne = notify_create(name, fs, null_mut(), null_mut(), null_mut(), null_mut(), None);
+        if ne.is_null() {
+            return;
+        }
+        format_add!((*ne).formats, "hook_option", "{}", option);
+        format_add!((*ne).formats, "hook_option_scope", "{}", scope);
+        format_add!((*ne).formats, "hook_option_value", "{}", _s(value));
+        notify_queue(ne);
+    }
+}
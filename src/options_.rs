@@ -366,6 +366,31 @@ pub unsafe fn options_default(
     }
 }
 
+/// Create the global options with their defaults, once, for tests.
+#[cfg(test)]
+pub(crate) fn options_test_globals() {
+    static ONCE: std::sync::Once = std::sync::Once::new();
+    ONCE.call_once(|| unsafe {
+        let server = options_create(null_mut());
+        let session = options_create(null_mut());
+        let window = options_create(null_mut());
+        for oe in &OPTIONS_TABLE {
+            if oe.scope & OPTIONS_TABLE_SERVER != 0 {
+                options_default(server, oe);
+            }
+            if oe.scope & OPTIONS_TABLE_SESSION != 0 {
+                options_default(session, oe);
+            }
+            if oe.scope & OPTIONS_TABLE_WINDOW != 0 {
+                options_default(window, oe);
+            }
+        }
+        GLOBAL_OPTIONS = server;
+        GLOBAL_S_OPTIONS = session;
+        GLOBAL_W_OPTIONS = window;
+    });
+}
+
 pub unsafe fn options_default_to_string(oe: *const options_table_entry) -> NonNull<u8> {
     unsafe {
         match (*oe).type_ {
@@ -1356,6 +1381,10 @@ pub unsafe fn options_push_changes(name: &str) {
             }
         }
 
+        if name == "unicode-version" {
+            utf8_table_set_version(options_get_number_(GLOBAL_OPTIONS, name));
+        }
+
         if name == "user-keys" {
             for loop_ in tailq_foreach(&raw mut CLIENTS).map(NonNull::as_ptr) {
                 if (*loop_).tty.flags.intersects(tty_flags::TTY_OPENED) {
@@ -1390,6 +1419,15 @@ pub unsafe fn options_push_changes(name: &str) {
             }
         }
 
+        if name == "window-style" || name == "window-active-style" || name == "pane-colours" {
+            input_report_theme_all();
+        }
+
+        #[cfg(feature = "web")]
+        if name == "web-listen" {
+            web_start();
+        }
+
         for s in rb_foreach(&raw mut SESSIONS) {
             status_update_cache(s.as_ptr());
         }
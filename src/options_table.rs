@@ -56,6 +56,8 @@ static OPTIONS_TABLE_DETACH_ON_DESTROY_LIST: [&str; 5] =
 static OPTIONS_TABLE_EXTENDED_KEYS_LIST: [&str; 3] = ["off", "on", "always"];
 static OPTIONS_TABLE_EXTENDED_KEYS_FORMAT_LIST: [&str; 2] = ["csi-u", "xterm"];
 static OPTIONS_TABLE_ALLOW_PASSTHROUGH_LIST: [&str; 3] = ["off", "on", "all"];
+static OPTIONS_TABLE_UNICODE_VERSION_LIST: [&str; 7] =
+    ["8.0", "9.0", "10.0", "11.0", "12.0", "13.0", "14.0"];
 
 #[rustfmt::skip]
 /// Map of name conversions.
@@ -162,6 +164,22 @@ pub static mut OPTIONS_TABLE_STATUS_FORMAT_DEFAULT: [*const u8; 3] = [
     null(),
 ];
 
+pub static mut OPTIONS_TABLE_COPY_MODE_HINT_PATTERNS_DEFAULT: [*const u8; 5] = [
+    c!("(https?|ftp|file)://[^[:space:]<>\"'()]+"),
+    c!("[[:alnum:]_.~+@%-]*(/[[:alnum:]_.~+@%-]+)+"),
+    c!("\\b[0-9]{1,3}(\\.[0-9]{1,3}){3}\\b"),
+    // Hashes are at least seven characters and, unlike numbers, have a letter
+    // in them.
+    cstring_concat!(
+        "\\b([0-9]{6,}[a-f]|[0-9]{5}[a-f][0-9a-f]|[0-9]{4}[a-f][0-9a-f]{2}|",
+        "[0-9]{3}[a-f][0-9a-f]{3}|[0-9]{2}[a-f][0-9a-f]{4}|[0-9][a-f][0-9a-f]{5}|",
+        "[a-f][0-9a-f]{6})[0-9a-f]{0,33}\\b"
+    )
+    .as_ptr()
+    .cast(),
+    null(),
+];
+
 // Helpers for hook options.
 macro_rules! options_table_hook {
     ($hook_name:expr, $default_value:expr) => {
@@ -205,7 +223,21 @@ macro_rules! options_table_window_hook {
     };
 }
 
-pub static OPTIONS_TABLE: [options_table_entry; 190] = [
+pub static OPTIONS_TABLE: [options_table_entry; 201] = [
+    options_table_entry {
+        name: "access-profiles",
+        type_: options_table_type::OPTIONS_TABLE_STRING,
+        scope: OPTIONS_TABLE_SERVER,
+        flags: OPTIONS_TABLE_IS_ARRAY,
+        default_str: Some(
+            "observer:attach-session:detach-client:switch-client:list-sessions:list-windows:list-panes:list-clients:capture-pane:copy-mode,operator:keys:attach-session:detach-client:switch-client:list-*:capture-pane:display-message:display-panes:copy-mode:select-window:select-pane:next-window:previous-window:last-window:last-pane:send-keys:paste-buffer",
+        ),
+        separator: c!(","),
+        text: c!(
+            "Permission profiles for server-access -p. Each entry is a name followed by what the profile allows separated by ':': command names or patterns, 'keys' to type into panes, 'run-shell' to also run shell commands from copy mode, if-shell, formats and detach-client -E, and 'other-clients' to detach or switch clients other than its own."
+        ),
+        ..options_table_entry::const_default()
+    },
     options_table_entry {
         name: "backspace",
         type_: options_table_type::OPTIONS_TABLE_KEY,
@@ -397,6 +429,16 @@ pub static OPTIONS_TABLE: [options_table_entry; 190] = [
         text: c!("Maximum number of server messages to keep."),
         ..options_table_entry::const_default()
     },
+    options_table_entry {
+        name: "posix-regex",
+        type_: options_table_type::OPTIONS_TABLE_FLAG,
+        scope: OPTIONS_TABLE_SERVER,
+        default_num: 0,
+        text: c!(
+            "Whether regular expressions use the POSIX extended syntax of the C library rather than the built in syntax."
+        ),
+        ..options_table_entry::const_default()
+    },
     options_table_entry {
         name: "prefix-timeout",
         type_: options_table_type::OPTIONS_TABLE_NUMBER,
@@ -420,6 +462,14 @@ pub static OPTIONS_TABLE: [options_table_entry; 190] = [
         text: c!("Maximum number of commands to keep in history."),
         ..options_table_entry::const_default()
     },
+    options_table_entry {
+        name: "restore-state",
+        type_: options_table_type::OPTIONS_TABLE_FLAG,
+        scope: OPTIONS_TABLE_SERVER,
+        default_num: 0,
+        text: c!("Whether to restore sessions from the state file when the server starts."),
+        ..options_table_entry::const_default()
+    },
     options_table_entry {
         name: "set-clipboard",
         type_: options_table_type::OPTIONS_TABLE_CHOICE,
@@ -431,6 +481,16 @@ pub static OPTIONS_TABLE: [options_table_entry; 190] = [
         ),
         ..options_table_entry::const_default()
     },
+    options_table_entry {
+        name: "state-file",
+        type_: options_table_type::OPTIONS_TABLE_STRING,
+        scope: OPTIONS_TABLE_SERVER,
+        default_str: Some(""),
+        text: c!(
+            "Location of the file used by save-state and restore-state. Empty means a path must be given."
+        ),
+        ..options_table_entry::const_default()
+    },
     options_table_entry {
         name: "terminal-overrides",
         type_: options_table_type::OPTIONS_TABLE_STRING,
@@ -453,6 +513,17 @@ pub static OPTIONS_TABLE: [options_table_entry; 190] = [
         text: c!("List of terminal features, used if they cannot be automatically detected."),
         ..options_table_entry::const_default()
     },
+    options_table_entry {
+        name: "unicode-version",
+        type_: options_table_type::OPTIONS_TABLE_CHOICE,
+        scope: OPTIONS_TABLE_SERVER,
+        choices: &OPTIONS_TABLE_UNICODE_VERSION_LIST,
+        default_num: 6,
+        text: c!(
+            "Unicode version of the character widths used when built with the utf8proc feature."
+        ),
+        ..options_table_entry::const_default()
+    },
     options_table_entry {
         name: "user-keys",
         type_: options_table_type::OPTIONS_TABLE_STRING,
@@ -465,6 +536,16 @@ pub static OPTIONS_TABLE: [options_table_entry; 190] = [
         ),
         ..options_table_entry::const_default()
     },
+    options_table_entry {
+        name: "web-listen",
+        type_: options_table_type::OPTIONS_TABLE_STRING,
+        scope: OPTIONS_TABLE_SERVER,
+        default_str: Some(""),
+        text: c!(
+            "Where to serve the web page, if built with web support: an absolute path for a unix socket or a port on 127.0.0.1. Empty does not listen."
+        ),
+        ..options_table_entry::const_default()
+    },
     // Session options.
     options_table_entry {
         name: "activity-action",
@@ -590,6 +671,19 @@ pub static OPTIONS_TABLE: [options_table_entry; 190] = [
         text: c!("Time for which status line messages should appear."),
         ..options_table_entry::const_default()
     },
+    options_table_entry {
+        name: "history-file-limit",
+        type_: options_table_type::OPTIONS_TABLE_NUMBER,
+        scope: OPTIONS_TABLE_SESSION,
+        minimum: 0,
+        maximum: i32::MAX as u32,
+        default_num: 0,
+        unit: c!("lines"),
+        text: c!(
+            "Maximum number of lines removed from the history to keep in a file for each pane, or 0 for none. If changed, the new value applies only to new panes."
+        ),
+        ..options_table_entry::const_default()
+    },
     options_table_entry {
         name: "history-limit",
         type_: options_table_type::OPTIONS_TABLE_NUMBER,
@@ -1032,6 +1126,17 @@ pub static OPTIONS_TABLE: [options_table_entry; 190] = [
         text: c!("Style of the current search match in copy mode."),
         ..options_table_entry::const_default()
     },
+    options_table_entry {
+        name: "copy-mode-hint-patterns",
+        type_: options_table_type::OPTIONS_TABLE_STRING,
+        scope: OPTIONS_TABLE_WINDOW,
+        flags: OPTIONS_TABLE_IS_ARRAY,
+        default_arr: &raw const OPTIONS_TABLE_COPY_MODE_HINT_PATTERNS_DEFAULT as *const *const u8,
+        text: c!(
+            "Regular expressions for the text picked by the copy mode hints command. Earlier patterns are preferred where matches overlap."
+        ),
+        ..options_table_entry::const_default()
+    },
     options_table_entry {
         name: "copy-mode-mark-style",
         type_: options_table_type::OPTIONS_TABLE_STRING,
@@ -1042,6 +1147,16 @@ pub static OPTIONS_TABLE: [options_table_entry; 190] = [
         text: c!("Style of the marked line in copy mode."),
         ..options_table_entry::const_default()
     },
+    options_table_entry {
+        name: "copy-mode-timestamps",
+        type_: options_table_type::OPTIONS_TABLE_FLAG,
+        scope: OPTIONS_TABLE_WINDOW,
+        default_num: 0,
+        text: c!(
+            "Whether copy mode shows the time each line was written, over the right of the line."
+        ),
+        ..options_table_entry::const_default()
+    },
     options_table_entry {
         name: "fill-character",
         type_: options_table_type::OPTIONS_TABLE_STRING,
@@ -1285,6 +1400,16 @@ pub static OPTIONS_TABLE: [options_table_entry; 190] = [
         text: c!("Whether typing should be sent to all panes simultaneously."),
         ..options_table_entry::const_default()
     },
+    options_table_entry {
+        name: "timestamp-format",
+        type_: options_table_type::OPTIONS_TABLE_STRING,
+        scope: OPTIONS_TABLE_WINDOW,
+        default_str: Some("%H:%M:%S"),
+        text: c!(
+            "Format of line times in copy mode and capture-pane, passed to strftime(3)."
+        ),
+        ..options_table_entry::const_default()
+    },
     options_table_entry {
         name: "window-active-style",
         type_: options_table_type::OPTIONS_TABLE_STRING,
@@ -1463,6 +1588,7 @@ pub static OPTIONS_TABLE: [options_table_entry; 190] = [
     options_table_pane_hook!("pane-fous-in", ""),
     options_table_pane_hook!("pane-fous-out", ""),
     options_table_pane_hook!("pane-mode-hanged", ""),
+    options_table_pane_hook!("pane-notification", ""),
     options_table_pane_hook!("pane-set-lipboard", ""),
     options_table_pane_hook!("pane-title-hanged", ""),
     options_table_hook!("session-closed", ""),
@@ -417,6 +417,8 @@ pub fn popup_make_pane(pd: *mut popup_data, type_: layout_type) {
         let lc = layout_split_pane(wp, type_, -1, spawn_flags::empty());
         let hlimit = options_get_number_((*s).options, "history-limit") as u32;
         let new_wp = window_add_pane((*wp).window, null_mut(), hlimit, spawn_flags::empty());
+        let limit = options_get_number_((*s).options, "history-file-limit");
+        grid_spill_set((*new_wp).base.grid, limit as u32);
         layout_assign_pane(lc, new_wp, 0);
 
         (*new_wp).fd = job_transfer(
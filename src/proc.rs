@@ -223,7 +223,8 @@ pub fn proc_start(name: &CStr) -> *mut tmuxproc {
         );
         #[cfg(feature = "utf8proc")]
         {
-            log_debug!("using utf8proc {}", _s(utf8proc_version()));
+            let (major, minor) = utf8_table_get_version();
+            log_debug!("using built-in Unicode {major}.{minor} width tables");
         }
 
         let tp = xcalloc1::<tmuxproc>();
@@ -0,0 +1,156 @@
+// Regular expressions for copy mode searches, format matches and
+// substitutions and find-window. Patterns use the built in engine, which has
+// a syntax like PCRE without lookaround, so it supports \b, non-greedy
+// repetition and Unicode classes and behaves the same everywhere. If the
+// posix-regex option is on, the POSIX extended syntax of the C library is
+// used instead.
+use ::regex::bytes::{Regex, RegexBuilder};
+
+use crate::libc::{REG_ICASE, REG_NOTBOL, regcomp, regex_t, regexec, regfree, regmatch_t};
+use crate::options_::options_get_number_;
+use crate::*;
+
+pub enum regexp {
+    Native(Regex),
+    Posix(Box<regex_t>),
+}
+
+impl Drop for regexp {
+    fn drop(&mut self) {
+        if let regexp::Posix(r) = self {
+            unsafe { regfree(&raw mut **r) };
+        }
+    }
+}
+
+/// Compile a pattern. The flags are those for regcomp, but only `REG_ICASE`
+/// affects the built in engine.
+pub unsafe fn regexp_compile(pattern: *const u8, cflags: i32) -> Option<regexp> {
+    unsafe {
+        if options_get_number_(GLOBAL_OPTIONS, "posix-regex") != 0 {
+            return regexp_compile_posix(pattern, cflags);
+        }
+        regexp_compile_native(pattern, cflags)
+    }
+}
+
+unsafe fn regexp_compile_posix(pattern: *const u8, cflags: i32) -> Option<regexp> {
+    unsafe {
+        let mut r: Box<regex_t> = Box::new(zeroed());
+        if regcomp(&raw mut *r, pattern, cflags) != 0 {
+            return None;
+        }
+        Some(regexp::Posix(r))
+    }
+}
+
+unsafe fn regexp_compile_native(pattern: *const u8, cflags: i32) -> Option<regexp> {
+    unsafe {
+        let pattern = CStr::from_ptr(pattern.cast()).to_str().ok()?;
+        RegexBuilder::new(pattern)
+            .case_insensitive(cflags & REG_ICASE != 0)
+            .build()
+            .ok()
+            .map(regexp::Native)
+    }
+}
+
+/// Find the first match in text at or after start, which is not taken to be
+/// the beginning of a line unless it is zero. The offsets from text of the
+/// match and its subexpressions are put into pmatch, -1 if they did not
+/// match.
+pub unsafe fn regexp_exec(
+    re: &regexp,
+    text: *const u8,
+    start: usize,
+    pmatch: &mut [regmatch_t],
+) -> bool {
+    unsafe {
+        match re {
+            regexp::Native(r) => {
+                let text = CStr::from_ptr(text.cast()).to_bytes();
+                if start > text.len() {
+                    return false;
+                }
+                if pmatch.is_empty() {
+                    return r.is_match_at(text, start);
+                }
+
+                let mut locs = r.capture_locations();
+                if r.captures_read_at(&mut locs, text, start).is_none() {
+                    return false;
+                }
+                for (i, m) in pmatch.iter_mut().enumerate() {
+                    let (so, eo) = locs.get(i).map_or((-1, -1), |(so, eo)| (so as _, eo as _));
+                    m.rm_so = so;
+                    m.rm_eo = eo;
+                }
+                true
+            }
+            regexp::Posix(r) => {
+                let eflags = if start != 0 { REG_NOTBOL } else { 0 };
+                if regexec(
+                    &raw const **r,
+                    text.add(start),
+                    pmatch.len(),
+                    pmatch.as_mut_ptr(),
+                    eflags,
+                ) != 0
+                {
+                    return false;
+                }
+                for m in pmatch.iter_mut().filter(|m| m.rm_so != -1) {
+                    m.rm_so += start as i32;
+                    m.rm_eo += start as i32;
+                }
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libc::REG_EXTENDED;
+
+    unsafe fn find(re: Option<regexp>, text: &CStr, start: usize) -> Option<(i32, i32)> {
+        unsafe {
+            let mut m: [regmatch_t; 1] = zeroed();
+            regexp_exec(&re?, text.as_ptr().cast(), start, &mut m)
+                .then_some((m[0].rm_so, m[0].rm_eo))
+        }
+    }
+
+    unsafe fn native(pattern: &CStr, cflags: i32) -> Option<regexp> {
+        unsafe { regexp_compile_native(pattern.as_ptr().cast(), cflags) }
+    }
+
+    unsafe fn posix(pattern: &CStr, cflags: i32) -> Option<regexp> {
+        unsafe { regexp_compile_posix(pattern.as_ptr().cast(), cflags | REG_EXTENDED) }
+    }
+
+    #[test]
+    fn test_regexp_native() {
+        unsafe {
+            assert_eq!(find(native(c"\\bcat\\b", 0), c"concat cat", 0), Some((7, 10)));
+            assert_eq!(find(native(c"a.+?b", 0), c"axxbyyb", 0), Some((0, 4)));
+            assert_eq!(find(native(c"\\p{Greek}+", 0), c"abc αβγ", 0), Some((4, 10)));
+            assert_eq!(find(native(c"HELLO", REG_ICASE), c"say hello", 0), Some((4, 9)));
+            assert_eq!(find(native(c"^b", 0), c"ab", 1), None);
+            assert_eq!(find(native(c"\\bb", 0), c"ab b", 1), Some((3, 4)));
+            assert!(native(c"(", 0).is_none());
+        }
+    }
+
+    #[test]
+    fn test_regexp_posix() {
+        unsafe {
+            assert_eq!(find(posix(c"a.+b", 0), c"axxbyyb", 0), Some((0, 7)));
+            assert_eq!(find(posix(c"HELLO", REG_ICASE), c"say hello", 0), Some((4, 9)));
+            assert_eq!(find(posix(c"^b", 0), c"ab", 1), None);
+            assert_eq!(find(posix(c"b", 0), c"abab", 2), Some((3, 4)));
+            assert!(posix(c"(", 0).is_none());
+        }
+    }
+}
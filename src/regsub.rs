@@ -15,7 +15,7 @@ use core::ffi::c_int;
 
 use xmalloc::xrealloc_;
 
-use crate::libc::{memcpy, regcomp, regex_t, regexec, regfree, regmatch_t, strlen};
+use crate::libc::{memcpy, regmatch_t, strlen};
 use crate::*;
 
 unsafe fn regsub_copy(
@@ -56,6 +56,7 @@ pub unsafe fn regsub_expand(
                             (*m.add(i as _)).rm_so as usize,
                             (*m.add(i as _)).rm_eo as usize,
                         );
+                        cp = cp.add(1);
                         continue;
                     }
                 }
@@ -76,7 +77,6 @@ pub unsafe fn regsub(
     flags: c_int,
 ) -> *mut u8 {
     unsafe {
-        let mut r: regex_t = zeroed();
         let mut m: [regmatch_t; 10] = zeroed(); // TODO can use uninit
         let mut len: isize = 0;
         let mut empty = 0;
@@ -85,23 +85,16 @@ pub unsafe fn regsub(
         if *text == b'\0' {
             return xstrdup(c!("")).cast().as_ptr();
         }
-        if regcomp(&raw mut r, pattern, flags) != 0 {
+        let Some(r) = regexp_compile(pattern, flags) else {
             return null_mut();
-        }
+        };
 
         let mut start: isize = 0;
         let mut last: isize = 0;
         let end: isize = strlen(text) as _;
 
         while start <= end {
-            if regexec(
-                &raw mut r,
-                text.add(start as _) as _,
-                m.len(),
-                m.as_mut_ptr(),
-                0,
-            ) != 0
-            {
+            if !regexp_exec(&r, text, start as usize, &mut m) {
                 regsub_copy(
                     &raw mut buf,
                     &raw mut len,
@@ -119,28 +112,28 @@ pub unsafe fn regsub(
                 &raw mut len,
                 text,
                 last as usize,
-                (m[0].rm_so as isize + start) as usize,
+                m[0].rm_so as usize,
             );
 
             // If the last match was empty and this one isn't (it is either
             // later or has matched text), expand this match. If it is
             // empty, move on one character and try again from there.
-            if empty != 0 || start + m[0].rm_so as isize != last || m[0].rm_so != m[0].rm_eo {
+            if empty != 0 || m[0].rm_so as isize != last || m[0].rm_so != m[0].rm_eo {
                 regsub_expand(
                     &raw mut buf,
                     &raw mut len,
                     with,
-                    text.offset(start),
+                    text,
                     m.as_mut_ptr(),
                     m.len() as u32,
                 );
 
-                last = start + m[0].rm_eo as isize;
-                start += m[0].rm_eo as isize;
+                last = m[0].rm_eo as isize;
+                start = m[0].rm_eo as isize;
                 empty = 0;
             } else {
-                last = start + m[0].rm_eo as isize;
-                start += (m[0].rm_eo + 1) as isize;
+                last = m[0].rm_eo as isize;
+                start = (m[0].rm_eo + 1) as isize;
                 empty = 1;
             }
 
@@ -158,7 +151,6 @@ pub unsafe fn regsub(
         }
         *buf.offset(len) = b'\0' as _;
 
-        regfree(&raw mut r);
         buf
     }
 }
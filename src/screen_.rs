@@ -110,6 +110,9 @@ pub unsafe fn screen_reinit(s: *mut screen) {
         (*s).saved_cx = u32::MAX;
         (*s).saved_cy = u32::MAX;
 
+        (*s).kitty_keys = screen_kitty_keys::default();
+        (*s).saved_kitty_keys = screen_kitty_keys::default();
+
         screen_reset_tabs(s);
 
         grid_clear_lines((*s).grid, (*(*s).grid).hsize, (*(*s).grid).sy, 8);
@@ -404,6 +407,7 @@ unsafe fn screen_resize_y(s: *mut screen, sy: u32, eat_empty: i32, cy: *mut u32)
             if (*gd).flags & GRID_HISTORY != 0 {
                 (*gd).hscrolled += needed;
                 (*gd).hsize += needed;
+                grid_index_add(gd, needed);
             } else if needed > 0 && available > 0 {
                 if available > needed {
                     available = needed;
@@ -429,6 +433,7 @@ unsafe fn screen_resize_y(s: *mut screen, sy: u32, eat_empty: i32, cy: *mut u32)
                 }
                 (*gd).hscrolled -= available;
                 (*gd).hsize -= available;
+                grid_index_remove(gd, available);
             } else {
                 available = 0;
             }
@@ -692,6 +697,9 @@ pub unsafe fn screen_alternate_on(s: *mut screen, gc: *mut grid_cell, cursor: i3
 
         (*s).saved_flags = (*(*s).grid).flags;
         (*(*s).grid).flags &= !GRID_HISTORY;
+
+        (*s).saved_kitty_keys = (*s).kitty_keys;
+        (*s).kitty_keys = screen_kitty_keys::default();
     }
 }
 
@@ -747,6 +755,9 @@ pub unsafe fn screen_alternate_off(s: *mut screen, gc: *mut grid_cell, cursor: i
         grid_destroy((*s).saved_grid);
         (*s).saved_grid = null_mut();
 
+        (*s).kitty_keys = (*s).saved_kitty_keys;
+        (*s).saved_kitty_keys = screen_kitty_keys::default();
+
         if (*s).cx > screen_size_x(s) - 1 {
             (*s).cx = screen_size_x(s) - 1;
         }
@@ -756,6 +767,53 @@ pub unsafe fn screen_alternate_off(s: *mut screen, gc: *mut grid_cell, cursor: i
     }
 }
 
+/// Push kitty keyboard flags, discarding the oldest entry if the stack is full.
+pub unsafe fn screen_kitty_keys_push(s: *mut screen, flags: kitty_key_flags) {
+    unsafe {
+        let kk = &mut (*s).kitty_keys;
+
+        if kk.depth == KITTY_KEYS_STACK_SIZE {
+            kk.stack.copy_within(1.., 0);
+            kk.depth -= 1;
+        }
+        kk.stack[kk.depth] = kk.flags;
+        kk.depth += 1;
+        kk.flags = flags;
+    }
+}
+
+/// Pop kitty keyboard flags. Popping more entries than were pushed resets all
+/// flags.
+pub unsafe fn screen_kitty_keys_pop(s: *mut screen, n: u32) {
+    unsafe {
+        let kk = &mut (*s).kitty_keys;
+
+        for _ in 0..n {
+            if kk.depth == 0 {
+                kk.flags = kitty_key_flags::empty();
+                break;
+            }
+            kk.depth -= 1;
+            kk.flags = kk.stack[kk.depth];
+        }
+    }
+}
+
+/// Change the current kitty keyboard flags: 1 replaces them, 2 adds to them
+/// and 3 removes from them.
+pub unsafe fn screen_kitty_keys_set(s: *mut screen, flags: kitty_key_flags, how: i32) {
+    unsafe {
+        let kk = &mut (*s).kitty_keys;
+
+        match how {
+            1 => kk.flags = flags,
+            2 => kk.flags |= flags,
+            3 => kk.flags &= !flags,
+            _ => (),
+        }
+    }
+}
+
 /// Get mode as a string.
 pub unsafe fn screen_mode_to_string(mode: mode_flag) -> *const u8 {
     const TMP_LEN: usize = 1024;
@@ -829,6 +887,15 @@ pub unsafe fn screen_mode_to_string(mode: mode_flag) -> *const u8 {
         if mode.intersects(mode_flag::MODE_KEYS_EXTENDED_2) {
             strlcat(addr_of_mut!(TMP).cast(), c!("KEYS_EXTENDED_2,"), TMP_LEN);
         }
+        if mode.intersects(mode_flag::MODE_SYNC) {
+            strlcat(addr_of_mut!(TMP).cast(), c!("SYNC,"), TMP_LEN);
+        }
+        if mode.intersects(mode_flag::MODE_THEME) {
+            strlcat(addr_of_mut!(TMP).cast(), c!("THEME,"), TMP_LEN);
+        }
+        if mode.intersects(mode_flag::MODE_GRAPHEMES) {
+            strlcat(addr_of_mut!(TMP).cast(), c!("GRAPHEMES,"), TMP_LEN);
+        }
 
         let len = strlen(addr_of!(TMP).cast());
         if len > 0 {
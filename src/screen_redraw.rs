@@ -704,6 +704,8 @@ pub unsafe fn screen_redraw_screen(c: *mut client) {
         }
         if flags.intersects(client_flag::REDRAWWINDOW) {
             log_debug!("{}: redrawing panes", _s((*c).name));
+            #[cfg(feature = "sixel")]
+            crate::tty_::tty_clear_kitty_images(&raw mut (*c).tty);
             screen_redraw_draw_panes(ctx);
         }
         if (*ctx).statuslines != 0
@@ -69,6 +69,59 @@ unsafe extern "C-unwind" fn screen_write_offset_timer(_fd: i32, _events: i16, w:
     }
 }
 
+/// How long to wait for an application to end a synchronized update before
+/// drawing anyway.
+const SCREEN_WRITE_SYNC_TIMEOUT: timeval = timeval {
+    tv_sec: 1,
+    tv_usec: 0,
+};
+
+unsafe extern "C-unwind" fn screen_write_sync_timer(_fd: i32, _events: i16, wp: NonNull<window_pane>) {
+    unsafe {
+        log_debug!("%{}: sync timer expired", (*wp.as_ptr()).id);
+        screen_write_stop_sync(wp.as_ptr());
+    }
+}
+
+/// Start a synchronized update. Changes are still made to the screen but are
+/// not written to clients until the update ends or the timer expires.
+pub unsafe fn screen_write_start_sync(wp: *mut window_pane) {
+    unsafe {
+        if wp.is_null() {
+            return;
+        }
+        (*wp).base.mode |= mode_flag::MODE_SYNC;
+
+        if event_initialized(&raw mut (*wp).sync_timer) == 0 {
+            evtimer_set(
+                &raw mut (*wp).sync_timer,
+                screen_write_sync_timer,
+                NonNull::new_unchecked(wp),
+            );
+        }
+        evtimer_del(&raw mut (*wp).sync_timer);
+        evtimer_add(&raw mut (*wp).sync_timer, &SCREEN_WRITE_SYNC_TIMEOUT);
+    }
+}
+
+/// End a synchronized update and redraw the pane so clients see the result
+/// in one go.
+pub unsafe fn screen_write_stop_sync(wp: *mut window_pane) {
+    unsafe {
+        if wp.is_null() {
+            return;
+        }
+        if event_initialized(&raw mut (*wp).sync_timer) != 0 {
+            evtimer_del(&raw mut (*wp).sync_timer);
+        }
+        if !(*wp).base.mode.intersects(mode_flag::MODE_SYNC) {
+            return;
+        }
+        (*wp).base.mode &= !mode_flag::MODE_SYNC;
+        (*wp).flags |= window_pane_flags::PANE_REDRAW;
+    }
+}
+
 /// Set cursor position.
 unsafe fn screen_write_set_cursor(ctx: *mut screen_write_ctx, mut cx: i32, mut cy: i32) {
     unsafe {
@@ -144,6 +197,12 @@ unsafe fn screen_write_set_client_cb(ttyctx: *mut tty_ctx, c: *mut client) -> i3
             return 0;
         }
 
+        // The application is in the middle of a synchronized update, so
+        // leave the screen alone until it has finished.
+        if (*wp).base.mode.intersects(mode_flag::MODE_SYNC) {
+            return 0;
+        }
+
         if (*wp)
             .flags
             .intersects(window_pane_flags::PANE_REDRAW | window_pane_flags::PANE_DROP)
@@ -342,6 +401,7 @@ pub unsafe fn screen_write_reset(ctx: *mut screen_write_ctx) {
         screen_write_scrollregion(ctx, 0, screen_size_y(s) - 1);
 
         (*s).mode = mode_flag::MODE_CURSOR | mode_flag::MODE_WRAP;
+        (*s).kitty_keys = screen_kitty_keys::default();
 
         if options_get_number_(GLOBAL_OPTIONS, "extended-keys") == 2 {
             (*s).mode = ((*s).mode & !EXTENDED_KEY_MODES) | mode_flag::MODE_KEYS_EXTENDED;
@@ -2325,10 +2385,24 @@ pub unsafe fn screen_write_combine(ctx: *mut screen_write_ctx, gc: *const grid_c
             return zero_width;
         }
 
-        // Check if we need to combine characters. This could be zero width
-        // (set above), a modifier character (with an existing Unicode
-        // character) or a previous ZWJ.
-        if zero_width == 0 {
+        // If the application has asked for grapheme clusters (mode 2027), use
+        // the UAX #29 rules to decide whether this character continues the
+        // cluster in the previous cell and work out the width of the result.
+        if (*s).mode.intersects(mode_flag::MODE_GRAPHEMES) {
+            let mut wc: wchar_t = 0;
+            let mut cluster = utf8_grapheme_chars(&last.data);
+            if cluster.is_empty() || utf8_towc(ud, &raw mut wc) != utf8_state::UTF8_DONE {
+                return zero_width;
+            }
+            if utf8_grapheme_break(&cluster, wc) {
+                return zero_width;
+            }
+            cluster.push(wc);
+            force_wide = (utf8_grapheme_width(&cluster, last.data.width) == 2) as u32;
+        } else if zero_width == 0 {
+            // Check if we need to combine characters. This could be zero width
+            // (set above), a modifier character (with an existing Unicode
+            // character) or a previous ZWJ.
             if utf8_is_modifier(ud) {
                 if last.data.size < 2 {
                     return 0;
@@ -2569,6 +2643,60 @@ pub(crate) unsafe fn screen_write_sixelimage(
     }
 }
 
+/// Write a kitty graphics protocol image.
+#[cfg(feature = "sixel")]
+pub(crate) unsafe fn screen_write_kittyimage(
+    ctx: *mut screen_write_ctx,
+    ki: *mut kitty_image,
+    bg: u32,
+) {
+    use crate::image_::{image_scroll_up, image_store_kitty};
+    use crate::image_kitty::{kitty_clamp, kitty_size_in_cells};
+
+    unsafe {
+        let s = (*ctx).s;
+        let gd = (*s).grid;
+        let mut ttyctx: tty_ctx = zeroed();
+
+        let cx: u32 = (*s).cx;
+        let cy: u32 = (*s).cy;
+
+        // The terminal scales the image to the cells it covers, so an image
+        // that is too big only needs fewer cells.
+        kitty_clamp(
+            &mut *ki,
+            screen_size_x(s) - cx,
+            screen_size_y(s) - 1,
+        );
+        let (_, y) = kitty_size_in_cells(&*ki);
+
+        let sy = screen_size_y(s) - cy;
+        if sy < y {
+            let lines = y - sy + 1;
+            if image_scroll_up(s, lines) && !(*ctx).wp.is_null() {
+                (*(*ctx).wp).flags |= window_pane_flags::PANE_REDRAW;
+            }
+            for _ in 0..lines {
+                grid_view_scroll_region_up(gd, 0, screen_size_y(s) - 1, bg);
+                screen_write_collect_scroll(ctx, bg);
+            }
+            (*ctx).scrolled += lines;
+            if lines > cy {
+                screen_write_cursormove(ctx, -1, 0, 0);
+            } else {
+                screen_write_cursormove(ctx, -1, cy as i32 - lines as i32, 0);
+            }
+        }
+        screen_write_collect_flush(ctx, 0, "screen_write_kittyimage");
+
+        screen_write_initctx(ctx, &raw mut ttyctx, 0);
+        ttyctx.ptr = image_store_kitty(s, ki).cast();
+        tty_write(crate::tty_::tty_cmd_kittyimage, &raw mut ttyctx);
+
+        screen_write_cursormove(ctx, 0, ((*s).cy + y) as i32, 0);
+    }
+}
+
 /// Turn alternate screen on.
 pub unsafe fn screen_write_alternateon(
     ctx: *mut screen_write_ctx,
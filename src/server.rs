@@ -285,6 +285,8 @@ pub unsafe fn server_start(
 
         job_kill_all();
         status_prompt_save_history();
+        #[cfg(feature = "web")]
+        web_stop();
 
         libc::exit(0)
     }
@@ -13,6 +13,9 @@
 // IN AN ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING
 // OUT OF OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
 use crate::libc::{getpwuid, getuid};
+use crate::options_::{
+    options_array_first, options_array_item_value, options_array_next, options_get_only,
+};
 use crate::*;
 
 bitflags::bitflags! {
@@ -27,6 +30,8 @@ pub struct server_acl_user {
     pub uid: uid_t,
 
     pub flags: server_acl_user_flags,
+    /// permission profile from access-profiles, or null
+    pub profile: *mut u8,
 
     pub entry: rb_entry<server_acl_user>,
 }
@@ -78,10 +83,15 @@ pub unsafe fn server_acl_display(item: *mut cmdq_item) {
             } else {
                 c!("unknown")
             };
-            if (*loop_).flags == server_acl_user_flags::SERVER_ACL_READONLY {
-                cmdq_print!(item, "{} (R)", _s(name));
+            let access = if (*loop_).flags == server_acl_user_flags::SERVER_ACL_READONLY {
+                "R"
             } else {
-                cmdq_print!(item, "{} (W)", _s(name));
+                "W"
+            };
+            if !(*loop_).profile.is_null() {
+                cmdq_print!(item, "{} ({}, {})", _s(name), access, _s((*loop_).profile));
+            } else {
+                cmdq_print!(item, "{} ({})", _s(name), access);
             }
         }
     }
@@ -105,6 +115,7 @@ pub unsafe fn server_acl_user_deny(uid: uid_t) {
         if !user.is_null() {
             // server_acl_entries
             rb_remove(&raw mut SERVER_ACL_ENTRIES, user);
+            free_((*user).profile);
             free_(user);
         }
     }
@@ -138,7 +149,7 @@ pub unsafe fn server_acl_user_deny_write(mut uid: uid_t) {
         for c in tailq_foreach(&raw mut CLIENTS).map(NonNull::as_ptr) {
             uid = proc_get_peer_uid((*c).peer);
             if uid != -1i32 as uid_t && uid == (*user).uid {
-                (*c).flags &= !client_flag::READONLY;
+                (*c).flags |= client_flag::READONLY;
             }
         }
     }
@@ -168,3 +179,252 @@ pub unsafe fn server_acl_join(c: *mut client) -> c_int {
 pub unsafe fn server_acl_get_uid(user: *mut server_acl_user) -> uid_t {
     unsafe { (*user).uid }
 }
+
+/// Set or clear (with null) the permission profile of a user.
+pub unsafe fn server_acl_user_set_profile(uid: uid_t, profile: *const u8) {
+    unsafe {
+        let user = server_acl_user_find(uid);
+        if user.is_null() {
+            return;
+        }
+        free_((*user).profile);
+        (*user).profile = if profile.is_null() {
+            null_mut()
+        } else {
+            xstrdup(profile).as_ptr()
+        };
+    }
+}
+
+/// The permission profile which applies to a client: its own if it has one,
+/// otherwise its user's. Null if neither has one.
+pub unsafe fn server_acl_get_profile(c: *mut client) -> *const u8 {
+    unsafe {
+        if !(*c).profile.is_null() {
+            return (*c).profile;
+        }
+        if (*c).peer.is_null() {
+            return null();
+        }
+        let user = server_acl_user_find(proc_get_peer_uid((*c).peer));
+        if user.is_null() {
+            return null();
+        }
+        (*user).profile
+    }
+}
+
+/// Find a profile in access-profiles and check whether it allows something,
+/// either keys or a command name. Returns None if there is no such profile.
+pub unsafe fn server_acl_profile_allows(profile: *const u8, what: &str) -> Option<bool> {
+    unsafe {
+        let o = options_get_only(GLOBAL_OPTIONS, "access-profiles");
+        if o.is_null() {
+            return None;
+        }
+        let profile = cstr_to_str(profile);
+        let what = CString::new(what).unwrap();
+
+        let mut a = options_array_first(o);
+        while !a.is_null() {
+            let ov = options_array_item_value(a);
+            let mut fields = cstr_to_str((*ov).string).split(':');
+            if fields.next() == Some(profile) {
+                return Some(fields.any(|pattern| {
+                    CString::new(pattern).is_ok_and(|pattern| {
+                        fnmatch(pattern.as_ptr().cast(), what.as_ptr().cast(), 0) == 0
+                    })
+                }));
+            }
+            a = options_array_next(a);
+        }
+        None
+    }
+}
+
+/// What a profile must allow for a command to run. Commands are allowed by
+/// name except those which act on a mode, send mouse events or might run a
+/// shell command, which need copy-mode, keys or run-shell instead. The hints
+/// copy mode command can pipe to a shell command or run any command. Acting on
+/// clients other than the caller's own also needs other-clients.
+unsafe fn server_acl_command_permission(cmd: *mut cmd) -> &'static [&'static str] {
+    unsafe {
+        let entry = cmd_get_entry(cmd);
+        let args = cmd_get_args(cmd);
+
+        match entry.name {
+            "send-keys" if args_has(args, 'X') => {
+                let command = args_string(args, 0);
+                if !command.is_null()
+                    && (strncmp(command, c!("copy-pipe"), 9) == 0
+                        || strncmp(command, c!("pipe"), 4) == 0)
+                {
+                    return &["run-shell"];
+                }
+                if !command.is_null()
+                    && streq_(command, "hints")
+                    && args_count(args) > 1
+                    && matches!(cstr_to_str(args_string(args, 1)), "pipe" | "run")
+                {
+                    return &["run-shell"];
+                }
+                &["copy-mode"]
+            }
+            "send-keys" if args_has(args, 'M') => &["keys"],
+            "if-shell" if args_has(args, 'F') => &[],
+            "if-shell" => &["run-shell"],
+            "detach-client" => {
+                let other = args_has(args, 'a') || args_has(args, 's') || args_has(args, 't');
+                match (args_has(args, 'E'), other) {
+                    (true, true) => &["run-shell", "other-clients"],
+                    (true, false) => &["run-shell"],
+                    (false, true) => &["detach-client", "other-clients"],
+                    (false, false) => &["detach-client"],
+                }
+            }
+            "switch-client" if args_has(args, 'c') => &["switch-client", "other-clients"],
+            _ => std::slice::from_ref(&entry.name),
+        }
+    }
+}
+
+/// Check the client of an item may run a command, logging and reporting it if
+/// not. Commands from hooks are always allowed.
+pub unsafe fn server_acl_check_command(item: *mut cmdq_item, cmd: *mut cmd) -> bool {
+    unsafe {
+        let c = cmdq_get_client(item);
+        if c.is_null() || cmdq_get_flags(item).intersects(cmdq_state_flags::CMDQ_STATE_NOHOOKS) {
+            return true;
+        }
+        let profile = server_acl_get_profile(c);
+        if profile.is_null() {
+            return true;
+        }
+
+        let name = cmd_get_entry(cmd).name;
+        let allowed = server_acl_command_permission(cmd)
+            .iter()
+            .all(|permission| server_acl_profile_allows(profile, permission) == Some(true));
+        if allowed {
+            return true;
+        }
+        server_add_message!(
+            "{} denied {} by profile {}",
+            _s((*c).name),
+            name,
+            _s(profile)
+        );
+        cmdq_error!(item, "{} not allowed by profile {}", name, _s(profile));
+        false
+    }
+}
+
+/// Whether formats expanded for an item may run shell commands with #().
+pub unsafe fn server_acl_check_jobs(item: *mut cmdq_item) -> bool {
+    unsafe {
+        let c = cmdq_get_client(item);
+        if c.is_null() || cmdq_get_flags(item).intersects(cmdq_state_flags::CMDQ_STATE_NOHOOKS) {
+            return true;
+        }
+        let profile = server_acl_get_profile(c);
+        profile.is_null() || server_acl_profile_allows(profile, "run-shell") == Some(true)
+    }
+}
+
+/// Check a client may send keys to a pane, logging and reporting it if not.
+pub unsafe fn server_acl_check_keys(c: *mut client) -> bool {
+    unsafe {
+        let profile = server_acl_get_profile(c);
+        if profile.is_null() || server_acl_profile_allows(profile, "keys") == Some(true) {
+            return true;
+        }
+
+        // Only log again once the last message has gone, rather than for
+        // every key.
+        if (*c).message_string.is_null() {
+            server_add_message!("{} denied keys by profile {}", _s((*c).name), _s(profile));
+            status_message_set!(
+                c,
+                -1,
+                1,
+                false,
+                "keys not allowed by profile {}",
+                _s(profile)
+            );
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options_::options_test_globals;
+
+    #[test]
+    fn test_server_acl_command_permission() {
+        unsafe {
+            options_test_globals();
+            let permission = |s: &str| {
+                let cmdlist = cmd_parse_from_string(s, None).unwrap();
+                let permission = server_acl_command_permission(cmd_list_first(cmdlist));
+                cmd_list_free(cmdlist);
+                permission
+            };
+
+            assert_eq!(permission("kill-server"), ["kill-server"]);
+            assert_eq!(permission("splitw -h"), ["split-window"]);
+            assert_eq!(permission("send-keys a"), ["send-keys"]);
+            assert_eq!(permission("send-keys -M"), ["keys"]);
+            assert_eq!(permission("send-keys -X cursor-up"), ["copy-mode"]);
+            assert_eq!(permission("send-keys -X copy-pipe-and-cancel cat"), ["run-shell"]);
+            assert_eq!(permission("send-keys -X pipe-no-clear cat"), ["run-shell"]);
+            assert_eq!(permission("send-keys -X hints"), ["copy-mode"]);
+            assert_eq!(permission("send-keys -X hints copy buf"), ["copy-mode"]);
+            assert_eq!(permission("send-keys -X hints pipe"), ["run-shell"]);
+            assert_eq!(permission("send-keys -X hints run kill-server"), ["run-shell"]);
+            assert_eq!(permission("if-shell true kill-server"), ["run-shell"]);
+            assert!(permission("if-shell -F 1 kill-server").is_empty());
+
+            assert_eq!(permission("detach-client"), ["detach-client"]);
+            assert_eq!(permission("detach-client -P"), ["detach-client"]);
+            assert_eq!(permission("detach-client -E sh"), ["run-shell"]);
+            assert_eq!(permission("detach -a"), ["detach-client", "other-clients"]);
+            assert_eq!(permission("detach -aP"), ["detach-client", "other-clients"]);
+            assert_eq!(permission("detach -s0"), ["detach-client", "other-clients"]);
+            assert_eq!(permission("detach -t/dev/pts/1"), ["detach-client", "other-clients"]);
+            assert_eq!(permission("detach -a -E sh"), ["run-shell", "other-clients"]);
+            assert_eq!(permission("switch-client -t0"), ["switch-client"]);
+            assert_eq!(permission("switchc -c/dev/pts/1"), ["switch-client", "other-clients"]);
+        }
+    }
+
+    #[test]
+    fn test_server_acl_profile_allows() {
+        unsafe {
+            options_test_globals();
+            let allows = |profile: &CStr, what| server_acl_profile_allows(profile.as_ptr().cast(), what);
+
+            assert_eq!(allows(c"observer", "copy-mode"), Some(true));
+            assert_eq!(allows(c"observer", "list-panes"), Some(true));
+            assert_eq!(allows(c"observer", "list-buffers"), Some(false));
+            assert_eq!(allows(c"observer", "keys"), Some(false));
+            assert_eq!(allows(c"observer", "send-keys"), Some(false));
+
+            assert_eq!(allows(c"operator", "list-buffers"), Some(true));
+            assert_eq!(allows(c"operator", "keys"), Some(true));
+            assert_eq!(allows(c"operator", "send-keys"), Some(true));
+            assert_eq!(allows(c"operator", "run-shell"), Some(false));
+            assert_eq!(allows(c"operator", "kill-server"), Some(false));
+
+            for profile in [c"observer", c"operator"] {
+                assert_eq!(allows(profile, "detach-client"), Some(true));
+                assert_eq!(allows(profile, "switch-client"), Some(true));
+                assert_eq!(allows(profile, "other-clients"), Some(false));
+            }
+
+            assert_eq!(allows(c"observers", "copy-mode"), None);
+            assert_eq!(allows(c"", "copy-mode"), None);
+        }
+    }
+}
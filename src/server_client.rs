@@ -449,6 +449,7 @@ pub unsafe fn server_client_lost(c: *mut client) {
 
         free_((*c).title);
         free_((*c).cwd.cast_mut()); // TODO cast away const
+        free_((*c).profile);
 
         evtimer_del(&raw mut (*c).repeat_timer);
         evtimer_del(&raw mut (*c).click_timer);
@@ -1878,6 +1879,11 @@ pub unsafe fn server_client_key_callback(item: *mut cmdq_item, data: *mut c_void
                 }
                 wp = fs.wp;
 
+                // Key releases are never bound, so go straight to the pane.
+                if (key & KEYC_RELEASE) != 0 {
+                    break 'forward_key;
+                }
+
                 // Forward mouse keys if disabled.
                 if KEYC_IS_MOUSE(key) && options_get_number_((*s).options, "mouse") == 0 {
                     break 'forward_key;
@@ -2078,7 +2084,9 @@ pub unsafe fn server_client_key_callback(item: *mut cmdq_item, data: *mut c_void
             if (*c).flags.intersects(client_flag::READONLY) {
                 break 'out;
             }
-            if !wp.is_null() {
+            // Only keys which reach the pane need the profile to allow keys,
+            // key bindings instead check each command as it runs.
+            if !wp.is_null() && server_acl_check_keys(c) {
                 window_pane_key(wp, c, s, wl, key, m);
             }
         } // 'out:
@@ -2100,6 +2108,27 @@ pub unsafe fn server_client_handle_key(c: *mut client, event: *mut key_event) ->
             return 0;
         }
 
+        // Only a pane which has asked for them with the kitty keyboard
+        // protocol gets key releases and repeats. For everything else, repeats
+        // are presses and releases are discarded.
+        if ((*event).key & KEYC_MASK_EVENT) != 0 {
+            let wp = server_client_get_pane(c);
+            if wp.is_null()
+                || !tailq_empty(&raw mut (*wp).modes)
+                || !(*(*wp).screen)
+                    .kitty_keys
+                    .flags
+                    .intersects(kitty_key_flags::KITTY_KEYS_REPORT_EVENTS)
+                || (*c).overlay_key.is_some()
+                || !(*c).prompt_string.is_null()
+            {
+                if ((*event).key & KEYC_RELEASE) != 0 {
+                    return 0;
+                }
+                (*event).key &= !KEYC_MASK_EVENT;
+            }
+        }
+
         // Key presses in overlay mode and the command prompt are a special
         // case. The queue might be blocked so they need to be processed
         // immediately rather than queued.
@@ -2706,7 +2735,9 @@ pub unsafe fn server_client_check_redraw(c: *mut client) {
             needed = true;
         } else {
             for wp in tailq_foreach::<_, discr_entry>(&raw mut (*w).panes).map(NonNull::as_ptr) {
-                if (*wp).flags.intersects(window_pane_flags::PANE_REDRAW) {
+                if (*wp).flags.intersects(window_pane_flags::PANE_REDRAW)
+                    && !(*wp).base.mode.intersects(mode_flag::MODE_SYNC)
+                {
                     needed = true;
                     break;
                 }
@@ -2772,7 +2803,7 @@ pub unsafe fn server_client_check_redraw(c: *mut client) {
                     redraw = ((*c).redraw_panes & (1 << bit)) != 0;
                 }
                 bit += 1;
-                if !redraw {
+                if !redraw || (*wp).base.mode.intersects(mode_flag::MODE_SYNC) {
                     continue;
                 }
                 // log_debug("%s: redrawing pane %%%u", __func__, (*wp).id);
@@ -3275,6 +3306,8 @@ pub unsafe fn server_client_control_flags(c: *mut client, next: *const u8) -> cl
             client_flag::CONTROL_NOOUTPUT
         } else if streq_(next, "wait-exit") {
             client_flag::CONTROL_WAITEXIT
+        } else if streq_(next, "json") {
+            client_flag::CONTROL_JSON
         } else {
             client_flag::empty()
         }
@@ -3292,7 +3325,7 @@ pub unsafe fn server_client_set_flags(c: *mut client, flags: *const u8) {
         let mut s = copy;
         while {
             next = strsep(&raw mut s, c!(","));
-            next.is_null()
+            !next.is_null()
         } {
             not = *next == b'!';
             if not {
@@ -3366,6 +3399,9 @@ pub unsafe fn server_client_get_flags(c: *mut client) -> *const u8 {
         if (*c).flags.intersects(client_flag::CONTROL_WAITEXIT) {
             strlcat((&raw mut S).cast(), c!("wait-exit,"), SIZEOF_S);
         }
+        if (*c).flags.intersects(client_flag::CONTROL_JSON) {
+            strlcat((&raw mut S).cast(), c!("json,"), SIZEOF_S);
+        }
         if (*c).flags.intersects(client_flag::CONTROL_PAUSEAFTER) {
             _ = xsnprintf_!(
                 (&raw mut TMP).cast(),
@@ -3501,13 +3537,13 @@ pub unsafe fn server_client_print(c: *mut client, parse: i32, evb: *mut evbuffer
                 if !(*c).flags.intersects(client_flag::UTF8) {
                     let sanitized = utf8_sanitize(msg);
                     if (*c).flags.intersects(client_flag::CONTROL) {
-                        control_write!(c, "{}", _s(sanitized));
+                        control_write_line(c, CStr::from_ptr(sanitized.cast()).to_bytes());
                     } else {
                         file_print!(c, "{}\n", _s(sanitized));
                     }
                     free_(sanitized);
                 } else if (*c).flags.intersects(client_flag::CONTROL) {
-                    control_write!(c, "{}", _s(msg));
+                    control_write_line(c, CStr::from_ptr(msg.cast()).to_bytes());
                 } else {
                     file_print!(c, "{}\n", _s(msg));
                 }
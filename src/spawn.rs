@@ -255,8 +255,18 @@ pub unsafe fn spawn_pane(sc: *mut spawn_context, cause: *mut *mut u8) -> *mut wi
         'complete: {
             spawn_log("spawn_pane", sc);
 
+            // Expand the directory for the target pane, so that
+            // #{pane_current_path} is the directory it reported with OSC 7 if
+            // it did.
             if !(*sc).cwd.is_null() {
-                cwd = format_single(item, cstr_to_str((*sc).cwd), c, (*target).s, null_mut(), null_mut());
+                cwd = format_single(
+                    item,
+                    cstr_to_str((*sc).cwd),
+                    c,
+                    (*target).s,
+                    (*target).wl,
+                    (*target).wp,
+                );
                 if *cwd != b'/' {
                     new_cwd =
                         format_nul!("{}/{}", _s(server_client_get_cwd(c, (*target).s)), _s(cwd));
@@ -306,6 +316,10 @@ pub unsafe fn spawn_pane(sc: *mut spawn_context, cause: *mut *mut u8) -> *mut wi
                     layout_assign_pane((*sc).lc, new_wp, 0);
                 }
             }
+            if !(*sc).flags.intersects(SPAWN_RESPAWN) {
+                let limit = options_get_number_((*s).options, "history-file-limit");
+                grid_spill_set((*new_wp).base.grid, limit as u32);
+            }
 
             // Now we have a pane with nothing running in it ready for the new
             // process. Work out the command and arguments and store the working
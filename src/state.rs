@@ -0,0 +1,665 @@
+// Saving sessions, windows and panes to a state file and recreating them from
+// it, so they can be brought back after the server exits.
+//
+// The file is text, one record per line. Fields are separated by tabs and
+// escaped with vis(3). The first line is "tmux-rs-state" and the version; a
+// session record is followed by its options and windows, a window by its
+// options and panes, and a pane by its options and the lines of its contents
+// if they were saved.
+use std::io::Write as _;
+use std::os::unix::fs::OpenOptionsExt as _;
+
+use crate::compat::queue::{tailq_first, tailq_foreach, tailq_insert_tail, tailq_remove};
+use crate::compat::strunvis;
+use crate::compat::tree::rb_foreach;
+use crate::*;
+use crate::options_::*;
+
+const STATE_MAGIC: &str = "tmux-rs-state";
+const STATE_VERSION: u32 = 1;
+
+/// A session read from a state file.
+struct state_session {
+    name: String,
+    cwd: CString,
+    options: Vec<(String, CString)>,
+    windows: Vec<state_window>,
+}
+
+/// A window read from a state file.
+struct state_window {
+    idx: i32,
+    name: CString,
+    layout: CString,
+    active: bool,
+    options: Vec<(String, CString)>,
+    panes: Vec<state_pane>,
+}
+
+/// A pane read from a state file.
+struct state_pane {
+    active: bool,
+    cwd: CString,
+    argv: Vec<CString>,
+    options: Vec<(String, CString)>,
+    contents: Vec<u8>,
+}
+
+/// Get the path of a state file. Relative paths are relative to the client's
+/// working directory, or not allowed without a client.
+pub unsafe fn state_find_file(c: *mut client, path: *const u8) -> Option<String> {
+    unsafe {
+        if path.is_null() || *path == b'\0' {
+            return None;
+        }
+        let path = cstr_to_str(path);
+        if path.starts_with('/') {
+            return Some(path.to_string());
+        }
+        if let Some(rest) = path.strip_prefix("~/") {
+            return Some(format!("{}/{}", find_home()?.to_str().ok()?, rest));
+        }
+        if c.is_null() {
+            return None;
+        }
+        Some(format!(
+            "{}/{}",
+            _s(server_client_get_cwd(c, null_mut())),
+            path
+        ))
+    }
+}
+
+/// Append a record to the state.
+unsafe fn state_write(out: &mut Vec<u8>, record: &str, fields: &[&CStr]) {
+    unsafe {
+        let flags =
+            vis_flags::VIS_OCTAL | vis_flags::VIS_CSTYLE | vis_flags::VIS_TAB | vis_flags::VIS_NL;
+
+        out.extend_from_slice(record.as_bytes());
+        for field in fields {
+            out.push(b'\t');
+            out.extend_from_slice(&utf8_stravis_(field.as_ptr().cast(), flags));
+        }
+        out.push(b'\n');
+    }
+}
+
+/// Append the options set directly on an options tree.
+unsafe fn state_save_options(out: &mut Vec<u8>, record: &str, oo: *mut options) {
+    unsafe {
+        let mut o = options_first(oo);
+        while !o.is_null() {
+            let name = options_name(o);
+            if options_is_array(o) {
+                let mut a = options_array_first(o);
+                while !a.is_null() {
+                    let idx = options_array_item_index(a);
+                    let value = options_to_string(o, idx as i32, 0);
+                    let name = CString::new(format!("{name}[{idx}]")).unwrap();
+                    state_write(out, record, &[&name, CStr::from_ptr(value.cast())]);
+                    free_(value);
+                    a = options_array_next(a);
+                }
+            } else {
+                let value = options_to_string(o, -1, 0);
+                let name = CString::new(name).unwrap();
+                state_write(out, record, &[&name, CStr::from_ptr(value.cast())]);
+                free_(value);
+            }
+            o = options_next(o);
+        }
+    }
+}
+
+/// Append the contents of a pane up to the line with the cursor, joining
+/// wrapped lines. The normal screen is saved if the alternate screen is in
+/// use.
+unsafe fn state_save_contents(out: &mut Vec<u8>, wp: *mut window_pane) {
+    unsafe {
+        let s = &raw mut (*wp).base;
+        let (gd, cy) = if !(*s).saved_grid.is_null() {
+            ((*s).saved_grid, (*s).saved_cy)
+        } else {
+            ((*s).grid, (*s).cy)
+        };
+        let mut gc: *mut grid_cell = null_mut();
+
+        let mut line = Vec::new();
+        for y in 0..(*gd).hsize + cy {
+            let cp = grid_string_cells(
+                gd,
+                0,
+                y,
+                (*gd).sx,
+                &raw mut gc,
+                grid_string_flags::GRID_STRING_WITH_SEQUENCES,
+                s,
+            );
+            line.extend_from_slice(CStr::from_ptr(cp.cast()).to_bytes());
+            free_(cp);
+
+            let gl = grid_peek_line(gd, y);
+            if !(*gl).flags.intersects(grid_line_flag::WRAPPED) {
+                state_write(out, "line", &[&CString::new(std::mem::take(&mut line)).unwrap()]);
+            }
+        }
+        if !line.is_empty() {
+            state_write(out, "line", &[&CString::new(line).unwrap()]);
+        }
+    }
+}
+
+/// Save every session to a state file, including the contents of each pane
+/// if contents is true.
+pub unsafe fn state_save(path: &str, contents: bool) -> Result<(), String> {
+    unsafe {
+        let mut out = Vec::new();
+        let version = CString::new(STATE_VERSION.to_string()).unwrap();
+        state_write(&mut out, STATE_MAGIC, &[&version]);
+
+        for s in rb_foreach(&raw mut SESSIONS).map(NonNull::as_ptr) {
+            let name = CString::new((*s).name.as_bytes()).unwrap();
+            state_write(&mut out, "session", &[&name, CStr::from_ptr((*s).cwd.cast())]);
+            state_save_options(&mut out, "session-option", (*s).options);
+
+            for wl in rb_foreach(&raw mut (*s).windows).map(NonNull::as_ptr) {
+                let w = (*wl).window;
+
+                let lc = if !(*w).saved_layout_root.is_null() {
+                    (*w).saved_layout_root
+                } else {
+                    (*w).layout_root
+                };
+                let Some(layout) = layout_dump(lc) else {
+                    continue;
+                };
+                let idx = CString::new((*wl).idx.to_string()).unwrap();
+                let layout = CString::new(layout).unwrap();
+                let active = if wl == (*s).curw { c"1" } else { c"0" };
+                state_write(
+                    &mut out,
+                    "window",
+                    &[&idx, CStr::from_ptr((*w).name.cast()), &layout, active],
+                );
+                state_save_options(&mut out, "window-option", (*w).options);
+
+                for wp in tailq_foreach::<_, discr_entry>(&raw mut (*w).panes).map(NonNull::as_ptr)
+                {
+                    let cwd = format_single(
+                        null_mut(),
+                        "#{pane_current_path}",
+                        null_mut(),
+                        s,
+                        wl,
+                        wp,
+                    );
+                    let active = if wp == (*w).active { c"1" } else { c"0" };
+                    let mut fields = vec![active, CStr::from_ptr(cwd.cast())];
+                    for i in 0..(*wp).argc {
+                        fields.push(CStr::from_ptr((*(*wp).argv.add(i as usize)).cast()));
+                    }
+                    state_write(&mut out, "pane", &fields);
+                    free_(cwd);
+                    state_save_options(&mut out, "pane-option", (*wp).options);
+
+                    if contents {
+                        state_save_contents(&mut out, wp);
+                    }
+                }
+            }
+        }
+
+        // Write to a temporary file and rename it so a failure does not
+        // destroy the last saved state. The file may hold pane contents, so
+        // only the owner may read it; a file left by an earlier failure is
+        // removed rather than opened, so its permissions are not kept.
+        let tmp = format!("{path}.tmp");
+        _ = std::fs::remove_file(&tmp);
+        let result = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp)
+            .and_then(|mut f| f.write_all(&out).and_then(|()| f.sync_all()))
+            .and_then(|()| std::fs::rename(&tmp, path));
+        if let Err(err) = result {
+            _ = std::fs::remove_file(&tmp);
+            return Err(format!("{path}: {err}"));
+        }
+        log_debug!("saved state to {path}");
+        Ok(())
+    }
+}
+
+/// Unescape one field of a record.
+unsafe fn state_unescape(field: &[u8]) -> Option<CString> {
+    unsafe {
+        let src = CString::new(field).ok()?;
+        let mut dst = vec![0u8; field.len() + 1];
+        let n = strunvis(dst.as_mut_ptr(), src.as_ptr().cast());
+        if n < 0 {
+            return None;
+        }
+        dst.truncate(n as usize);
+        CString::new(dst).ok()
+    }
+}
+
+/// Read a state file.
+unsafe fn state_read(path: &str) -> Result<Vec<state_session>, String> {
+    unsafe {
+        let data = std::fs::read(path).map_err(|err| format!("{path}: {err}"))?;
+        let mut sessions: Vec<state_session> = Vec::new();
+
+        for (n, line) in data.split(|&ch| ch == b'\n').enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = Vec::new();
+            for field in line.split(|&ch| ch == b'\t') {
+                let Some(field) = state_unescape(field) else {
+                    return Err(format!("{path}:{}: invalid line", n + 1));
+                };
+                fields.push(field);
+            }
+            let record = fields[0].to_string_lossy().into_owned();
+            let mut fields = fields.into_iter().skip(1);
+            let mut next = || fields.next().unwrap_or_default();
+
+            if n == 0 {
+                let version = next();
+                if record != STATE_MAGIC {
+                    return Err(format!("{path}: not a state file"));
+                }
+                if version.to_str().ok().and_then(|v| v.parse::<u32>().ok()) != Some(STATE_VERSION)
+                {
+                    return Err(format!(
+                        "{path}: unsupported state version {}",
+                        version.to_string_lossy()
+                    ));
+                }
+                continue;
+            }
+
+            let missing = || format!("{path}:{}: {record} outside of a session", n + 1);
+            match record.as_str() {
+                "session" => sessions.push(state_session {
+                    name: next().to_string_lossy().into_owned(),
+                    cwd: next(),
+                    options: Vec::new(),
+                    windows: Vec::new(),
+                }),
+                "session-option" => {
+                    let ss = sessions.last_mut().ok_or_else(missing)?;
+                    ss.options
+                        .push((next().to_string_lossy().into_owned(), next()));
+                }
+                "window" => {
+                    let ss = sessions.last_mut().ok_or_else(missing)?;
+                    let Ok(idx) = next().to_string_lossy().parse() else {
+                        return Err(format!("{path}:{}: invalid window index", n + 1));
+                    };
+                    ss.windows.push(state_window {
+                        idx,
+                        name: next(),
+                        layout: next(),
+                        active: next().as_bytes() == b"1",
+                        options: Vec::new(),
+                        panes: Vec::new(),
+                    });
+                }
+                "window-option" | "pane" | "pane-option" | "line" => {
+                    let Some(sw) = sessions.last_mut().and_then(|ss| ss.windows.last_mut())
+                    else {
+                        return Err(format!("{path}:{}: {record} outside of a window", n + 1));
+                    };
+                    if record == "window-option" {
+                        sw.options
+                            .push((next().to_string_lossy().into_owned(), next()));
+                        continue;
+                    }
+                    if record == "pane" {
+                        sw.panes.push(state_pane {
+                            active: next().as_bytes() == b"1",
+                            cwd: next(),
+                            argv: fields.collect(),
+                            options: Vec::new(),
+                            contents: Vec::new(),
+                        });
+                        continue;
+                    }
+                    let Some(sp) = sw.panes.last_mut() else {
+                        return Err(format!("{path}:{}: {record} outside of a pane", n + 1));
+                    };
+                    if record == "pane-option" {
+                        sp.options
+                            .push((next().to_string_lossy().into_owned(), next()));
+                    } else {
+                        sp.contents.extend_from_slice(next().as_bytes());
+                        sp.contents.extend_from_slice(b"\r\n");
+                    }
+                }
+                // Ignore records from newer versions.
+                _ => log_debug!("{path}:{}: unknown record {record}", n + 1),
+            }
+        }
+        if data.is_empty() {
+            return Err(format!("{path}: not a state file"));
+        }
+        Ok(sessions)
+    }
+}
+
+/// Set an option read from a state file.
+unsafe fn state_restore_option(oo: *mut options, name: &str, value: &CStr) -> Result<(), String> {
+    unsafe {
+        let Some((name, idx)) = options_parse(name) else {
+            return Err(format!("invalid option: {name}"));
+        };
+        if name.starts_with('@') {
+            options_set_string!(oo, &name, false, "{}", value.to_string_lossy());
+            return Ok(());
+        }
+
+        let parent = options_get(&mut *oo, &name);
+        if parent.is_null() {
+            return Err(format!("invalid option: {name}"));
+        }
+        let oe = options_table_entry(parent);
+        let result = if idx == -1 && !options_is_array(parent) {
+            options_from_string(oo, oe, (*oe).name, value.as_ptr().cast(), false)
+        } else {
+            let mut o = options_get_only(oo, &name);
+            if o.is_null() {
+                o = options_empty(oo, oe);
+            }
+            options_array_set(o, idx.max(0) as u32, Some(&value.to_string_lossy()), false)
+        };
+        if let Err(cause) = result {
+            return Err(format!("{name}: {}", cause.to_string_lossy()));
+        }
+        options_push_changes(&name);
+        Ok(())
+    }
+}
+
+/// Set the options read from a state file, logging any that are invalid.
+unsafe fn state_restore_options(oo: *mut options, list: &[(String, CString)]) {
+    unsafe {
+        for (name, value) in list {
+            if let Err(cause) = state_restore_option(oo, name, value) {
+                log_debug!("state_restore_options: {cause}");
+            }
+        }
+    }
+}
+
+/// Fill in the command and working directory for a pane.
+unsafe fn state_spawn_context(sc: *mut spawn_context, sp: &state_pane) -> CString {
+    unsafe {
+        if !sp.argv.is_empty() {
+            (*sc).argc = sp.argv.len() as i32;
+            (*sc).argv = xreallocarray_::<*mut u8>(null_mut(), sp.argv.len()).as_ptr();
+            for (i, arg) in sp.argv.iter().enumerate() {
+                *(*sc).argv.add(i) = xstrdup(arg.as_ptr().cast()).as_ptr();
+            }
+        }
+
+        // The directory is expanded as a format.
+        let cwd = sp.cwd.to_string_lossy().replace('#', "##");
+        CString::new(cwd).unwrap()
+    }
+}
+
+/// Recreate a window and its panes in a session.
+unsafe fn state_restore_window(
+    item: *mut cmdq_item,
+    s: *mut session,
+    sw: &mut state_window,
+) -> Result<*mut winlink, String> {
+    unsafe {
+        let mut cause = null_mut();
+
+        let Some(first) = sw.panes.first() else {
+            return Err(format!("window {} has no panes", sw.idx));
+        };
+        let mut sc: spawn_context = zeroed();
+        sc.item = item;
+        sc.s = s;
+        sc.idx = sw.idx;
+        sc.flags = SPAWN_DETACHED;
+        let cwd = state_spawn_context(&raw mut sc, first);
+        sc.cwd = cwd.as_ptr().cast();
+
+        let wl = spawn_window(&raw mut sc, &raw mut cause);
+        cmd_free_argv(sc.argc, sc.argv);
+        if wl.is_null() {
+            let error = format!("create window failed: {}", _s(cause));
+            free_(cause);
+            return Err(error);
+        }
+        let w = (*wl).window;
+        free_((*w).name);
+        (*w).name = xstrdup(sw.name.as_ptr().cast()).as_ptr();
+        state_restore_options((*w).options, &sw.options);
+
+        // Create the other panes by splitting the largest pane each time.
+        let mut panes = vec![tailq_first(&raw mut (*w).panes)];
+        for sp in &sw.panes[1..] {
+            let wp0 = *panes
+                .iter()
+                .max_by_key(|&&wp| (*wp).sx * (*wp).sy)
+                .unwrap();
+            let type_ = if (*wp0).sx > (*wp0).sy * 2 {
+                layout_type::LAYOUT_LEFTRIGHT
+            } else {
+                layout_type::LAYOUT_TOPBOTTOM
+            };
+            let lc = layout_split_pane(wp0, type_, -1, spawn_flags::empty());
+            if lc.is_null() {
+                log_debug!("state_restore_window: no space for pane in {}", sw.idx);
+                break;
+            }
+
+            let mut sc: spawn_context = zeroed();
+            sc.item = item;
+            sc.s = s;
+            sc.wl = wl;
+            sc.wp0 = wp0;
+            sc.lc = lc;
+            sc.idx = -1;
+            sc.flags = SPAWN_DETACHED;
+            let cwd = state_spawn_context(&raw mut sc, sp);
+            sc.cwd = cwd.as_ptr().cast();
+
+            let wp = spawn_pane(&raw mut sc, &raw mut cause);
+            cmd_free_argv(sc.argc, sc.argv);
+            if wp.is_null() {
+                log_debug!("state_restore_window: create pane failed: {}", _s(cause));
+                free_(cause);
+                cause = null_mut();
+                break;
+            }
+            panes.push(wp);
+        }
+
+        // Put the panes in the order they were saved and apply the layout,
+        // which assigns panes to cells in that order.
+        for &wp in &panes {
+            tailq_remove::<_, discr_entry>(&raw mut (*w).panes, wp);
+            tailq_insert_tail::<_, discr_entry>(&raw mut (*w).panes, wp);
+        }
+        if layout_parse(w, sw.layout.as_ptr().cast(), &raw mut cause) != 0 {
+            log_debug!("state_restore_window: {}", _s(cause));
+            free_(cause);
+        }
+
+        for (sp, &wp) in sw.panes.iter_mut().zip(&panes) {
+            state_restore_options((*wp).options, &sp.options);
+            if sp.active {
+                window_set_active_pane(w, wp, 0);
+            }
+            if !sp.contents.is_empty() {
+                sp.contents.extend_from_slice(b"\x1b[m");
+                input_parse_buffer(wp, sp.contents.as_mut_ptr(), sp.contents.len());
+            }
+        }
+        Ok(wl)
+    }
+}
+
+/// Recreate a session. Sessions that already exist are left alone.
+unsafe fn state_restore_session(
+    item: *mut cmdq_item,
+    ss: &mut state_session,
+) -> Result<bool, String> {
+    let __func__ = c!("state_restore_session");
+    unsafe {
+        let name = CString::new(ss.name.as_str()).unwrap_or_default();
+        if session_check_name(name.as_ptr().cast()).as_deref() != Some(ss.name.as_str()) {
+            return Err(format!("invalid session: {}", ss.name));
+        }
+        if !session_find(&ss.name).is_null() {
+            log_debug!("{}: {} already exists", _s(__func__), ss.name);
+            return Ok(false);
+        }
+
+        let oo = options_create(GLOBAL_S_OPTIONS);
+        state_restore_options(oo, &ss.options);
+        let env = environ_create().as_ptr();
+        let s = session_create(null(), Some(&ss.name), ss.cwd.as_ptr().cast(), env, oo, null_mut());
+
+        let mut active = None;
+        let mut error = None;
+        for sw in &mut ss.windows {
+            match state_restore_window(item, s, sw) {
+                Ok(wl) if sw.active => active = Some((*wl).idx),
+                Ok(_) => (),
+                Err(cause) => error = Some(format!("{}: {cause}", ss.name)),
+            }
+        }
+        if rb_empty(&raw mut (*s).windows) {
+            session_destroy(s, 0, __func__);
+            return Err(error.unwrap_or_else(|| format!("{}: no windows", ss.name)));
+        }
+        if let Some(idx) = active {
+            session_select(s, idx);
+        }
+        notify_session(c"session-created", s);
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(true),
+        }
+    }
+}
+
+/// Recreate the sessions in a state file, returning the number restored and
+/// any errors.
+pub unsafe fn state_restore(item: *mut cmdq_item, path: &str) -> (u32, Vec<String>) {
+    unsafe {
+        let mut sessions = match state_read(path) {
+            Ok(sessions) => sessions,
+            Err(cause) => return (0, vec![cause]),
+        };
+
+        let mut restored = 0;
+        let mut errors = Vec::new();
+        for ss in &mut sessions {
+            match state_restore_session(item, ss) {
+                Ok(true) => restored += 1,
+                Ok(false) => (),
+                Err(cause) => errors.push(cause),
+            }
+        }
+        log_debug!("restored {restored} sessions from {path}");
+        (restored, errors)
+    }
+}
+
+/// Restore from the state-file option when the server starts, if the
+/// restore-state option is on and there are no sessions yet.
+pub unsafe fn state_start(item: *mut cmdq_item, _data: *mut c_void) -> cmd_retval {
+    unsafe {
+        if options_get_number_(GLOBAL_OPTIONS, "restore-state") == 0
+            || !rb_empty(&raw mut SESSIONS)
+        {
+            return cmd_retval::CMD_RETURN_NORMAL;
+        }
+        let Some(path) = state_find_file(null_mut(), options_get_string_(GLOBAL_OPTIONS, "state-file"))
+        else {
+            return cmd_retval::CMD_RETURN_NORMAL;
+        };
+        if !std::path::Path::new(&path).exists() {
+            return cmd_retval::CMD_RETURN_NORMAL;
+        }
+        for cause in state_restore(item, &path).1 {
+            cfg_add_cause!("{cause}");
+        }
+        cmd_retval::CMD_RETURN_NORMAL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_escape() {
+        unsafe {
+            let mut out = Vec::new();
+            let fields = [c"a\tb\nc\\d", c"\x1b[31mé"];
+            state_write(&mut out, "line", &fields);
+            assert_eq!(out.iter().filter(|&&ch| ch == b'\t').count(), 2);
+            assert_eq!(out.last(), Some(&b'\n'));
+
+            let line = &out[..out.len() - 1];
+            let parsed: Vec<_> = line
+                .split(|&ch| ch == b'\t')
+                .map(|field| state_unescape(field).unwrap())
+                .collect();
+            assert_eq!(parsed[0].as_c_str(), c"line");
+            assert_eq!(parsed[1].as_c_str(), fields[0]);
+            assert_eq!(parsed[2].as_c_str(), fields[1]);
+        }
+    }
+
+    #[test]
+    fn test_state_save_read() {
+        unsafe {
+            let path = std::env::temp_dir().join(format!("tmux-rs-state-{}", std::process::id()));
+            let path = path.to_str().unwrap();
+
+            // With no sessions only the first line is written.
+            state_save(path, true).unwrap();
+            let mode = std::os::unix::fs::MetadataExt::mode(&std::fs::metadata(path).unwrap());
+            assert_eq!(mode & 0o777, 0o600);
+            assert!(state_read(path).unwrap().is_empty());
+
+            let mut out = std::fs::read(path).unwrap();
+            state_write(&mut out, "session", &[c"work", c"/tmp"]);
+            state_write(&mut out, "session-option", &[c"status-left", c"[#S]\t"]);
+            state_write(&mut out, "window", &[c"3", c"edit\tor", c"b25d,80x24,0,0,1", c"1"]);
+            state_write(&mut out, "pane", &[c"1", c"/home", c"vi", c"a file"]);
+            state_write(&mut out, "line", &[c"\x1b[1mhello\x1b[0m"]);
+            state_write(&mut out, "future-record", &[c"ignored"]);
+            std::fs::write(path, &out).unwrap();
+
+            let sessions = state_read(path).unwrap();
+            std::fs::remove_file(path).unwrap();
+            assert_eq!(sessions.len(), 1);
+            let ss = &sessions[0];
+            assert_eq!(ss.name, "work");
+            assert_eq!(ss.options, [("status-left".to_string(), c"[#S]\t".to_owned())]);
+            assert_eq!(ss.windows.len(), 1);
+            let sw = &ss.windows[0];
+            assert_eq!((sw.idx, sw.name.as_c_str(), sw.active), (3, c"edit\tor", true));
+            assert_eq!(sw.panes.len(), 1);
+            let sp = &sw.panes[0];
+            assert_eq!(sp.cwd.as_c_str(), c"/home");
+            assert_eq!(sp.argv, [c"vi".to_owned(), c"a file".to_owned()]);
+            assert_eq!(sp.contents, b"\x1b[1mhello\x1b[0m\r\n");
+        }
+    }
+}
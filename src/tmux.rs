@@ -44,7 +44,7 @@ pub static mut SHELL_COMMAND: *mut u8 = null_mut();
 
 pub fn usage() -> ! {
     eprintln!(
-        "usage: tmux-rs [-2CDlNuVv] [-c shell-command] [-f file] [-L socket-name]\n               [-S socket-path] [-T features] [command [flags]]\n"
+        "usage: tmux-rs [-2CDJlNuVv] [-c shell-command] [-f file] [-L socket-name]\n               [-S socket-path] [-T features] [command [flags]]\n"
     );
     std::process::exit(1)
 }
@@ -413,7 +413,7 @@ pub unsafe fn tmux_main(mut argc: i32, mut argv: *mut *mut u8, _env: *mut *mut u
         }
         expand_paths(TMUX_CONF, &mut CFG_FILES.lock().unwrap(), 1);
 
-        while let Some(opt) = getopt(argc, argv.cast(), c!("2c:CDdf:lL:NqS:T:uUvV")) {
+        while let Some(opt) = getopt(argc, argv.cast(), c!("2c:CDdf:JlL:NqS:T:uUvV")) {
             match opt {
                 b'2' => tty_add_features(&raw mut feat, "256", c!(":,")),
                 b'c' => SHELL_COMMAND = OPTARG.cast(),
@@ -436,6 +436,7 @@ pub unsafe fn tmux_main(mut argc: i32, mut argv: *mut *mut u8, _env: *mut *mut u
                         .push(CString::new(cstr_to_str(OPTARG)).unwrap());
                     CFG_QUIET.store(false, atomic::Ordering::Relaxed);
                 }
+                b'J' => flags |= client_flag::CONTROL_JSON,
                 b'V' => {
                     println!("tmux-rs {}", getversion());
                     std::process::exit(0);
@@ -460,6 +461,9 @@ pub unsafe fn tmux_main(mut argc: i32, mut argv: *mut *mut u8, _env: *mut *mut u
         argc -= OPTIND;
         argv = argv.add(OPTIND as usize);
 
+        if !flags.intersects(client_flag::CONTROL) {
+            flags &= !client_flag::CONTROL_JSON;
+        }
         if !SHELL_COMMAND.is_null() && argc != 0 {
             usage();
         }
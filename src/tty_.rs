@@ -17,6 +17,10 @@ use crate::options_::*;
 
 static mut TTY_LOG_FD: i32 = -1;
 
+/// Serial number for the next terminal started.
+#[cfg(feature = "sixel")]
+static mut TTY_NEXT_SERIAL: u64 = 1;
+
 #[inline]
 unsafe fn tty_use_margin(tty: *const tty) -> bool {
     unsafe { (*(*tty).term).flags.intersects(term_flags::TERM_DECSLRM) }
@@ -342,6 +346,12 @@ pub unsafe fn tty_start_tty(tty: *mut tty) {
         setblocking((*c).fd, 0);
         event_add(&raw mut (*tty).event_in, null_mut());
 
+        #[cfg(feature = "sixel")]
+        {
+            (*tty).serial = TTY_NEXT_SERIAL;
+            TTY_NEXT_SERIAL += 1;
+        }
+
         memcpy__(&raw mut tio, &raw const (*tty).tio);
         tio.c_iflag &= !(libc::IXON
             | libc::IXOFF
@@ -425,6 +435,9 @@ pub unsafe fn tty_send_requests(tty: *mut tty) {
             if !(*tty).flags.intersects(tty_flags::TTY_HAVEXDA) {
                 tty_puts(tty, c!("\x1b[>q"));
             }
+            // Ask to be told when the theme changes so the colours can be
+            // requested again.
+            tty_puts(tty, c!("\x1b[?2031h"));
             tty_puts(tty, c!("\x1b]10;?\x1b\\"));
             tty_puts(tty, c!("\x1b]11;?\x1b\\"));
         } else {
@@ -522,9 +535,11 @@ pub unsafe fn tty_stop_tty(tty: *mut tty) {
 
         if (*(*tty).term).flags.intersects(term_flags::TERM_VT100LIKE) {
             tty_raw(tty, b"\x1b[?7727l");
+            tty_raw(tty, b"\x1b[?2031l");
         }
         tty_raw(tty, tty_term_string((*tty).term, tty_code_code::TTYC_DSFCS));
         tty_raw(tty, tty_term_string((*tty).term, tty_code_code::TTYC_DSEKS));
+        tty_raw(tty, tty_term_string((*tty).term, tty_code_code::TTYC_DSKKS));
 
         if tty_use_margin(tty) {
             tty_raw(tty, tty_term_string((*tty).term, tty_code_code::TTYC_DSMG));
@@ -574,7 +589,11 @@ pub unsafe fn tty_update_features(tty: *mut tty) {
             tty_putcode(tty, tty_code_code::TTYC_ENMG);
         }
         if options_get_number_(GLOBAL_OPTIONS, "extended-keys") != 0 {
-            tty_puts_(tty, tty_term_string((*tty).term, tty_code_code::TTYC_ENEKS));
+            if tty_term_has((*tty).term, tty_code_code::TTYC_ENKKS) {
+                tty_puts_(tty, tty_term_string((*tty).term, tty_code_code::TTYC_ENKKS));
+            } else {
+                tty_puts_(tty, tty_term_string((*tty).term, tty_code_code::TTYC_ENEKS));
+            }
         }
         if options_get_number_(GLOBAL_OPTIONS, "focus-events") != 0 {
             tty_puts_(tty, tty_term_string((*tty).term, tty_code_code::TTYC_ENFCS));
@@ -1991,7 +2010,25 @@ pub unsafe fn tty_draw_images(c: *mut client, wp: *mut window_pane, s: *mut scre
             ttyctx.arg = wp.cast();
             ttyctx.set_client_cb = Some(tty_set_client_cb);
             ttyctx.allow_invisible_panes = 1;
-            tty_write_one(tty_cmd_sixelimage, c, &raw mut ttyctx);
+            if (*im).kitty.is_null() {
+                tty_write_one(tty_cmd_sixelimage, c, &raw mut ttyctx);
+            } else {
+                tty_write_one(tty_cmd_kittyimage, c, &raw mut ttyctx);
+            }
+        }
+    }
+}
+
+/// Remove all kitty graphics placements, the images are placed again when the
+/// panes are redrawn.
+#[cfg(feature = "sixel")]
+pub unsafe fn tty_clear_kitty_images(tty: *mut tty) {
+    unsafe {
+        if (*(*tty).term)
+            .flags
+            .intersects(term_flags::TERM_KITTYGRAPHICS)
+        {
+            tty_puts_(tty, crate::image_kitty::KITTY_DELETE_PLACEMENTS);
         }
     }
 }
@@ -2739,6 +2776,18 @@ pub unsafe fn tty_set_selection(tty: *mut tty, flags: *const u8, buf: *const u8,
     }
 }
 
+pub unsafe fn tty_notification(tty: *mut tty, title: *const u8, body: *const u8) {
+    unsafe {
+        if !(*tty).flags.intersects(tty_flags::TTY_STARTED) {
+            return;
+        }
+        if !tty_term_has((*tty).term, tty_code_code::TTYC_NOTIFY) {
+            return;
+        }
+        tty_putcode_ss(tty, tty_code_code::TTYC_NOTIFY, title, body);
+    }
+}
+
 pub unsafe fn tty_cmd_rawstring(tty: *mut tty, ctx: *const tty_ctx) {
     unsafe {
         (*tty).flags |= tty_flags::TTY_NOBLOCK;
@@ -2826,6 +2875,60 @@ pub unsafe fn tty_cmd_sixelimage(tty: *mut tty, ctx: *const tty_ctx) {
     }
 }
 
+#[cfg(feature = "sixel")]
+pub unsafe fn tty_cmd_kittyimage(tty: *mut tty, ctx: *const tty_ctx) {
+    use crate::image_kitty::{kitty_print, kitty_size_in_cells};
+
+    unsafe {
+        let im: *mut image = (*ctx).ptr.cast();
+        let ki = (*im).kitty;
+        let cx = (*ctx).ocx;
+        let cy = (*ctx).ocy;
+
+        let mut i: u32 = 0;
+        let mut j: u32 = 0;
+        let mut x: u32 = 0;
+        let mut y: u32 = 0;
+        let mut rx: u32 = 0;
+        let mut ry: u32 = 0;
+
+        let (sx, sy) = kitty_size_in_cells(&*ki);
+        if !tty_clamp_area(
+            tty,
+            ctx,
+            cx,
+            cy,
+            sx,
+            sy,
+            &raw mut i,
+            &raw mut j,
+            &raw mut x,
+            &raw mut y,
+            &raw mut rx,
+            &raw mut ry,
+        ) {
+            return;
+        }
+        log_debug!("tty_cmd_kittyimage: clamping to {i},{j}-{rx},{ry}");
+
+        tty_region_off(tty);
+        tty_margin_off(tty);
+        tty_cursor(tty, x, y);
+
+        (*tty).flags |= tty_flags::TTY_NOBLOCK;
+        if (*(*tty).term)
+            .flags
+            .intersects(term_flags::TERM_KITTYGRAPHICS)
+        {
+            let data = kitty_print(&mut *ki, (*tty).serial, i, j, rx, ry);
+            tty_add(tty, data.as_ptr(), data.len());
+        } else {
+            tty_add(tty, (*im).fallback, strlen((*im).fallback));
+        }
+        tty_invalidate(tty);
+    }
+}
+
 pub unsafe fn tty_cmd_syncstart(tty: *mut tty, ctx: *const tty_ctx) {
     unsafe {
         if (*ctx).num == 0x11 {
@@ -172,6 +172,31 @@ static TTY_FEATURE_EXTKEYS: tty_feature = tty_feature::new(
     term_flags::empty(),
 );
 
+/// Terminal supports the kitty keyboard protocol. Disambiguated keys, event
+/// types and alternate keys are requested; this can be changed with
+/// terminal-overrides.
+static TTY_FEATURE_KITTYKEYS_CAPABILITIES: &[&str] = &["Enkks=\\E[=7;1u", "Dskks=\\E[=0;1u"];
+static TTY_FEATURE_KITTYKEYS: tty_feature = tty_feature::new(
+    "kittykeys",
+    TTY_FEATURE_KITTYKEYS_CAPABILITIES,
+    term_flags::empty(),
+);
+
+/// Terminal supports the kitty graphics protocol.
+static TTY_FEATURE_KITTYGRAPHICS: tty_feature = tty_feature::new(
+    "kittygraphics",
+    &[],
+    term_flags::TERM_KITTYGRAPHICS,
+);
+
+/// Terminal supports desktop notifications.
+static TTY_FEATURE_NOTIFY_CAPABILITIES: &[&str] = &["Notify=\\E]777;notify;%p1%s;%p2%s\\E\\\\"];
+static TTY_FEATURE_NOTIFY: tty_feature = tty_feature::new(
+    "notify",
+    TTY_FEATURE_NOTIFY_CAPABILITIES,
+    term_flags::empty(),
+);
+
 /// Terminal supports DECSLRM margins.
 static TTY_FEATURE_MARGINS_CAPABILITIES: &[&str] = &[
     "Enmg=\\E[?69h",
@@ -219,7 +244,7 @@ static TTY_FEATURE_SIXEL: tty_feature = tty_feature::new(
 );
 
 /// Available terminal features.
-static TTY_FEATURES: [&tty_feature; 20] = [
+static TTY_FEATURES: [&tty_feature; 23] = [
     &TTY_FEATURE_256,
     &TTY_FEATURE_BPASTE,
     &TTY_FEATURE_CCOLOUR,
@@ -229,8 +254,11 @@ static TTY_FEATURES: [&tty_feature; 20] = [
     &TTY_FEATURE_EXTKEYS,
     &TTY_FEATURE_FOCUS,
     &TTY_FEATURE_IGNOREFKEYS,
+    &TTY_FEATURE_KITTYGRAPHICS,
+    &TTY_FEATURE_KITTYKEYS,
     &TTY_FEATURE_MARGINS,
     &TTY_FEATURE_MOUSE,
+    &TTY_FEATURE_NOTIFY,
     &TTY_FEATURE_OSC7,
     &TTY_FEATURE_OVERLINE,
     &TTY_FEATURE_RECTFILL,
@@ -347,6 +375,12 @@ pub unsafe fn tty_default_features(feat: *mut i32, name: *const u8, version: u32
         // disabled so not set it here - they will be added if
         // secondary DA shows VT420.
         entry { name: c"XTerm", features: concat!(TTY_FEATURES_BASE_MODERN_XTERM!(), ",ccolour,cstyle,extkeys,focus"), version: 0, },
+        // Terminals with the kitty keyboard protocol. Anything else they
+        // support comes from terminfo or terminal-features.
+        entry { name: c"kitty", features: "kittykeys", version: 0, },
+        entry { name: c"foot", features: "kittykeys", version: 0, },
+        entry { name: c"WezTerm", features: "kittykeys", version: 0, },
+        entry { name: c"ghostty", features: "kittykeys", version: 0, },
     ];
 
     unsafe {
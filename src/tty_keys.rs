@@ -1125,6 +1125,19 @@ pub unsafe fn tty_keys_next(tty: *mut tty) -> i32 {
                                 &raw mut (*tty).fg,
                                 &raw mut (*tty).bg,
                             ) {
+                                0 => {
+                                    // yes
+                                    input_report_theme_all();
+                                    key = KEYC_UNKNOWN;
+                                    break 'complete_key;
+                                }
+                                -1 => (), // no, or not valid
+                                1 => break 'partial_key,
+                                _ => (),
+                            }
+
+                            // Is this a theme change report?
+                            match tty_keys_theme(tty, buf.cast(), len, &raw mut size) {
                                 0 => {
                                     // yes
                                     key = KEYC_UNKNOWN;
@@ -1301,7 +1314,7 @@ pub unsafe fn tty_keys_next(tty: *mut tty) -> i32 {
                 let bspace: libc::cc_t = (*tty).tio.c_cc[libc::VERASE];
                 if bspace != libc::_POSIX_VDISABLE && (key & KEYC_MASK_KEY) as libc::cc_t == bspace
                 {
-                    key = (key & KEYC_MASK_MODIFIERS) | keyc::KEYC_BSPACE as u64;
+                    key = (key & (KEYC_MASK_MODIFIERS | KEYC_MASK_EVENT)) | keyc::KEYC_BSPACE as u64;
                 }
 
                 // Remove data from buffer.
@@ -1356,8 +1369,11 @@ unsafe extern "C-unwind" fn tty_keys_callback(_fd: i32, _events: i16, tty: NonNu
 }
 
 /// Handle extended key input. This has two forms: \x1b[27;m;k~ and \x1b[k;mu,
-/// where k is key as a number and m is a modifier. Returns 0 for success, -1
-/// for failure, 1 for partial;
+/// where k is key as a number and m is a modifier. The kitty keyboard protocol
+/// extends the second form with alternate keys, event types and associated
+/// text (\x1b[k:s:b;m:e;tu) and also reports functional keys with an event
+/// type (\x1b[1;m:eA or \x1b[k;m:e~). Returns 0 for success, -1 for failure,
+/// 1 for partial;
 unsafe fn tty_keys_extended_key(
     tty: *mut tty,
     buf: *const u8,
@@ -1367,11 +1383,7 @@ unsafe fn tty_keys_extended_key(
 ) -> i32 {
     unsafe {
         let c = (*tty).client;
-        let end: usize = 0;
-        let mut number: u32 = 0;
-        let mut modifiers: u32 = 0;
         const SIZE_OF_TMP: usize = 64;
-        let mut tmp: [u8; 64] = [0; 64];
 
         let mut ud: utf8_data = zeroed();
         let mut uc: utf8_char = zeroed();
@@ -1392,61 +1404,55 @@ unsafe fn tty_keys_extended_key(
             return 1;
         }
 
-        // Look for a terminator. Stop at either '~' or anything that isn't a
-        // number or ';'.
-        for end in 2..len.min(SIZE_OF_TMP) {
-            if *buf.add(end) == b'~' {
-                break;
-            }
-            if !(*buf.add(end)).is_ascii_digit() && *buf.add(end) != b';' {
+        // Look for a terminator. Stop at anything that isn't a number, ';' or
+        // ':'.
+        let mut end = 2;
+        while end < len.min(SIZE_OF_TMP) {
+            let ch = *buf.add(end);
+            if !ch.is_ascii_digit() && ch != b';' && ch != b':' {
                 break;
             }
+            end += 1;
         }
         if end == len {
             return 1;
         }
-        if end == SIZE_OF_TMP || (*buf.add(end) != b'~' && *buf.add(end) != b'u') {
+        if end == SIZE_OF_TMP {
             return -1;
         }
-
-        // Copy to the buffer.
-        libc::memcpy(tmp.as_mut_ptr().cast(), buf.add(2).cast(), end);
-        tmp[end] = 0;
-
-        // Try to parse either form of key.
-        if *buf.add(end) == b'~' {
-            if libc::sscanf(
-                tmp.as_ptr().cast(),
-                c"27;%u;%u".as_ptr(),
-                &raw mut modifiers,
-                &raw mut number,
-            ) != 2
-            {
-                return -1;
+        let params = std::slice::from_raw_parts(buf.add(2), end - 2);
+        let terminator = *buf.add(end);
+
+        // Try to parse each form of key. Functional keys without an event type
+        // are left for the key tree.
+        let has_event = params.contains(&b':');
+        let Some(parsed) = (match terminator {
+            b'~' if params.starts_with(b"27;") => tty_keys_parse_modify_other_keys(&params[3..]),
+            b'u' => tty_keys_parse_csi_u(params),
+            b'~' | b'A' | b'B' | b'C' | b'D' | b'F' | b'H' | b'P' | b'Q' | b'S' if has_event => {
+                tty_keys_parse_functional(params, terminator)
             }
-        } else if libc::sscanf(
-            tmp.as_ptr().cast(),
-            c"%u;%u".as_ptr(),
-            &raw mut number,
-            &raw mut modifiers,
-        ) != 2
-        {
+            _ => None,
+        }) else {
             return -1;
-        }
+        };
         *size = end + 1;
 
-        // Store the key.
+        // Keys with no tmux equivalent (modifier keys on their own, media keys
+        // and so on) are consumed but otherwise ignored.
+        let Some(mut nkey) = parsed.key else {
+            *key = KEYC_UNKNOWN;
+            return 0;
+        };
 
+        // Store the key.
         let bspace: libc::cc_t = (*tty).tio.c_cc[libc::VERASE];
-
-        let mut nkey: key_code = if bspace != libc::_POSIX_VDISABLE && number == bspace as u32 {
-            keyc::KEYC_BSPACE as key_code
-        } else {
-            number as key_code
-        };
+        if bspace != libc::_POSIX_VDISABLE && nkey == bspace as key_code {
+            nkey = keyc::KEYC_BSPACE as key_code;
+        }
 
         // Convert UTF-32 codepoint into internal representation.
-        if nkey != keyc::KEYC_BSPACE as key_code && (nkey & !0x7f) != 0 {
+        if nkey < KEYC_BASE && (nkey & !0x7f) != 0 {
             if utf8_fromwc(nkey as wchar_t, &raw mut ud) == utf8_state::UTF8_DONE
                 && utf8_from_data(&raw const ud, &raw mut uc) == utf8_state::UTF8_DONE
             {
@@ -1456,7 +1462,9 @@ unsafe fn tty_keys_extended_key(
             }
         }
 
-        // Update the modifiers.
+        // Update the modifiers. Caps Lock and Num Lock are not modifiers as
+        // far as tmux is concerned.
+        let mut modifiers = parsed.modifiers;
         if modifiers > 0 {
             modifiers -= 1;
             if (modifiers & 1) != 0 {
@@ -1468,8 +1476,8 @@ unsafe fn tty_keys_extended_key(
             if (modifiers & 4) != 0 {
                 nkey |= KEYC_CTRL;
             }
-            if (modifiers & 8) != 0 {
-                nkey |= KEYC_META | KEYC_IMPLIED_META; /* Meta */
+            if (modifiers & (8 | 32)) != 0 {
+                nkey |= KEYC_META | KEYC_IMPLIED_META; /* Meta or Super */
             }
         }
 
@@ -1497,6 +1505,13 @@ unsafe fn tty_keys_extended_key(
             nkey &= !KEYC_SHIFT;
         }
 
+        // Add the event type.
+        match parsed.event {
+            2 => nkey |= KEYC_REPEAT,
+            3 => nkey |= KEYC_RELEASE,
+            _ => (),
+        }
+
         if log_get_level() != 0 {
             log_debug!(
                 "{0}: extended key {2:1$} is {3:#x} ({4})",
@@ -1513,6 +1528,162 @@ unsafe fn tty_keys_extended_key(
     }
 }
 
+/// An extended key as reported by the terminal, before conversion to a key
+/// code. The key is `None` if tmux has no equivalent.
+struct tty_keys_extended {
+    key: Option<key_code>,
+    modifiers: u32,
+    event: u32,
+}
+
+/// Parse a decimal number from a sequence parameter. Empty is `None`.
+fn tty_keys_parse_number(s: &[u8]) -> Option<u32> {
+    if s.is_empty() || !s.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(s).ok()?.parse().ok()
+}
+
+/// Parse modifiers and event type, m:e, either of which may be missing.
+fn tty_keys_parse_modifiers(s: Option<&[u8]>) -> Option<(u32, u32)> {
+    let Some(s) = s.filter(|s| !s.is_empty()) else {
+        return Some((1, 1));
+    };
+    let mut fields = s.split(|&ch| ch == b':');
+    let modifiers = tty_keys_parse_number(fields.next()?)?;
+    let event = match fields.next() {
+        Some(e) => tty_keys_parse_number(e)?,
+        None => 1,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((modifiers, event))
+}
+
+/// Parse the m;k part of \x1b[27;m;k~.
+fn tty_keys_parse_modify_other_keys(params: &[u8]) -> Option<tty_keys_extended> {
+    let mut fields = params.split(|&ch| ch == b';');
+    let modifiers = tty_keys_parse_number(fields.next()?)?;
+    let number = tty_keys_parse_number(fields.next()?)?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(tty_keys_extended {
+        key: Some(number as key_code),
+        modifiers,
+        event: 1,
+    })
+}
+
+/// Parse CSI u, including the kitty extensions. When the shifted key is given
+/// and Shift is pressed, use it instead of the base key.
+fn tty_keys_parse_csi_u(params: &[u8]) -> Option<tty_keys_extended> {
+    let mut fields = params.split(|&ch| ch == b';');
+
+    let mut keys = fields.next()?.split(|&ch| ch == b':');
+    let number = tty_keys_parse_number(keys.next()?)?;
+    let shifted = keys.next().and_then(tty_keys_parse_number);
+
+    let (modifiers, event) = tty_keys_parse_modifiers(fields.next())?;
+
+    let number = match shifted {
+        Some(shifted) if modifiers > 0 && (modifiers - 1) & 1 != 0 => shifted,
+        _ => number,
+    };
+    Some(tty_keys_extended {
+        key: tty_keys_kitty_key(number),
+        modifiers,
+        event,
+    })
+}
+
+/// Parse a functional key with an event type, \x1b[1;m:eA or \x1b[k;m:e~.
+fn tty_keys_parse_functional(params: &[u8], terminator: u8) -> Option<tty_keys_extended> {
+    let mut fields = params.split(|&ch| ch == b';');
+    let number = tty_keys_parse_number(fields.next()?)?;
+    let (modifiers, event) = tty_keys_parse_modifiers(fields.next())?;
+    if fields.next().is_some() {
+        return None;
+    }
+
+    let key = match terminator {
+        b'A' => Some(keyc::KEYC_UP),
+        b'B' => Some(keyc::KEYC_DOWN),
+        b'C' => Some(keyc::KEYC_RIGHT),
+        b'D' => Some(keyc::KEYC_LEFT),
+        b'F' => Some(keyc::KEYC_END),
+        b'H' => Some(keyc::KEYC_HOME),
+        b'P' => Some(keyc::KEYC_F1),
+        b'Q' => Some(keyc::KEYC_F2),
+        b'S' => Some(keyc::KEYC_F4),
+        _ => match number {
+            2 => Some(keyc::KEYC_IC),
+            3 => Some(keyc::KEYC_DC),
+            5 => Some(keyc::KEYC_PPAGE),
+            6 => Some(keyc::KEYC_NPAGE),
+            7 => Some(keyc::KEYC_HOME),
+            8 => Some(keyc::KEYC_END),
+            11 => Some(keyc::KEYC_F1),
+            12 => Some(keyc::KEYC_F2),
+            13 => Some(keyc::KEYC_F3),
+            14 => Some(keyc::KEYC_F4),
+            15 => Some(keyc::KEYC_F5),
+            17 => Some(keyc::KEYC_F6),
+            18 => Some(keyc::KEYC_F7),
+            19 => Some(keyc::KEYC_F8),
+            20 => Some(keyc::KEYC_F9),
+            21 => Some(keyc::KEYC_F10),
+            23 => Some(keyc::KEYC_F11),
+            24 => Some(keyc::KEYC_F12),
+            _ => None,
+        },
+    };
+    Some(tty_keys_extended {
+        key: key.map(|k| k as key_code),
+        modifiers,
+        event,
+    })
+}
+
+/// Convert a kitty keyboard protocol key number into a key. Numbers in the
+/// Unicode private use area are functional keys.
+fn tty_keys_kitty_key(number: u32) -> Option<key_code> {
+    let key = match number {
+        57399 => keyc::KEYC_KP_ZERO,
+        57400 => keyc::KEYC_KP_ONE,
+        57401 => keyc::KEYC_KP_TWO,
+        57402 => keyc::KEYC_KP_THREE,
+        57403 => keyc::KEYC_KP_FOUR,
+        57404 => keyc::KEYC_KP_FIVE,
+        57405 => keyc::KEYC_KP_SIX,
+        57406 => keyc::KEYC_KP_SEVEN,
+        57407 => keyc::KEYC_KP_EIGHT,
+        57408 => keyc::KEYC_KP_NINE,
+        57409 => keyc::KEYC_KP_PERIOD,
+        57410 => keyc::KEYC_KP_SLASH,
+        57411 => keyc::KEYC_KP_STAR,
+        57412 => keyc::KEYC_KP_MINUS,
+        57413 => keyc::KEYC_KP_PLUS,
+        57414 => keyc::KEYC_KP_ENTER,
+        57415 => return Some(b'=' as key_code),
+        57416 => return Some(b',' as key_code),
+        57417 => keyc::KEYC_LEFT,
+        57418 => keyc::KEYC_RIGHT,
+        57419 => keyc::KEYC_UP,
+        57420 => keyc::KEYC_DOWN,
+        57421 => keyc::KEYC_PPAGE,
+        57422 => keyc::KEYC_NPAGE,
+        57423 => keyc::KEYC_HOME,
+        57424 => keyc::KEYC_END,
+        57425 => keyc::KEYC_IC,
+        57426 => keyc::KEYC_DC,
+        0xe000..=0xf8ff => return None,
+        _ => return Some(number as key_code),
+    };
+    Some(key as key_code)
+}
+
 /// Handle mouse key input. Returns 0 for success, -1 for failure, 1 for partial
 /// (probably a mouse sequence but need more data), -2 if an invalid mouse
 /// sequence.
@@ -1867,6 +2038,9 @@ unsafe fn tty_keys_device_attributes(
                 found = true;
                 break;
             }
+            if (0x40..=0x7e).contains(&*buf.add(3 + i)) {
+                return -1; // another sequence with a different final byte
+            }
             tmp[i] = *buf.add(3 + i);
         }
         if !found {
@@ -1969,6 +2143,9 @@ unsafe fn tty_keys_device_attributes2(
                 found = true;
                 break;
             }
+            if (0x40..=0x7e).contains(&*buf.add(3 + i)) {
+                return -1; // another sequence with a different final byte
+            }
             tmp[i] = *buf.add(3 + i);
         }
         if !found {
@@ -2095,6 +2272,14 @@ unsafe fn tty_keys_extended_device_attributes(
             tty_default_features(features, c!("XTerm"), 0);
         } else if libc::strncmp(tmp.as_ptr(), c!("mintty "), 7) == 0 {
             tty_default_features(features, c!("mintty"), 0);
+        } else if libc::strncmp(tmp.as_ptr(), c!("kitty("), 6) == 0 {
+            tty_default_features(features, c!("kitty"), 0);
+        } else if libc::strncmp(tmp.as_ptr(), c!("foot("), 5) == 0 {
+            tty_default_features(features, c!("foot"), 0);
+        } else if libc::strncmp(tmp.as_ptr(), c!("WezTerm "), 8) == 0 {
+            tty_default_features(features, c!("WezTerm"), 0);
+        } else if libc::strncmp(tmp.as_ptr(), c!("ghostty "), 8) == 0 {
+            tty_default_features(features, c!("ghostty"), 0);
         }
         // log_debug( c!("%s: received extended DA %.*s\0"), (*c).name, *size as i32, buf);
 
@@ -2205,3 +2390,67 @@ pub unsafe fn tty_keys_colours(
         0
     }
 }
+
+/// Handle a theme change report, \x1b[?997;Nn where N is 1 for dark or 2 for
+/// light, which is sent when mode 2031 is on. The colours are requested again
+/// and panes are told once they arrive.
+pub unsafe fn tty_keys_theme(tty: *mut tty, buf: *const u8, len: usize, size: *mut usize) -> i32 {
+    unsafe {
+        let prefix = b"\x1b[?997;";
+        let buf = std::slice::from_raw_parts(buf, len);
+
+        *size = 0;
+        if len <= prefix.len() {
+            return if prefix.starts_with(buf) { 1 } else { -1 };
+        }
+        if !buf.starts_with(prefix) {
+            return -1;
+        }
+        let Some(end) = buf[prefix.len()..].iter().position(|&ch| !ch.is_ascii_digit()) else {
+            return 1;
+        };
+        if end == 0 || buf[prefix.len() + end] != b'n' {
+            return -1;
+        }
+        *size = prefix.len() + end + 1;
+
+        log_debug!(
+            "{}: theme changed to {}",
+            _s((*(*tty).client).name),
+            String::from_utf8_lossy(&buf[prefix.len()..prefix.len() + end])
+        );
+        (*tty).last_requests = 0;
+        tty_repeat_requests(tty);
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tty_keys_parse_csi_u() {
+        let parsed = tty_keys_parse_csi_u(b"97:65;2:3").unwrap();
+        assert_eq!(parsed.key, Some(b'A' as key_code));
+        assert_eq!(parsed.modifiers, 2);
+        assert_eq!(parsed.event, 3);
+
+        let parsed = tty_keys_parse_csi_u(b"57399").unwrap();
+        assert_eq!(parsed.key, Some(keyc::KEYC_KP_ZERO as key_code));
+        assert_eq!((parsed.modifiers, parsed.event), (1, 1));
+
+        assert_eq!(tty_keys_parse_csi_u(b"57441;2").unwrap().key, None);
+        assert!(tty_keys_parse_csi_u(b"97;x").is_none());
+    }
+
+    #[test]
+    fn test_tty_keys_parse_functional() {
+        let parsed = tty_keys_parse_functional(b"1;5:2", b'A').unwrap();
+        assert_eq!(parsed.key, Some(keyc::KEYC_UP as key_code));
+        assert_eq!((parsed.modifiers, parsed.event), (5, 2));
+
+        let parsed = tty_keys_parse_functional(b"3;1:3", b'~').unwrap();
+        assert_eq!(parsed.key, Some(keyc::KEYC_DC as key_code));
+    }
+}
@@ -60,8 +60,8 @@ impl tty_term_code_entry {
 }
 
 #[rustfmt::skip]
-static TTY_TERM_CODES: [tty_term_code_entry; 232] = const {
-    let mut tmp: [tty_term_code_entry; 232] = unsafe { zeroed() };
+static TTY_TERM_CODES: [tty_term_code_entry; 235] = const {
+    let mut tmp: [tty_term_code_entry; 235] = unsafe { zeroed() };
 
     tmp[tty_code_code::TTYC_ACSC as usize] = tty_term_code_entry::new(tty_code_type::String, c"acsc");
     tmp[tty_code_code::TTYC_AM as usize] = tty_term_code_entry::new(tty_code_type::Flag, c"am");
@@ -98,6 +98,7 @@ static TTY_TERM_CODES: [tty_term_code_entry; 232] = const {
     tmp[tty_code_code::TTYC_DSEKS as usize] = tty_term_code_entry::new(tty_code_type::String, c"Dseks");
     tmp[tty_code_code::TTYC_DSFCS as usize] = tty_term_code_entry::new(tty_code_type::String, c"Dsfcs");
     tmp[tty_code_code::TTYC_DSBP as usize] = tty_term_code_entry::new(tty_code_type::String, c"Dsbp");
+    tmp[tty_code_code::TTYC_DSKKS as usize] = tty_term_code_entry::new(tty_code_type::String, c"Dskks");
     tmp[tty_code_code::TTYC_DSMG as usize] = tty_term_code_entry::new(tty_code_type::String, c"Dsmg");
     tmp[tty_code_code::TTYC_E3 as usize] = tty_term_code_entry::new(tty_code_type::String, c"E3");
     tmp[tty_code_code::TTYC_ECH as usize] = tty_term_code_entry::new(tty_code_type::String, c"ech");
@@ -108,6 +109,7 @@ static TTY_TERM_CODES: [tty_term_code_entry; 232] = const {
     tmp[tty_code_code::TTYC_ENBP as usize] = tty_term_code_entry::new(tty_code_type::String, c"Enbp");
     tmp[tty_code_code::TTYC_ENEKS as usize] = tty_term_code_entry::new(tty_code_type::String, c"Eneks");
     tmp[tty_code_code::TTYC_ENFCS as usize] = tty_term_code_entry::new(tty_code_type::String, c"Enfcs");
+    tmp[tty_code_code::TTYC_ENKKS as usize] = tty_term_code_entry::new(tty_code_type::String, c"Enkks");
     tmp[tty_code_code::TTYC_ENMG as usize] = tty_term_code_entry::new(tty_code_type::String, c"Enmg");
     tmp[tty_code_code::TTYC_FSL as usize] = tty_term_code_entry::new(tty_code_type::String, c"fsl");
     tmp[tty_code_code::TTYC_HLS as usize] = tty_term_code_entry::new(tty_code_type::String, c"Hls");
@@ -258,6 +260,7 @@ static TTY_TERM_CODES: [tty_term_code_entry; 232] = const {
     tmp[tty_code_code::TTYC_KUP7 as usize] = tty_term_code_entry::new(tty_code_type::String, c"kUP7");
     tmp[tty_code_code::TTYC_MS as usize] = tty_term_code_entry::new(tty_code_type::String, c"Ms");
     tmp[tty_code_code::TTYC_NOBR as usize] = tty_term_code_entry::new(tty_code_type::String, c"Nobr");
+    tmp[tty_code_code::TTYC_NOTIFY as usize] = tty_term_code_entry::new(tty_code_type::String, c"Notify");
     tmp[tty_code_code::TTYC_OL as usize] = tty_term_code_entry::new(tty_code_type::String, c"ol");
     tmp[tty_code_code::TTYC_OP as usize] = tty_term_code_entry::new(tty_code_type::String, c"op");
     tmp[tty_code_code::TTYC_RECT as usize] = tty_term_code_entry::new(tty_code_type::String, c"Rect");
@@ -22,13 +22,6 @@ use crate::compat::vis;
 use crate::libc::{memcpy, memset};
 use crate::*;
 
-#[cfg(feature = "utf8proc")]
-unsafe extern "C" {
-    fn utf8proc_wcwidth(_: wchar_t) -> i32;
-    fn utf8proc_mbtowc(_: *mut wchar_t, _: *const u8, _: usize) -> i32;
-    fn utf8proc_wctomb(_: *mut char, _: wchar_t) -> i32;
-}
-
 // A single UTF-8 character.
 pub(crate) type utf8_char = c_uint;
 
@@ -346,18 +339,15 @@ pub unsafe fn utf8_width(ud: *mut utf8_data, width: *mut i32) -> utf8_state {
             return utf8_state::UTF8_DONE;
         }
         if cfg!(feature = "utf8proc") {
-            #[cfg(feature = "utf8proc")]
-            {
-                *width = utf8proc_wcwidth(wc);
-                log_debug!("utf8proc_wcwidth({:05X}) returned {}", wc, *width);
-            }
+            *width = utf8_table_width(wc);
+            log_debug!("utf8_table_width({:05X}) returned {}", wc, *width);
         } else {
             *width = wcwidth(wc);
             log_debug!("wcwidth({:05X}) returned {}", wc, *width);
-            #[expect(clippy::bool_to_int_with_if, reason = "more readable this way")]
-            if *width < 0 {
-                *width = if (0x80..=0x9f).contains(&wc) { 0 } else { 1 };
-            }
+        }
+        #[expect(clippy::bool_to_int_with_if, reason = "more readable this way")]
+        if *width < 0 {
+            *width = if (0x80..=0x9f).contains(&wc) { 0 } else { 1 };
         }
         if *width >= 0 && *width <= 0xff {
             return utf8_state::UTF8_DONE;
@@ -369,7 +359,7 @@ pub unsafe fn utf8_width(ud: *mut utf8_data, width: *mut i32) -> utf8_state {
 pub unsafe fn utf8_towc(ud: *const utf8_data, wc: *mut wchar_t) -> utf8_state {
     unsafe {
         #[cfg(feature = "utf8proc")]
-        let value = utf8proc_mbtowc(wc, (*ud).data.as_ptr().cast(), (*ud).size as usize);
+        let value = utf8_table_mbtowc(wc, (*ud).data.as_ptr(), (*ud).size as usize);
         #[cfg(not(feature = "utf8proc"))]
         let value = mbtowc(wc, (*ud).data.as_ptr().cast(), (*ud).size as usize);
 
@@ -402,7 +392,7 @@ pub unsafe fn utf8_fromwc(wc: wchar_t, ud: *mut utf8_data) -> utf8_state {
         let mut width: i32 = 0;
 
         #[cfg(feature = "utf8proc")]
-        let size = utf8proc_wctomb((*ud).data.as_mut_ptr().cast(), wc);
+        let size = utf8_table_wctomb((*ud).data.as_mut_ptr(), wc);
         #[cfg(not(feature = "utf8proc"))]
         let size = wctomb((*ud).data.as_mut_ptr().cast(), wc);
 
@@ -491,6 +481,7 @@ pub unsafe fn utf8_strvis(
                 src = src.add(1);
                 while src < end && more == utf8_state::UTF8_MORE {
                     more = utf8_append(&raw mut ud, *src);
+                    src = src.add(1);
                 }
                 if more == utf8_state::UTF8_DONE {
                     // UTF-8 character finished.
@@ -535,6 +526,7 @@ pub unsafe fn utf8_strvis_(dst: &mut Vec<u8>, mut src: *const u8, len: usize, fl
                 src = src.add(1);
                 while src < end && more == utf8_state::UTF8_MORE {
                     more = utf8_append(&raw mut ud, *src);
+                    src = src.add(1);
                 }
                 if more == utf8_state::UTF8_DONE {
                     // UTF-8 character finished.
@@ -0,0 +1,444 @@
+// Built-in Unicode character widths.
+//
+// When built with the utf8proc feature, these tables are used instead of the C
+// library's wcwidth(), so the width of a character does not depend on the
+// version of the C library on the machine running the server. Each range in
+// the table has the Unicode version in which its characters were assigned, so
+// the unicode-version option can pick the widths an older terminal expects:
+// characters from later versions are treated as unassigned, which are one
+// cell wide.
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::*;
+
+/// Unicode versions which may be chosen with the unicode-version option, as
+/// major * 10 + minor. This must match the option's choices.
+static UTF8_TABLE_VERSIONS: [u8; 7] = [80, 90, 100, 110, 120, 130, 140];
+
+/// Version of the table in use.
+static UTF8_TABLE_VERSION: AtomicU8 = AtomicU8::new(140);
+
+/// Characters which are not one cell wide (Unicode 14): first, last, width and
+/// the version from which that width applies. Before Unicode 9, emoji with
+/// emoji presentation were narrow, so those ranges start at version 9.0.
+static UTF8_TABLE_WIDTH: [(wchar_t, wchar_t, u8, u8); 682] = [
+    (0x0300, 0x0345, 0, 11), (0x0346, 0x034E, 0, 30), (0x034F, 0x034F, 0, 32),
+    (0x0350, 0x0357, 0, 40), (0x0358, 0x035C, 0, 41), (0x035D, 0x035F, 0, 40),
+    (0x0360, 0x0361, 0, 11), (0x0362, 0x0362, 0, 30), (0x0363, 0x036F, 0, 32),
+    (0x0483, 0x0486, 0, 11), (0x0487, 0x0487, 0, 51), (0x0488, 0x0489, 0, 30),
+    (0x0591, 0x05A1, 0, 20), (0x05A2, 0x05A2, 0, 41), (0x05A3, 0x05AF, 0, 20),
+    (0x05B0, 0x05B9, 0, 11), (0x05BA, 0x05BA, 0, 50), (0x05BB, 0x05BD, 0, 11),
+    (0x05BF, 0x05BF, 0, 11), (0x05C1, 0x05C2, 0, 11), (0x05C4, 0x05C4, 0, 20),
+    (0x05C5, 0x05C5, 0, 41), (0x05C7, 0x05C7, 0, 41), (0x0610, 0x0615, 0, 40),
+    (0x0616, 0x061A, 0, 51), (0x061C, 0x061C, 0, 63), (0x064B, 0x0652, 0, 11),
+    (0x0653, 0x0655, 0, 30), (0x0656, 0x0658, 0, 40), (0x0659, 0x065E, 0, 41),
+    (0x065F, 0x065F, 0, 60), (0x0670, 0x0670, 0, 11), (0x06D6, 0x06DC, 0, 11),
+    (0x06DF, 0x06E4, 0, 11), (0x06E7, 0x06E8, 0, 11), (0x06EA, 0x06ED, 0, 11),
+    (0x0711, 0x0711, 0, 30), (0x0730, 0x074A, 0, 30), (0x07A6, 0x07B0, 0, 30),
+    (0x07EB, 0x07F3, 0, 50), (0x07FD, 0x07FD, 0, 110), (0x0816, 0x0819, 0, 52),
+    (0x081B, 0x0823, 0, 52), (0x0825, 0x0827, 0, 52), (0x0829, 0x082D, 0, 52),
+    (0x0859, 0x085B, 0, 60), (0x0898, 0x089F, 0, 140), (0x08CA, 0x08D2, 0, 140),
+    (0x08D3, 0x08D3, 0, 110), (0x08D4, 0x08E1, 0, 90), (0x08E3, 0x08E3, 0, 80),
+    (0x08E4, 0x08FE, 0, 61), (0x08FF, 0x08FF, 0, 70), (0x0900, 0x0900, 0, 52),
+    (0x0901, 0x0902, 0, 11), (0x093A, 0x093A, 0, 60), (0x093C, 0x093C, 0, 11),
+    (0x0941, 0x0948, 0, 11), (0x094D, 0x094D, 0, 11), (0x0951, 0x0954, 0, 11),
+    (0x0955, 0x0955, 0, 52), (0x0956, 0x0957, 0, 60), (0x0962, 0x0963, 0, 11),
+    (0x0981, 0x0981, 0, 11), (0x09BC, 0x09BC, 0, 11), (0x09C1, 0x09C4, 0, 11),
+    (0x09CD, 0x09CD, 0, 11), (0x09E2, 0x09E3, 0, 11), (0x09FE, 0x09FE, 0, 110),
+    (0x0A01, 0x0A01, 0, 40), (0x0A02, 0x0A02, 0, 11), (0x0A3C, 0x0A3C, 0, 11),
+    (0x0A41, 0x0A42, 0, 11), (0x0A47, 0x0A48, 0, 11), (0x0A4B, 0x0A4D, 0, 11),
+    (0x0A51, 0x0A51, 0, 51), (0x0A70, 0x0A71, 0, 11), (0x0A75, 0x0A75, 0, 51),
+    (0x0A81, 0x0A82, 0, 11), (0x0ABC, 0x0ABC, 0, 11), (0x0AC1, 0x0AC5, 0, 11),
+    (0x0AC7, 0x0AC8, 0, 11), (0x0ACD, 0x0ACD, 0, 11), (0x0AE2, 0x0AE3, 0, 40),
+    (0x0AFA, 0x0AFF, 0, 100), (0x0B01, 0x0B01, 0, 11), (0x0B3C, 0x0B3C, 0, 11),
+    (0x0B3F, 0x0B3F, 0, 11), (0x0B41, 0x0B43, 0, 11), (0x0B44, 0x0B44, 0, 51),
+    (0x0B4D, 0x0B4D, 0, 11), (0x0B55, 0x0B55, 0, 130), (0x0B56, 0x0B56, 0, 11),
+    (0x0B62, 0x0B63, 0, 51), (0x0B82, 0x0B82, 0, 11), (0x0BC0, 0x0BC0, 0, 11),
+    (0x0BCD, 0x0BCD, 0, 11), (0x0C00, 0x0C00, 0, 70), (0x0C04, 0x0C04, 0, 110),
+    (0x0C3C, 0x0C3C, 0, 140), (0x0C3E, 0x0C40, 0, 11), (0x0C46, 0x0C48, 0, 11),
+    (0x0C4A, 0x0C4D, 0, 11), (0x0C55, 0x0C56, 0, 11), (0x0C62, 0x0C63, 0, 51),
+    (0x0C81, 0x0C81, 0, 70), (0x0CBC, 0x0CBC, 0, 40), (0x0CBF, 0x0CBF, 0, 11),
+    (0x0CC6, 0x0CC6, 0, 11), (0x0CCC, 0x0CCD, 0, 11), (0x0CE2, 0x0CE3, 0, 50),
+    (0x0D00, 0x0D00, 0, 100), (0x0D01, 0x0D01, 0, 70), (0x0D3B, 0x0D3C, 0, 100),
+    (0x0D41, 0x0D43, 0, 11), (0x0D44, 0x0D44, 0, 51), (0x0D4D, 0x0D4D, 0, 11),
+    (0x0D62, 0x0D63, 0, 51), (0x0D81, 0x0D81, 0, 130), (0x0DCA, 0x0DCA, 0, 30),
+    (0x0DD2, 0x0DD4, 0, 30), (0x0DD6, 0x0DD6, 0, 30), (0x0E31, 0x0E31, 0, 11),
+    (0x0E34, 0x0E3A, 0, 11), (0x0E47, 0x0E4E, 0, 11), (0x0EB1, 0x0EB1, 0, 11),
+    (0x0EB4, 0x0EB9, 0, 11), (0x0EBA, 0x0EBA, 0, 120), (0x0EBB, 0x0EBC, 0, 11),
+    (0x0EC8, 0x0ECD, 0, 11), (0x0F18, 0x0F19, 0, 20), (0x0F35, 0x0F35, 0, 20),
+    (0x0F37, 0x0F37, 0, 20), (0x0F39, 0x0F39, 0, 20), (0x0F71, 0x0F7E, 0, 20),
+    (0x0F80, 0x0F84, 0, 20), (0x0F86, 0x0F87, 0, 20), (0x0F8D, 0x0F8F, 0, 60),
+    (0x0F90, 0x0F95, 0, 20), (0x0F96, 0x0F96, 0, 30), (0x0F97, 0x0F97, 0, 20),
+    (0x0F99, 0x0FAD, 0, 20), (0x0FAE, 0x0FB0, 0, 30), (0x0FB1, 0x0FB7, 0, 20),
+    (0x0FB8, 0x0FB8, 0, 30), (0x0FB9, 0x0FB9, 0, 20), (0x0FBA, 0x0FBC, 0, 30),
+    (0x0FC6, 0x0FC6, 0, 30), (0x102D, 0x1030, 0, 30), (0x1032, 0x1032, 0, 30),
+    (0x1033, 0x1035, 0, 51), (0x1036, 0x1037, 0, 30), (0x1039, 0x1039, 0, 30),
+    (0x103A, 0x103A, 0, 51), (0x103D, 0x103E, 0, 51), (0x1058, 0x1059, 0, 30),
+    (0x105E, 0x1060, 0, 51), (0x1071, 0x1074, 0, 51), (0x1082, 0x1082, 0, 51),
+    (0x1085, 0x1086, 0, 51), (0x108D, 0x108D, 0, 51), (0x109D, 0x109D, 0, 52),
+    (0x1100, 0x1159, 2, 11), (0x115A, 0x115E, 2, 52), (0x115F, 0x115F, 2, 11),
+    (0x1160, 0x11A2, 0, 11), (0x11A3, 0x11A7, 0, 52), (0x11A8, 0x11F9, 0, 11),
+    (0x11FA, 0x11FF, 0, 52), (0x135D, 0x135E, 0, 60), (0x135F, 0x135F, 0, 41),
+    (0x1712, 0x1714, 0, 32), (0x1732, 0x1733, 0, 32), (0x1752, 0x1753, 0, 32),
+    (0x1772, 0x1773, 0, 32), (0x17B4, 0x17B5, 0, 30), (0x17B7, 0x17BD, 0, 30),
+    (0x17C6, 0x17C6, 0, 30), (0x17C9, 0x17D3, 0, 30), (0x17DD, 0x17DD, 0, 40),
+    (0x180B, 0x180E, 0, 30), (0x180F, 0x180F, 0, 140), (0x1885, 0x1886, 0, 30),
+    (0x18A9, 0x18A9, 0, 30), (0x1920, 0x1922, 0, 40), (0x1927, 0x1928, 0, 40),
+    (0x1932, 0x1932, 0, 40), (0x1939, 0x193B, 0, 40), (0x1A17, 0x1A18, 0, 41),
+    (0x1A1B, 0x1A1B, 0, 41), (0x1A56, 0x1A56, 0, 52), (0x1A58, 0x1A5E, 0, 52),
+    (0x1A60, 0x1A60, 0, 52), (0x1A62, 0x1A62, 0, 52), (0x1A65, 0x1A6C, 0, 52),
+    (0x1A73, 0x1A7C, 0, 52), (0x1A7F, 0x1A7F, 0, 52), (0x1AB0, 0x1ABE, 0, 70),
+    (0x1ABF, 0x1AC0, 0, 130), (0x1AC1, 0x1ACE, 0, 140), (0x1B00, 0x1B03, 0, 50),
+    (0x1B34, 0x1B34, 0, 50), (0x1B36, 0x1B3A, 0, 50), (0x1B3C, 0x1B3C, 0, 50),
+    (0x1B42, 0x1B42, 0, 50), (0x1B6B, 0x1B73, 0, 50), (0x1B80, 0x1B81, 0, 51),
+    (0x1BA2, 0x1BA5, 0, 51), (0x1BA8, 0x1BA9, 0, 51), (0x1BAB, 0x1BAD, 0, 61),
+    (0x1BE6, 0x1BE6, 0, 60), (0x1BE8, 0x1BE9, 0, 60), (0x1BED, 0x1BED, 0, 60),
+    (0x1BEF, 0x1BF1, 0, 60), (0x1C2C, 0x1C33, 0, 51), (0x1C36, 0x1C37, 0, 51),
+    (0x1CD0, 0x1CD2, 0, 52), (0x1CD4, 0x1CE0, 0, 52), (0x1CE2, 0x1CE8, 0, 52),
+    (0x1CED, 0x1CED, 0, 52), (0x1CF4, 0x1CF4, 0, 61), (0x1CF8, 0x1CF9, 0, 70),
+    (0x1DC0, 0x1DC3, 0, 41), (0x1DC4, 0x1DCA, 0, 50), (0x1DCB, 0x1DE6, 0, 51),
+    (0x1DE7, 0x1DF5, 0, 70), (0x1DF6, 0x1DF9, 0, 100), (0x1DFA, 0x1DFA, 0, 140),
+    (0x1DFB, 0x1DFB, 0, 90), (0x1DFC, 0x1DFC, 0, 60), (0x1DFD, 0x1DFD, 0, 52),
+    (0x1DFE, 0x1DFF, 0, 50), (0x200B, 0x200F, 0, 11), (0x202A, 0x202E, 0, 11),
+    (0x2060, 0x2063, 0, 32), (0x2064, 0x2064, 0, 51), (0x2066, 0x2069, 0, 63),
+    (0x206A, 0x206F, 0, 11), (0x20D0, 0x20E1, 0, 11), (0x20E2, 0x20E3, 0, 30),
+    (0x20E4, 0x20EA, 0, 32), (0x20EB, 0x20EB, 0, 41), (0x20EC, 0x20EF, 0, 50),
+    (0x20F0, 0x20F0, 0, 51), (0x231A, 0x231B, 2, 90), (0x2329, 0x232A, 2, 11),
+    (0x23E9, 0x23EC, 2, 90), (0x23F0, 0x23F0, 2, 90), (0x23F3, 0x23F3, 2, 90),
+    (0x25FD, 0x25FE, 2, 90), (0x2614, 0x2615, 2, 90), (0x2648, 0x2653, 2, 90),
+    (0x267F, 0x267F, 2, 90), (0x2693, 0x2693, 2, 90), (0x26A1, 0x26A1, 2, 90),
+    (0x26AA, 0x26AB, 2, 90), (0x26BD, 0x26BE, 2, 90), (0x26C4, 0x26C5, 2, 90),
+    (0x26CE, 0x26CE, 2, 90), (0x26D4, 0x26D4, 2, 90), (0x26EA, 0x26EA, 2, 90),
+    (0x26F2, 0x26F3, 2, 90), (0x26F5, 0x26F5, 2, 90), (0x26FA, 0x26FA, 2, 90),
+    (0x26FD, 0x26FD, 2, 90), (0x2705, 0x2705, 2, 90), (0x270A, 0x270B, 2, 90),
+    (0x2728, 0x2728, 2, 90), (0x274C, 0x274C, 2, 90), (0x274E, 0x274E, 2, 90),
+    (0x2753, 0x2755, 2, 90), (0x2757, 0x2757, 2, 90), (0x2795, 0x2797, 2, 90),
+    (0x27B0, 0x27B0, 2, 90), (0x27BF, 0x27BF, 2, 90), (0x2B1B, 0x2B1C, 2, 90),
+    (0x2B50, 0x2B50, 2, 90), (0x2B55, 0x2B55, 2, 90), (0x2CEF, 0x2CF1, 0, 52),
+    (0x2D7F, 0x2D7F, 0, 60), (0x2DE0, 0x2DFF, 0, 51), (0x2E80, 0x2E99, 2, 30),
+    (0x2E9B, 0x2EF3, 2, 30), (0x2F00, 0x2FD5, 2, 30), (0x2FF0, 0x2FFB, 2, 30),
+    (0x3000, 0x3029, 2, 11), (0x302A, 0x302D, 0, 11), (0x302E, 0x3037, 2, 11),
+    (0x3038, 0x303A, 2, 30), (0x303B, 0x303D, 2, 32), (0x303E, 0x303E, 2, 30),
+    (0x3041, 0x3094, 2, 11), (0x3095, 0x3096, 2, 32), (0x3099, 0x309A, 0, 11),
+    (0x309B, 0x309E, 2, 11), (0x309F, 0x30A0, 2, 32), (0x30A1, 0x30FE, 2, 11),
+    (0x30FF, 0x30FF, 2, 32), (0x3105, 0x312C, 2, 11), (0x312D, 0x312D, 2, 51),
+    (0x312E, 0x312E, 2, 100), (0x312F, 0x312F, 2, 110), (0x3131, 0x318E, 2, 11),
+    (0x3190, 0x319F, 2, 11), (0x31A0, 0x31B7, 2, 30), (0x31B8, 0x31BA, 2, 60),
+    (0x31BB, 0x31BF, 2, 130), (0x31C0, 0x31CF, 2, 41), (0x31D0, 0x31E3, 2, 51),
+    (0x31F0, 0x31FF, 2, 32), (0x3200, 0x321C, 2, 11), (0x321D, 0x321E, 2, 40),
+    (0x3220, 0x3243, 2, 11), (0x3244, 0x3247, 2, 52), (0x3250, 0x3250, 2, 40),
+    (0x3251, 0x325F, 2, 32), (0x3260, 0x327B, 2, 11), (0x327C, 0x327D, 2, 40),
+    (0x327E, 0x327E, 2, 41), (0x327F, 0x32B0, 2, 11), (0x32B1, 0x32BF, 2, 32),
+    (0x32C0, 0x32CB, 2, 11), (0x32CC, 0x32CF, 2, 40), (0x32D0, 0x32FE, 2, 11),
+    (0x32FF, 0x32FF, 2, 121), (0x3300, 0x3376, 2, 11), (0x3377, 0x337A, 2, 40),
+    (0x337B, 0x33DD, 2, 11), (0x33DE, 0x33DF, 2, 40), (0x33E0, 0x33FE, 2, 11),
+    (0x33FF, 0x33FF, 2, 40), (0x3400, 0x4DB5, 2, 30), (0x4DB6, 0x4DBF, 2, 130),
+    (0x4E00, 0x9FA5, 2, 11), (0x9FA6, 0x9FBB, 2, 41), (0x9FBC, 0x9FC3, 2, 51),
+    (0x9FC4, 0x9FCB, 2, 52), (0x9FCC, 0x9FCC, 2, 61), (0x9FCD, 0x9FD5, 2, 80),
+    (0x9FD6, 0x9FEA, 2, 100), (0x9FEB, 0x9FEF, 2, 110), (0x9FF0, 0x9FFC, 2, 130),
+    (0x9FFD, 0x9FFF, 2, 140), (0xA000, 0xA48C, 2, 30), (0xA490, 0xA4A1, 2, 30),
+    (0xA4A2, 0xA4A3, 2, 32), (0xA4A4, 0xA4B3, 2, 30), (0xA4B4, 0xA4B4, 2, 32),
+    (0xA4B5, 0xA4C0, 2, 30), (0xA4C1, 0xA4C1, 2, 32), (0xA4C2, 0xA4C4, 2, 30),
+    (0xA4C5, 0xA4C5, 2, 32), (0xA4C6, 0xA4C6, 2, 30), (0xA66F, 0xA672, 0, 51),
+    (0xA674, 0xA67B, 0, 61), (0xA67C, 0xA67D, 0, 51), (0xA69E, 0xA69E, 0, 80),
+    (0xA69F, 0xA69F, 0, 61), (0xA6F0, 0xA6F1, 0, 52), (0xA802, 0xA802, 0, 41),
+    (0xA806, 0xA806, 0, 41), (0xA80B, 0xA80B, 0, 41), (0xA825, 0xA826, 0, 41),
+    (0xA82C, 0xA82C, 0, 130), (0xA8C4, 0xA8C4, 0, 51), (0xA8C5, 0xA8C5, 0, 90),
+    (0xA8E0, 0xA8F1, 0, 52), (0xA8FF, 0xA8FF, 0, 110), (0xA926, 0xA92D, 0, 51),
+    (0xA947, 0xA951, 0, 51), (0xA960, 0xA97C, 2, 52), (0xA980, 0xA982, 0, 52),
+    (0xA9B3, 0xA9B3, 0, 52), (0xA9B6, 0xA9B9, 0, 52), (0xA9BC, 0xA9BD, 0, 52),
+    (0xA9E5, 0xA9E5, 0, 70), (0xAA29, 0xAA2E, 0, 51), (0xAA31, 0xAA32, 0, 51),
+    (0xAA35, 0xAA36, 0, 51), (0xAA43, 0xAA43, 0, 51), (0xAA4C, 0xAA4C, 0, 51),
+    (0xAA7C, 0xAA7C, 0, 70), (0xAAB0, 0xAAB0, 0, 52), (0xAAB2, 0xAAB4, 0, 52),
+    (0xAAB7, 0xAAB8, 0, 52), (0xAABE, 0xAABF, 0, 52), (0xAAC1, 0xAAC1, 0, 52),
+    (0xAAEC, 0xAAED, 0, 61), (0xAAF6, 0xAAF6, 0, 61), (0xABE5, 0xABE5, 0, 52),
+    (0xABE8, 0xABE8, 0, 52), (0xABED, 0xABED, 0, 52), (0xAC00, 0xD7A3, 2, 20),
+    (0xD7B0, 0xD7C6, 0, 52), (0xD7CB, 0xD7FB, 0, 52), (0xF900, 0xFA2D, 2, 11),
+    (0xFA2E, 0xFA2F, 2, 61), (0xFA30, 0xFA6A, 2, 32), (0xFA6B, 0xFA6D, 2, 52),
+    (0xFA70, 0xFAD9, 2, 41), (0xFB1E, 0xFB1E, 0, 11), (0xFE00, 0xFE0F, 0, 32),
+    (0xFE10, 0xFE19, 2, 41), (0xFE20, 0xFE23, 0, 11), (0xFE24, 0xFE26, 0, 51),
+    (0xFE27, 0xFE2D, 0, 70), (0xFE2E, 0xFE2F, 0, 80), (0xFE30, 0xFE44, 2, 11),
+    (0xFE45, 0xFE46, 2, 32), (0xFE47, 0xFE48, 2, 40), (0xFE49, 0xFE52, 2, 11),
+    (0xFE54, 0xFE66, 2, 11), (0xFE68, 0xFE6B, 2, 11), (0xFEFF, 0xFEFF, 0, 11),
+    (0xFF01, 0xFF5E, 2, 11), (0xFF5F, 0xFF60, 2, 32), (0xFFE0, 0xFFE6, 2, 11),
+    (0xFFF9, 0xFFFB, 0, 30), (0x101FD, 0x101FD, 0, 51), (0x102E0, 0x102E0, 0, 70),
+    (0x10376, 0x1037A, 0, 70), (0x10A01, 0x10A03, 0, 41), (0x10A05, 0x10A06, 0, 41),
+    (0x10A0C, 0x10A0F, 0, 41), (0x10A38, 0x10A3A, 0, 41), (0x10A3F, 0x10A3F, 0, 41),
+    (0x10AE5, 0x10AE6, 0, 70), (0x10D24, 0x10D27, 0, 110), (0x10EAB, 0x10EAC, 0, 130),
+    (0x10F46, 0x10F50, 0, 110), (0x10F82, 0x10F85, 0, 140), (0x11001, 0x11001, 0, 60),
+    (0x11038, 0x11046, 0, 60), (0x11070, 0x11070, 0, 140), (0x11073, 0x11074, 0, 140),
+    (0x1107F, 0x1107F, 0, 70), (0x11080, 0x11081, 0, 52), (0x110B3, 0x110B6, 0, 52),
+    (0x110B9, 0x110BA, 0, 52), (0x110C2, 0x110C2, 0, 140), (0x11100, 0x11102, 0, 61),
+    (0x11127, 0x1112B, 0, 61), (0x1112D, 0x11134, 0, 61), (0x11173, 0x11173, 0, 70),
+    (0x11180, 0x11181, 0, 61), (0x111B6, 0x111BE, 0, 61), (0x111C9, 0x111CC, 0, 80),
+    (0x111CF, 0x111CF, 0, 130), (0x1122F, 0x11231, 0, 70), (0x11234, 0x11234, 0, 70),
+    (0x11236, 0x11237, 0, 70), (0x1123E, 0x1123E, 0, 90), (0x112DF, 0x112DF, 0, 70),
+    (0x112E3, 0x112EA, 0, 70), (0x11300, 0x11300, 0, 80), (0x11301, 0x11301, 0, 70),
+    (0x1133B, 0x1133B, 0, 110), (0x1133C, 0x1133C, 0, 70), (0x11340, 0x11340, 0, 70),
+    (0x11366, 0x1136C, 0, 70), (0x11370, 0x11374, 0, 70), (0x11438, 0x1143F, 0, 90),
+    (0x11442, 0x11444, 0, 90), (0x11446, 0x11446, 0, 90), (0x1145E, 0x1145E, 0, 110),
+    (0x114B3, 0x114B8, 0, 70), (0x114BA, 0x114BA, 0, 70), (0x114BF, 0x114C0, 0, 70),
+    (0x114C2, 0x114C3, 0, 70), (0x115B2, 0x115B5, 0, 70), (0x115BC, 0x115BD, 0, 70),
+    (0x115BF, 0x115C0, 0, 70), (0x115DC, 0x115DD, 0, 80), (0x11633, 0x1163A, 0, 70),
+    (0x1163D, 0x1163D, 0, 70), (0x1163F, 0x11640, 0, 70), (0x116AB, 0x116AB, 0, 61),
+    (0x116AD, 0x116AD, 0, 61), (0x116B0, 0x116B5, 0, 61), (0x116B7, 0x116B7, 0, 61),
+    (0x1171D, 0x1171F, 0, 80), (0x11722, 0x11725, 0, 80), (0x11727, 0x1172B, 0, 80),
+    (0x1182F, 0x11837, 0, 110), (0x11839, 0x1183A, 0, 110), (0x1193B, 0x1193C, 0, 130),
+    (0x1193E, 0x1193E, 0, 130), (0x11943, 0x11943, 0, 130), (0x119D4, 0x119D7, 0, 120),
+    (0x119DA, 0x119DB, 0, 120), (0x119E0, 0x119E0, 0, 120), (0x11A01, 0x11A0A, 0, 100),
+    (0x11A33, 0x11A38, 0, 100), (0x11A3B, 0x11A3E, 0, 100), (0x11A47, 0x11A47, 0, 100),
+    (0x11A51, 0x11A56, 0, 100), (0x11A59, 0x11A5B, 0, 100), (0x11A8A, 0x11A96, 0, 100),
+    (0x11A98, 0x11A99, 0, 100), (0x11C30, 0x11C36, 0, 90), (0x11C38, 0x11C3D, 0, 90),
+    (0x11C3F, 0x11C3F, 0, 90), (0x11C92, 0x11CA7, 0, 90), (0x11CAA, 0x11CB0, 0, 90),
+    (0x11CB2, 0x11CB3, 0, 90), (0x11CB5, 0x11CB6, 0, 90), (0x11D31, 0x11D36, 0, 100),
+    (0x11D3A, 0x11D3A, 0, 100), (0x11D3C, 0x11D3D, 0, 100), (0x11D3F, 0x11D45, 0, 100),
+    (0x11D47, 0x11D47, 0, 100), (0x11D90, 0x11D91, 0, 110), (0x11D95, 0x11D95, 0, 110),
+    (0x11D97, 0x11D97, 0, 110), (0x11EF3, 0x11EF4, 0, 110), (0x13430, 0x13438, 0, 120),
+    (0x16AF0, 0x16AF4, 0, 70), (0x16B30, 0x16B36, 0, 70), (0x16F4F, 0x16F4F, 0, 120),
+    (0x16F8F, 0x16F92, 0, 61), (0x16FE0, 0x16FE0, 2, 90), (0x16FE1, 0x16FE1, 2, 100),
+    (0x16FE2, 0x16FE3, 2, 120), (0x16FE4, 0x16FE4, 0, 130), (0x16FF0, 0x16FF1, 2, 130),
+    (0x17000, 0x187EC, 2, 90), (0x187ED, 0x187F1, 2, 110), (0x187F2, 0x187F7, 2, 120),
+    (0x18800, 0x18AF2, 2, 90), (0x18AF3, 0x18CD5, 2, 130), (0x18D00, 0x18D08, 2, 130),
+    (0x1AFF0, 0x1AFF3, 2, 140), (0x1AFF5, 0x1AFFB, 2, 140), (0x1AFFD, 0x1AFFE, 2, 140),
+    (0x1B000, 0x1B001, 2, 60), (0x1B002, 0x1B11E, 2, 100), (0x1B11F, 0x1B122, 2, 140),
+    (0x1B150, 0x1B152, 2, 120), (0x1B164, 0x1B167, 2, 120), (0x1B170, 0x1B2FB, 2, 100),
+    (0x1BC9D, 0x1BC9E, 0, 70), (0x1BCA0, 0x1BCA3, 0, 70), (0x1CF00, 0x1CF2D, 0, 140),
+    (0x1CF30, 0x1CF46, 0, 140), (0x1D167, 0x1D169, 0, 31), (0x1D173, 0x1D182, 0, 31),
+    (0x1D185, 0x1D18B, 0, 31), (0x1D1AA, 0x1D1AD, 0, 31), (0x1D242, 0x1D244, 0, 41),
+    (0x1DA00, 0x1DA36, 0, 80), (0x1DA3B, 0x1DA6C, 0, 80), (0x1DA75, 0x1DA75, 0, 80),
+    (0x1DA84, 0x1DA84, 0, 80), (0x1DA9B, 0x1DA9F, 0, 80), (0x1DAA1, 0x1DAAF, 0, 80),
+    (0x1E000, 0x1E006, 0, 90), (0x1E008, 0x1E018, 0, 90), (0x1E01B, 0x1E021, 0, 90),
+    (0x1E023, 0x1E024, 0, 90), (0x1E026, 0x1E02A, 0, 90), (0x1E130, 0x1E136, 0, 120),
+    (0x1E2AE, 0x1E2AE, 0, 140), (0x1E2EC, 0x1E2EF, 0, 120), (0x1E8D0, 0x1E8D6, 0, 70),
+    (0x1E944, 0x1E94A, 0, 90), (0x1F004, 0x1F004, 2, 90), (0x1F0CF, 0x1F0CF, 2, 90),
+    (0x1F18E, 0x1F18E, 2, 90), (0x1F191, 0x1F19A, 2, 90), (0x1F200, 0x1F200, 2, 52),
+    (0x1F201, 0x1F202, 2, 60), (0x1F210, 0x1F231, 2, 52), (0x1F232, 0x1F23A, 2, 60),
+    (0x1F23B, 0x1F23B, 2, 90), (0x1F240, 0x1F248, 2, 52), (0x1F250, 0x1F251, 2, 60),
+    (0x1F260, 0x1F265, 2, 100), (0x1F300, 0x1F320, 2, 90), (0x1F32D, 0x1F335, 2, 90),
+    (0x1F337, 0x1F37C, 2, 90), (0x1F37E, 0x1F393, 2, 90), (0x1F3A0, 0x1F3CA, 2, 90),
+    (0x1F3CF, 0x1F3D3, 2, 90), (0x1F3E0, 0x1F3F0, 2, 90), (0x1F3F4, 0x1F3F4, 2, 90),
+    (0x1F3F8, 0x1F43E, 2, 90), (0x1F440, 0x1F440, 2, 90), (0x1F442, 0x1F4FC, 2, 90),
+    (0x1F4FF, 0x1F53D, 2, 90), (0x1F54B, 0x1F54E, 2, 90), (0x1F550, 0x1F567, 2, 90),
+    (0x1F57A, 0x1F57A, 2, 90), (0x1F595, 0x1F596, 2, 90), (0x1F5A4, 0x1F5A4, 2, 90),
+    (0x1F5FB, 0x1F64F, 2, 90), (0x1F680, 0x1F6C5, 2, 90), (0x1F6CC, 0x1F6CC, 2, 90),
+    (0x1F6D0, 0x1F6D2, 2, 90), (0x1F6D5, 0x1F6D5, 2, 120), (0x1F6D6, 0x1F6D7, 2, 130),
+    (0x1F6DD, 0x1F6DF, 2, 140), (0x1F6EB, 0x1F6EC, 2, 90), (0x1F6F4, 0x1F6F6, 2, 90),
+    (0x1F6F7, 0x1F6F8, 2, 100), (0x1F6F9, 0x1F6F9, 2, 110), (0x1F6FA, 0x1F6FA, 2, 120),
+    (0x1F6FB, 0x1F6FC, 2, 130), (0x1F7E0, 0x1F7EB, 2, 120), (0x1F7F0, 0x1F7F0, 2, 140),
+    (0x1F90C, 0x1F90C, 2, 130), (0x1F90D, 0x1F90F, 2, 120), (0x1F910, 0x1F91E, 2, 90),
+    (0x1F91F, 0x1F91F, 2, 100), (0x1F920, 0x1F927, 2, 90), (0x1F928, 0x1F92F, 2, 100),
+    (0x1F930, 0x1F930, 2, 90), (0x1F931, 0x1F932, 2, 100), (0x1F933, 0x1F93A, 2, 90),
+    (0x1F93C, 0x1F93E, 2, 90), (0x1F93F, 0x1F93F, 2, 120), (0x1F940, 0x1F945, 2, 90),
+    (0x1F947, 0x1F94B, 2, 90), (0x1F94C, 0x1F94C, 2, 100), (0x1F94D, 0x1F94F, 2, 110),
+    (0x1F950, 0x1F95E, 2, 90), (0x1F95F, 0x1F96B, 2, 100), (0x1F96C, 0x1F970, 2, 110),
+    (0x1F971, 0x1F971, 2, 120), (0x1F972, 0x1F972, 2, 130), (0x1F973, 0x1F976, 2, 110),
+    (0x1F977, 0x1F978, 2, 130), (0x1F979, 0x1F979, 2, 140), (0x1F97A, 0x1F97A, 2, 110),
+    (0x1F97B, 0x1F97B, 2, 120), (0x1F97C, 0x1F97F, 2, 110), (0x1F980, 0x1F991, 2, 90),
+    (0x1F992, 0x1F997, 2, 100), (0x1F998, 0x1F9A2, 2, 110), (0x1F9A3, 0x1F9A4, 2, 130),
+    (0x1F9A5, 0x1F9AA, 2, 120), (0x1F9AB, 0x1F9AD, 2, 130), (0x1F9AE, 0x1F9AF, 2, 120),
+    (0x1F9B0, 0x1F9B9, 2, 110), (0x1F9BA, 0x1F9BF, 2, 120), (0x1F9C0, 0x1F9C0, 2, 90),
+    (0x1F9C1, 0x1F9C2, 2, 110), (0x1F9C3, 0x1F9CA, 2, 120), (0x1F9CB, 0x1F9CB, 2, 130),
+    (0x1F9CC, 0x1F9CC, 2, 140), (0x1F9CD, 0x1F9CF, 2, 120), (0x1F9D0, 0x1F9E6, 2, 100),
+    (0x1F9E7, 0x1F9FF, 2, 110), (0x1FA70, 0x1FA73, 2, 120), (0x1FA74, 0x1FA74, 2, 130),
+    (0x1FA78, 0x1FA7A, 2, 120), (0x1FA7B, 0x1FA7C, 2, 140), (0x1FA80, 0x1FA82, 2, 120),
+    (0x1FA83, 0x1FA86, 2, 130), (0x1FA90, 0x1FA95, 2, 120), (0x1FA96, 0x1FAA8, 2, 130),
+    (0x1FAA9, 0x1FAAC, 2, 140), (0x1FAB0, 0x1FAB6, 2, 130), (0x1FAB7, 0x1FABA, 2, 140),
+    (0x1FAC0, 0x1FAC2, 2, 130), (0x1FAC3, 0x1FAC5, 2, 140), (0x1FAD0, 0x1FAD6, 2, 130),
+    (0x1FAD7, 0x1FAD9, 2, 140), (0x1FAE0, 0x1FAE7, 2, 140), (0x1FAF0, 0x1FAF6, 2, 140),
+    (0x20000, 0x2A6D6, 2, 31), (0x2A6D7, 0x2A6DD, 2, 130), (0x2A6DE, 0x2A6DF, 2, 140),
+    (0x2A700, 0x2B734, 2, 52), (0x2B735, 0x2B738, 2, 140), (0x2B740, 0x2B81D, 2, 60),
+    (0x2B820, 0x2CEA1, 2, 80), (0x2CEB0, 0x2EBE0, 2, 100), (0x2F800, 0x2FA1D, 2, 31),
+    (0x30000, 0x3134A, 2, 130), (0xE0001, 0xE0001, 0, 31), (0xE0020, 0xE007F, 0, 31),
+    (0xE0100, 0xE01EF, 0, 40),
+];
+
+/// Get the Unicode version for a choice of the unicode-version option.
+fn utf8_table_version(choice: i64) -> u8 {
+    usize::try_from(choice)
+        .ok()
+        .and_then(|i| UTF8_TABLE_VERSIONS.get(i))
+        .copied()
+        .unwrap_or(140)
+}
+
+/// Set the Unicode version from the unicode-version option.
+pub fn utf8_table_set_version(choice: i64) {
+    let version = utf8_table_version(choice);
+    log_debug!("using Unicode {}.{} widths", version / 10, version % 10);
+    UTF8_TABLE_VERSION.store(version, Ordering::Relaxed);
+}
+
+/// Get the Unicode version in use, as major and minor.
+#[cfg_attr(not(feature = "utf8proc"), expect(dead_code))]
+pub fn utf8_table_get_version() -> (u8, u8) {
+    let version = UTF8_TABLE_VERSION.load(Ordering::Relaxed);
+    (version / 10, version % 10)
+}
+
+/// Look up the width of a character, like `wcwidth()`: -1 for control
+/// characters, otherwise 0, 1 or 2.
+pub fn utf8_table_width(wc: wchar_t) -> i32 {
+    utf8_table_width_version(wc, UTF8_TABLE_VERSION.load(Ordering::Relaxed))
+}
+
+/// Look up the width of a character in the given Unicode version.
+fn utf8_table_width_version(wc: wchar_t, current: u8) -> i32 {
+    if wc == 0 {
+        return 0;
+    }
+    if wc < 0x20 || (0x7f..0xa0).contains(&wc) {
+        return -1;
+    }
+
+    let found = UTF8_TABLE_WIDTH.binary_search_by(|&(first, last, _, _)| {
+        if last < wc {
+            std::cmp::Ordering::Less
+        } else if first > wc {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    });
+    match found {
+        Ok(i) => {
+            let (_, _, width, version) = UTF8_TABLE_WIDTH[i];
+            if version <= current {
+                width as i32
+            } else {
+                1
+            }
+        }
+        Err(_) => 1,
+    }
+}
+
+/// Decode one UTF-8 character, like `mbtowc()`: returns the number of bytes used
+/// or -1 if the data is not a valid UTF-8 character.
+#[cfg_attr(not(any(feature = "utf8proc", test)), expect(dead_code))]
+pub unsafe fn utf8_table_mbtowc(wc: *mut wchar_t, s: *const u8, n: usize) -> i32 {
+    unsafe {
+        if s.is_null() || n == 0 {
+            return 0;
+        }
+        let bytes = std::slice::from_raw_parts(s, n);
+        let size = match bytes[0] {
+            0x00..=0x7f => 1,
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => return -1,
+        };
+        if n < size {
+            return -1;
+        }
+        match std::str::from_utf8(&bytes[..size]) {
+            Ok(ch) => {
+                let c = ch.chars().next().unwrap();
+                if !wc.is_null() {
+                    *wc = c as wchar_t;
+                }
+                if c == '\0' { 0 } else { size as i32 }
+            }
+            Err(_) => -1,
+        }
+    }
+}
+
+/// Encode a character as UTF-8, like `wctomb()`: returns the number of bytes
+/// written or -1 if it is not a valid character.
+#[cfg_attr(not(any(feature = "utf8proc", test)), expect(dead_code))]
+pub unsafe fn utf8_table_wctomb(s: *mut u8, wc: wchar_t) -> i32 {
+    unsafe {
+        let Some(c) = u32::try_from(wc).ok().and_then(char::from_u32) else {
+            return -1;
+        };
+        if s.is_null() {
+            return 0;
+        }
+        let mut buf = [0u8; 4];
+        let encoded = c.encode_utf8(&mut buf);
+        std::ptr::copy_nonoverlapping(encoded.as_bytes().as_ptr(), s, encoded.len());
+        encoded.len() as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf8_table_width() {
+        assert_eq!(utf8_table_width('a' as wchar_t), 1);
+        assert_eq!(utf8_table_width(0x1b), -1);
+        assert_eq!(utf8_table_width(0x301), 0);
+        assert_eq!(utf8_table_width(0x200d), 0);
+        assert_eq!(utf8_table_width(0x4e00), 2);
+        assert_eq!(utf8_table_width(0xff21), 2);
+        assert_eq!(utf8_table_width(0x1160), 0);
+        assert_eq!(utf8_table_width(0xe000), 1);
+        assert_eq!(utf8_table_width(0x10ffff), 1);
+
+        // U+231A WATCH became wide in Unicode 9 and U+1FAE0 MELTING FACE was
+        // added in Unicode 14.
+        let (v8, v13, v14) = (utf8_table_version(0), utf8_table_version(5), utf8_table_version(6));
+        assert_eq!((v8, v13, v14), (80, 130, 140));
+        assert_eq!(utf8_table_version(7), 140);
+        assert_eq!(utf8_table_width_version(0x231a, v14), 2);
+        assert_eq!(utf8_table_width_version(0x1fae0, v14), 2);
+        assert_eq!(utf8_table_width_version(0x231a, v8), 1);
+        assert_eq!(utf8_table_width_version(0x4e00, v8), 2);
+        assert_eq!(utf8_table_width_version(0x231a, v13), 2);
+        assert_eq!(utf8_table_width_version(0x1fae0, v13), 1);
+    }
+
+    #[test]
+    fn test_utf8_table_mbtowc() {
+        let mut wc: wchar_t = 0;
+        let mut buf = [0u8; 4];
+        unsafe {
+            assert_eq!(utf8_table_mbtowc(&mut wc, "\u{3ba}".as_bytes().as_ptr(), 2), 2);
+            assert_eq!(wc, 0x3ba);
+            assert_eq!(utf8_table_mbtowc(&mut wc, b"\xed\xa0\x80".as_ptr(), 3), -1);
+            assert_eq!(utf8_table_mbtowc(&mut wc, b"\xc0\xaf".as_ptr(), 2), -1);
+            assert_eq!(utf8_table_mbtowc(&mut wc, b"\xf4\x90\x80\x80".as_ptr(), 4), -1);
+            assert_eq!(utf8_table_mbtowc(&mut wc, b"\xe2\x82".as_ptr(), 2), -1);
+            assert_eq!(utf8_table_wctomb(buf.as_mut_ptr(), 0x1f600), 4);
+            assert_eq!(&buf, "\u{1f600}".as_bytes());
+            assert_eq!(utf8_table_wctomb(buf.as_mut_ptr(), 0xd800), -1);
+        }
+    }
+
+    /// Every line of the UTF-8 decoder stress test made only of valid
+    /// characters is laid out to be exactly 79 cells wide. The exceptions are
+    /// the lines testing control characters, noncharacters and the unassigned
+    /// U+D7FF, which are expected to be invisible.
+    #[test]
+    fn test_utf8_table_regress() {
+        let text = include_bytes!("../regress/UTF-8-test.txt");
+        let mut checked = 0;
+        for line in text.split(|&b| b == b'\n') {
+            let Ok(line) = std::str::from_utf8(line) else {
+                continue;
+            };
+            if !line.ends_with('|') {
+                continue;
+            }
+            let chars: Vec<wchar_t> = line.chars().map(|c| c as wchar_t).collect();
+            if chars
+                .iter()
+                .any(|&wc| {
+                    utf8_table_width(wc) < 0
+                        || wc & 0xfffe == 0xfffe
+                        || (0xfdd0..=0xfdef).contains(&wc)
+                        || wc == 0xd7ff
+                })
+            {
+                continue;
+            }
+            let width: i32 = chars.iter().map(|&wc| utf8_table_width(wc)).sum();
+            assert_eq!(width, 79, "{line}");
+            checked += 1;
+        }
+        assert!(checked > 100);
+    }
+}
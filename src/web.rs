@@ -0,0 +1,1113 @@
+// A web page for watching and using sessions from a browser.
+//
+// When web-listen is set the server listens on a unix socket or on a port on
+// 127.0.0.1 and speaks just enough HTTP to serve a small page and upgrade one
+// path to a WebSocket. Each WebSocket runs a control client attached to the
+// session it asks for and the gateway drives that client: window and layout
+// changes are passed on as lists of windows and panes and a pane with new
+// output is captured as a whole, so the page draws full snapshots of each
+// pane rather than interpreting the output itself. Users the server access
+// list makes read-only get a read-only client and cannot send keys, and a
+// user's permission profile is given to their client.
+use std::collections::{BTreeSet, VecDeque};
+
+use tmux_rs_control::{Client, Command, Event, Incoming, Tag};
+
+use crate::compat::b64::b64_ntop;
+use crate::compat::getpeereid;
+use crate::libc::{
+    AF_INET, AF_UNIX, INADDR_LOOPBACK, SOCK_STREAM, accept, bind, getpeername, getsockname, listen,
+    sockaddr_in, sockaddr_un, socket, socklen_t, umask,
+};
+use crate::options_::options_get_string_;
+use crate::*;
+
+/// The page served for /.
+const WEB_PAGE: &str = include_str!("web.html");
+
+/// Longest request line and headers accepted.
+const WEB_REQUEST_MAX: usize = 8192;
+
+/// Longest message accepted from a browser.
+const WEB_FRAME_MAX: usize = 65536;
+
+/// Delay before capturing panes with new output, in milliseconds.
+const WEB_DELAY: i64 = 50;
+
+const WEB_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const WEB_OP_TEXT: u8 = 0x1;
+const WEB_OP_CLOSE: u8 = 0x8;
+const WEB_OP_PING: u8 = 0x9;
+const WEB_OP_PONG: u8 = 0xa;
+
+const WEB_CLOSE_NORMAL: u16 = 1000;
+const WEB_CLOSE_PROTOCOL: u16 = 1002;
+const WEB_CLOSE_UNSUPPORTED: u16 = 1003;
+const WEB_CLOSE_TOO_BIG: u16 = 1009;
+
+static mut WEB_FD: c_int = -1;
+static mut WEB_TCP: bool = false;
+static mut WEB_PATH: *mut u8 = null_mut();
+static mut WEB_EV_ACCEPT: event = unsafe { zeroed() };
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum web_state {
+    WEB_HTTP,
+    WEB_SOCKET,
+    WEB_CLOSING,
+}
+
+/// What a command sent to the control client was for.
+enum web_request {
+    WEB_WINDOWS,
+    /// List the panes, capturing all of them if set.
+    WEB_PANES(bool),
+    WEB_SNAPSHOT(u32),
+    WEB_COMMAND,
+}
+
+/// One connection to the listener.
+pub struct web_conn {
+    fd: c_int,
+    event: *mut bufferevent,
+    state: web_state,
+    tcp: bool,
+    readonly: bool,
+    profile: Option<String>,
+
+    job: *mut job,
+    client: Client,
+    attached: bool,
+    requests: VecDeque<(Tag, web_request)>,
+    refreshing: bool,
+
+    /// panes in the current window and those with output not yet captured
+    panes: Vec<u32>,
+    dirty: BTreeSet<u32>,
+    timer: event,
+}
+
+/// Start or stop listening after web-listen is changed.
+pub unsafe fn web_start() {
+    unsafe {
+        web_stop();
+
+        let listen = cstr_to_str(options_get_string_(GLOBAL_OPTIONS, "web-listen"));
+        if listen.is_empty() {
+            return;
+        }
+        let fd = match web_listen(listen) {
+            Ok(fd) => fd,
+            Err(cause) => {
+                log_debug!("web-listen {}: {}", listen, cause);
+                server_add_message!("web-listen {}: {}", listen, cause);
+                return;
+            }
+        };
+        log_debug!("web listening on {}", listen);
+
+        WEB_FD = fd;
+        event_set(
+            &raw mut WEB_EV_ACCEPT,
+            fd,
+            EV_READ | EV_PERSIST,
+            Some(web_accept),
+            null_mut(),
+        );
+        event_add(&raw mut WEB_EV_ACCEPT, null());
+    }
+}
+
+/// Stop listening. Connections already accepted are left alone.
+pub unsafe fn web_stop() {
+    unsafe {
+        if WEB_FD == -1 {
+            return;
+        }
+        event_del(&raw mut WEB_EV_ACCEPT);
+        close(WEB_FD);
+        WEB_FD = -1;
+
+        if !WEB_PATH.is_null() {
+            unlink(WEB_PATH);
+            free_(WEB_PATH);
+            WEB_PATH = null_mut();
+        }
+    }
+}
+
+/// Create the listening socket: an absolute path is a unix socket and
+/// anything else is a port on 127.0.0.1, optionally after 127.0.0.1: or
+/// localhost:.
+unsafe fn web_listen(address: &str) -> Result<c_int, String> {
+    unsafe {
+        let fd;
+        if address.starts_with('/') {
+            let mut sa: sockaddr_un = zeroed();
+            sa.sun_family = AF_UNIX as _;
+            if address.len() >= size_of_val(&sa.sun_path) {
+                return Err("path too long".to_string());
+            }
+            for (i, ch) in address.bytes().enumerate() {
+                sa.sun_path[i] = ch as _;
+            }
+            unlink(sa.sun_path.as_ptr().cast());
+
+            fd = socket(AF_UNIX, SOCK_STREAM, 0);
+            if fd == -1 {
+                return Err(strerror(errno!()).to_string());
+            }
+
+            // Use the same permissions as the default server socket.
+            let mask = umask(S_IXUSR | S_IXGRP | S_IRWXO);
+            let bound = bind(fd, &raw const sa as _, size_of::<sockaddr_un>() as _);
+            let saved_errno = errno!();
+            umask(mask);
+            if bound == -1 {
+                close(fd);
+                return Err(strerror(saved_errno).to_string());
+            }
+            WEB_PATH = xstrdup__(address);
+            WEB_TCP = false;
+        } else {
+            let port = address
+                .strip_prefix("127.0.0.1:")
+                .or_else(|| address.strip_prefix("localhost:"))
+                .unwrap_or(address);
+            let Ok(port) = port.parse::<u16>() else {
+                return Err("not a path or port".to_string());
+            };
+            if port == 0 {
+                return Err("not a path or port".to_string());
+            }
+
+            let mut sin: sockaddr_in = zeroed();
+            sin.sin_family = AF_INET as _;
+            sin.sin_port = port.to_be();
+            sin.sin_addr.s_addr = INADDR_LOOPBACK.to_be();
+
+            fd = socket(AF_INET, SOCK_STREAM, 0);
+            if fd == -1 {
+                return Err(strerror(errno!()).to_string());
+            }
+            let one: c_int = 1;
+            setsockopt(
+                fd,
+                SOL_SOCKET,
+                SO_REUSEADDR,
+                (&raw const one).cast(),
+                size_of::<c_int>() as socklen_t,
+            );
+            if bind(fd, &raw const sin as _, size_of::<sockaddr_in>() as _) == -1 {
+                let saved_errno = errno!();
+                close(fd);
+                return Err(strerror(saved_errno).to_string());
+            }
+            WEB_TCP = true;
+        }
+
+        if listen(fd, 16) == -1 {
+            let saved_errno = errno!();
+            close(fd);
+            return Err(strerror(saved_errno).to_string());
+        }
+        setblocking(fd, 0);
+        Ok(fd)
+    }
+}
+
+unsafe extern "C-unwind" fn web_accept(fd: i32, _events: i16, _data: *mut c_void) {
+    unsafe {
+        let newfd = accept(fd, null_mut(), null_mut());
+        if newfd == -1 {
+            return;
+        }
+        if SERVER_EXIT != 0 {
+            close(newfd);
+            return;
+        }
+        setblocking(newfd, 0);
+
+        let wc = Box::leak(Box::new(web_conn {
+            fd: newfd,
+            event: null_mut(),
+            state: web_state::WEB_HTTP,
+            tcp: WEB_TCP,
+            readonly: true,
+            profile: None,
+            job: null_mut(),
+            client: Client::new(),
+            attached: false,
+            requests: VecDeque::new(),
+            refreshing: false,
+            panes: Vec::new(),
+            dirty: BTreeSet::new(),
+            timer: zeroed(),
+        })) as *mut web_conn;
+        log_debug!("web connection {:p}: fd {}", wc, newfd);
+
+        (*wc).event = bufferevent_new(
+            newfd,
+            Some(web_read_callback),
+            Some(web_write_callback),
+            Some(web_error_callback),
+            wc.cast(),
+        );
+        if (*wc).event.is_null() {
+            fatalx("out of memory");
+        }
+        bufferevent_enable((*wc).event, EV_READ | EV_WRITE);
+    }
+}
+
+unsafe fn web_free(wc: *mut web_conn) {
+    unsafe {
+        log_debug!("web connection {:p} closed", wc);
+
+        if !(*wc).job.is_null() {
+            job_free((*wc).job);
+        }
+        if evtimer_initialized(&raw mut (*wc).timer) {
+            evtimer_del(&raw mut (*wc).timer);
+        }
+        bufferevent_free((*wc).event);
+        close((*wc).fd);
+        drop(Box::from_raw(wc));
+    }
+}
+
+unsafe extern "C-unwind" fn web_error_callback(
+    _bufev: *mut bufferevent,
+    _what: c_short,
+    data: *mut c_void,
+) {
+    unsafe { web_free(data.cast()) }
+}
+
+unsafe extern "C-unwind" fn web_write_callback(_bufev: *mut bufferevent, data: *mut c_void) {
+    unsafe {
+        let wc: *mut web_conn = data.cast();
+        if (*wc).state == web_state::WEB_CLOSING
+            && EVBUFFER_LENGTH(EVBUFFER_OUTPUT((*wc).event)) == 0
+        {
+            web_free(wc);
+        }
+    }
+}
+
+unsafe extern "C-unwind" fn web_read_callback(_bufev: *mut bufferevent, data: *mut c_void) {
+    unsafe {
+        let wc: *mut web_conn = data.cast();
+        let input = (*(*wc).event).input;
+
+        loop {
+            let size = EVBUFFER_LENGTH(input);
+            if size == 0 {
+                return;
+            }
+            let buf = std::slice::from_raw_parts(EVBUFFER_DATA(input), size);
+
+            match (*wc).state {
+                web_state::WEB_HTTP => {
+                    let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+                        if size > WEB_REQUEST_MAX {
+                            web_respond(wc, "431 Request Header Fields Too Large", "", "");
+                        }
+                        return;
+                    };
+                    if end > WEB_REQUEST_MAX {
+                        web_respond(wc, "431 Request Header Fields Too Large", "", "");
+                        return;
+                    }
+                    let request = String::from_utf8_lossy(&buf[..end]).into_owned();
+                    evbuffer_drain(input, end + 4);
+                    web_http(wc, &request);
+                }
+                web_state::WEB_SOCKET => match web_frame_parse(buf) {
+                    Ok(None) => return,
+                    Ok(Some((used, opcode, payload))) => {
+                        evbuffer_drain(input, used);
+                        web_frame(wc, opcode, &payload);
+                    }
+                    Err(code) => {
+                        web_close(wc, code);
+                        return;
+                    }
+                },
+                web_state::WEB_CLOSING => {
+                    evbuffer_drain(input, size);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Write an HTTP response and close the connection once it is sent.
+unsafe fn web_respond(wc: *mut web_conn, status: &str, content_type: &str, body: &str) {
+    unsafe {
+        let mut response = format!("HTTP/1.1 {status}\r\n");
+        if !content_type.is_empty() {
+            response.push_str(&format!("Content-Type: {content_type}\r\n"));
+        }
+        response.push_str(&format!(
+            "Content-Length: {}\r\n\
+             Cache-Control: no-store\r\n\
+             X-Frame-Options: DENY\r\n\
+             Connection: close\r\n\r\n{body}",
+            body.len()
+        ));
+        bufferevent_write(
+            (*wc).event,
+            response.as_bytes().as_ptr().cast(),
+            response.len(),
+        );
+        bufferevent_disable((*wc).event, EV_READ);
+        (*wc).state = web_state::WEB_CLOSING;
+    }
+}
+
+/// Handle a request. Only one is handled on each connection.
+unsafe fn web_http(wc: *mut web_conn, request: &str) {
+    unsafe {
+        let mut lines = request.split("\r\n");
+        let mut words = lines.next().unwrap_or("").split(' ');
+        let (method, target) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+        let headers: Vec<(String, &str)> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim()))
+            .collect();
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| *value)
+        };
+        log_debug!("web connection {:p}: {} {}", wc, method, target);
+
+        if method != "GET" {
+            web_respond(wc, "405 Method Not Allowed", "", "");
+            return;
+        }
+
+        // Check the page is being asked for by name as a local address and
+        // not from some other site, so a page elsewhere cannot reach the
+        // server by pointing a name at 127.0.0.1.
+        let Some(host) = header("host") else {
+            web_respond(wc, "400 Bad Request", "", "");
+            return;
+        };
+        if (*wc).tcp && !web_local_host(host) {
+            web_respond(wc, "403 Forbidden", "", "");
+            return;
+        }
+        if let Some(origin) = header("origin")
+            && origin != format!("http://{host}")
+            && origin != format!("https://{host}")
+        {
+            web_respond(wc, "403 Forbidden", "", "");
+            return;
+        }
+
+        let uid = if (*wc).tcp {
+            web_tcp_uid((*wc).fd)
+        } else {
+            let mut uid: uid_t = 0;
+            let mut gid: gid_t = 0;
+            (getpeereid((*wc).fd, &raw mut uid, &raw mut gid) == 0).then_some(uid)
+        };
+        let user = match uid {
+            Some(uid) => server_acl_user_find(uid),
+            None => null_mut(),
+        };
+        if user.is_null() {
+            log_debug!("web connection {:p}: uid {:?} not allowed", wc, uid);
+            web_respond(wc, "403 Forbidden", "", "");
+            return;
+        }
+        (*wc).readonly = (*user)
+            .flags
+            .contains(server_acl_user_flags::SERVER_ACL_READONLY);
+        if !(*user).profile.is_null() {
+            (*wc).profile = Some(cstr_to_str((*user).profile).to_string());
+        }
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        match path {
+            "/" => web_respond(wc, "200 OK", "text/html; charset=utf-8", WEB_PAGE),
+            "/ws" => {
+                let upgrade =
+                    header("upgrade").is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+                let key = header("sec-websocket-key");
+                if !upgrade || header("sec-websocket-version") != Some("13") || key.is_none() {
+                    web_respond(wc, "400 Bad Request", "", "");
+                    return;
+                }
+                let response = format!(
+                    "HTTP/1.1 101 Switching Protocols\r\n\
+                     Upgrade: websocket\r\n\
+                     Connection: Upgrade\r\n\
+                     Sec-WebSocket-Accept: {}\r\n\r\n",
+                    web_accept_key(key.unwrap())
+                );
+                bufferevent_write(
+                    (*wc).event,
+                    response.as_bytes().as_ptr().cast(),
+                    response.len(),
+                );
+                (*wc).state = web_state::WEB_SOCKET;
+                web_attach(wc, web_query(query, "session").as_deref());
+            }
+            _ => web_respond(wc, "404 Not Found", "", ""),
+        }
+    }
+}
+
+/// Whether a Host header names this machine.
+fn web_local_host(host: &str) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !name.is_empty() && port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    };
+    matches!(name, "127.0.0.1" | "localhost" | "[::1]")
+}
+
+/// Find a parameter in a query string and undo its percent encoding.
+fn web_query(query: &str, name: &str) -> Option<String> {
+    let value = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))?;
+    let mut out = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                out.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => out.push(b),
+        }
+    }
+    String::from_utf8(out).ok().filter(|s| !s.is_empty())
+}
+
+/// The uid which owns the other end of a TCP connection, from the kernel's
+/// list of sockets.
+#[cfg(target_os = "linux")]
+unsafe fn web_tcp_uid(fd: c_int) -> Option<uid_t> {
+    unsafe {
+        let mut ours: sockaddr_in = zeroed();
+        let mut theirs: sockaddr_in = zeroed();
+        let mut slen = size_of::<sockaddr_in>() as socklen_t;
+        if getsockname(fd, &raw mut ours as _, &raw mut slen) != 0 {
+            return None;
+        }
+        let mut slen = size_of::<sockaddr_in>() as socklen_t;
+        if getpeername(fd, &raw mut theirs as _, &raw mut slen) != 0 {
+            return None;
+        }
+        let address = |sin: &sockaddr_in| {
+            format!(
+                "{:08X}:{:04X}",
+                sin.sin_addr.s_addr,
+                u16::from_be(sin.sin_port)
+            )
+        };
+        let (local, remote) = (address(&theirs), address(&ours));
+
+        let table = std::fs::read_to_string("/proc/net/tcp").ok()?;
+        table.lines().skip(1).find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() > 7 && fields[1] == local && fields[2] == remote {
+                fields[7].parse().ok()
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn web_tcp_uid(_fd: c_int) -> Option<uid_t> {
+    None
+}
+
+/// The Sec-WebSocket-Accept value for a key.
+fn web_accept_key(key: &str) -> String {
+    let digest = web_sha1(format!("{key}{WEB_GUID}").as_bytes());
+    let mut out = [0u8; 29];
+    let n = unsafe { b64_ntop(digest.as_ptr(), digest.len(), out.as_mut_ptr(), out.len()) };
+    String::from_utf8_lossy(&out[..n.max(0) as usize]).into_owned()
+}
+
+/// SHA-1, which is needed for the handshake and nothing else.
+fn web_sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes(block[i * 4..i * 4 + 4].try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => ((b & c) | (!b & d), 0x5a827999),
+                20..40 => (b ^ c ^ d, 0x6ed9eba1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (hi, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *hi = hi.wrapping_add(x);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (i, hi) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&hi.to_be_bytes());
+    }
+    digest
+}
+
+/// Parse a frame from a browser. Returns the number of bytes used, the
+/// opcode and the unmasked payload, None if the frame is not complete or the
+/// status to close with if it is not acceptable. Fragmented messages are not
+/// supported.
+fn web_frame_parse(buf: &[u8]) -> Result<Option<(usize, u8, Vec<u8>)>, u16> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let (fin, opcode) = (buf[0] & 0x80 != 0, buf[0] & 0xf);
+    if !fin || opcode == 0 {
+        return Err(WEB_CLOSE_UNSUPPORTED);
+    }
+    if buf[1] & 0x80 == 0 {
+        return Err(WEB_CLOSE_PROTOCOL);
+    }
+
+    let (mut used, size) = match buf[1] & 0x7f {
+        126 if buf.len() >= 4 => (4, u16::from_be_bytes([buf[2], buf[3]]) as u64),
+        127 if buf.len() >= 10 => (10, u64::from_be_bytes(buf[2..10].try_into().unwrap())),
+        126 | 127 => return Ok(None),
+        size => (2, size as u64),
+    };
+    if size > WEB_FRAME_MAX as u64 {
+        return Err(WEB_CLOSE_TOO_BIG);
+    }
+    let size = size as usize;
+    if buf.len() < used + 4 + size {
+        return Ok(None);
+    }
+    let mask = &buf[used..used + 4];
+    used += 4;
+    let payload = buf[used..used + size]
+        .iter()
+        .enumerate()
+        .map(|(i, b)| b ^ mask[i % 4])
+        .collect();
+    Ok(Some((used + size, opcode, payload)))
+}
+
+/// Build an unmasked frame.
+fn web_frame_build(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        size @ 0..126 => frame.push(size as u8),
+        size @ 126..65536 => {
+            frame.push(126);
+            frame.extend_from_slice(&(size as u16).to_be_bytes());
+        }
+        size => {
+            frame.push(127);
+            frame.extend_from_slice(&(size as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+unsafe fn web_send_frame(wc: *mut web_conn, opcode: u8, payload: &[u8]) {
+    unsafe {
+        let frame = web_frame_build(opcode, payload);
+        bufferevent_write((*wc).event, frame.as_ptr().cast(), frame.len());
+    }
+}
+
+unsafe fn web_send(wc: *mut web_conn, json: control_json) {
+    unsafe {
+        if (*wc).state == web_state::WEB_SOCKET {
+            web_send_frame(wc, WEB_OP_TEXT, json.finish().as_bytes());
+        }
+    }
+}
+
+unsafe fn web_send_error(wc: *mut web_conn, message: &str) {
+    unsafe { web_send(wc, control_json::new("error").string("message", message)) }
+}
+
+/// Close the WebSocket and stop the control client.
+unsafe fn web_close(wc: *mut web_conn, code: u16) {
+    unsafe {
+        if (*wc).state != web_state::WEB_SOCKET {
+            return;
+        }
+        log_debug!("web connection {:p}: closing ({})", wc, code);
+        web_send_frame(wc, WEB_OP_CLOSE, &code.to_be_bytes());
+        bufferevent_disable((*wc).event, EV_READ);
+        (*wc).state = web_state::WEB_CLOSING;
+
+        if !(*wc).job.is_null() {
+            job_free((*wc).job);
+            (*wc).job = null_mut();
+        }
+    }
+}
+
+unsafe fn web_frame(wc: *mut web_conn, opcode: u8, payload: &[u8]) {
+    unsafe {
+        match opcode {
+            WEB_OP_TEXT => match std::str::from_utf8(payload) {
+                Ok(message) => web_message(wc, message),
+                Err(_) => web_close(wc, WEB_CLOSE_UNSUPPORTED),
+            },
+            WEB_OP_PING => web_send_frame(wc, WEB_OP_PONG, payload),
+            WEB_OP_PONG => (),
+            WEB_OP_CLOSE => web_close(wc, WEB_CLOSE_NORMAL),
+            _ => web_close(wc, WEB_CLOSE_UNSUPPORTED),
+        }
+    }
+}
+
+/// Handle a message from the page: keys to send as hex bytes or a window or
+/// pane to select.
+unsafe fn web_message(wc: *mut web_conn, message: &str) {
+    unsafe {
+        let (name, arg) = message.split_once(' ').unwrap_or((message, ""));
+        let command = match name {
+            "keys" => {
+                if arg.len() % 2 != 0 || !arg.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return;
+                }
+                let bytes = (0..arg.len()).step_by(2).map(|i| &arg[i..i + 2]);
+                Command::new("send-keys").flag('H').args(bytes)
+            }
+            "select-window" | "select-pane" => {
+                let Ok(id) = arg.parse::<u32>() else {
+                    return;
+                };
+                let prefix = if name == "select-window" { '@' } else { '%' };
+                Command::new(name).option('t', format!("{prefix}{id}"))
+            }
+            _ => return,
+        };
+        if (*wc).readonly {
+            web_send_error(wc, "read-only");
+            return;
+        }
+        if (*wc).attached {
+            web_command(wc, &command, web_request::WEB_COMMAND);
+        }
+    }
+}
+
+/// Start a control client attached to a session, read-only if this user may
+/// not write.
+unsafe fn web_attach(wc: *mut web_conn, session: Option<&str>) {
+    unsafe {
+        let Ok(exe) = std::env::current_exe() else {
+            web_send_error(wc, "cannot find tmux-rs");
+            web_close(wc, WEB_CLOSE_NORMAL);
+            return;
+        };
+        let mut args = vec![
+            exe.into_os_string().into_encoded_bytes(),
+            b"-S".to_vec(),
+            CStr::from_ptr(SOCKET_PATH.cast()).to_bytes().to_vec(),
+            b"-C".to_vec(),
+            b"attach-session".to_vec(),
+        ];
+        if let Some(session) = session {
+            args.push(b"-t".to_vec());
+            args.push(session.as_bytes().to_vec());
+        }
+        if (*wc).readonly {
+            args.push(b"-r".to_vec());
+        }
+        let args: Vec<CString> = args
+            .into_iter()
+            .filter_map(|arg| CString::new(arg).ok())
+            .collect();
+        let mut argv: Vec<*mut u8> = args.iter().map(|arg| arg.as_ptr() as *mut u8).collect();
+
+        (*wc).job = job_run(
+            null(),
+            argv.len() as c_int,
+            argv.as_mut_ptr(),
+            null_mut(),
+            null_mut(),
+            null(),
+            Some(web_job_update),
+            Some(web_job_complete),
+            None,
+            wc.cast(),
+            job_flag::JOB_NOWAIT | job_flag::JOB_KEEPWRITE,
+            0,
+            0,
+        );
+        if (*wc).job.is_null() {
+            web_send_error(wc, "cannot start client");
+            web_close(wc, WEB_CLOSE_NORMAL);
+            return;
+        }
+
+        let mut json = control_json::new("hello");
+        json = match session {
+            Some(session) => json.string("session", session),
+            None => json.null("session"),
+        };
+        json = match &(*wc).profile {
+            Some(profile) => json.string("profile", profile),
+            None => json.null("profile"),
+        };
+        web_send(wc, json.boolean("readonly", (*wc).readonly));
+    }
+}
+
+unsafe fn web_job_complete(job: *mut job) {
+    unsafe {
+        let wc: *mut web_conn = job_get_data(job).cast();
+        (*wc).job = null_mut();
+        web_close(wc, WEB_CLOSE_NORMAL);
+    }
+}
+
+unsafe fn web_job_update(job: *mut job) {
+    unsafe {
+        let wc: *mut web_conn = job_get_data(job).cast();
+        let input = (*job_get_event(job)).input;
+
+        loop {
+            let mut size = 0;
+            let line = evbuffer_readln(input, &raw mut size, evbuffer_eol_style::EVBUFFER_EOL_LF);
+            if line.is_null() {
+                break;
+            }
+            let incoming = (*wc).client.receive(std::slice::from_raw_parts(line, size));
+            free_(line);
+
+            match incoming {
+                Ok(Some(Incoming::Event(event))) => web_event(wc, event),
+                Ok(Some(Incoming::Reply(tag, reply))) => {
+                    while let Some((t, request)) = (*wc).requests.pop_front() {
+                        if t == tag {
+                            web_reply(wc, request, reply);
+                            break;
+                        }
+                    }
+                }
+                Ok(Some(Incoming::Unsolicited(reply))) if reply.error => {
+                    web_send_error(wc, &reply.output.join("\n"));
+                }
+                Ok(_) => (),
+                Err(error) => log_debug!("web connection {:p}: {}", wc, error),
+            }
+            if (*wc).state != web_state::WEB_SOCKET {
+                break;
+            }
+        }
+    }
+}
+
+/// Send a command to the control client.
+unsafe fn web_command(wc: *mut web_conn, command: &Command, request: web_request) {
+    unsafe {
+        if (*wc).job.is_null() {
+            return;
+        }
+        let (tag, line) = (*wc).client.send(command);
+        bufferevent_write(
+            job_get_event((*wc).job),
+            line.as_bytes().as_ptr().cast(),
+            line.len(),
+        );
+        (*wc).requests.push_back((tag, request));
+    }
+}
+
+/// Ask for the windows and the panes in the current window again.
+unsafe fn web_refresh(wc: *mut web_conn) {
+    unsafe {
+        if !(*wc).attached || (*wc).refreshing {
+            return;
+        }
+        (*wc).refreshing = true;
+
+        let windows = Command::new("list-windows").option(
+            'F',
+            "#{window_id} #{window_index} #{window_active} #{window_name}",
+        );
+        web_command(wc, &windows, web_request::WEB_WINDOWS);
+        web_list_panes(wc, true);
+    }
+}
+
+unsafe fn web_list_panes(wc: *mut web_conn, capture: bool) {
+    unsafe {
+        let panes = Command::new("list-panes").option(
+            'F',
+            "#{pane_id} #{pane_left} #{pane_top} #{pane_width} #{pane_height} \
+             #{pane_active} #{cursor_x} #{cursor_y} #{cursor_flag}",
+        );
+        web_command(wc, &panes, web_request::WEB_PANES(capture));
+    }
+}
+
+unsafe fn web_event(wc: *mut web_conn, event: Event) {
+    unsafe {
+        match event {
+            Event::SessionChanged { .. } => {
+                // The client connects as the server user, so restrict it to
+                // the profile of the user on the other end before it does
+                // anything else.
+                if !(*wc).attached
+                    && let Some(profile) = (*wc).profile.clone()
+                {
+                    let access = Command::new("server-access").option('p', profile);
+                    web_command(wc, &access, web_request::WEB_COMMAND);
+                }
+                (*wc).attached = true;
+                web_refresh(wc);
+            }
+            Event::SessionWindowChanged { .. }
+            | Event::WindowAdd { .. }
+            | Event::WindowClose { .. }
+            | Event::WindowRenamed { .. }
+            | Event::WindowPaneChanged { .. }
+            | Event::LayoutChange { .. } => web_refresh(wc),
+            Event::Output { pane, .. }
+            | Event::ExtendedOutput { pane, .. }
+            | Event::PaneModeChanged { pane }
+                if (*wc).panes.contains(&pane) =>
+            {
+                (*wc).dirty.insert(pane);
+                web_schedule(wc);
+            }
+            Event::Exit { reason } => {
+                let json = control_json::new("exit");
+                web_send(
+                    wc,
+                    match reason {
+                        Some(reason) => json.string("reason", reason),
+                        None => json.null("reason"),
+                    },
+                );
+            }
+            _ => (),
+        }
+    }
+}
+
+unsafe fn web_reply(wc: *mut web_conn, request: web_request, reply: tmux_rs_control::Reply) {
+    unsafe {
+        if reply.error {
+            if matches!(request, web_request::WEB_COMMAND) {
+                web_send_error(wc, &reply.output.join("\n"));
+            }
+            if matches!(request, web_request::WEB_PANES(true)) {
+                (*wc).refreshing = false;
+            }
+            return;
+        }
+
+        match request {
+            web_request::WEB_WINDOWS => {
+                let windows: Vec<String> = reply
+                    .output
+                    .iter()
+                    .filter_map(|line| {
+                        let mut fields = line.splitn(4, ' ');
+                        let id = fields.next()?.strip_prefix('@')?.parse::<u32>().ok()?;
+                        let index = fields.next()?.parse::<u32>().ok()?;
+                        let active = fields.next()? == "1";
+                        let name = fields.next().unwrap_or("");
+                        Some(
+                            control_json::object()
+                                .number("id", id)
+                                .number("index", index)
+                                .boolean("active", active)
+                                .string("name", name)
+                                .finish(),
+                        )
+                    })
+                    .collect();
+                let json = control_json::new("windows");
+                web_send(wc, json.raw("windows", &format!("[{}]", windows.join(","))));
+            }
+            web_request::WEB_PANES(capture) => {
+                let mut ids = Vec::new();
+                let panes: Vec<String> = reply
+                    .output
+                    .iter()
+                    .filter_map(|line| {
+                        let mut fields = line.split(' ');
+                        let id = fields.next()?.strip_prefix('%')?.parse::<u32>().ok()?;
+                        let mut n = [0u32; 8];
+                        for v in &mut n {
+                            *v = fields.next()?.parse().ok()?;
+                        }
+                        ids.push(id);
+                        Some(
+                            control_json::object()
+                                .number("id", id)
+                                .number("left", n[0])
+                                .number("top", n[1])
+                                .number("width", n[2])
+                                .number("height", n[3])
+                                .boolean("active", n[4] != 0)
+                                .number("cursor_x", n[5])
+                                .number("cursor_y", n[6])
+                                .boolean("cursor", n[7] != 0)
+                                .finish(),
+                        )
+                    })
+                    .collect();
+                let json = control_json::new("panes");
+                web_send(wc, json.raw("panes", &format!("[{}]", panes.join(","))));
+
+                (*wc).panes = ids;
+                if capture {
+                    (*wc).refreshing = false;
+                    for id in (*wc).panes.clone() {
+                        let capture = Command::new("capture-pane")
+                            .flag('p')
+                            .option('F', "json")
+                            .option('t', format!("%{id}"));
+                        web_command(wc, &capture, web_request::WEB_SNAPSHOT(id));
+                    }
+                    (*wc).dirty.clear();
+                }
+            }
+            web_request::WEB_SNAPSHOT(pane) => {
+                let json = control_json::new("snapshot").number("pane", pane);
+                web_send(
+                    wc,
+                    json.raw("lines", &format!("[{}]", reply.output.join(","))),
+                );
+            }
+            web_request::WEB_COMMAND => (),
+        }
+    }
+}
+
+/// Capture the panes with new output after a short delay, so a burst of
+/// output is drawn once.
+unsafe fn web_schedule(wc: *mut web_conn) {
+    unsafe {
+        let tv = timeval {
+            tv_sec: 0,
+            tv_usec: WEB_DELAY * 1000,
+        };
+        if !evtimer_initialized(&raw mut (*wc).timer) {
+            evtimer_set(&raw mut (*wc).timer, web_timer, NonNull::new_unchecked(wc));
+        }
+        if evtimer_pending(&raw mut (*wc).timer, null_mut()) == 0 {
+            evtimer_add(&raw mut (*wc).timer, &raw const tv);
+        }
+    }
+}
+
+unsafe extern "C-unwind" fn web_timer(_fd: c_int, _events: c_short, wc: NonNull<web_conn>) {
+    unsafe {
+        let wc = wc.as_ptr();
+
+        // Wait for earlier snapshots to be sent before asking for more, so a
+        // pane with constant output cannot get ahead of the browser.
+        if !(*wc).requests.is_empty() {
+            web_schedule(wc);
+            return;
+        }
+        for id in std::mem::take(&mut (*wc).dirty) {
+            let capture = Command::new("capture-pane")
+                .flag('p')
+                .option('F', "json")
+                .option('t', format!("%{id}"));
+            web_command(wc, &capture, web_request::WEB_SNAPSHOT(id));
+        }
+        web_list_panes(wc, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_web_accept_key() {
+        // The example from RFC 6455.
+        assert_eq!(
+            web_accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_web_frame() {
+        // A masked "Hello" from RFC 6455.
+        let frame = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        assert_eq!(
+            web_frame_parse(&frame),
+            Ok(Some((11, WEB_OP_TEXT, b"Hello".to_vec())))
+        );
+        assert_eq!(web_frame_parse(&frame[..8]), Ok(None));
+        assert_eq!(
+            web_frame_parse(&[0x81, 0x05, b'H', b'e', b'l', b'l', b'o']),
+            Err(WEB_CLOSE_PROTOCOL)
+        );
+        assert_eq!(web_frame_build(WEB_OP_TEXT, b"Hello")[..2], [0x81, 0x05]);
+        assert_eq!(
+            web_frame_build(WEB_OP_TEXT, &[0; 300])[..4],
+            [0x81, 126, 1, 44]
+        );
+    }
+
+    #[test]
+    fn test_web_request() {
+        assert!(web_local_host("127.0.0.1:8080"));
+        assert!(web_local_host("localhost"));
+        assert!(web_local_host("[::1]:80"));
+        assert!(!web_local_host("127.0.0.1.example.com:80"));
+        assert_eq!(
+            web_query("a=1&session=my%20session", "session").as_deref(),
+            Some("my session")
+        );
+        assert_eq!(web_query("session=", "session"), None);
+    }
+}
@@ -14,7 +14,8 @@
 use crate::compat::HOST_NAME_MAX;
 use crate::libc::{
     FIONREAD, FNM_CASEFOLD, TIOCSWINSZ, close, fnmatch, free, gethostname, gettimeofday, ioctl,
-    isspace, memset, regcomp, regex_t, regexec, regfree, strlen, winsize,
+    REG_EXTENDED, REG_ICASE, REG_NOSUB, isspace, memset, strlen,
+    winsize,
 };
 #[cfg(feature = "utempter")]
 use crate::utempter::utempter_remove_record;
@@ -982,6 +983,41 @@ pub unsafe fn window_pane_find_by_id_str(s: &str) -> *mut window_pane {
     }
 }
 
+/// Is a host reported by an application this host?
+pub unsafe fn window_pane_host_is_local(host: *const u8) -> bool {
+    unsafe {
+        let mut local: [u8; HOST_NAME_MAX + 1] = zeroed();
+        let host = cstr_to_str(host);
+        if host.is_empty() || host.eq_ignore_ascii_case("localhost") {
+            return true;
+        }
+        if gethostname(local.as_mut_ptr(), size_of_val(&local)) != 0 {
+            return false;
+        }
+        let local = cstr_to_str(local.as_ptr());
+        host.eq_ignore_ascii_case(local)
+            || local
+                .split('.')
+                .next()
+                .is_some_and(|short| host.eq_ignore_ascii_case(short))
+    }
+}
+
+/// Get the current working directory of a pane. The directory reported by the
+/// application is used if it is on this host and exists, otherwise it is
+/// looked up from the foreground process.
+pub unsafe fn window_pane_get_cwd(wp: *mut window_pane) -> *const u8 {
+    unsafe {
+        if !(*wp).reported_cwd.is_null()
+            && window_pane_host_is_local((*wp).reported_host)
+            && std::fs::metadata(cstr_to_str((*wp).reported_cwd)).is_ok_and(|md| md.is_dir())
+        {
+            return (*wp).reported_cwd;
+        }
+        osdep_get_cwd((*wp).fd)
+    }
+}
+
 pub unsafe fn window_pane_find_by_id(id: u32) -> *mut window_pane {
     unsafe {
         let mut wp: window_pane = zeroed();
@@ -1020,6 +1056,8 @@ pub unsafe fn window_pane_create(
 
         (*wp).pipe_fd = -1;
 
+        (*wp).command_status = -1;
+
         (*wp).control_bg = -1;
         (*wp).control_fg = -1;
 
@@ -1027,6 +1065,7 @@ pub unsafe fn window_pane_create(
         colour_palette_from_option(Some(&mut (*wp).palette), (*wp).options);
 
         screen_init(&raw mut (*wp).base, sx, sy, hlimit);
+        grid_index_create((*wp).base.grid);
         (*wp).screen = &raw mut (*wp).base;
         window_pane_default_cursor(wp);
 
@@ -1069,6 +1108,9 @@ unsafe fn window_pane_destroy(wp: *mut window_pane) {
         if event_initialized(&raw mut (*wp).resize_timer) != 0 {
             event_del(&raw mut (*wp).resize_timer);
         }
+        if event_initialized(&raw mut (*wp).sync_timer) != 0 {
+            event_del(&raw mut (*wp).sync_timer);
+        }
         for r in tailq_foreach(&raw mut (*wp).resize_queue).map(NonNull::as_ptr) {
             tailq_remove::<_, ()>(&raw mut (*wp).resize_queue, r);
             free_(r);
@@ -1078,7 +1120,11 @@ unsafe fn window_pane_destroy(wp: *mut window_pane) {
 
         options_free((*wp).options);
         free((*wp).cwd as _);
+        free((*wp).reported_host as _);
+        free((*wp).reported_cwd as _);
         free((*wp).shell as _);
+        free_((*wp).notification_title);
+        free_((*wp).notification_body);
         cmd_free_argv((*wp).argc, (*wp).argv);
         colour_palette_free(Some(&mut (*wp).palette));
         free(wp as _);
@@ -1107,6 +1153,8 @@ unsafe extern "C-unwind" fn window_pane_read_callback(_bufev: *mut bufferevent,
                 control_write_output(c, wp);
             }
         }
+        // Lines written by the input are marked with the current time.
+        CURRENT_TIME = libc::time(null_mut());
         input_parse_pane(wp);
         bufferevent_disable((*wp).event, EV_READ);
     }
@@ -1301,7 +1349,10 @@ pub unsafe fn window_pane_key(
             && let Some(key_fn) = (*(*wme.as_ptr()).mode).key
             && !c.is_null()
         {
-            key &= !KEYC_MASK_FLAGS;
+            if (key & KEYC_RELEASE) != 0 {
+                return 0;
+            }
+            key &= !(KEYC_MASK_FLAGS | KEYC_MASK_EVENT);
             key_fn(wme, c, s, wl, key, m);
             return 0;
         }
@@ -1346,7 +1397,7 @@ pub unsafe fn window_pane_search(
 ) -> u32 {
     unsafe {
         let s: *mut screen = &raw mut (*wp).base;
-        let mut r: regex_t = zeroed();
+        let mut r = None;
         let mut new: *mut u8 = null_mut();
         let mut flags = 0;
 
@@ -1359,14 +1410,14 @@ pub unsafe fn window_pane_search(
             if ignore != 0 {
                 flags |= REG_ICASE;
             }
-            if regcomp(&raw mut r, term, flags | REG_EXTENDED) != 0 {
+            r = regexp_compile(term, flags | REG_EXTENDED);
+            if r.is_none() {
                 return 0;
             }
         }
 
         let mut i = 0;
-        for j in 0..screen_size_y(s) {
-            i = j;
+        while i < screen_size_y(s) {
 
             let line = grid_view_string_cells((*s).grid, 0, i, screen_size_x(s));
             for n in (1..=strlen(line)).rev() {
@@ -1380,19 +1431,18 @@ pub unsafe fn window_pane_search(
             let found = if regex == 0 {
                 fnmatch(new, line, flags) == 0
             } else {
-                regexec(&r, line, 0, null_mut(), 0) == 0
+                r.as_ref().is_some_and(|r| regexp_exec(r, line, 0, &mut []))
             };
             free(line as _);
 
             if found {
                 break;
             }
+            i += 1;
         }
 
         if regex == 0 {
             free(new as _);
-        } else {
-            regfree(&raw mut r);
         }
 
         if i == screen_size_y(s) {
@@ -1403,6 +1453,103 @@ pub unsafe fn window_pane_search(
     }
 }
 
+/// Search the history of a pane, including any history spilled to disk, for
+/// lines containing a term or matching a regular expression. Wrapped lines are
+/// joined and lines the history index shows cannot match are skipped. The
+/// callback is given the line, the size of the history and the text of each
+/// line that matches. Returns false if the regular expression is invalid.
+pub unsafe fn window_pane_search_history(
+    wp: *mut window_pane,
+    term: *const u8,
+    regex: bool,
+    ignore: bool,
+    cb: &mut dyn FnMut(u32, u32, &CStr),
+) -> bool {
+    unsafe {
+        let text = CStr::from_ptr(term.cast()).to_bytes();
+        let mut r = None;
+
+        let mask = if regex {
+            let mut flags = REG_EXTENDED | REG_NOSUB;
+            if ignore {
+                flags |= REG_ICASE;
+            }
+            r = regexp_compile(term, flags);
+            if r.is_none() {
+                return false;
+            }
+            grid_index_regex_mask(text, ignore)
+        } else {
+            grid_index_mask(text, ignore)
+        };
+        let needle = if ignore {
+            text.to_ascii_lowercase()
+        } else {
+            text.to_vec()
+        };
+        let matches = |line: &CStr| -> bool {
+            if let Some(r) = &r {
+                return regexp_exec(r, line.as_ptr().cast(), 0, &mut []);
+            }
+            if needle.is_empty() {
+                return true;
+            }
+            let line = if ignore {
+                line.to_bytes().to_ascii_lowercase()
+            } else {
+                line.to_bytes().to_vec()
+            };
+            line.windows(needle.len()).any(|w| w == needle)
+        };
+
+        let base = (*wp).base.grid;
+        let view = grid_index_view(base);
+        let gd = if grid_spill_size(base) != 0 {
+            grid_spill_expand(base)
+        } else {
+            base
+        };
+        let total = (*gd).hsize + (*gd).sy;
+
+        let mut py = 0;
+        while py < total {
+            let mut end = py;
+            while end + 1 < total
+                && (*grid_get_line_compressed(gd, end))
+                    .flags
+                    .intersects(grid_line_flag::WRAPPED)
+            {
+                end += 1;
+            }
+
+            if mask
+                .as_ref()
+                .is_none_or(|mask| grid_index_check(base, &view, gd, py, mask))
+            {
+                // Lines are read without expanding them, so searching
+                // does not uncompress the whole history.
+                let mut text = Vec::new();
+                for row in py..=end {
+                    grid_peek_text(gd, 0, row, (*gd).sx, &mut text);
+                }
+                while text.last() == Some(&b' ') {
+                    text.pop();
+                }
+                let line = CString::new(text).unwrap_or_default();
+                if matches(&line) {
+                    cb(py, (*gd).hsize, &line);
+                }
+            }
+            py = end + 1;
+        }
+
+        if gd != base {
+            grid_destroy(gd);
+        }
+        true
+    }
+}
+
 /// Get MRU pane from a list.
 unsafe fn window_pane_choose_best(list: *mut *mut window_pane, size: u32) -> *mut window_pane {
     if size == 0 {
@@ -11,6 +11,8 @@
 // WHATSOEVER RESULTING FROM LOSS OF MIND, USE, DATA OR PROFITS, WHETHER
 // IN AN ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING
 // OUT OF OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+use std::collections::{BTreeMap, BTreeSet};
+
 use crate::options_::*;
 use crate::*;
 
@@ -109,6 +111,51 @@ enum cursordrag {
     CURSORDRAG_SEL,
 }
 
+/// A fuzzy match of the search string: the first line of the match, the cells
+/// of each matched character and its score.
+struct window_copy_fuzzy_match {
+    py: u32,
+    cells: Vec<(u32, u32)>,
+    score: i32,
+}
+
+/// The results of a fuzzy search, best match first.
+struct window_copy_fuzzy {
+    matches: Vec<window_copy_fuzzy_match>,
+    current: usize,
+}
+
+/// What to do with the text of a hint once its label is typed.
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum window_copy_hint_action {
+    WINDOW_COPY_HINT_COPY,
+    WINDOW_COPY_HINT_PIPE,
+    WINDOW_COPY_HINT_RUN,
+}
+
+/// A match of one of the hint patterns and the label that picks it.
+struct window_copy_hint {
+    label: String,
+    text: Vec<u8>,
+    cells: Vec<(u32, u32)>,
+}
+
+/// The hints on the visible lines, the label typed so far and which hint and
+/// character of it covers each cell.
+struct window_copy_hints {
+    hints: Vec<window_copy_hint>,
+    typed: String,
+    cells: BTreeMap<(u32, u32), (usize, usize)>,
+}
+
+/// The prompt reading a hint label. The pane is looked up again for each key
+/// because it may have gone or left copy mode.
+struct window_copy_hint_prompt {
+    pane: u32,
+    action: window_copy_hint_action,
+    arg: Option<CString>,
+}
+
 #[repr(i32)]
 #[derive(Copy, Clone, Eq, PartialEq)]
 enum line_sel {
@@ -161,6 +208,12 @@ pub struct window_copy_mode_data {
     rectflag: bool,      // in rectangle copy mode?
     scroll_exit: bool,   // exit on scroll to end?
     hide_position: bool, // hide position marker
+    timestamps: bool,    // show line times
+
+    /// pane the backing was copied from and the lines of the backing in its
+    /// history index
+    index_pane: u32,
+    index_view: grid_index_view,
 
     selflag: selflag,
 
@@ -203,6 +256,10 @@ pub struct window_copy_mode_data {
     searchy: i32,
     searcho: i32,
     searchgen: u8,
+    /// ranked matches of a fuzzy search
+    fuzzy: Option<Box<window_copy_fuzzy>>,
+    /// labelled matches shown by the hints command
+    hints: Option<Box<window_copy_hints>>,
 
     /// search has timed out
     timeout: i32,
@@ -269,15 +326,19 @@ pub unsafe fn window_copy_clone_screen(
             }
         }
         // log_debug( "%s: target screen is %ux%u, source %ux%u", __func__, screen_size_x(src), sy, screen_size_x(hint), screen_hsize(src) + screen_size_y(src),);
-        screen_init(dst, screen_size_x(src), sy, screen_hlimit(src));
+        let spilled = grid_spill_size((*src).grid);
+        screen_init(dst, screen_size_x(src), spilled + sy, screen_hlimit(src));
 
         // Ensure history is on for the backing grid so lines are not deleted
         // during resizing.
         (*(*dst).grid).flags |= GRID_HISTORY;
-        grid_duplicate_lines((*dst).grid, 0, (*src).grid, 0, sy);
+
+        // Put any history spilled to disk before the history in memory.
+        let spilled = grid_spill_load((*src).grid, (*dst).grid, 0);
+        grid_duplicate_lines((*dst).grid, spilled, (*src).grid, 0, sy);
 
         (*(*dst).grid).sy = sy - screen_hsize(src);
-        (*(*dst).grid).hsize = screen_hsize(src);
+        (*(*dst).grid).hsize = spilled + screen_hsize(src);
         (*(*dst).grid).hscrolled = (*(*src).grid).hscrolled;
         if (*src).cy > (*(*dst).grid).sy - 1 {
             (*dst).cx = 0;
@@ -344,6 +405,8 @@ pub unsafe fn window_copy_common_init(wme: *mut window_mode_entry) -> *mut windo
         (*data).modekeys =
             modekey::try_from(options_get_number_((*(*wp).window).options, "mode-keys") as i32)
                 .expect("invalid modekey");
+        (*data).timestamps =
+            options_get_number_((*(*wp).window).options, "copy-mode-timestamps") != 0;
 
         evtimer_set(
             &raw mut (*data).dragtimer,
@@ -355,6 +418,19 @@ pub unsafe fn window_copy_common_init(wme: *mut window_mode_entry) -> *mut windo
     }
 }
 
+/// Remember the pane the backing was copied from so its history index can be
+/// used for searching, unless the backing was reflowed.
+unsafe fn window_copy_set_index(data: *mut window_copy_mode_data, wp: *mut window_pane) {
+    unsafe {
+        (*data).index_pane = (*wp).id;
+        if screen_size_x(&raw mut (*wp).base) == screen_size_x((*data).backing) {
+            (*data).index_view = grid_index_view((*wp).base.grid);
+        } else {
+            (*data).index_view = grid_index_view::default();
+        }
+    }
+}
+
 pub unsafe fn window_copy_init(
     wme: NonNull<window_mode_entry>,
     _fs: *mut cmd_find_state,
@@ -376,6 +452,7 @@ pub unsafe fn window_copy_init(
             &raw mut cy,
             ((*wme).swp != (*wme).wp) as i32,
         );
+        window_copy_set_index(data, wp);
 
         (*data).cx = cx;
         if cy < screen_hsize((*data).backing) {
@@ -447,6 +524,8 @@ pub unsafe fn window_copy_free(wme: NonNull<window_mode_entry>) {
         free_((*data).searchmark);
         free_((*data).searchstr);
         free_((*data).jumpchar);
+        (*data).fuzzy = None;
+        (*data).hints = None;
 
         if !(*data).writing.is_null() {
             screen_free((*data).writing);
@@ -836,10 +915,13 @@ pub unsafe fn window_copy_size_changed(wme: *mut window_mode_entry) {
         let data: *mut window_copy_mode_data = (*wme).data.cast();
         let s: *mut screen = &raw mut (*data).screen;
         let mut ctx: screen_write_ctx = zeroed();
-        let search = !(*data).searchmark.is_null();
+        let search = !(*data).searchmark.is_null() && (*data).fuzzy.is_none();
 
+        // The lines of any fuzzy matches or hints may have moved.
         window_copy_clear_selection(wme);
         window_copy_clear_marks(wme);
+        (*data).fuzzy = None;
+        (*data).hints = None;
 
         screen_write_start(&raw mut ctx, s);
         window_copy_write_lines(wme, &raw mut ctx, 0, screen_size_y(s));
@@ -874,6 +956,7 @@ pub unsafe fn window_copy_resize(wme: NonNull<window_mode_entry>, sx: u32, sy: u
         screen_resize_cursor((*data).backing, sx, sy, 1, 0, 0);
         if reflow {
             grid_unwrap_position(gd, &raw mut cx, &raw mut cy, wx, wy);
+            (*data).index_view = grid_index_view::default();
         }
 
         (*data).cx = cx;
@@ -1491,6 +1574,18 @@ pub unsafe fn window_copy_cmd_toggle_position(
     }
 }
 
+pub unsafe fn window_copy_cmd_toggle_timestamps(
+    cs: *mut window_copy_cmd_state,
+) -> window_copy_cmd_action {
+    unsafe {
+        let wme: *mut window_mode_entry = (*cs).wme;
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+
+        (*data).timestamps = !(*data).timestamps;
+        window_copy_cmd_action::WINDOW_COPY_CMD_REDRAW
+    }
+}
+
 pub unsafe fn window_copy_cmd_history_bottom(
     cs: *mut window_copy_cmd_state,
 ) -> window_copy_cmd_action {
@@ -2156,7 +2251,9 @@ pub unsafe fn window_copy_cmd_search_again(
         let data: *mut window_copy_mode_data = (*wme).data.cast();
         let mut np = (*wme).prefix;
 
-        if (*data).searchtype == window_copy::WINDOW_COPY_SEARCHUP {
+        if (*data).fuzzy.is_some() {
+            window_copy_fuzzy_next(wme, np as i32);
+        } else if (*data).searchtype == window_copy::WINDOW_COPY_SEARCHUP {
             while np != 0 {
                 window_copy_search_up(wme, (*data).searchregex);
                 np -= 1;
@@ -2179,7 +2276,9 @@ pub unsafe fn window_copy_cmd_search_reverse(
         let data: *mut window_copy_mode_data = (*wme).data.cast();
         let mut np = (*wme).prefix;
 
-        if (*data).searchtype == window_copy::WINDOW_COPY_SEARCHUP {
+        if (*data).fuzzy.is_some() {
+            window_copy_fuzzy_next(wme, -(np as i32));
+        } else if (*data).searchtype == window_copy::WINDOW_COPY_SEARCHUP {
             while np != 0 {
                 window_copy_search_down(wme, (*data).searchregex);
                 np -= 1;
@@ -2423,6 +2522,18 @@ pub unsafe fn window_copy_cmd_goto_line(cs: *mut window_copy_cmd_state) -> windo
     }
 }
 
+pub unsafe fn window_copy_cmd_goto_time(cs: *mut window_copy_cmd_state) -> window_copy_cmd_action {
+    unsafe {
+        let wme: *mut window_mode_entry = (*cs).wme;
+        let arg1 = args_string((*cs).args, 1);
+
+        if *arg1 != b'\0' {
+            window_copy_goto_time(wme, arg1);
+        }
+        window_copy_cmd_action::WINDOW_COPY_CMD_NOTHING
+    }
+}
+
 pub unsafe fn window_copy_cmd_jump_backward(
     cs: *mut window_copy_cmd_state,
 ) -> window_copy_cmd_action {
@@ -2546,6 +2657,57 @@ pub unsafe fn window_copy_cmd_previous_prompt(
     }
 }
 
+pub unsafe fn window_copy_cmd_select_command_output(
+    cs: *mut window_copy_cmd_state,
+) -> window_copy_cmd_action {
+    unsafe {
+        let wme: *mut window_mode_entry = (*cs).wme;
+
+        if !window_copy_select_command_output(wme) {
+            return window_copy_cmd_action::WINDOW_COPY_CMD_NOTHING;
+        }
+        window_copy_cmd_action::WINDOW_COPY_CMD_REDRAW
+    }
+}
+
+pub unsafe fn window_copy_cmd_copy_command_output(
+    cs: *mut window_copy_cmd_state,
+) -> window_copy_cmd_action {
+    unsafe {
+        let wme: *mut window_mode_entry = (*cs).wme;
+        let c: *mut client = (*cs).c;
+        let s: *mut session = (*cs).s;
+        let wl: *mut winlink = (*cs).wl;
+        let wp: *mut window_pane = (*wme).wp;
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let mut prefix = null_mut();
+        let arg1 = args_string((*cs).args, 1);
+
+        let ocx = (*data).cx;
+        let ocy = (*data).cy;
+        let ooy = (*data).oy;
+
+        if !window_copy_select_command_output(wme) {
+            return window_copy_cmd_action::WINDOW_COPY_CMD_NOTHING;
+        }
+
+        if !arg1.is_null() {
+            prefix = format_single(null_mut(), cstr_to_str(arg1), c, s, wl, wp);
+        }
+        if !s.is_null() {
+            window_copy_copy_selection(wme, prefix);
+        }
+        window_copy_clear_selection(wme);
+
+        (*data).cx = ocx;
+        (*data).cy = ocy;
+        (*data).oy = ooy;
+
+        free_(prefix);
+        window_copy_cmd_action::WINDOW_COPY_CMD_REDRAW
+    }
+}
+
 pub unsafe fn window_copy_cmd_search_backward(
     cs: *mut window_copy_cmd_state,
 ) -> window_copy_cmd_action {
@@ -2646,6 +2808,99 @@ pub unsafe fn window_copy_cmd_search_forward_text(
     }
 }
 
+pub unsafe fn window_copy_cmd_search_backward_fuzzy(
+    cs: *mut window_copy_cmd_state,
+) -> window_copy_cmd_action {
+    unsafe {
+        let wme: *mut window_mode_entry = (*cs).wme;
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+
+        if !window_copy_expand_search_string(cs) {
+            return window_copy_cmd_action::WINDOW_COPY_CMD_NOTHING;
+        }
+
+        if !(*data).searchstr.is_null() {
+            (*data).searchtype = window_copy::WINDOW_COPY_SEARCHUP;
+            (*data).searchregex = 0;
+            (*data).timeout = 0;
+            window_copy_search_fuzzy(wme, 0);
+        }
+        window_copy_cmd_action::WINDOW_COPY_CMD_NOTHING
+    }
+}
+
+pub unsafe fn window_copy_cmd_hints(cs: *mut window_copy_cmd_state) -> window_copy_cmd_action {
+    unsafe {
+        let wme: *mut window_mode_entry = (*cs).wme;
+        let c: *mut client = (*cs).c;
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let argc = args_count((*cs).args);
+
+        let action = if argc < 2 {
+            window_copy_hint_action::WINDOW_COPY_HINT_COPY
+        } else {
+            match cstr_to_str(args_string((*cs).args, 1)) {
+                "copy" => window_copy_hint_action::WINDOW_COPY_HINT_COPY,
+                "pipe" => window_copy_hint_action::WINDOW_COPY_HINT_PIPE,
+                "run" => window_copy_hint_action::WINDOW_COPY_HINT_RUN,
+                _ => return window_copy_cmd_action::WINDOW_COPY_CMD_NOTHING,
+            }
+        };
+        let arg = if argc > 2 {
+            Some(CStr::from_ptr(args_string((*cs).args, 2).cast()).to_owned())
+        } else {
+            None
+        };
+        if c.is_null() {
+            return window_copy_cmd_action::WINDOW_COPY_CMD_NOTHING;
+        }
+
+        window_copy_hints(wme);
+        if (*data).hints.is_none() {
+            return window_copy_cmd_action::WINDOW_COPY_CMD_REDRAW;
+        }
+
+        let prompt = Box::new(window_copy_hint_prompt {
+            pane: (*(*wme).wp).id,
+            action,
+            arg,
+        });
+        status_prompt_set(
+            c,
+            null_mut(),
+            c!("(hint) "),
+            c!(""),
+            window_copy_hint_callback,
+            window_copy_hint_free,
+            Box::into_raw(prompt),
+            prompt_flags::PROMPT_NOFORMAT | prompt_flags::PROMPT_INCREMENTAL,
+            prompt_type::PROMPT_TYPE_COMMAND,
+        );
+        window_copy_cmd_action::WINDOW_COPY_CMD_REDRAW
+    }
+}
+
+pub unsafe fn window_copy_cmd_search_forward_fuzzy(
+    cs: *mut window_copy_cmd_state,
+) -> window_copy_cmd_action {
+    unsafe {
+        let wme: *mut window_mode_entry = (*cs).wme;
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+
+        if !window_copy_expand_search_string(cs) {
+            return window_copy_cmd_action::WINDOW_COPY_CMD_NOTHING;
+        }
+
+        if !(*data).searchstr.is_null() {
+            (*data).searchtype = window_copy::WINDOW_COPY_SEARCHDOWN;
+            (*data).searchregex = 0;
+            (*data).timeout = 0;
+            window_copy_search_fuzzy(wme, 1);
+        }
+        window_copy_cmd_action::WINDOW_COPY_CMD_NOTHING
+    }
+}
+
 pub unsafe fn window_copy_cmd_search_backward_incremental(
     cs: *mut window_copy_cmd_state,
 ) -> window_copy_cmd_action {
@@ -2781,6 +3036,7 @@ pub unsafe fn window_copy_cmd_refresh_from_pane(
             null_mut(),
             ((*wme).swp != (*wme).wp) as i32,
         );
+        window_copy_set_index(data, wp);
 
         window_copy_size_changed(wme);
         window_copy_cmd_action::WINDOW_COPY_CMD_REDRAW
@@ -2796,7 +3052,7 @@ struct window_copy_cmd_table_entry {
     f: unsafe fn(*mut window_copy_cmd_state) -> window_copy_cmd_action,
 }
 
-static WINDOW_COPY_CMD_TABLE: [window_copy_cmd_table_entry; 85] = [
+static WINDOW_COPY_CMD_TABLE: [window_copy_cmd_table_entry; 92] = [
     window_copy_cmd_table_entry {
         command: "append-selection",
         minargs: 0,
@@ -2993,6 +3249,13 @@ static WINDOW_COPY_CMD_TABLE: [window_copy_cmd_table_entry; 85] = [
         clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_EMACS_ONLY,
         f: window_copy_cmd_goto_line,
     },
+    window_copy_cmd_table_entry {
+        command: "goto-time",
+        minargs: 1,
+        maxargs: 1,
+        clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_EMACS_ONLY,
+        f: window_copy_cmd_goto_time,
+    },
     window_copy_cmd_table_entry {
         command: "halfpage-down",
         minargs: 0,
@@ -3014,6 +3277,13 @@ static WINDOW_COPY_CMD_TABLE: [window_copy_cmd_table_entry; 85] = [
         clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_EMACS_ONLY,
         f: window_copy_cmd_halfpage_up,
     },
+    window_copy_cmd_table_entry {
+        command: "hints",
+        minargs: 0,
+        maxargs: 2,
+        clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_ALWAYS,
+        f: window_copy_cmd_hints,
+    },
     window_copy_cmd_table_entry {
         command: "history-bottom",
         minargs: 0,
@@ -3091,6 +3361,20 @@ static WINDOW_COPY_CMD_TABLE: [window_copy_cmd_table_entry; 85] = [
         clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_ALWAYS,
         f: window_copy_cmd_previous_prompt,
     },
+    window_copy_cmd_table_entry {
+        command: "select-command-output",
+        minargs: 0,
+        maxargs: 0,
+        clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_ALWAYS,
+        f: window_copy_cmd_select_command_output,
+    },
+    window_copy_cmd_table_entry {
+        command: "copy-command-output",
+        minargs: 0,
+        maxargs: 1,
+        clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_ALWAYS,
+        f: window_copy_cmd_copy_command_output,
+    },
     window_copy_cmd_table_entry {
         command: "middle-line",
         minargs: 0,
@@ -3301,6 +3585,13 @@ static WINDOW_COPY_CMD_TABLE: [window_copy_cmd_table_entry; 85] = [
         clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_ALWAYS,
         f: window_copy_cmd_search_backward,
     },
+    window_copy_cmd_table_entry {
+        command: "search-backward-fuzzy",
+        minargs: 0,
+        maxargs: 1,
+        clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_ALWAYS,
+        f: window_copy_cmd_search_backward_fuzzy,
+    },
     window_copy_cmd_table_entry {
         command: "search-backward-text",
         minargs: 0,
@@ -3322,6 +3613,13 @@ static WINDOW_COPY_CMD_TABLE: [window_copy_cmd_table_entry; 85] = [
         clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_ALWAYS,
         f: window_copy_cmd_search_forward,
     },
+    window_copy_cmd_table_entry {
+        command: "search-forward-fuzzy",
+        minargs: 0,
+        maxargs: 1,
+        clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_ALWAYS,
+        f: window_copy_cmd_search_forward_fuzzy,
+    },
     window_copy_cmd_table_entry {
         command: "search-forward-text",
         minargs: 0,
@@ -3385,6 +3683,13 @@ static WINDOW_COPY_CMD_TABLE: [window_copy_cmd_table_entry; 85] = [
         clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_NEVER,
         f: window_copy_cmd_toggle_position,
     },
+    window_copy_cmd_table_entry {
+        command: "toggle-timestamps",
+        minargs: 0,
+        maxargs: 0,
+        clear: window_copy_cmd_clear::WINDOW_COPY_CMD_CLEAR_NEVER,
+        f: window_copy_cmd_toggle_timestamps,
+    },
     window_copy_cmd_table_entry {
         command: "top-line",
         minargs: 0,
@@ -3556,8 +3861,7 @@ pub unsafe fn window_copy_search_lr(
         let endline = (*gd).hsize + (*gd).sy - 1;
         for ax in first..last {
             let mut bx = 0;
-            for bx_ in 0..(*sgd).sx {
-                bx = bx_;
+            while bx < (*sgd).sx {
                 let mut px = ax + bx;
                 let mut pywrap = py;
                 // Wrap line.
@@ -3577,6 +3881,7 @@ pub unsafe fn window_copy_search_lr(
                 if !matched {
                     break;
                 }
+                bx += 1;
             }
             if bx == (*sgd).sx {
                 *ppx = ax;
@@ -3603,8 +3908,7 @@ pub unsafe fn window_copy_search_rl(
         let mut ax = last;
         while ax > first {
             let mut bx = 0;
-            for bx_ in 0..(*sgd).sx {
-                bx = bx_;
+            while bx < (*sgd).sx {
                 let mut px = ax - 1 + bx;
                 let mut pywrap = py;
                 // Wrap line.
@@ -3624,6 +3928,7 @@ pub unsafe fn window_copy_search_rl(
                 if !matched {
                     break;
                 }
+                bx += 1;
             }
             if bx == (*sgd).sx {
                 *ppx = ax - 1;
@@ -3642,16 +3947,11 @@ pub unsafe fn window_copy_search_lr_regex(
     py: u32,
     first: u32,
     last: u32,
-    reg: *mut libc::regex_t,
+    reg: &regexp,
 ) -> bool {
     unsafe {
-        let mut eflags = 0;
         let mut size: u32 = 1;
-        // u_int endline, foundx, foundy, len, pywrap, size = 1;
-        // char *buf;
-        // regmatch_t regmatch;
-        let mut regmatch: libc::regmatch_t = zeroed();
-        // struct grid_line *gl;
+        let mut regmatch: [libc::regmatch_t; 1] = zeroed();
 
         // This can happen during search if the last match was the last
         // character on a line.
@@ -3659,14 +3959,13 @@ pub unsafe fn window_copy_search_lr_regex(
             return false;
         }
 
-        // Set flags for regex search.
-        if first != 0 {
-            eflags |= libc::REG_NOTBOL;
-        }
-
-        // Need to look at the entire string.
+        // Need to look at the entire string. The text before first is
+        // included so the start of the search has its context, for example
+        // for word boundaries.
         let mut buf = xmalloc(size as usize).cast::<u8>().as_ptr();
         *buf = b'\0';
+        buf = window_copy_stringify(gd, py, 0, first, buf, &raw mut size);
+        let start = size as usize - 1;
         buf = window_copy_stringify(gd, py, first, (*gd).sx, buf, &raw mut size);
         let mut len = (*gd).sx - first;
         let endline = (*gd).hsize + (*gd).sy - 1;
@@ -3681,9 +3980,7 @@ pub unsafe fn window_copy_search_lr_regex(
             len += (*gd).sx;
         }
 
-        if libc::regexec(reg, buf, 1, &raw mut regmatch, eflags) == 0
-            && regmatch.rm_so != regmatch.rm_eo
-        {
+        if regexp_exec(reg, buf, start, &mut regmatch) && regmatch[0].rm_so != regmatch[0].rm_eo {
             let mut foundx = first;
             let mut foundy = py;
             window_copy_cstrtocellpos(
@@ -3691,7 +3988,7 @@ pub unsafe fn window_copy_search_lr_regex(
                 len,
                 &raw mut foundx,
                 &raw mut foundy,
-                buf.add(regmatch.rm_so as usize),
+                buf.add(regmatch[0].rm_so as usize),
             );
             if foundy == py && foundx < last {
                 *ppx = foundx;
@@ -3701,7 +3998,7 @@ pub unsafe fn window_copy_search_lr_regex(
                     len,
                     &raw mut foundx,
                     &raw mut foundy,
-                    buf.add(regmatch.rm_eo as usize),
+                    buf.add(regmatch[0].rm_eo as usize),
                 );
                 *psx = foundx;
                 while foundy > py {
@@ -3728,20 +4025,16 @@ pub unsafe fn window_copy_search_rl_regex(
     py: u32,
     first: u32,
     last: u32,
-    reg: *mut libc::regex_t,
+    reg: &regexp,
 ) -> bool {
     unsafe {
-        let mut eflags = 0;
         let mut size: u32 = 1;
 
-        // Set flags for regex search.
-        if first != 0 {
-            eflags |= libc::REG_NOTBOL;
-        }
-
         // Need to look at the entire string.
         let mut buf = xmalloc(size as usize).cast::<u8>().as_ptr();
         *buf = b'\0';
+        buf = window_copy_stringify(gd, py, 0, first, buf, &raw mut size);
+        let start = size as usize - 1;
         buf = window_copy_stringify(gd, py, first, (*gd).sx, buf, &raw mut size);
         let mut len = (*gd).sx - first;
         let endline = (*gd).hsize + (*gd).sy - 1;
@@ -3756,7 +4049,7 @@ pub unsafe fn window_copy_search_rl_regex(
             len += (*gd).sx;
         }
 
-        if window_copy_last_regex(gd, py, first, last, len, ppx, psx, buf, reg, eflags) {
+        if window_copy_last_regex(gd, py, first, last, len, ppx, psx, buf, reg, start) {
             free_(buf);
             return true;
         }
@@ -3820,20 +4113,20 @@ pub unsafe fn window_copy_last_regex(
     ppx: *mut u32,
     psx: *mut u32,
     buf: *const u8,
-    preg: *const libc::regex_t,
-    eflags: i32,
+    preg: &regexp,
+    start: usize,
 ) -> bool {
     unsafe {
-        let mut px = 0;
+        let mut px = start;
         let mut savepx = 0;
         let mut savesx = 0;
-        let mut regmatch: libc::regmatch_t = zeroed();
+        let mut regmatch: [libc::regmatch_t; 1] = zeroed();
 
         let mut foundx = first;
         let mut foundy = py;
         let mut oldx = first;
-        while libc::regexec(preg, buf.add(px), 1, &raw mut regmatch, eflags) == 0 {
-            if regmatch.rm_so == regmatch.rm_eo {
+        while regexp_exec(preg, buf, px, &mut regmatch) {
+            if regmatch[0].rm_so == regmatch[0].rm_eo {
                 break;
             }
             window_copy_cstrtocellpos(
@@ -3841,7 +4134,7 @@ pub unsafe fn window_copy_last_regex(
                 len,
                 &raw mut foundx,
                 &raw mut foundy,
-                buf.add(px + regmatch.rm_so as usize),
+                buf.add(regmatch[0].rm_so as usize),
             );
             if foundy > py || foundx >= last {
                 break;
@@ -3853,7 +4146,7 @@ pub unsafe fn window_copy_last_regex(
                 len,
                 &raw mut foundx,
                 &raw mut foundy,
-                buf.add(px + regmatch.rm_eo as usize),
+                buf.add(regmatch[0].rm_eo as usize),
             );
             if foundy > py || foundx >= last {
                 *ppx = savepx;
@@ -3869,7 +4162,7 @@ pub unsafe fn window_copy_last_regex(
                 len -= savesx;
                 oldx = foundx;
             }
-            px += regmatch.rm_eo as usize;
+            px = regmatch[0].rm_eo as usize;
         }
 
         if savesx > 0 {
@@ -4083,7 +4376,7 @@ pub unsafe fn window_copy_is_lowercase(mut ptr: *const u8) -> bool {
 
 pub unsafe fn window_copy_search_back_overlap(
     gd: *mut grid,
-    preg: *mut libc::regex_t,
+    preg: &regexp,
     ppx: *mut u32,
     psx: *mut u32,
     ppy: *mut u32,
@@ -4139,6 +4432,48 @@ pub unsafe fn window_copy_search_back_overlap(
     }
 }
 
+/// Get the history index and mask for the current search, or None if the
+/// index can't be used.
+unsafe fn window_copy_search_index(
+    data: *mut window_copy_mode_data,
+    regex: i32,
+    cis: i32,
+) -> Option<(*mut grid, grid_index_bloom)> {
+    unsafe {
+        let wp = window_pane_find_by_id((*data).index_pane);
+        if wp.is_null() || (*data).searchstr.is_null() {
+            return None;
+        }
+        let text = CStr::from_ptr((*data).searchstr.cast()).to_bytes();
+        let mask = if regex != 0 {
+            grid_index_regex_mask(text, cis != 0)
+        } else {
+            grid_index_mask(text, cis != 0)
+        }?;
+        Some(((*wp).base.grid, mask))
+    }
+}
+
+/// Check if a search could match starting on a line of the backing.
+unsafe fn window_copy_search_candidate(
+    data: *mut window_copy_mode_data,
+    index: &Option<(*mut grid, grid_index_bloom)>,
+    py: u32,
+) -> bool {
+    unsafe {
+        match index {
+            Some((igd, mask)) => grid_index_check(
+                *igd,
+                &(*data).index_view,
+                (*(*data).backing).grid,
+                py,
+                mask,
+            ),
+            None => true,
+        }
+    }
+}
+
 // Search for text stored in sgd starting from position fx,fy up to endline. If
 // found, jump to it. If cis then ignore case. The direction is 0 for searching
 // up, down otherwise. If wrap then go to begin/end of grid and try again if
@@ -4161,7 +4496,7 @@ pub unsafe fn window_copy_search_jump(
         let mut ssize: u32 = 1;
         let mut found = false;
         let mut cflags = libc::REG_EXTENDED;
-        let mut reg: libc::regex_t = zeroed();
+        let mut reg = None;
 
         if regex != 0 {
             let mut sbuf: *mut u8 = xmalloc(ssize as usize).as_ptr().cast();
@@ -4170,19 +4505,26 @@ pub unsafe fn window_copy_search_jump(
             if cis != 0 {
                 cflags |= REG_ICASE;
             }
-            if libc::regcomp(&raw mut reg, sbuf, cflags) != 0 {
-                free_(sbuf);
+            reg = regexp_compile(sbuf, cflags);
+            free_(sbuf);
+            if reg.is_none() {
                 return false;
             }
-            free_(sbuf);
         }
 
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let index = window_copy_search_index(data, regex, cis);
+
         let mut i = 0;
         if direction != 0 {
             for i_ in fy..=endline {
                 i = i_;
+                if !window_copy_search_candidate(data, &index, i) {
+                    fx = 0;
+                    continue;
+                }
 
-                if regex != 0 {
+                if let Some(reg) = &reg {
                     found = window_copy_search_lr_regex(
                         gd,
                         &raw mut px,
@@ -4190,7 +4532,7 @@ pub unsafe fn window_copy_search_jump(
                         i,
                         fx,
                         (*gd).sx,
-                        &raw mut reg,
+                        reg,
                     );
                 } else {
                     found = window_copy_search_lr(gd, sgd, &raw mut px, i, fx, (*gd).sx, cis);
@@ -4203,7 +4545,12 @@ pub unsafe fn window_copy_search_jump(
         } else {
             i = fy + 1;
             while endline < i {
-                if regex != 0 {
+                if !window_copy_search_candidate(data, &index, i - 1) {
+                    fx = (*gd).sx - 1;
+                    i -= 1;
+                    continue;
+                }
+                if let Some(reg) = &reg {
                     found = window_copy_search_rl_regex(
                         gd,
                         &raw mut px,
@@ -4211,12 +4558,12 @@ pub unsafe fn window_copy_search_jump(
                         i - 1,
                         0,
                         fx + 1,
-                        &raw mut reg,
+                        reg,
                     );
                     if found {
                         window_copy_search_back_overlap(
                             gd,
-                            &raw mut reg,
+                            reg,
                             &raw mut px,
                             &raw mut sx,
                             &raw mut i,
@@ -4234,10 +4581,6 @@ pub unsafe fn window_copy_search_jump(
                 i -= 1;
             }
         }
-        if regex != 0 {
-            libc::regfree(&raw mut reg);
-        }
-
         if found {
             window_copy_scroll_to(wme, px, i, true);
             return true;
@@ -4317,6 +4660,7 @@ pub unsafe fn window_copy_search(
         }
 
         (*data).searchdirection = direction;
+        (*data).fuzzy = None;
 
         if (*data).timeout != 0 {
             return false;
@@ -4411,7 +4755,7 @@ pub unsafe fn window_copy_search(
                 if keys == Ok(modekey::MODEKEY_EMACS) {
                     window_copy_move_after_search_mark(data, &raw mut fx, &raw mut fy, wrapflag);
                     (*data).cx = fx;
-                    (*data).cy = fy - screen_hsize((*data).backing) + (*data).oy;
+                    (*data).cy = fy + (*data).oy - screen_hsize((*data).backing);
                 }
             } else {
                 // When searching backward, position the cursor at the
@@ -4423,7 +4767,7 @@ pub unsafe fn window_copy_search(
                             == *(*data).searchmark.add(start as usize)
                     {
                         (*data).cx = fx;
-                        (*data).cy = fy - screen_hsize((*data).backing) + (*data).oy;
+                        (*data).cy = fy + (*data).oy - screen_hsize((*data).backing);
                         if at == 0 {
                             break;
                         }
@@ -4495,6 +4839,11 @@ pub unsafe fn window_copy_search_marks(
         let mut found: bool;
         let mut stopped: i32 = 0;
 
+        if (*data).fuzzy.is_some() {
+            window_copy_fuzzy_marks(wme);
+            return true;
+        }
+
         let mut cflags = libc::REG_EXTENDED;
         let mut px: u32;
         let mut nfound: u32 = 0;
@@ -4504,7 +4853,7 @@ pub unsafe fn window_copy_search_marks(
         let mut start: u32 = 0;
         let mut end: u32 = 0;
 
-        let mut reg: libc::regex_t = zeroed();
+        let mut reg = None;
         let mut stop: u64 = 0;
         'out: {
             if ssp.is_null() {
@@ -4540,12 +4889,13 @@ pub unsafe fn window_copy_search_marks(
                 if cis != 0 {
                     cflags |= REG_ICASE;
                 }
-                if libc::regcomp(&raw mut reg, sbuf, cflags) != 0 {
-                    free_(sbuf);
+                reg = regexp_compile(sbuf, cflags);
+                free_(sbuf);
+                if reg.is_none() {
                     return false;
                 }
-                free_(sbuf);
             }
+            let index = window_copy_search_index(data, regex, cis);
             let tstart = get_timer();
 
             if visible_only != 0 {
@@ -4564,9 +4914,12 @@ pub unsafe fn window_copy_search_marks(
                 (*data).searchgen = 1;
 
                 for py in start..end {
+                    if !window_copy_search_candidate(data, &index, py) {
+                        continue;
+                    }
                     px = 0;
                     loop {
-                        if regex != 0 {
+                        if let Some(reg) = &reg {
                             found = window_copy_search_lr_regex(
                                 gd,
                                 &raw mut px,
@@ -4574,7 +4927,7 @@ pub unsafe fn window_copy_search_marks(
                                 py,
                                 px,
                                 (*gd).sx,
-                                &raw mut reg,
+                                reg,
                             );
                             if !found {
                                 break;
@@ -4660,9 +5013,6 @@ pub unsafe fn window_copy_search_marks(
         if ssp == &raw mut ss {
             screen_free(&raw mut ss);
         }
-        if regex != 0 {
-            libc::regfree(&raw mut reg);
-        }
         true
     }
 }
@@ -4684,20 +5034,581 @@ pub unsafe fn window_copy_search_down(wme: *mut window_mode_entry, regex: i32) -
     unsafe { window_copy_search(wme, 1, regex) }
 }
 
-pub unsafe fn window_copy_goto_line(wme: *mut window_mode_entry, linestr: *const u8) {
+/// Get the characters of the line starting at py, joining wrapped lines, and
+/// the cell of each. Returns the last line. Compressed lines are read without
+/// being expanded, since this looks at the whole history.
+unsafe fn window_copy_logical_line(
+    gd: *mut grid,
+    py: u32,
+    text: &mut Vec<char>,
+    cells: &mut Vec<(u32, u32)>,
+) -> u32 {
     unsafe {
-        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let last = (*gd).hsize + (*gd).sy - 1;
 
-        let Ok(mut lineno) = strtonum(linestr, -1, i32::MAX) else {
-            return;
-        };
-        if lineno < 0 || lineno as u32 > screen_hsize((*data).backing) {
-            lineno = screen_hsize((*data).backing) as i32;
-        }
+        text.clear();
+        cells.clear();
+        let mut y = py;
+        loop {
+            // Trailing spaces are left out, as grid_line_length does.
+            let mut keep = text.len();
+            grid_peek_cells(gd, y, (*gd).sx, |x, gc| {
+                if gc.flags.intersects(grid_flag::PADDING) {
+                    keep = text.len();
+                    return;
+                }
+                let ch = std::str::from_utf8(gc.data.initialized_slice())
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .unwrap_or(' ');
+                text.push(ch);
+                cells.push((x, y));
+                if gc.data.size != 1 || gc.data.data[0] != b' ' {
+                    keep = text.len();
+                }
+            });
+            text.truncate(keep);
+            cells.truncate(keep);
 
-        (*data).oy = lineno as u32;
-        window_copy_update_selection(wme, 1, 0);
-        window_copy_redraw_screen(wme);
+            let gl = (*gd).linedata.add(y as usize);
+            if y == last || !(*gl).flags.intersects(grid_line_flag::WRAPPED) {
+                return y;
+            }
+            y += 1;
+        }
+    }
+}
+
+/// Fuzzy search for the search string. The best match on each line is found
+/// and the matches are ranked by score, with matches in the direction of the
+/// search from the cursor first among those with the same score. The cursor
+/// moves to the best match and search-again and search-reverse then step
+/// through them in order.
+pub unsafe fn window_copy_search_fuzzy(wme: *mut window_mode_entry, direction: i32) -> bool {
+    unsafe {
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let gd: *mut grid = (*(*data).backing).grid;
+        let cis = window_copy_is_lowercase((*data).searchstr);
+        let pattern: Vec<char> = cstr_to_str((*data).searchstr).chars().collect();
+        let cy = screen_hsize((*data).backing) - (*data).oy + (*data).cy;
+        let tstart = get_timer();
+
+        window_copy_clear_marks(wme);
+        (*data).fuzzy = None;
+
+        let mut matches = Vec::new();
+        let mut text = Vec::new();
+        let mut cells = Vec::new();
+        let mut py = 0;
+        while py < (*gd).hsize + (*gd).sy {
+            let end = window_copy_logical_line(gd, py, &mut text, &mut cells);
+            if let Some((score, positions)) = fuzzy_match(&pattern, &text, cis) {
+                matches.push(window_copy_fuzzy_match {
+                    py,
+                    cells: positions.into_iter().map(|i| cells[i]).collect(),
+                    score,
+                });
+            }
+            py = end + 1;
+
+            if get_timer() - tstart > WINDOW_COPY_SEARCH_TIMEOUT {
+                (*data).timeout = 1;
+                break;
+            }
+        }
+        if matches.is_empty() {
+            window_copy_redraw_screen(wme);
+            return false;
+        }
+
+        // Lines after the cursor come first going down and lines before it
+        // going up; the rest follow as if the search had wrapped.
+        let total = (*gd).hsize + (*gd).sy;
+        let distance = |py: u32| {
+            if direction != 0 {
+                (py + total - cy - 1) % total
+            } else {
+                (cy + total - py - 1) % total
+            }
+        };
+        matches.sort_by_key(|m| (std::cmp::Reverse(m.score), distance(m.py)));
+
+        (*data).searchcount = matches.len() as i32;
+        (*data).searchmore = 0;
+        (*data).fuzzy = Some(Box::new(window_copy_fuzzy {
+            matches,
+            current: 0,
+        }));
+        window_copy_fuzzy_jump(wme);
+        true
+    }
+}
+
+/// Move n matches through the ranked fuzzy matches, backwards if n is
+/// negative.
+pub unsafe fn window_copy_fuzzy_next(wme: *mut window_mode_entry, n: i32) {
+    unsafe {
+        let wp: *mut window_pane = (*wme).wp;
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let wrapflag = options_get_number_((*(*wp).window).options, "wrap-search") != 0;
+
+        let Some(fuzzy) = &mut (*data).fuzzy else {
+            return;
+        };
+        let count = fuzzy.matches.len() as i32;
+        let mut current = fuzzy.current as i32 + n;
+        if wrapflag {
+            current = current.rem_euclid(count);
+        } else {
+            current = current.clamp(0, count - 1);
+        }
+        fuzzy.current = current as usize;
+        window_copy_fuzzy_jump(wme);
+    }
+}
+
+/// Move the cursor to the first character of the current fuzzy match.
+unsafe fn window_copy_fuzzy_jump(wme: *mut window_mode_entry) {
+    unsafe {
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+
+        let Some(fuzzy) = &(*data).fuzzy else {
+            return;
+        };
+        let (px, py) = fuzzy.matches[fuzzy.current].cells[0];
+        window_copy_scroll_to(wme, px, py, true);
+        window_copy_fuzzy_marks(wme);
+        window_copy_redraw_screen(wme);
+    }
+}
+
+/// Mark the characters of the fuzzy matches on the visible lines.
+unsafe fn window_copy_fuzzy_marks(wme: *mut window_mode_entry) {
+    unsafe {
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let gd: *mut grid = (*(*data).backing).grid;
+        let mut start = 0;
+        let mut end = 0;
+
+        let Some(fuzzy) = &(*data).fuzzy else {
+            return;
+        };
+        window_copy_visible_lines(data, &raw mut start, &raw mut end);
+
+        free_((*data).searchmark);
+        (*data).searchmark = xcalloc((*gd).sx as usize, (*gd).sy as usize)
+            .cast()
+            .as_ptr();
+        (*data).searchgen = 1;
+        for m in &fuzzy.matches {
+            if m.py >= end || m.cells.last().is_some_and(|(_, py)| *py < start) {
+                continue;
+            }
+            for (px, py) in &m.cells {
+                if let Ok(at) = window_copy_search_mark_at(data, *px, *py) {
+                    *(*data).searchmark.add(at as usize) = (*data).searchgen;
+                }
+            }
+            if (*data).searchgen == u8::MAX {
+                (*data).searchgen = 1;
+            } else {
+                (*data).searchgen += 1;
+            }
+        }
+    }
+}
+
+/// Find the matches of the hint patterns on the visible lines and label them
+/// in the order they appear.
+unsafe fn window_copy_hints(wme: *mut window_mode_entry) {
+    unsafe {
+        let wp: *mut window_pane = (*wme).wp;
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let gd: *mut grid = (*(*data).backing).grid;
+        let top = (*gd).hsize - (*data).oy;
+        let mut start = 0;
+        let mut end = 0;
+
+        (*data).hints = None;
+
+        let mut patterns = Vec::new();
+        let o = options_get(&mut *(*(*wp).window).options, "copy-mode-hint-patterns");
+        let mut a = options_array_first(o);
+        while !a.is_null() {
+            let pattern = (*options_array_item_value(a)).string;
+            match regexp_compile(pattern, libc::REG_EXTENDED) {
+                Some(re) => patterns.push(re),
+                None => log_debug!("bad hint pattern: {}", _s(pattern)),
+            }
+            a = options_array_next(a);
+        }
+
+        // Each byte of the line maps to the cell of its character, so the
+        // match offsets can be turned into cells.
+        window_copy_visible_lines(data, &raw mut start, &raw mut end);
+        let mut hints: Vec<window_copy_hint> = Vec::new();
+        let mut taken = BTreeSet::new();
+        let mut text = Vec::new();
+        let mut cells = Vec::new();
+        let mut py = start;
+        while py < end && py < (*gd).hsize + (*gd).sy {
+            let last = window_copy_logical_line(gd, py, &mut text, &mut cells);
+            let mut line = Vec::new();
+            let mut offsets = Vec::new();
+            for (i, ch) in text.iter().enumerate() {
+                let mut tmp = [0; 4];
+                for b in ch.encode_utf8(&mut tmp).bytes() {
+                    line.push(b);
+                    offsets.push(i);
+                }
+            }
+            line.push(b'\0');
+            let len = offsets.len();
+
+            for re in &patterns {
+                let mut m: [regmatch_t; 1] = zeroed();
+                let mut at = 0;
+                while at < len && regexp_exec(re, line.as_ptr(), at, &mut m) {
+                    let (so, eo) = (m[0].rm_so as usize, m[0].rm_eo as usize);
+                    if eo <= so {
+                        at = so + 1;
+                        continue;
+                    }
+                    at = eo;
+
+                    let first = offsets[so];
+                    let chars = first..=offsets[eo - 1];
+                    if cells[first].1 < top || chars.clone().any(|i| taken.contains(&cells[i])) {
+                        continue;
+                    }
+                    taken.extend(chars.clone().map(|i| cells[i]));
+                    hints.push(window_copy_hint {
+                        label: String::new(),
+                        text: line[so..eo].to_vec(),
+                        cells: chars.map(|i| cells[i]).collect(),
+                    });
+                }
+            }
+            py = last + 1;
+        }
+        if hints.is_empty() {
+            return;
+        }
+
+        // Use one character labels if there are few enough hints, otherwise
+        // two.
+        const ALPHABET: &[u8] = b"asdfghjklqwertyuiopzxcvbnm";
+        let n = ALPHABET.len();
+        hints.sort_by_key(|hint| (hint.cells[0].1, hint.cells[0].0));
+        hints.truncate(n * n);
+        let single = hints.len() <= n;
+        let mut map = BTreeMap::new();
+        for (i, hint) in hints.iter_mut().enumerate() {
+            hint.label = if single {
+                (ALPHABET[i] as char).to_string()
+            } else {
+                format!("{}{}", ALPHABET[i / n] as char, ALPHABET[i % n] as char)
+            };
+            for (j, cell) in hint.cells.iter().enumerate() {
+                map.insert(*cell, (i, j));
+            }
+        }
+        (*data).hints = Some(Box::new(window_copy_hints {
+            hints,
+            typed: String::new(),
+            cells: map,
+        }));
+    }
+}
+
+/// Draw a cell covered by a hint whose label starts with what has been typed:
+/// the label over its first characters and the rest in the match style.
+unsafe fn window_copy_hint_cell(
+    data: *mut window_copy_mode_data,
+    fx: u32,
+    fy: u32,
+    gc: *mut grid_cell,
+    mgc: *const grid_cell,
+    cgc: *const grid_cell,
+) {
+    unsafe {
+        let Some(hints) = &(*data).hints else {
+            return;
+        };
+        let Some((i, j)) = hints.cells.get(&(fx, fy)) else {
+            return;
+        };
+        let label = hints.hints[*i].label.as_bytes();
+        if !label.starts_with(hints.typed.as_bytes()) {
+            return;
+        }
+
+        let sgc = if *j < label.len() { cgc } else { mgc };
+        (*gc).attr = (*sgc).attr;
+        (*gc).fg = (*sgc).fg;
+        (*gc).bg = (*sgc).bg;
+        if *j < label.len() && (*gc).data.width == 1 {
+            utf8_set(&raw mut (*gc).data, label[*j]);
+        }
+    }
+}
+
+/// Act on the text of a hint and leave copy mode.
+unsafe fn window_copy_hint_run(
+    wme: *mut window_mode_entry,
+    c: *mut client,
+    prompt: &window_copy_hint_prompt,
+    text: &[u8],
+) {
+    unsafe {
+        let wp: *mut window_pane = (*wme).wp;
+        let arg: *const u8 = prompt.arg.as_ref().map_or(null(), |arg| arg.as_ptr().cast());
+        let mut fs: cmd_find_state = zeroed();
+
+        match prompt.action {
+            window_copy_hint_action::WINDOW_COPY_HINT_RUN if !arg.is_null() => {
+                // The text is given to the command as a format rather than
+                // put into it, so it cannot change how the command is parsed.
+                cmd_find_from_pane(&raw mut fs, wp, cmd_find_flags::empty());
+                let state = cmdq_new_state(&raw mut fs, null_mut(), cmdq_state_flags::empty());
+                cmdq_add_format!(state, c!("hint_text"), "{}", String::from_utf8_lossy(text));
+                let mut error: *mut u8 = null_mut();
+                let status = cmd_parse_and_append(cstr_to_str(arg), None, c, state, &raw mut error);
+                if status == cmd_parse_status::CMD_PARSE_ERROR {
+                    *error = (*error).to_ascii_uppercase();
+                    status_message_set!(c, -1, 1, false, "{}", _s(error));
+                    free_(error);
+                }
+                cmdq_free_state(state);
+            }
+            window_copy_hint_action::WINDOW_COPY_HINT_PIPE => {
+                let mut cmd = arg;
+                if cmd.is_null() || *cmd == b'\0' {
+                    cmd = options_get_string_(GLOBAL_OPTIONS, "copy-command");
+                }
+                if !cmd.is_null() && *cmd != b'\0' {
+                    let job = job_run(
+                        cmd,
+                        0,
+                        null_mut(),
+                        null_mut(),
+                        (*c).session,
+                        null_mut(),
+                        None,
+                        None,
+                        None,
+                        null_mut(),
+                        job_flag::JOB_NOWAIT,
+                        -1,
+                        -1,
+                    );
+                    bufferevent_write(job_get_event(job), text.as_ptr().cast(), text.len());
+                }
+                let buf: *mut u8 = xmalloc(text.len()).cast().as_ptr();
+                std::ptr::copy_nonoverlapping(text.as_ptr(), buf, text.len());
+                window_copy_copy_buffer(wme, null(), buf.cast(), text.len());
+            }
+            _ => {
+                let buf: *mut u8 = xmalloc(text.len()).cast().as_ptr();
+                std::ptr::copy_nonoverlapping(text.as_ptr(), buf, text.len());
+                window_copy_copy_buffer(wme, arg, buf.cast(), text.len());
+            }
+        }
+        window_pane_reset_mode(wp);
+    }
+}
+
+/// Read the label of a hint. Each key narrows the hints shown to those with
+/// matching labels; a complete label picks its hint and a key that matches
+/// nothing or escape removes the hints.
+unsafe fn window_copy_hint_callback(
+    c: *mut client,
+    prompt: NonNull<window_copy_hint_prompt>,
+    mut s: *const u8,
+    done: i32,
+) -> i32 {
+    unsafe {
+        let prompt = prompt.as_ptr();
+
+        let wp = window_pane_find_by_id((*prompt).pane);
+        if wp.is_null() {
+            return 0;
+        }
+        let wme = tailq_first(&raw mut (*wp).modes);
+        if wme.is_null()
+            || ((*wme).mode != &raw const WINDOW_COPY_MODE
+                && (*wme).mode != &raw const WINDOW_VIEW_MODE)
+        {
+            return 0;
+        }
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let Some(hints) = &mut (*data).hints else {
+            return 0;
+        };
+
+        // Skip the prefix given while typing.
+        if !s.is_null() && done == 0 && *s != b'\0' {
+            s = s.add(1);
+        }
+        if s.is_null() {
+            (*data).hints = None;
+            window_copy_redraw_screen(wme);
+            return 0;
+        }
+        let typed = cstr_to_str(s);
+
+        if let Some(i) = hints.hints.iter().position(|hint| hint.label == typed) {
+            let text = hints.hints.swap_remove(i).text;
+            window_copy_hint_run(wme, c, &*prompt, &text);
+            status_prompt_clear(c);
+            return 0;
+        }
+        if done != 0 || !hints.hints.iter().any(|hint| hint.label.starts_with(typed)) {
+            (*data).hints = None;
+            window_copy_redraw_screen(wme);
+            if done == 0 {
+                status_prompt_clear(c);
+            }
+            return 0;
+        }
+        hints.typed = typed.to_string();
+        window_copy_redraw_screen(wme);
+        0
+    }
+}
+
+unsafe fn window_copy_hint_free(prompt: NonNull<window_copy_hint_prompt>) {
+    unsafe {
+        drop(Box::from_raw(prompt.as_ptr()));
+    }
+}
+
+pub unsafe fn window_copy_goto_line(wme: *mut window_mode_entry, linestr: *const u8) {
+    unsafe {
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+
+        let Ok(mut lineno) = strtonum(linestr, -1, i32::MAX) else {
+            return;
+        };
+        if lineno < 0 || lineno as u32 > screen_hsize((*data).backing) {
+            lineno = screen_hsize((*data).backing) as i32;
+        }
+
+        (*data).oy = lineno as u32;
+        window_copy_update_selection(wme, 1, 0);
+        window_copy_redraw_screen(wme);
+    }
+}
+
+/// Parse a time for goto-time: a date and time or a time today. A time later
+/// than now is taken to be yesterday.
+unsafe fn window_copy_parse_time(timestr: *const u8) -> Option<time_t> {
+    unsafe {
+        const FORMATS: [&CStr; 6] = [
+            c"%Y-%m-%d %H:%M:%S",
+            c"%Y-%m-%d %H:%M",
+            c"%Y-%m-%dT%H:%M:%S",
+            c"%Y-%m-%dT%H:%M",
+            c"%H:%M:%S",
+            c"%H:%M",
+        ];
+        let now = libc::time(null_mut());
+
+        for (i, fmt) in FORMATS.iter().enumerate() {
+            let mut tm: tm = zeroed();
+            localtime_r(&raw const now, &raw mut tm);
+            tm.tm_sec = 0;
+
+            let end = strptime(timestr, fmt.as_ptr().cast(), &raw mut tm);
+            if end.is_null() || *end != b'\0' {
+                continue;
+            }
+            tm.tm_isdst = -1;
+            let mut t = mktime(&raw mut tm);
+            if t == -1 {
+                return None;
+            }
+            if i >= 4 && t > now {
+                t -= 24 * 3600;
+            }
+            return Some(t);
+        }
+        None
+    }
+}
+
+/// Move to the first line written at or after a time.
+pub unsafe fn window_copy_goto_time(wme: *mut window_mode_entry, timestr: *const u8) {
+    unsafe {
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let gd = (*(*data).backing).grid;
+        let hsize = screen_hsize((*data).backing);
+
+        let Some(t) = window_copy_parse_time(timestr) else {
+            return;
+        };
+
+        let Some(py) = (0..hsize + (*gd).sy).find(|&py| {
+            let time = (*grid_peek_line(gd, py)).time;
+            time != 0 && time >= t
+        }) else {
+            return;
+        };
+
+        if py < hsize {
+            (*data).oy = hsize - py;
+            (*data).cy = 0;
+        } else {
+            (*data).oy = 0;
+            (*data).cy = py - hsize;
+        }
+        (*data).cx = 0;
+        window_copy_update_selection(wme, 1, 0);
+        window_copy_redraw_screen(wme);
+    }
+}
+
+/// Move to the first match of a search in a line of the backing, given by its
+/// absolute position as found by searching the pane history. If the search
+/// does not match in that line, move to the start of the line instead.
+pub unsafe fn window_copy_goto_match(
+    wme: *mut window_mode_entry,
+    py: u32,
+    searchstr: *const u8,
+    regex: bool,
+) {
+    unsafe {
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let gd = (*(*data).backing).grid;
+        let py = py.min((*gd).hsize + (*gd).sy - 1);
+
+        let mut end = py;
+        while end + 1 < (*gd).hsize + (*gd).sy
+            && (*grid_get_line_compressed(gd, end))
+                .flags
+                .intersects(grid_line_flag::WRAPPED)
+        {
+            end += 1;
+        }
+
+        free_((*data).searchstr);
+        (*data).searchstr = xstrdup(searchstr).as_ptr();
+        (*data).searchtype = window_copy::WINDOW_COPY_SEARCHDOWN;
+        (*data).searchregex = regex as i32;
+        (*data).timeout = 0;
+
+        // Search from the end of the line before so a match at the start of
+        // the line is found.
+        if py != 0 {
+            window_copy_scroll_to(wme, (*gd).sx - 1, py - 1, true);
+            if window_copy_search_down(wme, regex as i32) {
+                let fy = screen_hsize((*data).backing) - (*data).oy + (*data).cy;
+                if fy >= py && fy <= end {
+                    return;
+                }
+            }
+        }
+        window_copy_scroll_to(wme, 0, py, false);
     }
 }
 
@@ -4816,6 +5727,25 @@ pub unsafe fn window_copy_update_style(
             return;
         }
 
+        // Fuzzy matches are not contiguous and the current one is known from
+        // its rank rather than found from the cursor.
+        if let Some(fuzzy) = &(*data).fuzzy {
+            let sgc = if fuzzy.matches[fuzzy.current].cells.contains(&(fx, fy)) {
+                cgc
+            } else {
+                mgc
+            };
+            (*gc).attr = (*sgc).attr;
+            if inv != 0 {
+                (*gc).fg = (*sgc).bg;
+                (*gc).bg = (*sgc).fg;
+            } else {
+                (*gc).fg = (*sgc).fg;
+                (*gc).bg = (*sgc).bg;
+            }
+            return;
+        }
+
         let cy = screen_hsize((*data).backing) - (*data).oy + (*data).cy;
         if let Ok(mut cursor) = window_copy_search_mark_at(data, (*data).cx, cy) {
             let keys =
@@ -4875,6 +5805,7 @@ pub unsafe fn window_copy_write_one(
             grid_get_cell(gd, fx, fy, &raw mut gc);
             if fx + gc.data.width as u32 <= nx {
                 window_copy_update_style(wme, fx, fy, &raw mut gc, mgc, cgc, mkgc);
+                window_copy_hint_cell(data, fx, fy, &raw mut gc, mgc, cgc);
                 screen_write_cell(ctx, &raw mut gc);
             }
         }
@@ -4951,6 +5882,16 @@ pub unsafe fn window_copy_write_line(
                     )
                     .unwrap() as usize;
                 }
+            } else if let Some(fuzzy) = &(*data).fuzzy {
+                size = xsnprintf_!(
+                    (&raw mut hdr).cast(),
+                    512,
+                    "({}/{} results) {}",
+                    fuzzy.current + 1,
+                    fuzzy.matches.len(),
+                    _s(&raw const tmp as *const u8)
+                )
+                .unwrap() as usize;
             } else if (*data).searchcount == -1 {
                 size = xsnprintf_!(
                     (&raw mut hdr).cast(),
@@ -4975,6 +5916,18 @@ pub unsafe fn window_copy_write_line(
             }
             screen_write_cursormove(ctx, screen_size_x(s) as i32 - size as i32, 0, 0);
             screen_write_puts!(ctx, &raw mut gc, "{}", _s((&raw const hdr).cast::<u8>()));
+        } else if (*data).timestamps {
+            // The time is drawn over the right of the line, like the
+            // position indicator, so it hides any text under it.
+            let line = grid_peek_line((*(*data).backing).grid, hsize - (*data).oy + py);
+            let time: String =
+                format_line_time((*line).time, options_get_string_(oo, "timestamp-format"))
+                    .chars()
+                    .take(screen_size_x(s) as usize)
+                    .collect();
+            size = time.chars().count();
+            screen_write_cursormove(ctx, screen_size_x(s) as i32 - size as i32, py as i32, 0);
+            screen_write_puts!(ctx, &raw mut gc, "{}", time);
         } else {
             size = 0;
         }
@@ -6295,6 +7248,19 @@ pub unsafe fn window_copy_cursor_prompt(
             }
         }
 
+        window_copy_cursor_to_line(wme, line);
+
+        window_copy_update_selection(wme, 1, 0);
+        window_copy_redraw_screen(wme);
+    }
+}
+
+/// Move the cursor to the start of a line, scrolling if needed.
+unsafe fn window_copy_cursor_to_line(wme: *mut window_mode_entry, line: u32) {
+    unsafe {
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let gd: *mut grid = (*(*data).backing).grid;
+
         (*data).cx = 0;
         if line > (*gd).hsize {
             (*data).cy = line - (*gd).hsize;
@@ -6303,9 +7269,61 @@ pub unsafe fn window_copy_cursor_prompt(
             (*data).cy = 0;
             (*data).oy = (*gd).hsize - line;
         }
+    }
+}
+
+/// Find the output of the command at or above the cursor, as marked by the
+/// shell with OSC 133. Returns the first and last lines.
+unsafe fn window_copy_command_output(wme: *mut window_mode_entry) -> Option<(u32, u32)> {
+    unsafe {
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+        let gd: *mut grid = (*(*data).backing).grid;
+        let last = (*gd).hsize + (*gd).sy - 1;
+
+        let mut start = (*gd).hsize - (*data).oy + (*data).cy;
+        while !(*grid_get_line(gd, start))
+            .flags
+            .intersects(grid_line_flag::START_OUTPUT)
+        {
+            if start == 0 {
+                return None;
+            }
+            start -= 1;
+        }
 
+        // The output ends before the line where the command finished or the
+        // next prompt started.
+        let mut end = start;
+        while end < last
+            && !(*grid_get_line(gd, end + 1))
+                .flags
+                .intersects(grid_line_flag::END_OUTPUT | grid_line_flag::START_PROMPT)
+        {
+            end += 1;
+        }
+        Some((start, end))
+    }
+}
+
+/// Select the output of the command at or above the cursor.
+unsafe fn window_copy_select_command_output(wme: *mut window_mode_entry) -> bool {
+    unsafe {
+        let data: *mut window_copy_mode_data = (*wme).data.cast();
+
+        let Some((start, end)) = window_copy_command_output(wme) else {
+            return false;
+        };
+
+        (*data).lineflag = line_sel::LINE_SEL_NONE;
+        (*data).rectflag = false;
+        (*data).selflag = selflag::SEL_CHAR;
+
+        window_copy_cursor_to_line(wme, start);
+        window_copy_start_selection(wme);
+        window_copy_cursor_to_line(wme, end);
+        window_copy_cursor_end_of_line(wme);
         window_copy_update_selection(wme, 1, 0);
-        window_copy_redraw_screen(wme);
+        true
     }
 }
 
@@ -6650,3 +7668,108 @@ unsafe fn window_copy_acquire_cursor_down(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn local_time(year: i32, mon: i32, mday: i32, hour: i32, min: i32, sec: i32) -> time_t {
+        unsafe {
+            let mut tm: tm = zeroed();
+            tm.tm_year = year - 1900;
+            tm.tm_mon = mon - 1;
+            tm.tm_mday = mday;
+            tm.tm_hour = hour;
+            tm.tm_min = min;
+            tm.tm_sec = sec;
+            tm.tm_isdst = -1;
+            mktime(&raw mut tm)
+        }
+    }
+
+    #[test]
+    fn test_window_copy_logical_line() {
+        unsafe {
+            let gd = grid_create(4, 3, 0);
+            let gc: grid_cell = GRID_DEFAULT_CELL;
+            grid_set_cells(gd, 0, 0, &gc, b"abcd".as_ptr(), 4);
+            (*(*gd).linedata).flags |= grid_line_flag::WRAPPED;
+            grid_set_cells(gd, 0, 1, &gc, b"e f ".as_ptr(), 4);
+            grid_set_cells(gd, 0, 2, &gc, b"g".as_ptr(), 1);
+            grid_compress_line((*gd).linedata);
+
+            let mut text = Vec::new();
+            let mut cells = Vec::new();
+            assert_eq!(window_copy_logical_line(gd, 0, &mut text, &mut cells), 1);
+            assert_eq!(text.iter().collect::<String>(), "abcde f");
+            assert_eq!(cells[4..], [(0, 1), (1, 1), (2, 1)]);
+            assert!((*(*gd).linedata).flags.intersects(grid_line_flag::COMPRESSED));
+            assert!((*gd).expanded.is_empty());
+
+            assert_eq!(window_copy_logical_line(gd, 2, &mut text, &mut cells), 2);
+            assert_eq!(text, ['g']);
+
+            grid_destroy(gd);
+        }
+    }
+
+    #[test]
+    fn test_window_copy_parse_time_absolute() {
+        unsafe {
+            let parse = |s: &CStr| window_copy_parse_time(s.as_ptr().cast());
+
+            let t = local_time(2024, 3, 9, 14, 5, 30);
+            assert_eq!(parse(c"2024-03-09 14:05:30"), Some(t));
+            assert_eq!(parse(c"2024-03-09T14:05:30"), Some(t));
+            assert_eq!(parse(c"2024-03-09 14:05"), Some(t - 30));
+            assert_eq!(parse(c"2024-03-09T14:05"), Some(t - 30));
+
+            assert_eq!(parse(c""), None);
+            assert_eq!(parse(c"yesterday"), None);
+            assert_eq!(parse(c"2024-03-09 14:05:30 trailing"), None);
+            assert_eq!(parse(c"25:00"), None);
+        }
+    }
+
+    #[test]
+    fn test_window_copy_parse_time_relative() {
+        unsafe {
+            let parse = |s: &CStr| window_copy_parse_time(s.as_ptr().cast());
+            let now = libc::time(null_mut());
+
+            // A time of day is today, or yesterday if it has not happened
+            // yet, so it is always in the last day.
+            for s in [c"00:00", c"12:34", c"23:59:59"] {
+                let t = parse(s).unwrap();
+                assert!(t <= now && t > now - 24 * 3600 - 3600, "{s:?}");
+
+                let mut tm: tm = zeroed();
+                localtime_r(&raw const t, &raw mut tm);
+                let text = format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec);
+                assert!(text.starts_with(s.to_str().unwrap()), "{s:?} is {text}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_window_copy_hint_hash_pattern() {
+        unsafe {
+            let pattern = CStr::from_ptr(OPTIONS_TABLE_COPY_MODE_HINT_PATTERNS_DEFAULT[3].cast());
+            let r = ::regex::bytes::Regex::new(pattern.to_str().unwrap()).unwrap();
+            let find = |s: &str| r.find(s.as_bytes()).map(|m| m.as_bytes().to_vec());
+
+            assert_eq!(find("commit a66b55b fix"), Some(b"a66b55b".to_vec()));
+            assert_eq!(find("at 1234567f"), Some(b"1234567f".to_vec()));
+            assert_eq!(find("at 123456f"), Some(b"123456f".to_vec()));
+            assert_eq!(find("0123f45"), Some(b"0123f45".to_vec()));
+            let sha1 = "2fe94243c1b0b6d0a9f2e7d6c3b5a4f3e2d1c0b9";
+            assert_eq!(find(sha1), Some(sha1.as_bytes().to_vec()));
+
+            assert_eq!(find("pid 12345678 exited"), None);
+            assert_eq!(find("deadbe"), None);
+            assert_eq!(find("12345f"), None);
+            assert_eq!(find(&"a".repeat(41)), None);
+            assert_eq!(find("a66b55bx"), None);
+        }
+    }
+}
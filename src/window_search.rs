@@ -0,0 +1,746 @@
+// Search the history of every pane and show the matching lines grouped by
+// pane. Choosing a line switches to the pane and opens copy mode on it.
+use crate::compat::{queue::tailq_foreach, tree::rb_foreach};
+use crate::*;
+
+const WINDOW_SEARCH_DEFAULT_COMMAND: &str = "switch-client -Zt '%%'";
+const WINDOW_SEARCH_DEFAULT_FORMAT: &str = concat!(
+    "#{?match_format,",
+    "#{search_text}",
+    ",",
+    "#{pane_current_command}#{?pane_active,*,}: ",
+    "#{search_matches} #{?#{==:#{search_matches},1},match,matches}",
+    "}"
+);
+
+const WINDOW_SEARCH_DEFAULT_KEY_FORMAT: &str = concat!(
+    "#{?#{e|<:#{line},10},", //
+    "#{line}",
+    ",",
+    "#{?#{e|<:#{line},36},",
+    "M-#{a:#{e|+:97,#{e|-:#{line},10}}}",
+    ",",
+    "",
+    "}",
+    "}"
+);
+
+static WINDOW_SEARCH_MENU_ITEMS: [menu_item; 6] = [
+    menu_item::new("Select", b'\r' as key_code, null()),
+    menu_item::new("Expand", keyc::KEYC_RIGHT as key_code, null()),
+    menu_item::new("", KEYC_NONE, null()),
+    menu_item::new("Search", b's' as key_code, null()),
+    menu_item::new("", KEYC_NONE, null()),
+    menu_item::new("Cancel", b'q' as key_code, null()),
+];
+
+pub static WINDOW_SEARCH_MODE: window_mode = window_mode {
+    name: "search-mode",
+    default_format: Some(WINDOW_SEARCH_DEFAULT_FORMAT),
+
+    init: window_search_init,
+    free: window_search_free,
+    resize: window_search_resize,
+    update: Some(window_search_update),
+    key: Some(window_search_key),
+    key_table: None,
+    command: None,
+    formats: None,
+};
+
+#[derive(num_enum::TryFromPrimitive)]
+#[repr(u32)]
+enum window_search_sort_type {
+    WINDOW_SEARCH_BY_INDEX,
+    WINDOW_SEARCH_BY_MATCHES,
+}
+
+static WINDOW_SEARCH_SORT_LIST: [&str; 2] = ["index", "matches"];
+
+struct window_search_itemdata {
+    pane: u32,
+    /// absolute line in the pane history when it was searched or `None` for
+    /// the pane itself
+    line: Option<u32>,
+    /// the pane history when it was searched, to find where the line is now
+    view: grid_index_view,
+    text: CString,
+}
+
+struct window_search_pane {
+    order: u32,
+    item: Box<window_search_itemdata>,
+    lines: Vec<window_search_itemdata>,
+}
+
+struct window_search_modedata {
+    wp: *mut window_pane,
+    dead: bool,
+    references: u32,
+
+    data: *mut mode_tree_data,
+    screen: *mut screen,
+    format: *mut u8,
+    key_format: *mut u8,
+    command: *mut u8,
+
+    pattern: *mut u8,
+    regex: bool,
+    ignore: bool,
+
+    panes: Vec<window_search_pane>,
+
+    /// copy of the history of a pane including the lines spilled to disk,
+    /// kept for drawing until the history changes
+    expanded: *mut grid,
+    expanded_pane: u32,
+    expanded_view: grid_index_view,
+}
+
+/// Search the history of every pane for the pattern. Windows linked into
+/// several sessions are only searched once. Returns false if the pattern is
+/// an invalid regular expression, leaving the previous results.
+unsafe fn window_search_find(data: *mut window_search_modedata, pattern: *const u8) -> bool {
+    unsafe {
+        let mut panes = Vec::new();
+        let mut searched = Vec::new();
+
+        for s in rb_foreach(&raw mut SESSIONS).map(NonNull::as_ptr) {
+            for wl in rb_foreach(&raw mut (*s).windows).map(NonNull::as_ptr) {
+                let w = (*wl).window;
+                if searched.contains(&(*w).id) {
+                    continue;
+                }
+                searched.push((*w).id);
+
+                for wp in tailq_foreach::<_, discr_entry>(&raw mut (*w).panes).map(NonNull::as_ptr) {
+                    let view = grid_index_view((*wp).base.grid);
+                    let mut lines = Vec::new();
+                    let mut cb = |py: u32, _hsize: u32, text: &CStr| {
+                        lines.push(window_search_itemdata {
+                            pane: (*wp).id,
+                            line: Some(py),
+                            view,
+                            text: text.to_owned(),
+                        });
+                    };
+                    if !window_pane_search_history(
+                        wp,
+                        pattern,
+                        (*data).regex,
+                        (*data).ignore,
+                        &mut cb,
+                    ) {
+                        return false;
+                    }
+                    if lines.is_empty() {
+                        continue;
+                    }
+                    panes.push(window_search_pane {
+                        order: panes.len() as u32,
+                        item: Box::new(window_search_itemdata {
+                            pane: (*wp).id,
+                            line: None,
+                            view,
+                            text: CString::default(),
+                        }),
+                        lines,
+                    });
+                }
+            }
+        }
+
+        free_((*data).pattern);
+        (*data).pattern = xstrdup(pattern).as_ptr();
+        (*data).panes = panes;
+        true
+    }
+}
+
+unsafe fn window_search_build(
+    modedata: NonNull<c_void>,
+    sort_crit: *mut mode_tree_sort_criteria,
+    _tag: *mut u64,
+    filter: *const u8,
+) {
+    unsafe {
+        let data: *mut window_search_modedata = modedata.cast().as_ptr();
+        let mut fs: cmd_find_state = zeroed();
+        let mut idx = 0;
+
+        match window_search_sort_type::try_from((*sort_crit).field) {
+            Ok(window_search_sort_type::WINDOW_SEARCH_BY_MATCHES) => {
+                (*data).panes.sort_by(|a, b| {
+                    b.lines
+                        .len()
+                        .cmp(&a.lines.len())
+                        .then_with(|| a.order.cmp(&b.order))
+                        .maybe_reverse((*sort_crit).reversed)
+                });
+            }
+            Ok(window_search_sort_type::WINDOW_SEARCH_BY_INDEX) | Err(_) => {
+                (*data)
+                    .panes
+                    .sort_by(|a, b| a.order.cmp(&b.order).maybe_reverse((*sort_crit).reversed));
+            }
+        }
+
+        for pane in &mut (*data).panes {
+            let wp = window_pane_find_by_id(pane.item.pane);
+            if wp.is_null() || cmd_find_from_pane(&raw mut fs, wp, cmd_find_flags::empty()) != 0 {
+                continue;
+            }
+            let s = NonNull::new(fs.s);
+            let wl = NonNull::new(fs.wl);
+
+            let mut lines = Vec::new();
+            for item in &mut pane.lines {
+                // Lines which have left the history are no longer shown.
+                let Some((py, hsize)) = window_search_line(wp, item) else {
+                    continue;
+                };
+                let ft = format_create(null_mut(), null_mut(), FORMAT_NONE, format_flags::empty());
+                format_defaults(ft, null_mut(), s, wl, NonNull::new(wp));
+                window_search_add_formats(ft, py, hsize, item);
+                if !filter.is_null() {
+                    let cp = format_expand(ft, filter);
+                    if !format_true(cp) {
+                        free_(cp);
+                        format_free(ft);
+                        continue;
+                    }
+                    free_(cp);
+                }
+                let text = format_expand(ft, (*data).format);
+                lines.push((&raw mut *item, py as i64 - hsize as i64, text));
+                format_free(ft);
+            }
+            if lines.is_empty() {
+                continue;
+            }
+
+            let ft = format_create(null_mut(), null_mut(), FORMAT_NONE, format_flags::empty());
+            format_defaults(ft, null_mut(), s, wl, NonNull::new(wp));
+            format_add!(ft, "match_format", "0");
+            format_add!(ft, "search_matches", "{}", lines.len());
+            let text = format_expand(ft, (*data).format);
+            format_free(ft);
+
+            window_pane_index(wp, &raw mut idx);
+            let name = format!("{}:{}.{}", (*fs.s).name, (*fs.wl).idx, idx);
+            let parent = mode_tree_add(
+                (*data).data,
+                null_mut(),
+                (&raw mut *pane.item).cast(),
+                (1 << 63) | (*wp).id as u64,
+                &name,
+                text,
+                None,
+            );
+            free_(text);
+
+            for (item, line, text) in lines {
+                let py = (*item).line.unwrap_or_default();
+                mode_tree_add(
+                    (*data).data,
+                    parent,
+                    item.cast(),
+                    (((*wp).id as u64) << 32) | py as u64,
+                    &format!("{line}"),
+                    text,
+                    None,
+                );
+                free_(text);
+            }
+        }
+    }
+}
+
+/// Find where the line for an item is in the pane's history now, as the line
+/// and the size of the history including lines spilled to disk.
+unsafe fn window_search_line(
+    wp: *mut window_pane,
+    item: &window_search_itemdata,
+) -> Option<(u32, u32)> {
+    unsafe {
+        let gd = (*wp).base.grid;
+        let py = grid_index_find(gd, &item.view, item.line?)?;
+        Some((py, grid_spill_size(gd) + (*gd).hsize))
+    }
+}
+
+unsafe fn window_search_add_formats(
+    ft: *mut format_tree,
+    py: u32,
+    hsize: u32,
+    item: &window_search_itemdata,
+) {
+    unsafe {
+        format_add!(ft, "match_format", "1");
+        format_add!(ft, "search_line", "{}", py as i64 - hsize as i64);
+        format_add!(ft, "search_text", "{}", item.text.to_string_lossy());
+    }
+}
+
+/// Get a copy of the history of a pane including the lines spilled to disk,
+/// making it again only if the history has changed.
+unsafe fn window_search_expand(data: *mut window_search_modedata, wp: *mut window_pane) -> *mut grid {
+    unsafe {
+        let base = (*wp).base.grid;
+        let view = grid_index_view(base);
+        if !(*data).expanded.is_null()
+            && (*data).expanded_pane == (*wp).id
+            && (*data).expanded_view == view
+        {
+            return (*data).expanded;
+        }
+        window_search_free_expanded(data);
+
+        (*data).expanded = grid_spill_expand(base);
+        (*data).expanded_pane = (*wp).id;
+        (*data).expanded_view = view;
+        (*data).expanded
+    }
+}
+
+unsafe fn window_search_free_expanded(data: *mut window_search_modedata) {
+    unsafe {
+        if !(*data).expanded.is_null() {
+            grid_destroy((*data).expanded);
+            (*data).expanded = null_mut();
+        }
+    }
+}
+
+/// Draw the history of the pane around a matching line, with the line
+/// highlighted.
+unsafe fn window_search_draw_line(
+    data: *mut window_search_modedata,
+    ctx: *mut screen_write_ctx,
+    wp: *mut window_pane,
+    py: u32,
+    sx: u32,
+    sy: u32,
+) {
+    unsafe {
+        let s = (*ctx).s;
+        let oo = (*(*wp).window).options;
+        let base = (*wp).base.grid;
+        let spilled = grid_spill_size(base);
+        let total = spilled + (*base).hsize + (*base).sy;
+        let mut gc: grid_cell = zeroed();
+        let mut hl: grid_cell = zeroed();
+
+        if py >= total {
+            return;
+        }
+        let mut start = py.saturating_sub(sy / 3);
+        if start + sy > total {
+            start = total.saturating_sub(sy);
+        }
+
+        // Only load the history spilled to disk if it is needed. The lines
+        // after it are read from the pane, which may have changed since.
+        let expanded = if start < spilled {
+            window_search_expand(data, wp)
+        } else {
+            null_mut()
+        };
+        let at = |row: u32| {
+            if row < spilled {
+                (expanded, row)
+            } else {
+                (base, row - spilled)
+            }
+        };
+        style_apply(&raw mut hl, oo, c!("copy-mode-current-match-style"), null_mut());
+
+        let mut highlight = false;
+        for yy in 0..sy.min(total - start) {
+            if start + yy == py {
+                highlight = true;
+            } else if start + yy > py {
+                let (gd, row) = at(start + yy - 1);
+                if !(*grid_get_line_compressed(gd, row))
+                    .flags
+                    .intersects(grid_line_flag::WRAPPED)
+                {
+                    highlight = false;
+                }
+            }
+
+            let (gd, row) = at(start + yy);
+            let cellsize = (*grid_get_line(gd, row)).cellsize;
+            let end = if highlight { sx.min((*gd).sx) } else { sx.min(cellsize) };
+            let mut xx = 0;
+            while xx < end {
+                grid_get_cell(gd, xx, row, &raw mut gc);
+                if xx + gc.data.width as u32 > sx {
+                    break;
+                }
+                if highlight {
+                    gc.fg = hl.fg;
+                    gc.bg = hl.bg;
+                    gc.attr = hl.attr;
+                }
+                grid_view_set_cell((*s).grid, (*s).cx + xx, (*s).cy + yy, &gc);
+                xx += 1;
+            }
+        }
+    }
+}
+
+unsafe fn window_search_draw(
+    modedata: *mut c_void,
+    itemdata: Option<NonNull<c_void>>,
+    ctx: *mut screen_write_ctx,
+    sx: u32,
+    sy: u32,
+) {
+    unsafe {
+        let Some(item) = itemdata.map(NonNull::cast::<window_search_itemdata>) else {
+            return;
+        };
+        let wp = window_pane_find_by_id((*item.as_ptr()).pane);
+        if wp.is_null() {
+            return;
+        }
+
+        if (*item.as_ptr()).line.is_none() {
+            screen_write_preview(ctx, &raw mut (*wp).base, sx, sy);
+        } else if let Some((py, _)) = window_search_line(wp, &*item.as_ptr()) {
+            window_search_draw_line(modedata.cast(), ctx, wp, py, sx, sy);
+        }
+    }
+}
+
+/// Show that nothing matched, since the tree is empty and draws nothing.
+unsafe fn window_search_draw_empty(data: *mut window_search_modedata) {
+    unsafe {
+        let mut ctx: screen_write_ctx = zeroed();
+
+        screen_write_start(&raw mut ctx, (*data).screen);
+        screen_write_clearscreen(&raw mut ctx, 8);
+        screen_write_cursormove(&raw mut ctx, 0, 0, 0);
+        screen_write_nputs!(
+            &raw mut ctx,
+            screen_size_x((*data).screen) as isize,
+            &raw const GRID_DEFAULT_CELL,
+            "No matches for: {}",
+            _s((*data).pattern),
+        );
+        screen_write_stop(&raw mut ctx);
+    }
+}
+
+unsafe fn window_search_redraw(data: *mut window_search_modedata) {
+    unsafe {
+        if (*data).panes.is_empty() {
+            window_search_draw_empty(data);
+        } else {
+            mode_tree_draw(&mut *(*data).data);
+        }
+        (*(*data).wp).flags |= window_pane_flags::PANE_REDRAW;
+    }
+}
+
+unsafe fn window_search_search(
+    _modedata: *mut c_void,
+    itemdata: NonNull<c_void>,
+    ss: *const u8,
+) -> bool {
+    unsafe {
+        let item: NonNull<window_search_itemdata> = itemdata.cast();
+        let ss = CStr::from_ptr(ss.cast()).to_bytes();
+        (*item.as_ptr())
+            .text
+            .to_bytes()
+            .windows(ss.len())
+            .any(|w| w == ss)
+    }
+}
+
+unsafe fn window_search_menu(modedata: NonNull<c_void>, c: *mut client, key: key_code) {
+    unsafe {
+        let data: NonNull<window_search_modedata> = modedata.cast();
+        let wp: *mut window_pane = (*data.as_ptr()).wp;
+
+        if let Some(wme) = NonNull::new(tailq_first(&raw mut (*wp).modes))
+            && (*wme.as_ptr()).data == modedata.as_ptr()
+        {
+            window_search_key(wme, c, null_mut(), null_mut(), key, null_mut());
+        }
+    }
+}
+
+unsafe fn window_search_get_key(
+    modedata: NonNull<c_void>,
+    itemdata: NonNull<c_void>,
+    line: u32,
+) -> key_code {
+    unsafe {
+        let data: NonNull<window_search_modedata> = modedata.cast();
+        let item: NonNull<window_search_itemdata> = itemdata.cast();
+        let mut fs: cmd_find_state = zeroed();
+
+        let ft = format_create(null_mut(), null_mut(), FORMAT_NONE, format_flags::empty());
+        let wp = window_pane_find_by_id((*item.as_ptr()).pane);
+        if !wp.is_null() && cmd_find_from_pane(&raw mut fs, wp, cmd_find_flags::empty()) == 0 {
+            format_defaults(
+                ft,
+                null_mut(),
+                NonNull::new(fs.s),
+                NonNull::new(fs.wl),
+                NonNull::new(wp),
+            );
+        }
+        if (*item.as_ptr()).line.is_some() {
+            let (py, hsize) = NonNull::new(wp)
+                .and_then(|wp| window_search_line(wp.as_ptr(), &*item.as_ptr()))
+                .unwrap_or_default();
+            window_search_add_formats(ft, py, hsize, &*item.as_ptr());
+        } else {
+            format_add!(ft, "match_format", "0");
+        }
+        format_add!(ft, "line", "{line}");
+
+        let expanded = format_expand(ft, (*data.as_ptr()).key_format);
+        let key = key_string_lookup_string(expanded);
+        free_(expanded);
+        format_free(ft);
+        key
+    }
+}
+
+unsafe fn window_search_init(
+    wme: NonNull<window_mode_entry>,
+    _fs: *mut cmd_find_state,
+    args: *mut args,
+) -> *mut screen {
+    unsafe {
+        let wp = (*wme.as_ptr()).wp;
+        let mut s = null_mut();
+
+        let data = Box::leak(Box::new(window_search_modedata {
+            wp,
+            dead: false,
+            references: 1,
+            data: null_mut(),
+            screen: null_mut(),
+            format: null_mut(),
+            key_format: null_mut(),
+            command: null_mut(),
+            pattern: null_mut(),
+            regex: args_has(args, 'r'),
+            ignore: args_has(args, 'i'),
+            panes: Vec::new(),
+            expanded: null_mut(),
+            expanded_pane: 0,
+            expanded_view: grid_index_view::default(),
+        })) as *mut window_search_modedata;
+        (*wme.as_ptr()).data = data.cast();
+
+        if !args_has(args, 'F') {
+            (*data).format = xstrdup__(WINDOW_SEARCH_DEFAULT_FORMAT);
+        } else {
+            (*data).format = xstrdup(args_get_(args, 'F')).as_ptr();
+        }
+        if !args_has(args, 'K') {
+            (*data).key_format = xstrdup__(WINDOW_SEARCH_DEFAULT_KEY_FORMAT);
+        } else {
+            (*data).key_format = xstrdup(args_get_(args, 'K')).as_ptr();
+        }
+        if args_count(args) < 2 {
+            (*data).command = xstrdup__(WINDOW_SEARCH_DEFAULT_COMMAND);
+        } else {
+            (*data).command = xstrdup(args_string(args, 1)).as_ptr();
+        }
+        if !window_search_find(data, args_string(args, 0)) {
+            (*data).pattern = xstrdup(args_string(args, 0)).as_ptr();
+        }
+
+        (*data).data = mode_tree_start(
+            wp,
+            args,
+            Some(window_search_build),
+            Some(window_search_draw),
+            Some(window_search_search),
+            Some(window_search_menu),
+            None,
+            Some(window_search_get_key),
+            data.cast(),
+            WINDOW_SEARCH_MENU_ITEMS.as_slice(),
+            &WINDOW_SEARCH_SORT_LIST,
+            &raw mut s,
+        );
+        (*data).screen = s;
+        mode_tree_zoom((*data).data, args);
+
+        mode_tree_build((*data).data);
+        window_search_redraw(data);
+
+        s
+    }
+}
+
+unsafe fn window_search_destroy(data: *mut window_search_modedata) {
+    unsafe {
+        (*data).references -= 1;
+        if (*data).references != 0 {
+            return;
+        }
+
+        free_((*data).format);
+        free_((*data).key_format);
+        free_((*data).command);
+        free_((*data).pattern);
+        window_search_free_expanded(data);
+
+        drop(Box::from_raw(data));
+    }
+}
+
+unsafe fn window_search_free(wme: NonNull<window_mode_entry>) {
+    unsafe {
+        let data: *mut window_search_modedata = (*wme.as_ptr()).data.cast();
+        if data.is_null() {
+            return;
+        }
+
+        (*data).dead = true;
+        mode_tree_free((*data).data);
+        window_search_destroy(data);
+    }
+}
+
+unsafe fn window_search_resize(wme: NonNull<window_mode_entry>, sx: u32, sy: u32) {
+    unsafe {
+        let data: *mut window_search_modedata = (*wme.as_ptr()).data.cast();
+
+        mode_tree_resize((*data).data, sx, sy);
+        if (*data).panes.is_empty() {
+            window_search_draw_empty(data);
+        }
+    }
+}
+
+unsafe fn window_search_update(wme: NonNull<window_mode_entry>) {
+    unsafe {
+        let data: *mut window_search_modedata = (*wme.as_ptr()).data.cast();
+
+        mode_tree_build((*data).data);
+        window_search_redraw(data);
+    }
+}
+
+unsafe fn window_search_pattern_callback(
+    c: *mut client,
+    data: NonNull<window_search_modedata>,
+    s: *const u8,
+    _done: i32,
+) -> i32 {
+    unsafe {
+        let data = data.as_ptr();
+
+        if s.is_null() || *s == b'\0' || (*data).dead {
+            return 0;
+        }
+
+        if !window_search_find(data, s) {
+            status_message_set!(c, -1, 1, false, "Invalid regular expression: {}", _s(s));
+            return 0;
+        }
+        mode_tree_build((*data).data);
+        window_search_redraw(data);
+
+        0
+    }
+}
+
+unsafe fn window_search_pattern_free(data: NonNull<window_search_modedata>) {
+    unsafe {
+        window_search_destroy(data.as_ptr());
+    }
+}
+
+/// Switch to the pane for the current item and, for a line, open copy mode
+/// on it.
+unsafe fn window_search_select(data: *mut window_search_modedata, c: *mut client) {
+    unsafe {
+        let item: NonNull<window_search_itemdata> = mode_tree_get_current((*data).data).cast();
+        let id = (*item.as_ptr()).pane;
+        let pattern = xstrdup((*data).pattern).as_ptr();
+        let regex = (*data).regex;
+
+        let wp = window_pane_find_by_id(id);
+        let mut line = None;
+        if !wp.is_null() {
+            line = window_search_line(wp, &*item.as_ptr()).map(|(py, _)| py);
+            let name = format!("%{id}");
+            mode_tree_run_command(c, null_mut(), (*data).command, Some(&name));
+        }
+
+        // This frees the mode data, so it must not be used after this.
+        window_pane_reset_mode((*data).wp);
+
+        if let Some(py) = line
+            && !wp.is_null()
+        {
+            let args = args_create();
+            window_pane_set_mode(wp, wp, &raw const WINDOW_COPY_MODE, null_mut(), args);
+            args_free(args);
+
+            let wme = tailq_first(&raw mut (*wp).modes);
+            if !wme.is_null() && std::ptr::eq((*wme).mode, &raw const WINDOW_COPY_MODE) {
+                window_copy_goto_match(wme, py, pattern, regex);
+            }
+        }
+        free_(pattern);
+    }
+}
+
+unsafe fn window_search_key(
+    wme: NonNull<window_mode_entry>,
+    c: *mut client,
+    _s: *mut session,
+    _wl: *mut winlink,
+    mut key: key_code,
+    m: *mut mouse_event,
+) {
+    unsafe {
+        let wp = (*wme.as_ptr()).wp;
+        let data: *mut window_search_modedata = (*wme.as_ptr()).data.cast();
+        let mtd = (*data).data;
+
+        // The tree is empty if nothing matched, so only a new search is
+        // possible.
+        let finished = if (*data).panes.is_empty() {
+            key != b's' as key_code
+        } else {
+            mode_tree_key(mtd, c, &raw mut key, m, null_mut(), null_mut()) != 0
+        };
+        if key == b's' as key_code {
+            (*data).references += 1;
+            status_prompt_set(
+                c,
+                null_mut(),
+                c!("(search) "),
+                (*data).pattern,
+                window_search_pattern_callback,
+                window_search_pattern_free,
+                data,
+                prompt_flags::PROMPT_NOFORMAT,
+                prompt_type::PROMPT_TYPE_SEARCH,
+            );
+        } else if key == b'\r' as key_code && !finished {
+            window_search_select(data, c);
+            return;
+        }
+
+        if finished {
+            window_pane_reset_mode(wp);
+        } else {
+            window_search_redraw(data);
+        }
+    }
+}
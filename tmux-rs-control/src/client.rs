@@ -0,0 +1,108 @@
+// Matching replies to commands. The server runs the commands from a control
+// client in the order they were sent and each is answered by a %begin and an
+// %end or %error with the same command number and with bit 0 of the flags
+// set, so each such reply belongs to the oldest command still waiting. Replies
+// without the flag are for commands the client did not send and match
+// nothing.
+use std::collections::VecDeque;
+
+use crate::command::Command;
+use crate::event::{Event, Message, ParseError, Parser, Reply};
+
+/// Identifies a command sent with `Client::send`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Tag(u64);
+
+/// Something received by a client.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Incoming {
+    Event(Event),
+    /// The reply to the command sent with this tag.
+    Reply(Tag, Reply),
+    /// A reply to a command this client did not send.
+    Unsolicited(Reply),
+}
+
+/// The protocol state of one control client, without any I/O: lines to write
+/// come from `send` and each line read is given to `receive`.
+#[derive(Debug, Default)]
+pub struct Client {
+    parser: Parser,
+    next: u64,
+    pending: VecDeque<Tag>,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a command. Returns its tag and the line, with its newline, to
+    /// write to the client.
+    pub fn send(&mut self, command: &Command) -> (Tag, String) {
+        let tag = Tag(self.next);
+        self.next += 1;
+        self.pending.push_back(tag);
+        (tag, format!("{command}\n"))
+    }
+
+    /// Handle one line read from the client. Returns None for lines which are
+    /// only part of a reply.
+    pub fn receive(&mut self, line: &[u8]) -> Result<Option<Incoming>, ParseError> {
+        let incoming = match self.parser.parse(line)? {
+            None => return Ok(None),
+            Some(Message::Event(event)) => Incoming::Event(event),
+            Some(Message::Reply(reply)) if reply.sent_by_client() => match self.pending.pop_front()
+            {
+                Some(tag) => Incoming::Reply(tag, reply),
+                None => Incoming::Unsolicited(reply),
+            },
+            Some(Message::Reply(reply)) => Incoming::Unsolicited(reply),
+        };
+        Ok(Some(incoming))
+    }
+
+    /// The number of commands still waiting for a reply.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client() {
+        let mut client = Client::new();
+        let (first, line) = client.send(&Command::new("list-windows"));
+        assert_eq!(line, "list-windows\n");
+        let (second, _) = client.send(&Command::new("kill-window").option('t', "@9"));
+        assert_eq!(client.pending(), 2);
+
+        let lines: [&[u8]; 8] = [
+            b"%begin 1 10 0",
+            b"%end 1 10 0",
+            b"%begin 1 11 1",
+            b"0: zsh",
+            b"%end 1 11 1",
+            b"%window-close @1",
+            b"%begin 1 12 1",
+            b"%error 1 12 1",
+        ];
+        let received: Vec<Incoming> = lines
+            .iter()
+            .filter_map(|line| client.receive(line).unwrap())
+            .collect();
+        assert!(matches!(&received[0], Incoming::Unsolicited(reply) if reply.number == 10));
+        assert!(matches!(&received[1], Incoming::Reply(tag, reply)
+            if *tag == first && reply.output == ["0: zsh"] && !reply.error));
+        assert_eq!(
+            received[2],
+            Incoming::Event(Event::WindowClose { window: 1 })
+        );
+        assert!(matches!(&received[3], Incoming::Reply(tag, reply)
+            if *tag == second && reply.error));
+        assert_eq!(client.pending(), 0);
+    }
+}
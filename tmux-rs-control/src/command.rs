@@ -0,0 +1,133 @@
+// Building command lines. Arguments are quoted so the command parser gives
+// back exactly the same strings: nothing in them is taken as a separator,
+// comment, brace, variable, home directory or escape.
+use std::fmt;
+
+/// A single command and its arguments, written to the server as one line.
+///
+/// Only one command is sent on each line so that each gets exactly one reply.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Command {
+    name: String,
+    args: Vec<String>,
+}
+
+impl Command {
+    /// Start a command with its name, for example `new-window` or `neww`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Add an argument.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Add several arguments.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Add a flag without a value, for example `flag('d')` for `-d`.
+    pub fn flag(self, flag: char) -> Self {
+        self.arg(format!("-{flag}"))
+    }
+
+    /// Add a flag and its value, for example `option('t', "%1")`.
+    pub fn option(self, flag: char, value: impl Into<String>) -> Self {
+        self.flag(flag).arg(value)
+    }
+
+    /// The command name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The arguments, unquoted.
+    pub fn get_args(&self) -> &[String] {
+        &self.args
+    }
+}
+
+impl fmt::Display for Command {
+    /// Write the command line, without the trailing newline.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&quote(&self.name))?;
+        for arg in &self.args {
+            write!(f, " {}", quote(arg))?;
+        }
+        Ok(())
+    }
+}
+
+fn quote_safe(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || "-_./:@%+,".contains(ch)
+}
+
+/// Quote an argument for the command parser. Words of only plain characters
+/// are left alone, anything else is put in single quotes, or in double quotes
+/// with escapes if it has a single quote or control characters in it.
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(quote_safe) {
+        return arg.to_string();
+    }
+    if !arg.chars().any(|ch| ch == '\'' || ch.is_ascii_control()) {
+        return format!("'{arg}'");
+    }
+
+    let mut out = String::with_capacity(arg.len() + 2);
+    out.push('"');
+    for ch in arg.chars() {
+        match ch {
+            '\\' | '"' | '$' | '~' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ if ch.is_ascii_control() => out.push_str(&format!("\\{:03o}", ch as u32)),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("abc-1.2"), "abc-1.2");
+        assert_eq!(quote("%1"), "%1");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b;c"), "'a b;c'");
+        assert_eq!(quote("#{pane_id}"), "'#{pane_id}'");
+        assert_eq!(quote("~/$HOME"), "'~/$HOME'");
+        assert_eq!(quote("it's"), "\"it's\"");
+        assert_eq!(quote("~\"$x\\\n\x1b"), "\"\\~\\\"\\$x\\\\\\n\\033\"");
+    }
+
+    #[test]
+    fn test_command() {
+        let command = Command::new("new-window")
+            .flag('d')
+            .option('n', "my window")
+            .arg("echo 'hi'; sleep 1");
+        assert_eq!(
+            command.to_string(),
+            "new-window -d -n 'my window' \"echo 'hi'; sleep 1\""
+        );
+    }
+}
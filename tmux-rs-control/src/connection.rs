@@ -0,0 +1,121 @@
+// A blocking client which runs a control client process and talks to it over
+// pipes.
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, ExitStatus, Stdio};
+
+use crate::client::{Client, Incoming, Tag};
+use crate::command::Command;
+use crate::event::{Event, Reply};
+
+/// A control client process. Events which arrive while waiting for a reply
+/// are kept until they are asked for.
+pub struct Connection {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+    client: Client,
+    events: VecDeque<Event>,
+    replies: HashMap<Tag, Reply>,
+}
+
+impl Connection {
+    /// Start a control client. The command should run the client with -C and
+    /// the command to start with, for example `tmux-rs -S path -C attach`;
+    /// its standard input and output are replaced with pipes.
+    pub fn spawn(mut command: std::process::Command) -> io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+            client: Client::new(),
+            events: VecDeque::new(),
+            replies: HashMap::new(),
+        })
+    }
+
+    /// Send a command without waiting for its reply.
+    pub fn send(&mut self, command: &Command) -> io::Result<Tag> {
+        let (tag, line) = self.client.send(command);
+        let Some(stdin) = &mut self.stdin else {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        };
+        stdin.write_all(line.as_bytes())?;
+        stdin.flush()?;
+        Ok(tag)
+    }
+
+    /// Wait for the reply to a command sent earlier.
+    pub fn wait(&mut self, tag: Tag) -> io::Result<Reply> {
+        loop {
+            if let Some(reply) = self.replies.remove(&tag) {
+                return Ok(reply);
+            }
+            if !self.read()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    /// Send a command and wait for its reply. A command which fails is not an
+    /// error here; check the reply's error flag.
+    pub fn execute(&mut self, command: &Command) -> io::Result<Reply> {
+        let tag = self.send(command)?;
+        self.wait(tag)
+    }
+
+    /// Wait for the next event. Returns None once the client has exited.
+    pub fn next_event(&mut self) -> io::Result<Option<Event>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+            if !self.read()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Close the client's input so it exits and wait for it. Any events not
+    /// yet read are dropped.
+    pub fn close(mut self) -> io::Result<ExitStatus> {
+        self.stdin = None;
+        while self.read()? {}
+        self.child.wait()
+    }
+
+    /// Read and handle one line. Returns false at the end of the output.
+    fn read(&mut self) -> io::Result<bool> {
+        let mut line = Vec::new();
+        if self.stdout.read_until(b'\n', &mut line)? == 0 {
+            return Ok(false);
+        }
+        let incoming = self
+            .client
+            .receive(&line)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        match incoming {
+            Some(Incoming::Event(event)) => self.events.push_back(event),
+            Some(Incoming::Reply(tag, reply)) => {
+                self.replies.insert(tag, reply);
+            }
+            Some(Incoming::Unsolicited(_)) | None => {}
+        }
+        Ok(true)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
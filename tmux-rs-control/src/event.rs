@@ -0,0 +1,541 @@
+// Parsing the lines a control client writes. Each line is either part of a
+// command reply, between a %begin and a %end or %error with the same command
+// number, or a notification starting with %. Notifications are never written
+// inside a reply so every line in one is output from the command.
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A notification from the server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    /// Output from a pane.
+    Output { pane: u32, data: Vec<u8> },
+    /// Output from a pane when the pause-after flag is set, with the time in
+    /// milliseconds it was buffered for.
+    ExtendedOutput { pane: u32, age: u64, data: Vec<u8> },
+    /// Output from a pane has been paused because it was buffered for too
+    /// long.
+    Pause { pane: u32 },
+    /// A paused pane has been continued.
+    Continue { pane: u32 },
+    /// A pane has entered or left a mode.
+    PaneModeChanged { pane: u32 },
+    /// A desktop notification from an application in a pane.
+    Notification {
+        pane: u32,
+        title: String,
+        body: String,
+    },
+    /// The layout of a window has changed.
+    LayoutChange {
+        window: u32,
+        layout: String,
+        visible_layout: String,
+        flags: String,
+    },
+    /// The active pane of a window has changed.
+    WindowPaneChanged { window: u32, pane: u32 },
+    /// A window in the attached session has been closed.
+    WindowClose { window: u32 },
+    /// A window in another session has been closed.
+    UnlinkedWindowClose { window: u32 },
+    /// A window has been added to the attached session.
+    WindowAdd { window: u32 },
+    /// A window has been added to another session.
+    UnlinkedWindowAdd { window: u32 },
+    /// A window in the attached session has been renamed.
+    WindowRenamed { window: u32, name: String },
+    /// A window in another session has been renamed.
+    UnlinkedWindowRenamed { window: u32, name: String },
+    /// This client is now attached to a different session.
+    SessionChanged { session: u32, name: String },
+    /// Another client is now attached to a different session.
+    ClientSessionChanged {
+        client: String,
+        session: u32,
+        name: String,
+    },
+    /// A client has detached.
+    ClientDetached { client: String },
+    /// The attached session has been renamed.
+    SessionRenamed { session: u32, name: String },
+    /// A session has been created or destroyed.
+    SessionsChanged,
+    /// The current window of a session has changed.
+    SessionWindowChanged { session: u32, window: u32 },
+    /// A paste buffer has been added or changed.
+    PasteBufferChanged { name: String },
+    /// A paste buffer has been deleted.
+    PasteBufferDeleted { name: String },
+    /// The value of a subscription set with `refresh-client -B` has changed.
+    /// The window is its ID and index.
+    SubscriptionChanged {
+        name: String,
+        session: u32,
+        window: Option<(u32, u32)>,
+        pane: Option<u32>,
+        value: String,
+    },
+    /// A notification matched a subscription set with `refresh-client -B
+    /// name:event=pattern:format`, with the format expanded for it.
+    SubscriptionNotify {
+        name: String,
+        event: String,
+        value: String,
+    },
+    /// An error in the configuration file.
+    ConfigError { message: String },
+    /// A message from `display-message` for this client.
+    Message { message: String },
+    /// The client is exiting, with the reason if there is one.
+    Exit { reason: Option<String> },
+    /// A notification this library does not know about, with its name
+    /// without the % and the rest of the line.
+    Other { name: String, args: String },
+}
+
+/// The reply to a command: its output and whether it failed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Reply {
+    pub time: i64,
+    pub number: u32,
+    pub flags: u32,
+    pub output: Vec<String>,
+    pub error: bool,
+}
+
+impl Reply {
+    /// Whether the command was sent by this client rather than run for it
+    /// some other way, such as the command the client was started with.
+    pub fn sent_by_client(&self) -> bool {
+        self.flags & 1 != 0
+    }
+}
+
+/// A complete notification or reply.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Message {
+    Event(Event),
+    Reply(Reply),
+}
+
+/// A line that could not be understood.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    pub line: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bad control mode line: {}", self.line)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Turns the lines from a control client into messages.
+#[derive(Debug, Default)]
+pub struct Parser {
+    block: Option<Reply>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse one line, with or without its line ending. Returns None for the
+    /// lines of a reply until the last.
+    pub fn parse(&mut self, line: &[u8]) -> Result<Option<Message>, ParseError> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if let Some(block) = &mut self.block {
+            let mut fields = Fields::new(line);
+            let error = match fields.word() {
+                Some(b"%end") => false,
+                Some(b"%error") => true,
+                _ => {
+                    block.output.push(string(line));
+                    return Ok(None);
+                }
+            };
+            if fields.number() == Some(block.time) && fields.number() == Some(block.number) {
+                let mut reply = self.block.take().unwrap();
+                reply.error = error;
+                return Ok(Some(Message::Reply(reply)));
+            }
+            block.output.push(string(line));
+            return Ok(None);
+        }
+
+        // With -CC the first line starts with a DCS sequence and the ST
+        // ending it comes after the last.
+        let line = line.strip_prefix(b"\x1bP1000p").unwrap_or(line);
+        let line = line.strip_prefix(b"\x1b\\").unwrap_or(line);
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let bad = || ParseError { line: string(line) };
+        let mut fields = Fields::new(line);
+        let name = fields.word().ok_or_else(bad)?;
+        if name == b"%begin" {
+            self.block = Some(Reply {
+                time: fields.number().ok_or_else(bad)?,
+                number: fields.number().ok_or_else(bad)?,
+                flags: fields.number().ok_or_else(bad)?,
+                ..Reply::default()
+            });
+            return Ok(None);
+        }
+        let name = name.strip_prefix(b"%").ok_or_else(bad)?;
+        let event = parse_event(name, fields).ok_or_else(bad)?;
+        Ok(Some(Message::Event(event)))
+    }
+}
+
+fn parse_event(name: &[u8], mut fields: Fields<'_>) -> Option<Event> {
+    let event = match name {
+        b"output" => Event::Output {
+            pane: fields.id(b'%')?,
+            data: unescape(fields.rest().unwrap_or_default()),
+        },
+        b"extended-output" => {
+            let pane = fields.id(b'%')?;
+            let age = fields.number()?;
+            while fields.word()? != b":" {}
+            Event::ExtendedOutput {
+                pane,
+                age,
+                data: unescape(fields.rest().unwrap_or_default()),
+            }
+        }
+        b"pause" => Event::Pause {
+            pane: fields.id(b'%')?,
+        },
+        b"continue" => Event::Continue {
+            pane: fields.id(b'%')?,
+        },
+        b"pane-mode-changed" => Event::PaneModeChanged {
+            pane: fields.id(b'%')?,
+        },
+        b"notification" => Event::Notification {
+            pane: fields.id(b'%')?,
+            title: string(&unescape(fields.word()?)),
+            body: string(&unescape(fields.rest().unwrap_or_default())),
+        },
+        b"layout-change" => Event::LayoutChange {
+            window: fields.id(b'@')?,
+            layout: string(fields.word()?),
+            visible_layout: string(fields.word()?),
+            flags: string(fields.rest().unwrap_or_default()),
+        },
+        b"window-pane-changed" => Event::WindowPaneChanged {
+            window: fields.id(b'@')?,
+            pane: fields.id(b'%')?,
+        },
+        b"window-close" => Event::WindowClose {
+            window: fields.id(b'@')?,
+        },
+        b"unlinked-window-close" => Event::UnlinkedWindowClose {
+            window: fields.id(b'@')?,
+        },
+        b"window-add" => Event::WindowAdd {
+            window: fields.id(b'@')?,
+        },
+        b"unlinked-window-add" => Event::UnlinkedWindowAdd {
+            window: fields.id(b'@')?,
+        },
+        b"window-renamed" => Event::WindowRenamed {
+            window: fields.id(b'@')?,
+            name: string(fields.rest()?),
+        },
+        b"unlinked-window-renamed" => Event::UnlinkedWindowRenamed {
+            window: fields.id(b'@')?,
+            name: string(fields.rest()?),
+        },
+        b"session-changed" => Event::SessionChanged {
+            session: fields.id(b'$')?,
+            name: string(fields.rest()?),
+        },
+        b"client-session-changed" => Event::ClientSessionChanged {
+            client: string(fields.word()?),
+            session: fields.id(b'$')?,
+            name: string(fields.rest()?),
+        },
+        b"client-detached" => Event::ClientDetached {
+            client: string(fields.rest()?),
+        },
+        b"session-renamed" => Event::SessionRenamed {
+            session: fields.id(b'$')?,
+            name: string(fields.rest()?),
+        },
+        b"sessions-changed" => Event::SessionsChanged,
+        b"session-window-changed" => Event::SessionWindowChanged {
+            session: fields.id(b'$')?,
+            window: fields.id(b'@')?,
+        },
+        b"paste-buffer-changed" => Event::PasteBufferChanged {
+            name: string(fields.rest()?),
+        },
+        b"paste-buffer-deleted" => Event::PasteBufferDeleted {
+            name: string(fields.rest()?),
+        },
+        b"subscription-changed" => {
+            let name = string(fields.word()?);
+            let session = fields.id(b'$')?;
+            let window = match fields.word()? {
+                b"-" => {
+                    fields.word()?;
+                    None
+                }
+                window => {
+                    let window = Fields::new(window).id(b'@')?;
+                    Some((window, fields.number()?))
+                }
+            };
+            let pane = match fields.word()? {
+                b"-" => None,
+                pane => Some(Fields::new(pane).id(b'%')?),
+            };
+            if fields.word()? != b":" {
+                return None;
+            }
+            Event::SubscriptionChanged {
+                name,
+                session,
+                window,
+                pane,
+                value: string(fields.rest().unwrap_or_default()),
+            }
+        }
+        b"subscription-event" => {
+            let name = string(fields.word()?);
+            let event = string(fields.word()?);
+            if fields.word()? != b":" {
+                return None;
+            }
+            Event::SubscriptionNotify {
+                name,
+                event,
+                value: string(fields.rest().unwrap_or_default()),
+            }
+        }
+        b"config-error" => Event::ConfigError {
+            message: string(fields.rest().unwrap_or_default()),
+        },
+        b"message" => Event::Message {
+            message: string(fields.rest().unwrap_or_default()),
+        },
+        b"exit" => Event::Exit {
+            reason: fields.rest().map(string),
+        },
+        _ => Event::Other {
+            name: string(name),
+            args: string(fields.rest().unwrap_or_default()),
+        },
+    };
+    Some(event)
+}
+
+/// The space separated fields of a line, the last of which may have spaces
+/// in it.
+struct Fields<'a> {
+    rest: Option<&'a [u8]>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(line: &'a [u8]) -> Self {
+        Self { rest: Some(line) }
+    }
+
+    fn word(&mut self) -> Option<&'a [u8]> {
+        let rest = self.rest.take()?;
+        match rest.iter().position(|&ch| ch == b' ') {
+            Some(i) => {
+                self.rest = Some(&rest[i + 1..]);
+                Some(&rest[..i])
+            }
+            None => Some(rest),
+        }
+    }
+
+    fn rest(&mut self) -> Option<&'a [u8]> {
+        self.rest.take()
+    }
+
+    fn number<T: FromStr>(&mut self) -> Option<T> {
+        std::str::from_utf8(self.word()?).ok()?.parse().ok()
+    }
+
+    /// A pane, window or session ID with its prefix.
+    fn id(&mut self, prefix: u8) -> Option<u32> {
+        let word = self.word()?.strip_prefix(&[prefix])?;
+        std::str::from_utf8(word).ok()?.parse().ok()
+    }
+}
+
+fn string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Undo the escaping of pane output and notifications: octal escapes for
+/// control characters and backslashes, and C style escapes.
+fn unescape(s: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] != b'\\' || i + 1 == s.len() {
+            out.push(s[i]);
+            i += 1;
+            continue;
+        }
+        let octal = s.get(i + 1..i + 4).filter(|digits| {
+            digits[0] <= b'3' && digits.iter().all(|ch| (b'0'..=b'7').contains(ch))
+        });
+        if let Some(digits) = octal {
+            out.push(digits.iter().fold(0, |n, ch| n * 8 + (ch - b'0')));
+            i += 4;
+            continue;
+        }
+        out.push(match s[i + 1] {
+            b'0' => b'\0',
+            b'a' => b'\x07',
+            b'b' => b'\x08',
+            b'f' => b'\x0c',
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b's' => b' ',
+            b't' => b'\t',
+            b'v' => b'\x0b',
+            ch => ch,
+        });
+        i += 2;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(line: &str) -> Option<Event> {
+        match Parser::new().parse(line.as_bytes()) {
+            Ok(Some(Message::Event(event))) => Some(event),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape(b"a\\015\\012\\134b"), b"a\r\n\\b");
+        assert_eq!(unescape(b"\\040x\\ty\\\\"), b" x\ty\\");
+        assert_eq!(unescape(b"\\9\\"), b"9\\");
+    }
+
+    #[test]
+    fn test_parse_event() {
+        assert_eq!(
+            event("%output %3 hi\\015\\012"),
+            Some(Event::Output {
+                pane: 3,
+                data: b"hi\r\n".to_vec()
+            })
+        );
+        assert_eq!(
+            event("%extended-output %1 250 : x"),
+            Some(Event::ExtendedOutput {
+                pane: 1,
+                age: 250,
+                data: b"x".to_vec()
+            })
+        );
+        assert_eq!(
+            event("%window-renamed @2 my window"),
+            Some(Event::WindowRenamed {
+                window: 2,
+                name: "my window".to_string()
+            })
+        );
+        assert_eq!(
+            event("%layout-change @1 b25f,80x24,0,0,0 b25f,80x24,0,0,0 *"),
+            Some(Event::LayoutChange {
+                window: 1,
+                layout: "b25f,80x24,0,0,0".to_string(),
+                visible_layout: "b25f,80x24,0,0,0".to_string(),
+                flags: "*".to_string()
+            })
+        );
+        assert_eq!(
+            event("%subscription-changed sub $0 @1 2 %3 : a : b"),
+            Some(Event::SubscriptionChanged {
+                name: "sub".to_string(),
+                session: 0,
+                window: Some((1, 2)),
+                pane: Some(3),
+                value: "a : b".to_string()
+            })
+        );
+        assert_eq!(
+            event("%subscription-changed sub $0 - - - : "),
+            Some(Event::SubscriptionChanged {
+                name: "sub".to_string(),
+                session: 0,
+                window: None,
+                pane: None,
+                value: String::new()
+            })
+        );
+        assert_eq!(
+            event("%subscription-event sub option-set : status on"),
+            Some(Event::SubscriptionNotify {
+                name: "sub".to_string(),
+                event: "option-set".to_string(),
+                value: "status on".to_string()
+            })
+        );
+        assert_eq!(
+            event("%message hello  world"),
+            Some(Event::Message {
+                message: "hello  world".to_string()
+            })
+        );
+        assert_eq!(
+            event("%message"),
+            Some(Event::Message {
+                message: String::new()
+            })
+        );
+        assert_eq!(event("%exit"), Some(Event::Exit { reason: None }));
+        assert_eq!(event("%sessions-changed"), Some(Event::SessionsChanged));
+        assert_eq!(event("%window-add 1"), None);
+        assert_eq!(
+            event("%something new"),
+            Some(Event::Other {
+                name: "something".to_string(),
+                args: "new".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(b"\x1bP1000p%begin 100 7 1\n"), Ok(None));
+        assert_eq!(parser.parse(b"%end 100 6 1\n"), Ok(None));
+        assert_eq!(parser.parse(b"%window-add @1\n"), Ok(None));
+        assert_eq!(
+            parser.parse(b"%error 100 7 1\n"),
+            Ok(Some(Message::Reply(Reply {
+                time: 100,
+                number: 7,
+                flags: 1,
+                output: vec!["%end 100 6 1".to_string(), "%window-add @1".to_string()],
+                error: true
+            })))
+        );
+        assert!(parser.parse(b"stray\n").is_err());
+    }
+}
@@ -0,0 +1,17 @@
+// A client library for tmux-rs control mode (-C).
+//
+// The protocol is handled without doing any I/O: Command builds command lines
+// quoted the way the server parses them, Parser turns the lines written by the
+// server into typed events and replies, and Client matches each reply to the
+// command it answers. These work the same with any runtime. Connection is a
+// small blocking client on top of them which runs a control client process.
+
+mod client;
+mod command;
+mod connection;
+mod event;
+
+pub use client::{Client, Incoming, Tag};
+pub use command::{Command, quote};
+pub use connection::Connection;
+pub use event::{Event, Message, ParseError, Parser, Reply};
@@ -0,0 +1,264 @@
+// Drive a real tmux-rs server through a control client attached over its
+// socket. The tests use the tmux-rs binary built next to them, or the one in
+// $TMUX_RS if it is set, building it into a separate directory if neither
+// exists.
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use tmux_rs_control::{Command, Connection, Event};
+
+fn tmux_rs() -> &'static Path {
+    static BINARY: OnceLock<PathBuf> = OnceLock::new();
+    BINARY.get_or_init(|| {
+        if let Some(binary) = env::var_os("TMUX_RS") {
+            return binary.into();
+        }
+        let exe = env::current_exe().unwrap();
+        let binary = exe.parent().unwrap().parent().unwrap().join("tmux-rs");
+        if binary.exists() {
+            return binary;
+        }
+
+        // The target directory is locked while the tests run.
+        let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("tmux-rs");
+        let status = process::Command::new(env!("CARGO"))
+            .args(["build", "--bin", "tmux-rs", "--target-dir"])
+            .arg(&target)
+            .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/.."))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        target.join("debug").join("tmux-rs")
+    })
+}
+
+/// A server with one detached session called test, killed when dropped or
+/// if a test is still running after a minute so it fails rather than waiting
+/// forever for an event.
+struct Server {
+    socket: PathBuf,
+}
+
+impl Server {
+    fn start(name: &str) -> Self {
+        let socket = env::temp_dir().join(format!("tmux-rs-control-{}-{name}", process::id()));
+        let server = Self { socket };
+        let status = server
+            .command()
+            .args([
+                "-f/dev/null",
+                "new-session",
+                "-d",
+                "-s",
+                "test",
+                "-x",
+                "80",
+                "-y",
+                "24",
+            ])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut kill = server.command();
+        kill.arg("kill-server");
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs(60));
+            let _ = kill.status();
+        });
+        server
+    }
+
+    fn command(&self) -> process::Command {
+        let mut command = process::Command::new(tmux_rs());
+        command.arg("-S").arg(&self.socket).env("TERM", "screen");
+        command
+    }
+
+    fn attach(&self) -> Connection {
+        let mut command = self.command();
+        command.args(["-C", "attach-session", "-t", "test"]);
+        Connection::spawn(command).unwrap()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.command().arg("kill-server").status();
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
+
+/// Wait for an event matching f.
+fn wait_for(connection: &mut Connection, f: impl Fn(&Event) -> bool) -> Event {
+    loop {
+        let event = connection.next_event().unwrap().expect("client exited");
+        if f(&event) {
+            return event;
+        }
+    }
+}
+
+#[test]
+fn test_attach() {
+    let server = Server::start("attach");
+    let mut connection = server.attach();
+    let event = wait_for(&mut connection, |event| {
+        matches!(event, Event::SessionChanged { .. })
+    });
+    assert!(matches!(event, Event::SessionChanged { name, .. } if name == "test"));
+    assert!(connection.close().unwrap().success());
+}
+
+#[test]
+fn test_quoting() {
+    let server = Server::start("quoting");
+    let mut connection = server.attach();
+    let values = [
+        "plain",
+        "two words",
+        "it's \"quoted\"",
+        "$HOME ~ \\ ; } { #{session_name} # comment",
+        "tab\tand\\nnewline",
+    ];
+    for value in values {
+        let reply = connection
+            .execute(&Command::new("set-buffer").option('b', "test").arg(value))
+            .unwrap();
+        assert!(!reply.error, "{value}: {:?}", reply.output);
+        let reply = connection
+            .execute(&Command::new("show-buffer").option('b', "test"))
+            .unwrap();
+        assert_eq!(reply.output, [value]);
+    }
+}
+
+#[test]
+fn test_replies() {
+    let server = Server::start("replies");
+    let mut connection = server.attach();
+
+    // Several commands in flight are each matched to their own reply.
+    let tags: Vec<_> = (0..5)
+        .map(|i| {
+            let command = Command::new("display-message")
+                .flag('p')
+                .arg(format!("reply {i}"));
+            connection.send(&command).unwrap()
+        })
+        .collect();
+    let bad = connection
+        .send(&Command::new("select-window").option('t', "@999"))
+        .unwrap();
+    for (i, tag) in tags.into_iter().enumerate().rev() {
+        let reply = connection.wait(tag).unwrap();
+        assert!(!reply.error);
+        assert_eq!(reply.output, [format!("reply {i}")]);
+    }
+    let reply = connection.wait(bad).unwrap();
+    assert!(reply.error);
+    assert_eq!(reply.output.len(), 1);
+}
+
+#[test]
+fn test_events() {
+    let server = Server::start("events");
+    let mut connection = server.attach();
+
+    let reply = connection
+        .execute(
+            &Command::new("new-window")
+                .flag('P')
+                .option('F', "#{window_id} #{pane_id}")
+                .option('n', "first window")
+                .arg("printf 'hello\\tthere\\n'; exec sleep 10"),
+        )
+        .unwrap();
+    assert!(!reply.error);
+    let ids: Vec<u32> = reply.output[0]
+        .split(' ')
+        .map(|id| id[1..].parse().unwrap())
+        .collect();
+    let (window, pane) = (ids[0], ids[1]);
+    wait_for(&mut connection, |event| {
+        *event == Event::WindowAdd { window }
+    });
+    wait_for(&mut connection, |event| {
+        matches!(event, Event::Output { pane: p, data }
+            if *p == pane && data.windows(12).any(|w| w == b"hello\tthere\r"))
+    });
+
+    connection
+        .execute(
+            &Command::new("rename-window")
+                .option('t', format!("@{window}"))
+                .arg("my window"),
+        )
+        .unwrap();
+    let name = "my window".to_string();
+    wait_for(&mut connection, |event| {
+        *event
+            == Event::WindowRenamed {
+                window,
+                name: name.clone(),
+            }
+    });
+
+    connection
+        .execute(&Command::new("kill-window").option('t', format!("@{window}")))
+        .unwrap();
+    wait_for(&mut connection, |event| {
+        matches!(event, Event::WindowClose { window: w } | Event::UnlinkedWindowClose { window: w }
+            if *w == window)
+    });
+}
+
+#[test]
+fn test_subscriptions() {
+    let server = Server::start("subscriptions");
+    let mut connection = server.attach();
+
+    let subscriptions = [
+        "options:event=option-*:#{hook_option_scope} #{hook_option}=#{hook_option_value}",
+        "buffers:event=paste-buffer-changed:#{hook_buffer}",
+    ];
+    for subscription in subscriptions {
+        let reply = connection
+            .execute(&Command::new("refresh-client").option('B', subscription))
+            .unwrap();
+        assert!(!reply.error);
+    }
+    connection
+        .execute(
+            &Command::new("set-option")
+                .flag('g')
+                .arg("@colour")
+                .arg("red"),
+        )
+        .unwrap();
+    connection
+        .execute(&Command::new("set-buffer").option('b', "clip").arg("text"))
+        .unwrap();
+
+    let mut wanted = vec![
+        ("options", "option-set", "session @colour=red"),
+        ("buffers", "paste-buffer-changed", "clip"),
+    ];
+    while !wanted.is_empty() {
+        let event = wait_for(&mut connection, |event| {
+            matches!(event, Event::SubscriptionNotify { .. })
+        });
+        let Event::SubscriptionNotify { name, event, value } = event else {
+            unreachable!();
+        };
+        let i = wanted
+            .iter()
+            .position(|w| *w == (name.as_str(), event.as_str(), value.as_str()))
+            .unwrap_or_else(|| panic!("unexpected {name} {event}: {value}"));
+        wanted.remove(i);
+    }
+}